@@ -0,0 +1,177 @@
+//! Admission control for tool calls: a fixed number run at a time, a
+//! bounded number wait for a slot, and the rest are turned away with a hint
+//! of when to retry, so a burst of calls can't pile up without bound.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Call duration assumed until one has been measured.
+const INITIAL_CALL_DURATION: Duration = Duration::from_secs(1);
+
+/// Weight of the latest call in the average call duration.
+const AVERAGE_WEIGHT: f64 = 0.2;
+
+pub struct Admission {
+    limit: usize,
+    queue_depth: usize,
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+    /// Moving average of how long calls hold a slot.
+    average: Mutex<Duration>,
+}
+
+/// Why a call wasn't admitted.
+#[derive(Debug, PartialEq)]
+pub enum Refusal {
+    /// The queue is full; a retry after this long may find a slot.
+    Full(Duration),
+    /// No slot freed up before the call's deadline.
+    Deadline,
+}
+
+/// A call waiting for a slot, counted as queued until it gets one, runs out
+/// of time or is cancelled.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A running call's slot, released when dropped.
+pub struct Slot<'a> {
+    admission: &'a Admission,
+    started: Instant,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Admission {
+    /// Runs `limit` calls at a time with up to `queue_depth` waiting.
+    pub fn new(limit: usize, queue_depth: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            queue_depth,
+            permits: Arc::new(Semaphore::new(limit.max(1))),
+            queued: AtomicUsize::new(0),
+            average: Mutex::new(INITIAL_CALL_DURATION),
+        }
+    }
+
+    /// Waits for a slot until `deadline`, if given. Returns after how long a
+    /// retry may find one if the queue is full.
+    pub async fn admit(&self, deadline: Option<Instant>) -> Result<Slot<'_>, Refusal> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(self.slot(permit));
+        }
+        let queued = self.queued.fetch_add(1, Ordering::SeqCst);
+        let _waiting = Waiting(&self.queued);
+        if queued >= self.queue_depth {
+            return Err(Refusal::Full(self.retry_after(queued)));
+        }
+        let acquire = self.permits.clone().acquire_owned();
+        let permit = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), acquire)
+                .await
+                .map_err(|_| Refusal::Deadline)?,
+            None => acquire.await,
+        };
+        // The semaphore is never closed.
+        Ok(self.slot(permit.expect("Admission semaphore closed")))
+    }
+
+    /// The limits and the calls running and waiting.
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "limit": self.limit,
+            "queue_depth": self.queue_depth,
+            "running": self.limit - self.permits.available_permits(),
+            "queued": self.queued.load(Ordering::SeqCst),
+        })
+    }
+
+    fn slot(&self, permit: OwnedSemaphorePermit) -> Slot<'_> {
+        Slot {
+            admission: self,
+            started: Instant::now(),
+            _permit: permit,
+        }
+    }
+
+    /// Roughly how long the queued calls, and one running round, take.
+    fn retry_after(&self, queued: usize) -> Duration {
+        let average = *self.average.lock().unwrap_or_else(|e| e.into_inner());
+        let rounds = queued as f64 / self.limit as f64 + 1.0;
+        Duration::from_secs(average.mul_f64(rounds).as_secs_f64().ceil().max(1.0) as u64)
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        let mut average = self
+            .admission
+            .average
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *average = average.mul_f64(1.0 - AVERAGE_WEIGHT) + elapsed.mul_f64(AVERAGE_WEIGHT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_calls_queue_and_then_are_turned_away() {
+        let admission = Arc::new(Admission::new(1, 1));
+        let running = admission.admit(None).await.unwrap();
+
+        let waiting = {
+            let admission = admission.clone();
+            tokio::spawn(async move { admission.admit(None).await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(admission.snapshot()["queued"], 1);
+
+        // One queued call ahead, at the initial one second per call.
+        assert_eq!(
+            admission.admit(None).await.err(),
+            Some(Refusal::Full(Duration::from_secs(2)))
+        );
+
+        drop(running);
+        assert!(waiting.await.unwrap());
+        assert_eq!(
+            admission.snapshot(),
+            json!({"limit": 1, "queue_depth": 1, "running": 0, "queued": 0})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_and_expired_calls_leave_the_queue() {
+        let admission = Arc::new(Admission::new(1, 1));
+        let _running = admission.admit(None).await.unwrap();
+
+        let waiting = {
+            let admission = admission.clone();
+            tokio::spawn(async move { admission.admit(None).await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(admission.snapshot()["queued"], 1);
+        waiting.abort();
+        assert!(waiting.await.unwrap_err().is_cancelled());
+        assert_eq!(admission.snapshot()["queued"], 0);
+
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert_eq!(
+            admission.admit(Some(deadline)).await.err(),
+            Some(Refusal::Deadline)
+        );
+        assert_eq!(admission.snapshot()["queued"], 0);
+    }
+}
@@ -0,0 +1,357 @@
+//! Alerts on tool error rates: failures are counted per tool over a rolling
+//! window, and crossing the threshold logs a structured warning and, if
+//! configured, posts to a webhook, so on-call hears of broken retrieval
+//! before users do. Recovery is announced the same way, once the rate is
+//! clearly below the threshold, and a tool that recovered stays quiet for a
+//! cooldown, so a rate hovering at the threshold doesn't page over and over.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use serde_json::{Value, json};
+
+/// How long a webhook request may take.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Webhook posts allowed per [`WEBHOOK_RATE_WINDOW`], across all tools;
+/// alerts beyond them are only logged.
+const MAX_WEBHOOK_POSTS: usize = 10;
+const WEBHOOK_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Settings of error-rate alerts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ErrorAlertConfig {
+    /// Error rate, in percent of a tool's calls, from which it alerts.
+    pub threshold_percent: u32,
+    /// Window over which calls are counted.
+    pub window: Duration,
+    /// Calls in the window below which a tool never alerts, so a single
+    /// failure on an idle server doesn't page anyone.
+    pub min_calls: u64,
+    /// Percentage points below the threshold the error rate must drop to for
+    /// an alert to resolve.
+    pub resolve_margin_percent: u32,
+    /// How long after resolving a tool can't alert again.
+    pub cooldown: Duration,
+    /// Receives a JSON POST when an alert fires or resolves. Posted even when
+    /// telemetry is off, as it is a destination the operator chose.
+    pub webhook_url: Option<String>,
+}
+
+impl ErrorAlertConfig {
+    pub fn new(threshold_percent: u32) -> Self {
+        Self {
+            threshold_percent,
+            window: Duration::from_secs(300),
+            min_calls: 20,
+            resolve_margin_percent: 10,
+            cooldown: Duration::from_secs(600),
+            webhook_url: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    /// Seconds since tracking started.
+    second: u64,
+    calls: u64,
+    errors: u64,
+}
+
+#[derive(Default)]
+struct ToolWindow {
+    buckets: VecDeque<Bucket>,
+    alerting: bool,
+    resolved_at: Option<Instant>,
+}
+
+impl ToolWindow {
+    fn totals(&self) -> (u64, u64) {
+        self.buckets.iter().fold((0, 0), |(calls, errors), bucket| {
+            (calls + bucket.calls, errors + bucket.errors)
+        })
+    }
+}
+
+/// Rolling error rates per tool. Clones share the counts.
+#[derive(Clone)]
+pub struct ErrorRates {
+    config: Arc<ErrorAlertConfig>,
+    client: Client,
+    started: Instant,
+    tools: Arc<Mutex<BTreeMap<String, ToolWindow>>>,
+    /// When recent webhook posts were made, oldest first.
+    posts: Arc<Mutex<VecDeque<Instant>>>,
+}
+
+impl ErrorRates {
+    pub fn new(config: ErrorAlertConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            client: Client::new(),
+            started: Instant::now(),
+            tools: Arc::default(),
+            posts: Arc::default(),
+        }
+    }
+
+    /// Counts a call, alerting if it moved the tool across the threshold.
+    pub fn record(&self, tool_name: &str, failed: bool) {
+        let now = Instant::now();
+        if let Some(alert) = self.record_at(tool_name, failed, now) {
+            self.send(alert, now);
+        }
+    }
+
+    /// The calls, errors and error rate of each tool over the window.
+    pub fn snapshot(&self) -> Value {
+        let mut tools = self.lock();
+        let now = Instant::now();
+        let rates: BTreeMap<&String, Value> = tools
+            .iter_mut()
+            .map(|(name, window)| {
+                self.prune(window, now);
+                let (calls, errors) = window.totals();
+                let rate = (calls > 0).then(|| errors as f64 / calls as f64);
+                let rate =
+                    json!({"calls": calls, "errors": errors, "error_rate": rate, "alerting": window.alerting});
+                (name, rate)
+            })
+            .collect();
+        json!({
+            "window_secs": self.config.window.as_secs(),
+            "threshold_percent": self.config.threshold_percent,
+            "tools": rates,
+        })
+    }
+
+    /// Counts a call and returns the alert to send, if any.
+    fn record_at(&self, tool_name: &str, failed: bool, now: Instant) -> Option<Value> {
+        let second = now.saturating_duration_since(self.started).as_secs();
+        let mut tools = self.lock();
+        let window = tools.entry(tool_name.to_string()).or_default();
+        self.prune(window, now);
+        if window.buckets.back().is_none_or(|b| b.second != second) {
+            window.buckets.push_back(Bucket {
+                second,
+                calls: 0,
+                errors: 0,
+            });
+        }
+        if let Some(bucket) = window.buckets.back_mut() {
+            bucket.calls += 1;
+            bucket.errors += u64::from(failed);
+        }
+
+        let (calls, errors) = window.totals();
+        let cooling_down = window
+            .resolved_at
+            .is_some_and(|resolved| now.saturating_duration_since(resolved) < self.config.cooldown);
+        let above = calls >= self.config.min_calls
+            && errors * 100 >= calls * u64::from(self.config.threshold_percent);
+        // Recovery is judged on the same calls, so an idle tool that saw no
+        // further failures doesn't stay alerting forever.
+        let resolve_percent = self
+            .config
+            .threshold_percent
+            .saturating_sub(self.config.resolve_margin_percent);
+        let below = errors == 0 || errors * 100 < calls * u64::from(resolve_percent);
+        let status = match (window.alerting, above, below) {
+            (false, true, _) if !cooling_down => "firing",
+            (true, _, true) => "resolved",
+            _ => return None,
+        };
+        window.alerting = status == "firing";
+        if !window.alerting {
+            window.resolved_at = Some(now);
+        }
+        Some(json!({
+            "event": "error_rate_alert",
+            "status": status,
+            "tool": tool_name,
+            "calls": calls,
+            "errors": errors,
+            "error_rate": errors as f64 / calls as f64,
+            "threshold_percent": self.config.threshold_percent,
+            "window_secs": self.config.window.as_secs(),
+        }))
+    }
+
+    fn send(&self, alert: Value, now: Instant) {
+        let tool = alert["tool"].as_str().unwrap_or_default();
+        let rate = alert["error_rate"].as_f64().unwrap_or_default() * 100.0;
+        if alert["status"] == "firing" {
+            tracing::warn!(
+                alert = "error_rate",
+                tool,
+                error_rate = rate,
+                calls = alert["calls"].as_u64(),
+                errors = alert["errors"].as_u64(),
+                "Error rate of {tool} is {rate:.0}%, at or above {}%",
+                self.config.threshold_percent
+            );
+        } else {
+            tracing::info!(
+                alert = "error_rate",
+                tool,
+                error_rate = rate,
+                "Error rate of {tool} is back to {rate:.0}%"
+            );
+        }
+
+        let Some(url) = self.config.webhook_url.clone() else {
+            return;
+        };
+        if !self.allow_post(now) {
+            tracing::warn!("Not posting the alert on {tool}: too many alerts posted recently");
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let request = self.client.post(url).timeout(WEBHOOK_TIMEOUT).json(&alert);
+        runtime.spawn(async move {
+            match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => tracing::debug!("Posted error-rate alert to the webhook"),
+                Err(e) => tracing::warn!("Failed to post error-rate alert: {e}"),
+            }
+        });
+    }
+
+    /// Takes a slot for a webhook post, if the rate limit has one left.
+    fn allow_post(&self, now: Instant) -> bool {
+        let mut posts = self.posts.lock().unwrap_or_else(|e| e.into_inner());
+        while posts
+            .front()
+            .is_some_and(|post| now.saturating_duration_since(*post) >= WEBHOOK_RATE_WINDOW)
+        {
+            posts.pop_front();
+        }
+        if posts.len() >= MAX_WEBHOOK_POSTS {
+            return false;
+        }
+        posts.push_back(now);
+        true
+    }
+
+    fn prune(&self, window: &mut ToolWindow, now: Instant) {
+        let second = now.saturating_duration_since(self.started).as_secs();
+        let oldest = second.saturating_sub(self.config.window.as_secs().max(1) - 1);
+        while window.buckets.front().is_some_and(|b| b.second < oldest) {
+            window.buckets.pop_front();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, ToolWindow>> {
+        self.tools.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(min_calls: u64) -> ErrorRates {
+        ErrorRates::new(ErrorAlertConfig {
+            window: Duration::from_secs(60),
+            min_calls,
+            ..ErrorAlertConfig::new(50)
+        })
+    }
+
+    #[test]
+    fn test_alerts_fire_once_and_resolve() {
+        let rates = rates(4);
+        let now = rates.started;
+        let record = |failed| rates.record_at("assistant_context", failed, now);
+
+        // Too few calls to judge.
+        assert!(record(true).is_none());
+        assert!(record(true).is_none());
+        assert!(record(false).is_none());
+        let fired = record(false).unwrap();
+        assert_eq!(fired["status"], "firing");
+        assert_eq!(fired["error_rate"], 0.5);
+        assert!(record(true).is_none());
+
+        // Back under the threshold, but not yet by the margin.
+        assert!(record(false).is_none());
+        assert!(record(false).is_none());
+        let resolved = record(false).unwrap();
+        assert_eq!(resolved["status"], "resolved");
+        assert_eq!(rates.snapshot()["tools"]["assistant_context"]["calls"], 8);
+    }
+
+    #[test]
+    fn test_resolved_alerts_cool_down_before_firing_again() {
+        let rates = rates(1);
+        let start = rates.started;
+        assert!(rates.record_at("health", true, start).is_some());
+        let later = start + Duration::from_secs(60);
+        assert!(rates.record_at("health", false, later).is_some());
+
+        assert!(rates.record_at("health", true, later).is_none());
+        assert!(rates.record_at("health", true, later).is_none());
+
+        let cooled = later + rates.config.cooldown;
+        let fired = rates.record_at("health", true, cooled).unwrap();
+        assert_eq!(fired["status"], "firing");
+    }
+
+    #[test]
+    fn test_webhook_posts_are_rate_limited() {
+        let rates = rates(1);
+        let start = rates.started;
+        for _ in 0..MAX_WEBHOOK_POSTS {
+            assert!(rates.allow_post(start));
+        }
+        assert!(!rates.allow_post(start + Duration::from_secs(1)));
+        assert!(rates.allow_post(start + WEBHOOK_RATE_WINDOW));
+    }
+
+    #[test]
+    fn test_old_failures_leave_the_window() {
+        let rates = rates(1);
+        let start = rates.started;
+        assert!(rates.record_at("health", true, start).is_some());
+
+        let later = start + Duration::from_secs(60);
+        let resolved = rates.record_at("health", false, later).unwrap();
+
+        assert_eq!(resolved["status"], "resolved");
+        assert_eq!(resolved["calls"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_alerts_are_posted_to_the_webhook() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = server
+            .mock("POST", "/alerts")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "event": "error_rate_alert",
+                "status": "firing",
+                "tool": "assistant_context",
+            })))
+            .create_async()
+            .await;
+        let rates = ErrorRates::new(ErrorAlertConfig {
+            min_calls: 1,
+            webhook_url: Some(format!("{}/alerts", server.url())),
+            ..ErrorAlertConfig::new(50)
+        });
+
+        rates.record("assistant_context", true);
+
+        for _ in 0..50 {
+            if webhook.matched_async().await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        webhook.assert_async().await;
+    }
+}
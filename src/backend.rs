@@ -0,0 +1,298 @@
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+
+use serde_json::Value;
+
+use crate::pinecone::{
+    Assistant, AssistantChat, AssistantChatResponse, AssistantContext, AssistantContextResponse,
+    AssistantFile, AssistantSettings, ChatDeltas, Message, PineconeClient, PineconeError,
+};
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, PineconeError>> + Send + 'a>>;
+
+/// A source of context snippets for assistant queries.
+///
+/// [`PineconeClient`] is the production implementation; alternative backends
+/// (mocks, local development indexes) can be plugged into the router through
+/// [`PineconeAssistantRouter::with_backend`](crate::PineconeAssistantRouter::with_backend).
+pub trait ContextBackend: Send + Sync {
+    fn assistant_context<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        query: &'a str,
+        top_k: Option<u32>,
+    ) -> BackendFuture<'a, AssistantContextResponse>;
+
+    /// Retrieves context honoring every option of `request`. Backends without
+    /// support for the extra options serve the query (the last message of a
+    /// conversation) and top_k only.
+    fn assistant_context_with<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        request: AssistantContext,
+    ) -> BackendFuture<'a, AssistantContextResponse> {
+        Box::pin(async move {
+            let query = match request.messages.as_deref() {
+                Some([.., last]) => last.content.as_str(),
+                _ => request.query.as_str(),
+            };
+            self.assistant_context(assistant_name, query, request.top_k)
+                .await
+        })
+    }
+
+    /// Generates an answer to the conversation so far. Backends that only
+    /// serve context need not implement it.
+    fn assistant_chat<'a>(
+        &'a self,
+        _assistant_name: &'a str,
+        _messages: Vec<Message>,
+    ) -> BackendFuture<'a, AssistantChatResponse> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not support chat".to_string(),
+            ))
+        })
+    }
+
+    /// Generates an answer honoring every option of `request`. Backends
+    /// without support for the extra options answer the messages only.
+    fn assistant_chat_with<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        request: AssistantChat,
+    ) -> BackendFuture<'a, AssistantChatResponse> {
+        self.assistant_chat(assistant_name, request.messages)
+    }
+
+    /// Generates an answer like `assistant_chat_with`, passing its text to
+    /// `on_delta` as it is generated. Backends that cannot stream pass the
+    /// whole answer at once.
+    fn assistant_chat_stream<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        request: AssistantChat,
+        on_delta: &'a ChatDeltas<'a>,
+    ) -> BackendFuture<'a, AssistantChatResponse> {
+        Box::pin(async move {
+            let response = self.assistant_chat_with(assistant_name, request).await?;
+            on_delta(&response.message.content);
+            Ok(response)
+        })
+    }
+
+    /// Describes an assistant (instructions, metadata, status).
+    fn describe_assistant<'a>(&'a self, _assistant_name: &'a str) -> BackendFuture<'a, Assistant> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not describe assistants".to_string(),
+            ))
+        })
+    }
+
+    /// Lists the assistants of the project.
+    fn list_assistants(&self) -> BackendFuture<'_, Vec<Assistant>> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not list assistants".to_string(),
+            ))
+        })
+    }
+
+    /// Reports whether the service behind the backend is reachable and
+    /// accepts its credentials, checking `assistant_name` if given.
+    fn health<'a>(&'a self, _assistant_name: Option<&'a str>) -> BackendFuture<'a, Value> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not report its health".to_string(),
+            ))
+        })
+    }
+
+    /// Creates an assistant.
+    fn create_assistant<'a>(
+        &'a self,
+        _assistant_name: &'a str,
+        _settings: AssistantSettings,
+    ) -> BackendFuture<'a, Assistant> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not create assistants".to_string(),
+            ))
+        })
+    }
+
+    /// Changes an assistant's instructions or metadata.
+    fn update_assistant<'a>(
+        &'a self,
+        _assistant_name: &'a str,
+        _settings: AssistantSettings,
+    ) -> BackendFuture<'a, Assistant> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not update assistants".to_string(),
+            ))
+        })
+    }
+
+    /// Adds a local file to an assistant's knowledge base.
+    fn upload_file<'a>(
+        &'a self,
+        _assistant_name: &'a str,
+        _path: &'a Path,
+        _metadata: Option<Value>,
+    ) -> BackendFuture<'a, AssistantFile> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not support uploads".to_string(),
+            ))
+        })
+    }
+
+    /// Lists an assistant's files, optionally filtered by metadata.
+    fn list_files<'a>(
+        &'a self,
+        _assistant_name: &'a str,
+        _filter: Option<Value>,
+    ) -> BackendFuture<'a, Vec<AssistantFile>> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not list files".to_string(),
+            ))
+        })
+    }
+
+    /// Removes a file from an assistant's knowledge base.
+    fn delete_file<'a>(
+        &'a self,
+        _assistant_name: &'a str,
+        _file_id: &'a str,
+    ) -> BackendFuture<'a, ()> {
+        Box::pin(async {
+            Err(PineconeError::Unsupported(
+                "this backend does not delete files".to_string(),
+            ))
+        })
+    }
+}
+
+impl ContextBackend for PineconeClient {
+    fn assistant_context<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        query: &'a str,
+        top_k: Option<u32>,
+    ) -> BackendFuture<'a, AssistantContextResponse> {
+        Box::pin(PineconeClient::assistant_context(
+            self,
+            assistant_name,
+            query,
+            top_k,
+        ))
+    }
+
+    fn assistant_context_with<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        request: AssistantContext,
+    ) -> BackendFuture<'a, AssistantContextResponse> {
+        Box::pin(async move {
+            PineconeClient::assistant_context_with(self, assistant_name, &request).await
+        })
+    }
+
+    fn assistant_chat<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        messages: Vec<Message>,
+    ) -> BackendFuture<'a, AssistantChatResponse> {
+        Box::pin(PineconeClient::assistant_chat(
+            self,
+            assistant_name,
+            messages,
+        ))
+    }
+
+    fn assistant_chat_with<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        request: AssistantChat,
+    ) -> BackendFuture<'a, AssistantChatResponse> {
+        Box::pin(async move {
+            PineconeClient::assistant_chat_with(self, assistant_name, &request).await
+        })
+    }
+
+    fn assistant_chat_stream<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        request: AssistantChat,
+        on_delta: &'a ChatDeltas<'a>,
+    ) -> BackendFuture<'a, AssistantChatResponse> {
+        Box::pin(async move {
+            PineconeClient::assistant_chat_stream(self, assistant_name, &request, on_delta).await
+        })
+    }
+
+    fn describe_assistant<'a>(&'a self, assistant_name: &'a str) -> BackendFuture<'a, Assistant> {
+        Box::pin(PineconeClient::describe_assistant(self, assistant_name))
+    }
+
+    fn list_assistants(&self) -> BackendFuture<'_, Vec<Assistant>> {
+        Box::pin(PineconeClient::list_assistants(self))
+    }
+
+    fn health<'a>(&'a self, assistant_name: Option<&'a str>) -> BackendFuture<'a, Value> {
+        Box::pin(async move { Ok(PineconeClient::health(self, assistant_name).await) })
+    }
+
+    fn create_assistant<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        settings: AssistantSettings,
+    ) -> BackendFuture<'a, Assistant> {
+        Box::pin(
+            async move { PineconeClient::create_assistant(self, assistant_name, &settings).await },
+        )
+    }
+
+    fn update_assistant<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        settings: AssistantSettings,
+    ) -> BackendFuture<'a, Assistant> {
+        Box::pin(
+            async move { PineconeClient::update_assistant(self, assistant_name, &settings).await },
+        )
+    }
+
+    fn upload_file<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        path: &'a Path,
+        metadata: Option<Value>,
+    ) -> BackendFuture<'a, AssistantFile> {
+        Box::pin(async move {
+            PineconeClient::upload_file(self, assistant_name, path, metadata.as_ref()).await
+        })
+    }
+
+    fn list_files<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        filter: Option<Value>,
+    ) -> BackendFuture<'a, Vec<AssistantFile>> {
+        Box::pin(
+            async move { PineconeClient::list_files(self, assistant_name, filter.as_ref()).await },
+        )
+    }
+
+    fn delete_file<'a>(
+        &'a self,
+        assistant_name: &'a str,
+        file_id: &'a str,
+    ) -> BackendFuture<'a, ()> {
+        Box::pin(PineconeClient::delete_file(self, assistant_name, file_id))
+    }
+}
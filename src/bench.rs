@@ -0,0 +1,135 @@
+//! Latency benchmark: sends context queries at a fixed concurrency and
+//! reports the latency distribution and error rate, to size concurrency
+//! limits and spot slow regions.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use assistant_mcp::PineconeAssistantRouter;
+use futures::StreamExt;
+use serde_json::{Value, json};
+
+use crate::eval;
+
+/// Errors are grouped by message, cut to this many characters.
+const ERROR_MESSAGE_CHARS: usize = 200;
+
+/// A completed request: its latency in milliseconds, and the error if it
+/// failed.
+pub type Sample = (f64, Option<String>);
+
+/// Sends `requests` queries, cycling through `queries`, with at most
+/// `concurrency` in flight.
+pub async fn run(
+    router: &PineconeAssistantRouter,
+    queries: &[String],
+    assistant: Option<&str>,
+    top_k: Option<u32>,
+    requests: usize,
+    concurrency: usize,
+) -> (Vec<Sample>, f64) {
+    let started = Instant::now();
+    let samples = futures::stream::iter(queries.iter().cycle().take(requests))
+        .map(|query| {
+            let mut arguments = json!({"query": query, "response_format": "json"});
+            if let Some(assistant) = assistant {
+                arguments["assistant_name"] = assistant.into();
+            }
+            if let Some(top_k) = top_k {
+                arguments["top_k"] = top_k.into();
+            }
+            async move {
+                let (latency_ms, result) = eval::retrieve(router, arguments).await;
+                (latency_ms, result.err())
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    (samples, started.elapsed().as_secs_f64())
+}
+
+/// Aggregates samples: error rate, throughput, latency percentiles of the
+/// successful requests and the errors by message.
+pub fn summarize(samples: &[Sample], concurrency: usize, elapsed_secs: f64) -> Value {
+    let mut latencies: Vec<f64> = samples
+        .iter()
+        .filter(|(_, error)| error.is_none())
+        .map(|(latency, _)| *latency)
+        .collect();
+    latencies.sort_by(f64::total_cmp);
+    let mut errors: BTreeMap<String, usize> = BTreeMap::new();
+    for error in samples.iter().filter_map(|(_, error)| error.as_deref()) {
+        let message: String = error
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take(ERROR_MESSAGE_CHARS)
+            .collect();
+        *errors.entry(message).or_default() += 1;
+    }
+    let failed = samples.len() - latencies.len();
+
+    json!({
+        "requests": samples.len(),
+        "concurrency": concurrency,
+        "failed": failed,
+        "error_rate": (!samples.is_empty()).then(|| failed as f64 / samples.len() as f64),
+        "elapsed_secs": elapsed_secs,
+        "requests_per_sec": (elapsed_secs > 0.0).then(|| samples.len() as f64 / elapsed_secs),
+        "latency_ms": eval::latency_summary(&latencies),
+        "errors": errors,
+    })
+}
+
+/// Renders a summary for reading in a terminal.
+pub fn format_summary(summary: &Value) -> String {
+    let mut out = format!(
+        "{} requests at concurrency {} in {:.1} s ({:.1} req/s)\n",
+        summary["requests"],
+        summary["concurrency"],
+        summary["elapsed_secs"].as_f64().unwrap_or_default(),
+        summary["requests_per_sec"].as_f64().unwrap_or_default(),
+    );
+    out.push_str(&format!(
+        "{} failed ({:.1}%)\n",
+        summary["failed"],
+        summary["error_rate"].as_f64().unwrap_or_default() * 100.0
+    ));
+    for (message, count) in summary["errors"].as_object().into_iter().flatten() {
+        out.push_str(&format!("    {count} x {message}\n"));
+    }
+    let latency = &summary["latency_ms"];
+    if let Some(max) = latency["max"].as_f64() {
+        out.push_str(&format!(
+            "latency p50 {:.0} ms, p95 {:.0} ms, p99 {:.0} ms, max {max:.0} ms\n",
+            latency["p50"].as_f64().unwrap_or_default(),
+            latency["p95"].as_f64().unwrap_or_default(),
+            latency["p99"].as_f64().unwrap_or_default(),
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_separates_errors_from_latencies() {
+        let mut samples: Vec<Sample> = (1..=18).map(|i| (f64::from(i) * 10.0, None)).collect();
+        samples.push((5.0, Some("Request timed out\nafter 30s".to_string())));
+        samples.push((7.0, Some("Request timed out".to_string())));
+
+        let summary = summarize(&samples, 4, 2.0);
+
+        assert_eq!(summary["requests"], 20);
+        assert_eq!(summary["failed"], 2);
+        assert_eq!(summary["error_rate"], 0.1);
+        assert_eq!(summary["requests_per_sec"], 10.0);
+        assert_eq!(summary["errors"], json!({"Request timed out": 2}));
+        assert_eq!(summary["latency_ms"]["p50"], 90.0);
+        assert_eq!(summary["latency_ms"]["p95"], 180.0);
+    }
+}
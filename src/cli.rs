@@ -0,0 +1,880 @@
+//! Subcommands run from the terminal instead of serving MCP over stdio.
+
+use std::fmt::Display;
+use std::io::Write;
+use std::path::PathBuf;
+
+use assistant_mcp::router::is_error_result;
+use assistant_mcp::{
+    Config, ConfigError, EnvNames, PineconeAssistantRouter, PineconeClient, PineconeError,
+};
+use clap::{Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressStyle};
+use is_terminal::IsTerminal;
+use mcp_server::Router;
+use mcp_spec::tool::Tool;
+use reqwest::Url;
+use serde_json::{Map, Value, json};
+
+use crate::{bench, eval};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "MCP server for Pinecone Assistant")]
+pub struct Cli {
+    /// Serves MCP over stdio when no command is given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Checks the configuration and the connection to Pinecone, with hints
+    /// for fixing what is wrong.
+    Check,
+
+    /// Retrieves context for a question and prints the snippets, to check
+    /// retrieval quality outside an MCP client.
+    Query {
+        /// Assistant to query; defaults to PINECONE_DEFAULT_ASSISTANT.
+        #[arg(long, short)]
+        assistant: Option<String>,
+        /// Maximum number of snippets.
+        #[arg(long)]
+        top_k: Option<u32>,
+        /// Metadata filter as JSON, e.g. '{"team": "support"}'.
+        #[arg(long)]
+        filter: Option<String>,
+        /// Prints the result as JSON instead of text.
+        #[arg(long)]
+        json: bool,
+        question: String,
+    },
+
+    /// Prints the tools the server offers with their input schemas, as
+    /// JSON or markdown, without an MCP client.
+    Tools {
+        /// Prints markdown documentation instead of JSON.
+        #[arg(long)]
+        markdown: bool,
+    },
+
+    /// Prints the effective configuration as JSON, secrets masked, or the
+    /// schema of every supported variable. Exits non-zero if the
+    /// configuration is invalid.
+    PrintConfig {
+        /// Prints the schema of the variables instead.
+        #[arg(long)]
+        schema: bool,
+    },
+
+    /// Uploads files, and the supported documents in directories, to an
+    /// assistant. Exits non-zero if any upload fails.
+    Upload {
+        /// Assistant to upload to; defaults to PINECONE_DEFAULT_ASSISTANT.
+        #[arg(long, short)]
+        assistant: Option<String>,
+        /// Metadata attached to every file, as a JSON object.
+        #[arg(long)]
+        metadata: Option<String>,
+        /// Prints the upload report as JSON.
+        #[arg(long)]
+        json: bool,
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Runs a query set against the assistants and reports retrieval
+    /// metrics: hit rate and recall of the expected documents, and latency
+    /// percentiles. Exits non-zero if any query fails.
+    Eval {
+        /// Query set, one JSON object per line: `query`, and optionally
+        /// `expected` file names or ids, `assistant`, `top_k` and `filter`.
+        #[arg(long, short)]
+        file: PathBuf,
+        /// Assistant for queries that don't name one; defaults to
+        /// PINECONE_DEFAULT_ASSISTANT.
+        #[arg(long, short)]
+        assistant: Option<String>,
+        /// Maximum number of snippets for queries that don't set one.
+        #[arg(long)]
+        top_k: Option<u32>,
+        /// Writes each query's retrieved documents, scores and latency to
+        /// this file, one JSON object per line.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Compares the documents with a snapshot written by `--output`,
+        /// and exits non-zero if documents went missing or scores dropped.
+        #[arg(long)]
+        compare: Option<PathBuf>,
+        /// Score changes up to this much are not reported by `--compare`.
+        #[arg(long, default_value_t = 0.05)]
+        score_tolerance: f64,
+        /// Prints the summary as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Sends context queries concurrently and reports the latency
+    /// percentiles and error rate, bypassing the retrieval caches. Exits
+    /// non-zero if any request fails.
+    Bench {
+        /// Assistant to query; defaults to PINECONE_DEFAULT_ASSISTANT.
+        #[arg(long, short)]
+        assistant: Option<String>,
+        /// Maximum number of snippets.
+        #[arg(long)]
+        top_k: Option<u32>,
+        /// Number of requests to send.
+        #[arg(long, short = 'n', default_value_t = 100)]
+        requests: usize,
+        /// Number of requests in flight at a time.
+        #[arg(long, short, default_value_t = 8)]
+        concurrency: usize,
+        /// Prints the summary as JSON.
+        #[arg(long)]
+        json: bool,
+        /// Questions to send, in turn.
+        #[arg(required = true)]
+        queries: Vec<String>,
+    },
+}
+
+impl Command {
+    /// Runs the command; returns whether it succeeded.
+    pub async fn run(self) -> bool {
+        match self {
+            Self::Check => check().await,
+            Self::Query {
+                assistant,
+                top_k,
+                filter,
+                json,
+                question,
+            } => query(assistant, top_k, filter, json, question).await,
+            Self::Tools { markdown } => tools(markdown),
+            Self::PrintConfig { schema } => print_config(schema),
+            Self::Upload {
+                assistant,
+                metadata,
+                json,
+                paths,
+            } => upload(assistant, metadata, json, paths).await,
+            Self::Eval {
+                file,
+                assistant,
+                top_k,
+                output,
+                compare,
+                score_tolerance,
+                json,
+            } => {
+                let snapshot = compare.map(|path| (path, score_tolerance));
+                evaluate(file, assistant, top_k, output, snapshot, json).await
+            }
+            Self::Bench {
+                assistant,
+                top_k,
+                requests,
+                concurrency,
+                json,
+                queries,
+            } => bench(assistant, top_k, requests, concurrency, json, queries).await,
+        }
+    }
+}
+
+/// Router for commands that run tools, or `None` after reporting why the
+/// configuration can't be loaded.
+fn router() -> Option<PineconeAssistantRouter> {
+    config().map(PineconeAssistantRouter::new)
+}
+
+/// The configuration, or `None` after reporting why it can't be loaded.
+fn config() -> Option<Config> {
+    match Config::try_from_env() {
+        Ok(config) => Some(config),
+        Err(e) => {
+            eprintln!("{e}; run `assistant-mcp check` for help");
+            None
+        }
+    }
+}
+
+async fn query(
+    assistant: Option<String>,
+    top_k: Option<u32>,
+    filter: Option<String>,
+    as_json: bool,
+    question: String,
+) -> bool {
+    let mut arguments = json!({"query": question, "response_format": "json"});
+    if let Some(assistant) = assistant {
+        arguments["assistant_name"] = assistant.into();
+    }
+    if let Some(top_k) = top_k {
+        arguments["top_k"] = top_k.into();
+    }
+    if let Some(filter) = filter {
+        match serde_json::from_str::<Value>(&filter) {
+            Ok(filter) => arguments["filter"] = filter,
+            Err(e) => {
+                eprintln!("Invalid --filter: {e}");
+                return false;
+            }
+        }
+    }
+    let Some(router) = router() else {
+        return false;
+    };
+
+    let result = router.call_tool("assistant_context", arguments).await;
+    router.shutdown().await;
+    let contents = match result {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+    let Some(result) = contents
+        .first()
+        .and_then(|content| content.as_text())
+        .and_then(|text| serde_json::from_str::<Value>(text).ok())
+    else {
+        eprintln!("Unexpected response from assistant_context");
+        return false;
+    };
+    // Failures the agent could act on come back as results describing them.
+    if let Some(error) = result.get("error").filter(|_| is_error_result(&contents)) {
+        eprintln!("{}", error["message"].as_str().unwrap_or_default());
+        return false;
+    }
+
+    if as_json {
+        emit(&format!("{result:#}\n"));
+    } else {
+        emit(&format_snippets(&result));
+    }
+    true
+}
+
+fn tools(markdown: bool) -> bool {
+    // Tool definitions don't need credentials, so an incomplete
+    // configuration still documents the defaults.
+    let config = Config::try_from_env().unwrap_or_default();
+    let tools = PineconeAssistantRouter::new(config).list_tools();
+    if markdown {
+        emit(&tools_markdown(&tools));
+    } else {
+        emit(&format!("{:#}\n", json!({ "tools": tools })));
+    }
+    true
+}
+
+fn print_config(schema: bool) -> bool {
+    if schema {
+        emit(&format!("{:#}\n", Config::schema(&EnvNames::from_env())));
+        return true;
+    }
+    match Config::try_from_env() {
+        Ok(config) => {
+            emit(&format!("{:#}\n", config.redacted()));
+            true
+        }
+        Err(e) => {
+            eprintln!("{e}; run `assistant-mcp check` for help");
+            false
+        }
+    }
+}
+
+async fn upload(
+    assistant: Option<String>,
+    metadata: Option<String>,
+    as_json: bool,
+    paths: Vec<PathBuf>,
+) -> bool {
+    let metadata = match metadata.map(|m| serde_json::from_str::<Map<String, Value>>(&m)) {
+        None => None,
+        Some(Ok(metadata)) => Some(metadata),
+        Some(Err(e)) => {
+            eprintln!("Invalid --metadata, expected a JSON object: {e}");
+            return false;
+        }
+    };
+    let Some(router) = router() else {
+        return false;
+    };
+
+    // Drawn on stderr, and only when it is a terminal.
+    let bar = ProgressBar::new(0).with_style(
+        ProgressStyle::with_template("{bar:30} {pos}/{len} {wide_msg}")
+            .expect("Invalid progress bar template"),
+    );
+    let on_progress = |done: usize, total: usize, file: &Value| {
+        bar.set_length(total as u64);
+        bar.set_position(done as u64);
+        bar.set_message(file["file_path"].as_str().unwrap_or_default().to_string());
+    };
+    let report = router
+        .upload_paths(
+            assistant.as_deref(),
+            &paths,
+            metadata.as_ref(),
+            &on_progress,
+        )
+        .await;
+    router.shutdown().await;
+    bar.finish_and_clear();
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+
+    if as_json {
+        emit(&format!("{report:#}\n"));
+    } else {
+        emit(&format_upload_report(&report));
+    }
+    report["failed"] == 0
+}
+
+fn format_upload_report(report: &Value) -> String {
+    let mut out = String::new();
+    let files = report["files"].as_array().map_or(&[][..], Vec::as_slice);
+    for file in files.iter().filter(|file| file["status"] == "failed") {
+        out.push_str(&format!(
+            "failed: {}: {}\n",
+            file["file_path"].as_str().unwrap_or_default(),
+            file["error"].as_str().unwrap_or_default()
+        ));
+    }
+    out.push_str(&format!(
+        "{} uploaded, {} duplicate(s) skipped, {} failed\n",
+        report["uploaded"], report["duplicates"], report["failed"]
+    ));
+    out
+}
+
+async fn evaluate(
+    file: PathBuf,
+    assistant: Option<String>,
+    top_k: Option<u32>,
+    output: Option<PathBuf>,
+    snapshot: Option<(PathBuf, f64)>,
+    as_json: bool,
+) -> bool {
+    let cases = match eval::read_lines::<eval::Case>(&file) {
+        Ok(cases) => cases,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+    let snapshot = match snapshot {
+        None => None,
+        Some((path, tolerance)) => match eval::read_lines::<Value>(&path) {
+            Ok(snapshot) => Some((snapshot, tolerance)),
+            Err(e) => {
+                eprintln!("{e}");
+                return false;
+            }
+        },
+    };
+    let Some(mut config) = config() else {
+        return false;
+    };
+    // Cached or stale answers would score the cache, not retrieval.
+    config.retrieval_cache_ttl = None;
+    config.semantic_cache = None;
+    config.serve_stale = None;
+    let router = PineconeAssistantRouter::new(config);
+
+    let records = eval::run(&router, &cases, assistant.as_deref(), top_k).await;
+    router.shutdown().await;
+    if let Some(output) = output {
+        let lines: String = records.iter().map(|record| format!("{record}\n")).collect();
+        if let Err(e) = std::fs::write(&output, lines) {
+            eprintln!("Cannot write {}: {e}", output.display());
+            return false;
+        }
+    }
+    let mut summary = eval::summarize(&records);
+    let diffs = snapshot.map(|(snapshot, tolerance)| eval::compare(&snapshot, &records, tolerance));
+    let regressed = diffs.iter().flatten().any(eval::is_regression);
+    if as_json {
+        if let Some(diffs) = &diffs {
+            summary["diffs"] = diffs.clone().into();
+        }
+        emit(&format!("{summary:#}\n"));
+    } else {
+        let mut text = eval::format_summary(&summary, &records);
+        if let Some(diffs) = &diffs {
+            text.push_str(&eval::format_diffs(diffs));
+        }
+        emit(&text);
+    }
+    summary["failed"] == 0 && !regressed
+}
+
+async fn bench(
+    assistant: Option<String>,
+    top_k: Option<u32>,
+    requests: usize,
+    concurrency: usize,
+    as_json: bool,
+    queries: Vec<String>,
+) -> bool {
+    let Some(mut config) = config() else {
+        return false;
+    };
+    // Cached answers would measure the cache, not Pinecone.
+    config.retrieval_cache_ttl = None;
+    config.semantic_cache = None;
+    let router = PineconeAssistantRouter::new(config);
+
+    let (samples, elapsed) = bench::run(
+        &router,
+        &queries,
+        assistant.as_deref(),
+        top_k,
+        requests,
+        concurrency,
+    )
+    .await;
+    router.shutdown().await;
+    let summary = bench::summarize(&samples, concurrency, elapsed);
+    if as_json {
+        emit(&format!("{summary:#}\n"));
+    } else {
+        emit(&bench::format_summary(&summary));
+    }
+    summary["failed"] == 0
+}
+
+/// Writes command output to stdout, ignoring a reader that went away
+/// (e.g. `| head`) rather than panicking like `print!`.
+fn emit(text: &str) {
+    let _ = std::io::stdout().lock().write_all(text.as_bytes());
+}
+
+fn tools_markdown(tools: &[Tool]) -> String {
+    let mut out = String::from("# Tools\n");
+    for tool in tools {
+        out.push_str(&format!("\n## `{}`\n\n{}\n", tool.name, tool.description));
+        let Some(properties) = tool.input_schema["properties"]
+            .as_object()
+            .filter(|p| !p.is_empty())
+        else {
+            out.push_str("\nNo parameters.\n");
+            continue;
+        };
+        let required: Vec<&str> = tool.input_schema["required"]
+            .as_array()
+            .map(|r| r.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        out.push_str("\n| Parameter | Type | Required | Description |\n");
+        out.push_str("|---|---|---|---|\n");
+        for (name, schema) in properties {
+            let description = schema["description"]
+                .as_str()
+                .unwrap_or_default()
+                .replace('|', "\\|")
+                .replace('\n', " ");
+            out.push_str(&format!(
+                "| `{name}` | {} | {} | {description} |\n",
+                schema_type(schema),
+                if required.contains(&name.as_str()) {
+                    "yes"
+                } else {
+                    "no"
+                },
+            ));
+        }
+    }
+    out
+}
+
+/// Short type name of a JSON schema, e.g. `array of string`.
+fn schema_type(schema: &Value) -> String {
+    match &schema["type"] {
+        Value::String(kind) if kind == "array" => match schema.get("items") {
+            Some(items) => format!("array of {}", schema_type(items)),
+            None => kind.clone(),
+        },
+        Value::String(kind) => kind.clone(),
+        Value::Array(kinds) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" or "),
+        _ => "any".to_string(),
+    }
+}
+
+/// Renders an `assistant_context` JSON result for reading in a terminal.
+fn format_snippets(result: &Value) -> String {
+    let snippets = result["snippets"].as_array().map_or(&[][..], Vec::as_slice);
+    if snippets.is_empty() {
+        return "No snippets found\n".to_string();
+    }
+    let mut out = String::new();
+    for (index, snippet) in snippets.iter().enumerate() {
+        let reference = &snippet["reference"];
+        let mut source = reference["file"]["name"]
+            .as_str()
+            .unwrap_or("unknown file")
+            .to_string();
+        if let Some(pages) = reference["pages"].as_array().filter(|p| !p.is_empty()) {
+            let pages: Vec<String> = pages.iter().map(Value::to_string).collect();
+            source.push_str(&format!(", p. {}", pages.join(", ")));
+        }
+        if let Some(score) = snippet["score"].as_f64() {
+            source.push_str(&format!(" (score {score:.3})"));
+        }
+        out.push_str(&format!("[{}] {source}\n", index + 1));
+        let content = snippet["content"].as_str().unwrap_or_default();
+        for line in content.lines() {
+            out.push_str(&format!("    {line}\n"));
+        }
+        out.push('\n');
+    }
+    if let Some(omitted) = result["omitted"].as_u64().filter(|n| *n > 0) {
+        out.push_str(&format!(
+            "{omitted} snippet(s) omitted to fit the context budget\n"
+        ));
+    }
+    if let Some(dropped) = result["truncated"]["dropped_snippets"].as_u64() {
+        out.push_str(&format!(
+            "{dropped} snippet(s) dropped to stay under the response size limit\n"
+        ));
+    }
+    out
+}
+
+async fn check() -> bool {
+    let mut report = Report::new();
+
+    let config = match Config::try_from_env() {
+        Ok(config) => {
+            report.pass("Configuration loaded");
+            config
+        }
+        Err(ConfigError::MissingVariable(name)) => {
+            report.fail(
+                format!("Missing environment variable: {name}"),
+                "Set it in the environment the MCP host starts the server with (the `env` \
+                 section of its configuration); API keys are listed at https://app.pinecone.io",
+            );
+            return report.finish();
+        }
+        Err(e) => {
+            report.fail(e, "Fix the configuration and run the check again");
+            return report.finish();
+        }
+    };
+    if config.pinecone_api_key.trim().is_empty() {
+        report.fail(
+            "PINECONE_API_KEY is empty",
+            "Set it to an API key from https://app.pinecone.io",
+        );
+        return report.finish();
+    }
+
+    let mut hosts_ok = true;
+    let hosts = std::iter::once(&config.pinecone_assistant_host)
+        .chain(&config.pinecone_failover_hosts)
+        .map(|url| ("PINECONE_ASSISTANT_HOST", url))
+        .chain([(
+            "PINECONE_CONTROL_PLANE_HOST",
+            &config.pinecone_control_plane_host,
+        )]);
+    for (variable, url) in hosts {
+        match resolve(url).await {
+            Ok(host) => report.pass(format!("{variable} {url} resolves ({host})")),
+            Err(e) => {
+                hosts_ok = false;
+                report.fail(
+                    format!("{variable} {url}: {e}"),
+                    "Use the full URL, e.g. https://prod-1-data.ke.pinecone.io, and check DNS, \
+                     proxy and firewall settings",
+                );
+            }
+        }
+    }
+    if !hosts_ok {
+        return report.finish();
+    }
+
+    let client = PineconeClient::new(
+        config.pinecone_api_key.clone(),
+        config.pinecone_assistant_host.clone(),
+    )
+    .with_control_plane_url(config.pinecone_control_plane_host.clone())
+    .with_http_options(&config.http)
+    .with_retry_policy(config.retry.clone())
+    .with_failover_hosts(config.pinecone_failover_hosts.clone());
+    let assistants = match client.list_assistants().await {
+        Ok(assistants) => {
+            let names: Vec<&str> = assistants.iter().map(|a| a.name.as_str()).collect();
+            report.pass(format!(
+                "Authenticated; {} assistant(s): {}",
+                names.len(),
+                names.join(", ")
+            ));
+            assistants
+        }
+        Err(e) => {
+            report.fail(format!("Listing assistants failed: {e}"), error_hint(&e));
+            return report.finish();
+        }
+    };
+    if assistants.is_empty() {
+        report.fail(
+            "The project has no assistants",
+            "Create one in the Pinecone console, or check that the API key belongs to the \
+             intended project",
+        );
+    }
+
+    // Assistants with a dedicated key live in other projects.
+    for (name, api_key) in &config.assistant_api_keys {
+        let client = PineconeClient::new(api_key.clone(), config.pinecone_assistant_host.clone())
+            .with_control_plane_url(config.pinecone_control_plane_host.clone())
+            .with_http_options(&config.http)
+            .with_retry_policy(config.retry.clone())
+            .with_failover_hosts(config.pinecone_failover_hosts.clone());
+        match client.describe_assistant(name).await {
+            Ok(_) => report.pass(format!(
+                "Assistant {name} is reachable with its dedicated key"
+            )),
+            Err(e) => report.fail(format!("Assistant {name}: {e}"), error_hint(&e)),
+        }
+    }
+
+    let in_project = |name: &str| {
+        config.assistant_api_keys.contains_key(name) || assistants.iter().any(|a| a.name == name)
+    };
+    for (alias, name) in &config.assistant_aliases {
+        if !in_project(name) {
+            report.fail(
+                format!("Alias {alias} points to unknown assistant {name}"),
+                "Fix PINECONE_ASSISTANT_ALIASES",
+            );
+        }
+    }
+    if let Some(name) = &config.default_assistant {
+        match assistants.iter().find(|a| &a.name == name) {
+            Some(assistant) => {
+                report.pass(format!("Default assistant {name} exists"));
+                if let Some(host) = &assistant.host {
+                    if same_host(host, &config.pinecone_assistant_host) {
+                        report.pass(format!("Default assistant {name} is served by {host}"));
+                    } else {
+                        report.fail(
+                            format!(
+                                "Default assistant {name} is served by {host}, not {}",
+                                config.pinecone_assistant_host
+                            ),
+                            format!("Set PINECONE_ASSISTANT_HOST=https://{}", bare_host(host)),
+                        );
+                    }
+                }
+            }
+            None if in_project(name) => {}
+            None => report.fail(
+                format!("Default assistant {name} does not exist"),
+                "Fix PINECONE_DEFAULT_ASSISTANT; the assistants found are listed above",
+            ),
+        }
+    }
+
+    report.finish()
+}
+
+/// Resolves the host of `url`, returning the first address found.
+async fn resolve(url: &str) -> Result<String, String> {
+    let url = Url::parse(url).map_err(|e| format!("not a valid URL ({e})"))?;
+    let host = url.host_str().ok_or("URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let mut addresses = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("cannot resolve {host} ({e})"))?;
+    addresses
+        .next()
+        .map(|address| address.ip().to_string())
+        .ok_or_else(|| format!("{host} has no addresses"))
+}
+
+fn error_hint(error: &PineconeError) -> &'static str {
+    match error {
+        PineconeError::Api { status: 401, .. } => {
+            "The API key was rejected; create a new one at https://app.pinecone.io and update \
+             the key variable"
+        }
+        PineconeError::Forbidden { .. } => {
+            "The API key lacks permission for assistants; check its role in the Pinecone console"
+        }
+        PineconeError::QuotaExceeded { .. } => {
+            "The project's plan limits are used up; upgrade the plan or wait for the quota to reset"
+        }
+        PineconeError::NotFound { .. } => {
+            "Check the assistant name, and that the API key belongs to its project"
+        }
+        PineconeError::Dns { .. } => {
+            "Check the host URL for typos, and that DNS works from this machine"
+        }
+        PineconeError::Tls { .. } => {
+            "A proxy may be intercepting HTTPS; make its CA certificate trusted or bypass it \
+             for pinecone.io"
+        }
+        PineconeError::Connect { .. } | PineconeError::ConnectTimeout { .. } => {
+            "Check network, proxy and firewall settings; HTTPS_PROXY is honored"
+        }
+        PineconeError::Request(_) => "Check network, proxy and firewall settings",
+        _ => "Check the Pinecone status page, or run again with RUST_LOG=debug for details",
+    }
+}
+
+/// Host name of a URL or bare host, without scheme, port or path.
+fn bare_host(url: &str) -> &str {
+    let host = url.split_once("://").map_or(url, |(_, rest)| rest);
+    host.split(['/', ':']).next().unwrap_or(host)
+}
+
+fn same_host(a: &str, b: &str) -> bool {
+    bare_host(a).eq_ignore_ascii_case(bare_host(b))
+}
+
+/// Prints check results as they come in.
+struct Report {
+    color: bool,
+    failures: usize,
+}
+
+impl Report {
+    fn new() -> Self {
+        Self {
+            color: std::io::stdout().is_terminal(),
+            failures: 0,
+        }
+    }
+
+    fn pass(&mut self, message: impl Display) {
+        println!("{} {message}", self.mark("✓", "32"));
+    }
+
+    fn fail(&mut self, message: impl Display, hint: impl Display) {
+        self.failures += 1;
+        println!("{} {message}", self.mark("✗", "31"));
+        println!("  hint: {hint}");
+    }
+
+    fn mark(&self, mark: &str, color: &str) -> String {
+        if self.color {
+            format!("\x1b[{color}m{mark}\x1b[0m")
+        } else {
+            mark.to_string()
+        }
+    }
+
+    /// Prints the summary; returns whether every check passed.
+    fn finish(self) -> bool {
+        if self.failures == 0 {
+            println!("All checks passed");
+        } else {
+            println!("{} check(s) failed", self.failures);
+        }
+        self.failures == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tools_markdown_lists_parameters() {
+        let tools = vec![Tool::new(
+            "search",
+            "Searches.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string", "description": "What to find"},
+                    "tags": {"type": "array", "items": {"type": "string"}}
+                },
+                "required": ["query"]
+            }),
+        )];
+
+        let markdown = tools_markdown(&tools);
+
+        assert!(markdown.contains("## `search`\n\nSearches.\n"));
+        assert!(markdown.contains("| `query` | string | yes | What to find |"));
+        assert!(markdown.contains("| `tags` | array of string | no |  |"));
+    }
+
+    #[test]
+    fn test_format_upload_report_lists_failures() {
+        let report = json!({
+            "uploaded": 1,
+            "duplicates": 0,
+            "failed": 1,
+            "files": [
+                {"file_path": "a.md", "status": "uploaded", "file_id": "f1"},
+                {"file_path": "b.exe", "status": "failed", "error": "type not allowed"}
+            ]
+        });
+
+        assert_eq!(
+            format_upload_report(&report),
+            "failed: b.exe: type not allowed\n1 uploaded, 0 duplicate(s) skipped, 1 failed\n"
+        );
+    }
+
+    #[test]
+    fn test_format_snippets() {
+        let result = json!({
+            "snippets": [
+                {
+                    "content": "Refunds take 5 days.\nContact support.",
+                    "score": 0.91234,
+                    "reference": {"file": {"name": "faq.pdf"}, "pages": [2, 3]}
+                },
+                {"content": "Other", "reference": {}}
+            ],
+            "omitted": 1
+        });
+
+        assert_eq!(
+            format_snippets(&result),
+            "[1] faq.pdf, p. 2, 3 (score 0.912)\n    Refunds take 5 days.\n    Contact support.\n\n\
+             [2] unknown file\n    Other\n\n\
+             1 snippet(s) omitted to fit the context budget\n"
+        );
+        assert_eq!(
+            format_snippets(&json!({"snippets": []})),
+            "No snippets found\n"
+        );
+    }
+
+    #[test]
+    fn test_same_host_ignores_scheme_and_path() {
+        assert!(same_host(
+            "prod-1-data.ke.pinecone.io",
+            "https://prod-1-data.ke.pinecone.io/"
+        ));
+        assert!(same_host(
+            "https://PROD-1-data.ke.pinecone.io:443",
+            "prod-1-data.ke.pinecone.io"
+        ));
+        assert!(!same_host(
+            "https://prod-1-data.ke.pinecone.io",
+            "https://prod-2-data.ke.pinecone.io"
+        ));
+    }
+}
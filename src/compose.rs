@@ -0,0 +1,294 @@
+//! Building blocks for embedding [`PineconeAssistantRouter`](crate::PineconeAssistantRouter)
+//! into a larger MCP server next to other tools.
+//!
+//! ```ignore
+//! let router = Merged::new(
+//!     "internal-tools",
+//!     Prefixed::new("pinecone_", PineconeAssistantRouter::new(config)),
+//!     MyRouter::new(),
+//! );
+//! let server = Server::new(RouterService(router));
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+
+use mcp_server::Router;
+use mcp_spec::content::Content;
+use mcp_spec::handler::{PromptError, ResourceError, ToolError};
+use mcp_spec::prompt::Prompt;
+use mcp_spec::{protocol::ServerCapabilities, resource::Resource, tool::Tool};
+use serde_json::Value;
+
+/// Exposes a router's tools under a name prefix so they can't collide with
+/// tools of other routers. Resources and prompts are passed through as-is.
+#[derive(Clone)]
+pub struct Prefixed<R> {
+    prefix: String,
+    inner: R,
+}
+
+impl<R: Router> Prefixed<R> {
+    pub fn new(prefix: impl Into<String>, inner: R) -> Self {
+        Self {
+            prefix: prefix.into(),
+            inner,
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Router> Router for Prefixed<R> {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn instructions(&self) -> String {
+        format!(
+            "{} (tools of this component are prefixed with '{}')",
+            self.inner.instructions(),
+            self.prefix
+        )
+    }
+
+    fn capabilities(&self) -> ServerCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn list_tools(&self) -> Vec<Tool> {
+        self.inner
+            .list_tools()
+            .into_iter()
+            .map(|mut tool| {
+                tool.name = format!("{}{}", self.prefix, tool.name);
+                tool
+            })
+            .collect()
+    }
+
+    fn call_tool(
+        &self,
+        tool_name: &str,
+        arguments: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>> {
+        match tool_name.strip_prefix(&self.prefix) {
+            Some(inner_name) => self.inner.call_tool(inner_name, arguments),
+            None => {
+                let tool_name = tool_name.to_string();
+                Box::pin(async move {
+                    Err(ToolError::NotFound(format!("Tool {} not found", tool_name)))
+                })
+            }
+        }
+    }
+
+    fn list_resources(&self) -> Vec<Resource> {
+        self.inner.list_resources()
+    }
+
+    fn read_resource(
+        &self,
+        uri: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ResourceError>> + Send + 'static>> {
+        self.inner.read_resource(uri)
+    }
+
+    fn list_prompts(&self) -> Vec<Prompt> {
+        self.inner.list_prompts()
+    }
+
+    fn get_prompt(
+        &self,
+        prompt_name: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>> {
+        self.inner.get_prompt(prompt_name)
+    }
+}
+
+/// Serves the tools, resources and prompts of two routers as one.
+///
+/// Calls are dispatched to the router that lists the requested name, with
+/// `first` winning on collisions. Nest `Merged` to combine more than two.
+#[derive(Clone)]
+pub struct Merged<A, B> {
+    name: String,
+    first: A,
+    second: B,
+}
+
+impl<A: Router, B: Router> Merged<A, B> {
+    pub fn new(name: impl Into<String>, first: A, second: B) -> Self {
+        Self {
+            name: name.into(),
+            first,
+            second,
+        }
+    }
+}
+
+impl<A: Router, B: Router> Router for Merged<A, B> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn instructions(&self) -> String {
+        [self.first.instructions(), self.second.instructions()]
+            .into_iter()
+            .filter(|instructions| !instructions.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn capabilities(&self) -> ServerCapabilities {
+        let first = self.first.capabilities();
+        let second = self.second.capabilities();
+        ServerCapabilities {
+            prompts: first.prompts.or(second.prompts),
+            resources: first.resources.or(second.resources),
+            tools: first.tools.or(second.tools),
+        }
+    }
+
+    fn list_tools(&self) -> Vec<Tool> {
+        let mut tools = self.first.list_tools();
+        for tool in self.second.list_tools() {
+            if !tools.iter().any(|t| t.name == tool.name) {
+                tools.push(tool);
+            }
+        }
+        tools
+    }
+
+    fn call_tool(
+        &self,
+        tool_name: &str,
+        arguments: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>> {
+        if self.first.list_tools().iter().any(|t| t.name == tool_name) {
+            self.first.call_tool(tool_name, arguments)
+        } else {
+            self.second.call_tool(tool_name, arguments)
+        }
+    }
+
+    fn list_resources(&self) -> Vec<Resource> {
+        let mut resources = self.first.list_resources();
+        resources.extend(self.second.list_resources());
+        resources
+    }
+
+    fn read_resource(
+        &self,
+        uri: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ResourceError>> + Send + 'static>> {
+        if self.first.list_resources().iter().any(|r| r.uri == uri) {
+            self.first.read_resource(uri)
+        } else {
+            self.second.read_resource(uri)
+        }
+    }
+
+    fn list_prompts(&self) -> Vec<Prompt> {
+        let mut prompts = self.first.list_prompts();
+        prompts.extend(self.second.list_prompts());
+        prompts
+    }
+
+    fn get_prompt(
+        &self,
+        prompt_name: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>> {
+        if self
+            .first
+            .list_prompts()
+            .iter()
+            .any(|p| p.name == prompt_name)
+        {
+            self.first.get_prompt(prompt_name)
+        } else {
+            self.second.get_prompt(prompt_name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_server::router::CapabilitiesBuilder;
+
+    #[derive(Clone)]
+    struct EchoRouter(&'static str);
+
+    impl Router for EchoRouter {
+        fn name(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn instructions(&self) -> String {
+            format!("{} instructions", self.0)
+        }
+
+        fn capabilities(&self) -> ServerCapabilities {
+            CapabilitiesBuilder::new().with_tools(false).build()
+        }
+
+        fn list_tools(&self) -> Vec<Tool> {
+            vec![Tool::new(self.0, "echo", serde_json::json!({}))]
+        }
+
+        fn call_tool(
+            &self,
+            tool_name: &str,
+            _arguments: Value,
+        ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>>
+        {
+            let text = format!("{} handled {}", self.0, tool_name);
+            Box::pin(async move { Ok(vec![Content::text(text)]) })
+        }
+
+        fn list_resources(&self) -> Vec<Resource> {
+            vec![]
+        }
+
+        fn read_resource(
+            &self,
+            uri: &str,
+        ) -> Pin<Box<dyn Future<Output = Result<String, ResourceError>> + Send + 'static>> {
+            let uri = uri.to_string();
+            Box::pin(async move { Err(ResourceError::NotFound(uri)) })
+        }
+
+        fn list_prompts(&self) -> Vec<Prompt> {
+            vec![]
+        }
+
+        fn get_prompt(
+            &self,
+            prompt_name: &str,
+        ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>> {
+            let prompt_name = prompt_name.to_string();
+            Box::pin(async move { Err(PromptError::NotFound(prompt_name)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merged_dispatches_to_prefixed_tools() {
+        let router = Merged::new(
+            "combined",
+            Prefixed::new("pc_", EchoRouter("search")),
+            EchoRouter("other"),
+        );
+
+        let names: Vec<String> = router.list_tools().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["pc_search", "other"]);
+
+        let result = router.call_tool("pc_search", Value::Null).await.unwrap();
+        assert_eq!(result[0].as_text(), Some("search handled search"));
+
+        let result = router.call_tool("other", Value::Null).await.unwrap();
+        assert_eq!(result[0].as_text(), Some("other handled other"));
+    }
+}
@@ -1,32 +1,1064 @@
-use std::env;
+use std::{borrow::Cow, collections::HashMap, env, path::PathBuf, time::Duration};
+
+use thiserror::Error;
+
+use crate::alerting::ErrorAlertConfig;
+use crate::scrub::{self, Scrubber};
+use crate::semantic_cache::SemanticCacheConfig;
+use crate::usage::Pricing;
+
+use crate::pinecone::{
+    DEFAULT_CONTROL_PLANE_URL, DEFAULT_UPLOAD_CHUNK_SIZE, HttpOptions, Region, RetryPolicy,
+};
 
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub struct Config {
     pub pinecone_api_key: String,
     pub pinecone_assistant_host: String,
+    /// Data-plane hosts to fail over to, in order, when the assistant host
+    /// is unreachable or keeps failing.
+    pub pinecone_failover_hosts: Vec<String>,
+    pub pinecone_control_plane_host: String,
     pub log_level: String,
+    pub privacy_mode: bool,
+    pub default_assistant: Option<String>,
+    pub assistant_descriptions: Vec<(String, String)>,
+    pub assistant_aliases: HashMap<String, String>,
+    /// API keys for assistants living in other Pinecone projects, keyed by assistant name.
+    pub assistant_api_keys: HashMap<String, String>,
+    /// Whether tool calls may pass their own Pinecone API key, for servers
+    /// shared by several tenants.
+    pub allow_api_key_argument: bool,
+    /// Replaces the generic server instructions; the default assistant's
+    /// own instructions are still appended.
+    pub server_instructions: Option<String>,
+    /// Replacement descriptions of tools, keyed by tool name.
+    pub tool_descriptions: HashMap<String, String>,
+    /// Generation model of chat answers when a call names none; Pinecone's
+    /// default when unset.
+    pub chat_model: Option<String>,
+    /// Models chat calls may use; any model when empty.
+    pub allowed_chat_models: Vec<String>,
+    /// Sampling temperature of chat answers when a call passes none;
+    /// Pinecone's default when unset.
+    pub chat_temperature: Option<f32>,
+    /// Highest temperature chat calls may ask for.
+    pub max_chat_temperature: f32,
+    /// Directory where chat session histories are persisted, if any.
+    pub chat_session_dir: Option<PathBuf>,
+    /// How long session state (defaults, chat histories) may stay unused
+    /// before it is dropped; `None` keeps it for the lifetime of the process.
+    pub session_idle_ttl: Option<Duration>,
+    /// Maximum number of tool calls a batch runs at the same time.
+    pub max_concurrent_requests: usize,
+    /// Maximum number of tool calls handled at the same time; unlimited if
+    /// `None`.
+    pub max_concurrent_tool_calls: Option<usize>,
+    /// Tool calls that wait for a slot before further ones are rejected.
+    pub tool_call_queue_depth: usize,
+    /// Upper bound for the `timeout_ms` tool parameter.
+    pub max_timeout: Duration,
+    /// Size of the chunks in which uploads are streamed from disk.
+    pub upload_chunk_size: usize,
+    /// Directory where the upload journal is persisted, so interrupted
+    /// uploads can be resumed after a restart.
+    pub upload_state_dir: Option<PathBuf>,
+    /// Directory where uploads that failed for lack of a network are
+    /// spooled and retried from; they fail right away when unset.
+    pub upload_spool_dir: Option<PathBuf>,
+    /// Time between background retries of the spooled uploads.
+    pub upload_spool_retry_interval: Duration,
+    /// Largest file, in bytes, that may be uploaded.
+    pub max_upload_bytes: u64,
+    /// File extensions (without the dot) and MIME types that may be uploaded.
+    pub allowed_upload_types: Vec<String>,
+    /// Directories whose files tool calls may read, for uploads, checks and
+    /// syncs, besides the roots the client shares.
+    pub upload_roots: Vec<PathBuf>,
+    /// Directory where `export_assistant` may write manifests; they are only
+    /// returned inline when unset.
+    pub export_dir: Option<PathBuf>,
+    /// Directory where every retrieval is recorded as a snapshot resource;
+    /// snapshots are off when unset.
+    pub snapshot_dir: Option<PathBuf>,
+    /// Snapshots kept; the oldest are deleted beyond it. `None` keeps all.
+    pub snapshot_max_count: Option<usize>,
+    /// Age from which snapshots are deleted. `None` keeps them forever.
+    pub snapshot_max_age: Option<Duration>,
+    /// Cap on the bytes of snippets one retrieval returns; the lowest-scoring
+    /// snippets are dropped to fit. `None` disables the cap.
+    pub max_response_bytes: Option<usize>,
+    /// Connection settings of the HTTP client used for Pinecone calls.
+    pub http: HttpOptions,
+    /// Which failed Pinecone responses are retried.
+    pub retry: RetryPolicy,
+    /// Whether assistants named in the configuration are verified at startup.
+    pub startup_check: StartupCheck,
+    /// Prices the `estimate_cost` tool applies to the usage reported.
+    pub pricing: Pricing,
+    /// Directory where daily usage rollups are written; off when unset.
+    pub usage_dir: Option<PathBuf>,
+    /// How long a retrieval's results are reused when the same client repeats
+    /// it within its conversation; `None` turns this off.
+    pub retrieval_cache_ttl: Option<Duration>,
+    /// How many of a client's latest queries are remembered, to qualify
+    /// follow-up queries that ask for it; 0 remembers none.
+    pub retrieval_memory: usize,
+    /// How old the snippets of an identical earlier retrieval may be to be
+    /// served, marked stale, when Pinecone fails with a 5xx or can't be
+    /// reached; `None` turns this off.
+    pub serve_stale: Option<Duration>,
+    /// Reuse of retrieval results across similar queries; off when unset.
+    pub semantic_cache: Option<SemanticCacheConfig>,
+    /// Alerts on tool error rates; off when unset.
+    pub error_alerts: Option<ErrorAlertConfig>,
+    /// How long the project's assistant list is reused before it is
+    /// refreshed in the background.
+    pub assistant_inventory_ttl: Duration,
+    /// How often the files of the assistants in use are relisted to notify
+    /// clients browsing them as resources of changes; `None` only relists
+    /// them after uploads.
+    pub file_poll_interval: Option<Duration>,
+    /// How often the processing status of files uploaded by a client is
+    /// checked to notify it of changes; `None` turns this off.
+    pub file_status_interval: Option<Duration>,
+    /// Named regular expressions whose matches are redacted from snippets,
+    /// compiled while the configuration is read.
+    pub scrubber: Scrubber,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            pinecone_api_key: String::new(),
+            pinecone_assistant_host: Region::default().assistant_host().to_string(),
+            pinecone_failover_hosts: Vec::new(),
+            pinecone_control_plane_host: DEFAULT_CONTROL_PLANE_URL.to_string(),
+            log_level: "info".to_string(),
+            privacy_mode: false,
+            default_assistant: None,
+            assistant_descriptions: Vec::new(),
+            assistant_aliases: HashMap::new(),
+            assistant_api_keys: HashMap::new(),
+            allow_api_key_argument: false,
+            server_instructions: None,
+            tool_descriptions: HashMap::new(),
+            chat_model: None,
+            allowed_chat_models: Vec::new(),
+            chat_temperature: None,
+            max_chat_temperature: DEFAULT_MAX_CHAT_TEMPERATURE,
+            chat_session_dir: None,
+            session_idle_ttl: Some(Duration::from_secs(3600)),
+            max_concurrent_requests: 8,
+            max_concurrent_tool_calls: None,
+            tool_call_queue_depth: DEFAULT_TOOL_CALL_QUEUE_DEPTH,
+            max_timeout: Duration::from_secs(120),
+            upload_chunk_size: DEFAULT_UPLOAD_CHUNK_SIZE,
+            upload_state_dir: None,
+            upload_spool_dir: None,
+            upload_spool_retry_interval: Duration::from_secs(60),
+            max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            allowed_upload_types: default_upload_types(),
+            upload_roots: Vec::new(),
+            export_dir: None,
+            snapshot_dir: None,
+            snapshot_max_count: Some(DEFAULT_SNAPSHOT_MAX_COUNT),
+            snapshot_max_age: Some(DEFAULT_SNAPSHOT_MAX_AGE),
+            max_response_bytes: Some(DEFAULT_MAX_RESPONSE_BYTES),
+            http: HttpOptions::default(),
+            retry: RetryPolicy::default(),
+            startup_check: StartupCheck::Off,
+            pricing: Pricing::default(),
+            usage_dir: None,
+            retrieval_cache_ttl: Some(Duration::from_secs(300)),
+            retrieval_memory: 3,
+            serve_stale: None,
+            semantic_cache: None,
+            error_alerts: None,
+            assistant_inventory_ttl: Duration::from_secs(300),
+            file_poll_interval: Some(Duration::from_secs(60)),
+            file_status_interval: Some(Duration::from_secs(5)),
+            scrubber: Scrubber::default(),
+        }
+    }
+}
+
+/// Whether the configured assistants are checked when the server starts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StartupCheck {
+    #[default]
+    Off,
+    /// Problems are logged and the server starts anyway.
+    Warn,
+    /// The server refuses to start if a check fails.
+    Strict,
+}
+
+impl std::str::FromStr for StartupCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "0" | "false" | "no" => Ok(Self::Off),
+            "warn" | "1" | "true" | "yes" | "on" => Ok(Self::Warn),
+            "strict" => Ok(Self::Strict),
+            other => Err(format!("unknown startup check mode: {other}")),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    #[error("Missing environment variable: {0}")]
+    MissingVariable(String),
+
+    #[error("Invalid value for {name}: {value:?} (expected {expected})")]
+    InvalidValue {
+        name: String,
+        value: String,
+        expected: String,
+    },
+}
+
+/// The environment variables settings are read from: their documented names,
+/// the names behind a common prefix, or names mapped one by one, to fit
+/// existing secret-injection conventions.
+#[derive(Clone, Debug, Default)]
+pub struct EnvNames {
+    prefix: String,
+    mapping: HashMap<String, String>,
+}
+
+impl EnvNames {
+    /// Takes the prefix from `ASSISTANT_MCP_ENV_PREFIX` and the mapping from
+    /// `ASSISTANT_MCP_ENV_MAP`, comma-separated `SETTING=VARIABLE` entries.
+    /// These two are always read under their own names.
+    pub fn from_env() -> Self {
+        const ASSISTANT_MCP_ENV_PREFIX: &str = "ASSISTANT_MCP_ENV_PREFIX";
+        const ASSISTANT_MCP_ENV_MAP: &str = "ASSISTANT_MCP_ENV_MAP";
+
+        let prefix = env::var(ASSISTANT_MCP_ENV_PREFIX).unwrap_or_default();
+        let mapping = env::var(ASSISTANT_MCP_ENV_MAP)
+            .map(|v| parse_pairs(&v, ','))
+            .unwrap_or_default();
+        mapping.into_iter().fold(
+            Self::with_prefix(prefix.trim()),
+            |names, (setting, variable)| names.with_mapping(setting, variable),
+        )
+    }
+
+    /// Reads every setting from its name behind `prefix`, e.g.
+    /// `ACME_PINECONE_API_KEY`; the unprefixed names are then ignored.
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            mapping: HashMap::new(),
+        }
+    }
+
+    /// Reads `setting`, e.g. `PINECONE_API_KEY`, from `variable` instead,
+    /// whatever the prefix.
+    pub fn with_mapping(mut self, setting: impl Into<String>, variable: impl Into<String>) -> Self {
+        self.mapping.insert(setting.into(), variable.into());
+        self
+    }
+
+    /// The variable a setting is read from.
+    pub fn name<'a>(&'a self, setting: &'a str) -> Cow<'a, str> {
+        match self.mapping.get(setting) {
+            Some(variable) => Cow::Borrowed(variable),
+            None if self.prefix.is_empty() => Cow::Borrowed(setting),
+            None => Cow::Owned(format!("{}{setting}", self.prefix)),
+        }
+    }
+
+    /// The value of a setting.
+    pub fn var(&self, setting: &str) -> Result<String, env::VarError> {
+        env::var(self.name(setting).as_ref())
+    }
+
+    /// Parses a setting, warning about (and ignoring) unparsable values.
+    fn parse<T: std::str::FromStr>(&self, setting: &str) -> Option<T> {
+        let value = self.var(setting).ok()?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                let name = self.name(setting);
+                tracing::warn!("Ignoring invalid value for {name}: {value:?}");
+                None
+            }
+        }
+    }
+
+    fn flag(&self, setting: &str) -> bool {
+        self.var(setting)
+            .map(|v| {
+                matches!(
+                    v.trim().to_ascii_lowercase().as_str(),
+                    "1" | "true" | "yes" | "on"
+                )
+            })
+            .unwrap_or(false)
+    }
+
+    /// Parses `key=value` entries separated by `separator`, skipping malformed ones.
+    fn pairs(&self, setting: &str, separator: char) -> Vec<(String, String)> {
+        self.var(setting)
+            .map(|v| parse_pairs(&v, separator))
+            .unwrap_or_default()
+    }
 }
 
 impl Config {
+    /// Reads the configuration from the environment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a required variable is missing or a setting is invalid; see
+    /// [`Config::try_from_env`].
     pub fn from_env() -> Self {
+        Self::try_from_env().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Reads the configuration from the environment, under the names set by
+    /// `ASSISTANT_MCP_ENV_PREFIX` and `ASSISTANT_MCP_ENV_MAP`; see
+    /// [`EnvNames::from_env`].
+    pub fn try_from_env() -> Result<Self, ConfigError> {
+        Self::try_from_env_with(&EnvNames::from_env())
+    }
+
+    /// Reads the configuration from the environment, each setting from the
+    /// variable `names` maps it to.
+    pub fn try_from_env_with(names: &EnvNames) -> Result<Self, ConfigError> {
         const PINECONE_API_KEY: &str = "PINECONE_API_KEY";
         const PINECONE_ASSISTANT_HOST: &str = "PINECONE_ASSISTANT_HOST";
+        const PINECONE_CONTROL_PLANE_HOST: &str = "PINECONE_CONTROL_PLANE_HOST";
+        const PINECONE_REGION: &str = "PINECONE_REGION";
         const LOG_LEVEL: &str = "LOG_LEVEL";
+        const PRIVACY_MODE: &str = "PRIVACY_MODE";
+        const PINECONE_DEFAULT_ASSISTANT: &str = "PINECONE_DEFAULT_ASSISTANT";
+        const PINECONE_ASSISTANT_DESCRIPTIONS: &str = "PINECONE_ASSISTANT_DESCRIPTIONS";
+        const PINECONE_ASSISTANT_ALIASES: &str = "PINECONE_ASSISTANT_ALIASES";
+        const PINECONE_ASSISTANT_API_KEYS: &str = "PINECONE_ASSISTANT_API_KEYS";
+        const ALLOW_API_KEY_ARGUMENT: &str = "ALLOW_API_KEY_ARGUMENT";
+        const SERVER_INSTRUCTIONS: &str = "SERVER_INSTRUCTIONS";
+        const TOOL_DESCRIPTIONS: &str = "TOOL_DESCRIPTIONS";
+        const CHAT_MODEL: &str = "CHAT_MODEL";
+        const CHAT_ALLOWED_MODELS: &str = "CHAT_ALLOWED_MODELS";
+        const CHAT_TEMPERATURE: &str = "CHAT_TEMPERATURE";
+        const CHAT_MAX_TEMPERATURE: &str = "CHAT_MAX_TEMPERATURE";
+        const CHAT_SESSION_DIR: &str = "CHAT_SESSION_DIR";
+        const SESSION_IDLE_TTL_SECS: &str = "SESSION_IDLE_TTL_SECS";
+        const MAX_CONCURRENT_REQUESTS: &str = "MAX_CONCURRENT_REQUESTS";
+        const MAX_CONCURRENT_TOOL_CALLS: &str = "MAX_CONCURRENT_TOOL_CALLS";
+        const TOOL_CALL_QUEUE_DEPTH: &str = "TOOL_CALL_QUEUE_DEPTH";
+        const MAX_TIMEOUT_MS: &str = "MAX_TIMEOUT_MS";
+        const UPLOAD_CHUNK_SIZE: &str = "UPLOAD_CHUNK_SIZE";
+        const UPLOAD_STATE_DIR: &str = "UPLOAD_STATE_DIR";
+        const UPLOAD_SPOOL_DIR: &str = "UPLOAD_SPOOL_DIR";
+        const UPLOAD_SPOOL_RETRY_SECS: &str = "UPLOAD_SPOOL_RETRY_SECS";
+        const MAX_UPLOAD_BYTES: &str = "MAX_UPLOAD_BYTES";
+        const UPLOAD_ALLOWED_TYPES: &str = "UPLOAD_ALLOWED_TYPES";
+        const UPLOAD_ROOTS: &str = "UPLOAD_ROOTS";
+        const EXPORT_DIR: &str = "EXPORT_DIR";
+        const SNAPSHOT_DIR: &str = "SNAPSHOT_DIR";
+        const SNAPSHOT_MAX_COUNT: &str = "SNAPSHOT_MAX_COUNT";
+        const SNAPSHOT_MAX_AGE_DAYS: &str = "SNAPSHOT_MAX_AGE_DAYS";
+        const MAX_RESPONSE_BYTES: &str = "MAX_RESPONSE_BYTES";
+        const HTTP_VERSION: &str = "HTTP_VERSION";
+        const HTTP_POOL_MAX_IDLE: &str = "HTTP_POOL_MAX_IDLE";
+        const HTTP_POOL_IDLE_TIMEOUT_SECS: &str = "HTTP_POOL_IDLE_TIMEOUT_SECS";
+        const HTTP_KEEP_ALIVE_SECS: &str = "HTTP_KEEP_ALIVE_SECS";
+        const HTTP_CONNECT_TIMEOUT_SECS: &str = "HTTP_CONNECT_TIMEOUT_SECS";
+        const HTTP_REQUEST_TIMEOUT_SECS: &str = "HTTP_REQUEST_TIMEOUT_SECS";
+        const RETRY_STATUSES: &str = "RETRY_STATUSES";
+        const MAX_RETRIES: &str = "MAX_RETRIES";
+        const RETRY_BUDGET_PERCENT: &str = "RETRY_BUDGET_PERCENT";
+        const RETRY_BUDGET_WINDOW_SECS: &str = "RETRY_BUDGET_WINDOW_SECS";
+        const STARTUP_CHECK: &str = "STARTUP_CHECK";
+        const PRICE_CONTEXT_TOKENS: &str = "PRICE_CONTEXT_TOKENS";
+        const PRICE_CHAT_INPUT_TOKENS: &str = "PRICE_CHAT_INPUT_TOKENS";
+        const PRICE_CHAT_OUTPUT_TOKENS: &str = "PRICE_CHAT_OUTPUT_TOKENS";
+        const PRICE_PER_REQUEST: &str = "PRICE_PER_REQUEST";
+        const USAGE_DIR: &str = "USAGE_DIR";
+        const RETRIEVAL_CACHE_TTL_SECS: &str = "RETRIEVAL_CACHE_TTL_SECS";
+        const RETRIEVAL_MEMORY_QUERIES: &str = "RETRIEVAL_MEMORY_QUERIES";
+        const SERVE_STALE_SECS: &str = "SERVE_STALE_SECS";
+        const SEMANTIC_CACHE_EMBEDDING_URL: &str = "SEMANTIC_CACHE_EMBEDDING_URL";
+        const SEMANTIC_CACHE_EMBEDDING_MODEL: &str = "SEMANTIC_CACHE_EMBEDDING_MODEL";
+        const SEMANTIC_CACHE_API_KEY: &str = "SEMANTIC_CACHE_API_KEY";
+        const SEMANTIC_CACHE_THRESHOLD: &str = "SEMANTIC_CACHE_THRESHOLD";
+        const SEMANTIC_CACHE_TTL_SECS: &str = "SEMANTIC_CACHE_TTL_SECS";
+        const SEMANTIC_CACHE_MAX_ENTRIES: &str = "SEMANTIC_CACHE_MAX_ENTRIES";
+        const ALERT_ERROR_RATE_PERCENT: &str = "ALERT_ERROR_RATE_PERCENT";
+        const ALERT_WINDOW_SECS: &str = "ALERT_WINDOW_SECS";
+        const ALERT_MIN_CALLS: &str = "ALERT_MIN_CALLS";
+        const ALERT_WEBHOOK_URL: &str = "ALERT_WEBHOOK_URL";
+        const ALERT_RESOLVE_MARGIN_PERCENT: &str = "ALERT_RESOLVE_MARGIN_PERCENT";
+        const ALERT_COOLDOWN_SECS: &str = "ALERT_COOLDOWN_SECS";
+        const ASSISTANT_INVENTORY_TTL_SECS: &str = "ASSISTANT_INVENTORY_TTL_SECS";
+        const FILE_POLL_INTERVAL_SECS: &str = "FILE_POLL_INTERVAL_SECS";
+        const FILE_STATUS_INTERVAL_SECS: &str = "FILE_STATUS_INTERVAL_SECS";
+        const SCRUB_PII: &str = "SCRUB_PII";
+        const SCRUB_PATTERNS: &str = "SCRUB_PATTERNS";
 
-        let pinecone_api_key = env::var(PINECONE_API_KEY).expect(&format!(
-            "Missing environment variable: {}",
-            PINECONE_API_KEY
-        ));
+        let pinecone_api_key = names
+            .var(PINECONE_API_KEY)
+            .map_err(|_| ConfigError::MissingVariable(names.name(PINECONE_API_KEY).into_owned()))?;
 
-        let pinecone_assistant_host = env::var(PINECONE_ASSISTANT_HOST)
-            .unwrap_or_else(|_| "https://prod-1-data.ke.pinecone.io".to_string());
+        // A typo must not silently send data to another region.
+        let region = match names.var(PINECONE_REGION) {
+            Ok(value) if !value.trim().is_empty() => Some(value.parse::<Region>().map_err(
+                |_| ConfigError::InvalidValue {
+                    name: names.name(PINECONE_REGION).into_owned(),
+                    value,
+                    expected: "us or eu".to_string(),
+                },
+            )?),
+            _ => None,
+        };
 
-        let log_level = env::var(LOG_LEVEL).unwrap_or_else(|_| "info".to_string());
+        // The first host is preferred; the others are failovers.
+        let mut hosts = names
+            .var(PINECONE_ASSISTANT_HOST)
+            .map(|v| {
+                v.split(',')
+                    .map(|host| host.trim().to_string())
+                    .filter(|host| !host.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        match region {
+            None if hosts.is_empty() => hosts.push(Region::default().assistant_host().to_string()),
+            Some(region) if hosts.is_empty() => hosts.push(region.assistant_host().to_string()),
+            Some(region) if hosts[0].trim_end_matches('/') != region.assistant_host() => {
+                tracing::warn!(
+                    "{PINECONE_ASSISTANT_HOST} {} overrides the host of {PINECONE_REGION} {region:?}",
+                    hosts[0]
+                );
+            }
+            _ => {}
+        }
+        let pinecone_assistant_host = hosts.remove(0);
+        let pinecone_failover_hosts = hosts;
 
-        Self {
+        let pinecone_control_plane_host = names
+            .var(PINECONE_CONTROL_PLANE_HOST)
+            .unwrap_or_else(|_| DEFAULT_CONTROL_PLANE_URL.to_string());
+
+        let log_level = names.var(LOG_LEVEL).unwrap_or_else(|_| "info".to_string());
+
+        let privacy_mode = names.flag(PRIVACY_MODE);
+
+        let default_assistant = names
+            .var(PINECONE_DEFAULT_ASSISTANT)
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+
+        let assistant_descriptions = names.pairs(PINECONE_ASSISTANT_DESCRIPTIONS, ';');
+
+        let assistant_aliases = names
+            .pairs(PINECONE_ASSISTANT_ALIASES, ',')
+            .into_iter()
+            .collect();
+
+        // Entries map an assistant to the *name* of the variable holding its
+        // key, so the keys themselves stay in the usual secret-injection path.
+        let assistant_api_keys = names
+            .pairs(PINECONE_ASSISTANT_API_KEYS, ',')
+            .into_iter()
+            .map(|(assistant, key_var)| {
+                let key = env::var(&key_var).map_err(|_| {
+                    ConfigError::MissingVariable(format!(
+                        "{key_var} (API key for assistant {assistant})"
+                    ))
+                })?;
+                Ok((assistant, key))
+            })
+            .collect::<Result<_, ConfigError>>()?;
+
+        let allow_api_key_argument = names.flag(ALLOW_API_KEY_ARGUMENT);
+
+        let server_instructions = names
+            .var(SERVER_INSTRUCTIONS)
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+
+        let tool_descriptions = names.pairs(TOOL_DESCRIPTIONS, ';').into_iter().collect();
+
+        let allowed_chat_models: Vec<String> = names
+            .var(CHAT_ALLOWED_MODELS)
+            .map(|v| {
+                v.split(',')
+                    .map(|model| model.trim().to_string())
+                    .filter(|model| !model.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let chat_model = names
+            .var(CHAT_MODEL)
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        // A default the allowlist rejects would fail every chat call.
+        if let Some(model) = &chat_model {
+            if !allowed_chat_models.is_empty() && !allowed_chat_models.contains(model) {
+                return Err(ConfigError::InvalidValue {
+                    name: names.name(CHAT_MODEL).into_owned(),
+                    value: model.clone(),
+                    expected: allowed_chat_models.join(" or "),
+                });
+            }
+        }
+
+        let max_chat_temperature = names
+            .parse::<f32>(CHAT_MAX_TEMPERATURE)
+            .filter(|max| *max >= 0.0)
+            .unwrap_or(DEFAULT_MAX_CHAT_TEMPERATURE);
+        let chat_temperature = match names.var(CHAT_TEMPERATURE) {
+            Ok(value) if !value.trim().is_empty() => match value.trim().parse::<f32>() {
+                Ok(temperature) if (0.0..=max_chat_temperature).contains(&temperature) => {
+                    Some(temperature)
+                }
+                _ => {
+                    return Err(ConfigError::InvalidValue {
+                        name: names.name(CHAT_TEMPERATURE).into_owned(),
+                        value,
+                        expected: format!("a number from 0 to {max_chat_temperature}"),
+                    });
+                }
+            },
+            _ => None,
+        };
+
+        let chat_session_dir = names
+            .var(CHAT_SESSION_DIR)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+
+        // 0 disables expiry.
+        let session_idle_ttl = match names.parse::<u64>(SESSION_IDLE_TTL_SECS) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(Duration::from_secs(3600)),
+        };
+
+        let max_concurrent_requests = names
+            .parse::<usize>(MAX_CONCURRENT_REQUESTS)
+            .filter(|n| *n > 0)
+            .unwrap_or(8);
+        let max_concurrent_tool_calls = names
+            .parse::<usize>(MAX_CONCURRENT_TOOL_CALLS)
+            .filter(|n| *n > 0);
+        let tool_call_queue_depth = names
+            .parse::<usize>(TOOL_CALL_QUEUE_DEPTH)
+            .unwrap_or(DEFAULT_TOOL_CALL_QUEUE_DEPTH);
+
+        let max_timeout = Duration::from_millis(names.parse(MAX_TIMEOUT_MS).unwrap_or(120_000));
+
+        let upload_chunk_size = names
+            .parse::<usize>(UPLOAD_CHUNK_SIZE)
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_UPLOAD_CHUNK_SIZE);
+
+        let upload_state_dir = names
+            .var(UPLOAD_STATE_DIR)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+
+        let upload_spool_dir = names
+            .var(UPLOAD_SPOOL_DIR)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+        let upload_spool_retry_interval = Duration::from_secs(
+            names
+                .parse::<u64>(UPLOAD_SPOOL_RETRY_SECS)
+                .filter(|secs| *secs > 0)
+                .unwrap_or(60),
+        );
+
+        let max_upload_bytes = names
+            .parse(MAX_UPLOAD_BYTES)
+            .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES);
+
+        let allowed_upload_types = names
+            .var(UPLOAD_ALLOWED_TYPES)
+            .map(|v| parse_upload_types(&v))
+            .ok()
+            .filter(|types| !types.is_empty())
+            .unwrap_or_else(default_upload_types);
+
+        let upload_roots = names
+            .var(UPLOAD_ROOTS)
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|root| !root.is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let export_dir = names
+            .var(EXPORT_DIR)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+
+        let snapshot_dir = names
+            .var(SNAPSHOT_DIR)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+
+        // 0 keeps snapshots however many or old they are.
+        let snapshot_max_count = match names.parse::<usize>(SNAPSHOT_MAX_COUNT) {
+            Some(0) => None,
+            Some(count) => Some(count),
+            None => Some(DEFAULT_SNAPSHOT_MAX_COUNT),
+        };
+        let snapshot_max_age = match names.parse::<u64>(SNAPSHOT_MAX_AGE_DAYS) {
+            Some(0) => None,
+            Some(days) => Some(Duration::from_secs(days * 24 * 60 * 60)),
+            None => Some(DEFAULT_SNAPSHOT_MAX_AGE),
+        };
+
+        // 0 disables the cap.
+        let max_response_bytes = match names.parse::<usize>(MAX_RESPONSE_BYTES) {
+            Some(0) => None,
+            Some(bytes) => Some(bytes),
+            None => Some(DEFAULT_MAX_RESPONSE_BYTES),
+        };
+
+        // 0 turns off idle expiry, keep-alives and timeouts respectively.
+        let defaults = HttpOptions::default();
+        let secs = |name, default| match names.parse::<u64>(name) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => default,
+        };
+        let http = HttpOptions {
+            version: names.parse(HTTP_VERSION).unwrap_or(defaults.version),
+            pool_max_idle_per_host: names
+                .parse(HTTP_POOL_MAX_IDLE)
+                .unwrap_or(defaults.pool_max_idle_per_host),
+            pool_idle_timeout: secs(HTTP_POOL_IDLE_TIMEOUT_SECS, defaults.pool_idle_timeout),
+            keep_alive_interval: secs(HTTP_KEEP_ALIVE_SECS, defaults.keep_alive_interval),
+            connect_timeout: secs(HTTP_CONNECT_TIMEOUT_SECS, defaults.connect_timeout),
+            request_timeout: secs(HTTP_REQUEST_TIMEOUT_SECS, defaults.request_timeout),
+        };
+
+        let mut retry = RetryPolicy::default();
+        if let Ok(value) = names.var(RETRY_STATUSES) {
+            retry.statuses = parse_statuses(&value);
+        }
+        if let Some(max_retries) = names.parse(MAX_RETRIES) {
+            retry.max_retries = max_retries;
+        }
+        if let Some(percent) = names.parse(RETRY_BUDGET_PERCENT) {
+            retry.budget_percent = percent;
+        }
+        if let Some(secs) = names
+            .parse::<u64>(RETRY_BUDGET_WINDOW_SECS)
+            .filter(|s| *s > 0)
+        {
+            retry.budget_window = Duration::from_secs(secs);
+        }
+
+        let startup_check = names.parse(STARTUP_CHECK).unwrap_or_default();
+
+        let defaults = Pricing::default();
+        let price = |name, default| {
+            names
+                .parse::<f64>(name)
+                .filter(|price| *price >= 0.0)
+                .unwrap_or(default)
+        };
+        let pricing = Pricing {
+            context_tokens: price(PRICE_CONTEXT_TOKENS, defaults.context_tokens),
+            chat_input_tokens: price(PRICE_CHAT_INPUT_TOKENS, defaults.chat_input_tokens),
+            chat_output_tokens: price(PRICE_CHAT_OUTPUT_TOKENS, defaults.chat_output_tokens),
+            request: price(PRICE_PER_REQUEST, defaults.request),
+        };
+
+        let usage_dir = names
+            .var(USAGE_DIR)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+
+        // 0 turns the cache off.
+        let retrieval_cache_ttl = match names.parse::<u64>(RETRIEVAL_CACHE_TTL_SECS) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(Duration::from_secs(300)),
+        };
+
+        let retrieval_memory = names.parse(RETRIEVAL_MEMORY_QUERIES).unwrap_or(3);
+
+        // Off unless set; 0 turns it off too.
+        let serve_stale = names
+            .parse::<u64>(SERVE_STALE_SECS)
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs);
+
+        let semantic_cache = names
+            .var(SEMANTIC_CACHE_EMBEDDING_URL)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(|url| {
+                let defaults = SemanticCacheConfig::new(url.trim());
+                let non_empty = |name| names.var(name).ok().filter(|v| !v.trim().is_empty());
+                SemanticCacheConfig {
+                    embedding_model: non_empty(SEMANTIC_CACHE_EMBEDDING_MODEL),
+                    api_key: non_empty(SEMANTIC_CACHE_API_KEY),
+                    threshold: names
+                        .parse::<f32>(SEMANTIC_CACHE_THRESHOLD)
+                        .filter(|t| (0.0..=1.0).contains(t))
+                        .unwrap_or(defaults.threshold),
+                    ttl: names
+                        .parse(SEMANTIC_CACHE_TTL_SECS)
+                        .map(Duration::from_secs)
+                        .unwrap_or(defaults.ttl),
+                    max_entries: names
+                        .parse(SEMANTIC_CACHE_MAX_ENTRIES)
+                        .unwrap_or(defaults.max_entries),
+                    ..defaults
+                }
+            });
+
+        let error_alerts = match names.var(ALERT_ERROR_RATE_PERCENT) {
+            Ok(value) if !value.trim().is_empty() => match value.trim().parse::<u32>() {
+                Ok(percent) if (1..=100).contains(&percent) => {
+                    let defaults = ErrorAlertConfig::new(percent);
+                    Some(ErrorAlertConfig {
+                        window: names
+                            .parse::<u64>(ALERT_WINDOW_SECS)
+                            .filter(|secs| *secs > 0)
+                            .map(Duration::from_secs)
+                            .unwrap_or(defaults.window),
+                        min_calls: names.parse(ALERT_MIN_CALLS).unwrap_or(defaults.min_calls),
+                        resolve_margin_percent: names
+                            .parse(ALERT_RESOLVE_MARGIN_PERCENT)
+                            .unwrap_or(defaults.resolve_margin_percent),
+                        cooldown: names
+                            .parse::<u64>(ALERT_COOLDOWN_SECS)
+                            .map(Duration::from_secs)
+                            .unwrap_or(defaults.cooldown),
+                        webhook_url: names
+                            .var(ALERT_WEBHOOK_URL)
+                            .ok()
+                            .map(|url| url.trim().to_string())
+                            .filter(|url| !url.is_empty()),
+                        ..defaults
+                    })
+                }
+                _ => {
+                    return Err(ConfigError::InvalidValue {
+                        name: names.name(ALERT_ERROR_RATE_PERCENT).into_owned(),
+                        value,
+                        expected: "a percentage from 1 to 100".to_string(),
+                    });
+                }
+            },
+            _ => None,
+        };
+
+        let assistant_inventory_ttl =
+            Duration::from_secs(names.parse(ASSISTANT_INVENTORY_TTL_SECS).unwrap_or(300));
+
+        // 0 turns polling off.
+        let file_poll_interval = match names.parse::<u64>(FILE_POLL_INTERVAL_SECS) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(Duration::from_secs(60)),
+        };
+        let file_status_interval = match names.parse::<u64>(FILE_STATUS_INTERVAL_SECS) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(Duration::from_secs(5)),
+        };
+
+        // Scrubbing that silently doesn't happen would expose personal data,
+        // so unknown names and invalid patterns are errors.
+        let mut scrub_patterns = Vec::new();
+        for name in names.var(SCRUB_PII).unwrap_or_default().split(',') {
+            let name = name.trim().to_ascii_lowercase();
+            if name.is_empty() {
+                continue;
+            }
+            let pattern = scrub::builtin(&name).ok_or_else(|| ConfigError::InvalidValue {
+                name: names.name(SCRUB_PII).into_owned(),
+                value: name.clone(),
+                expected: scrub::BUILTIN_PATTERNS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+            })?;
+            scrub_patterns.push((name, pattern.to_string()));
+        }
+        scrub_patterns.extend(names.pairs(SCRUB_PATTERNS, ';'));
+        let scrubber = Scrubber::new(&scrub_patterns).map_err(|e| ConfigError::InvalidValue {
+            name: names.name(SCRUB_PATTERNS).into_owned(),
+            value: e.to_string(),
+            expected: "name=regex entries separated by ';'".to_string(),
+        })?;
+
+        Ok(Self {
             pinecone_api_key,
             pinecone_assistant_host,
+            pinecone_failover_hosts,
+            pinecone_control_plane_host,
             log_level,
-        }
+            privacy_mode,
+            default_assistant,
+            assistant_descriptions,
+            assistant_aliases,
+            assistant_api_keys,
+            allow_api_key_argument,
+            server_instructions,
+            tool_descriptions,
+            chat_model,
+            allowed_chat_models,
+            chat_temperature,
+            max_chat_temperature,
+            chat_session_dir,
+            session_idle_ttl,
+            max_concurrent_requests,
+            max_concurrent_tool_calls,
+            tool_call_queue_depth,
+            max_timeout,
+            upload_chunk_size,
+            upload_state_dir,
+            upload_spool_dir,
+            upload_spool_retry_interval,
+            max_upload_bytes,
+            allowed_upload_types,
+            upload_roots,
+            export_dir,
+            snapshot_dir,
+            snapshot_max_count,
+            snapshot_max_age,
+            max_response_bytes,
+            http,
+            retry,
+            startup_check,
+            pricing,
+            usage_dir,
+            retrieval_cache_ttl,
+            retrieval_memory,
+            serve_stale,
+            semantic_cache,
+            error_alerts,
+            assistant_inventory_ttl,
+            file_poll_interval,
+            file_status_interval,
+            scrubber,
+        })
+    }
+}
+
+impl Config {
+    /// Every variable the server reads, under the names given by `names`,
+    /// with its type, default and description, as JSON.
+    pub fn schema(names: &EnvNames) -> serde_json::Value {
+        crate::options::schema(names)
+    }
+
+    /// The effective configuration as JSON, with API keys masked, for
+    /// diagnosing misbehavior without sharing secrets.
+    pub fn redacted(&self) -> serde_json::Value {
+        let secs = |duration: Option<Duration>| duration.map(|d| d.as_secs());
+        let api_keys: HashMap<&String, String> = self
+            .assistant_api_keys
+            .iter()
+            .map(|(assistant, key)| (assistant, mask_secret(key)))
+            .collect();
+        let error_alerts = self.error_alerts.as_ref().map(|alerts| {
+            serde_json::json!({
+                "threshold_percent": alerts.threshold_percent,
+                "window_secs": alerts.window.as_secs(),
+                "min_calls": alerts.min_calls,
+                "resolve_margin_percent": alerts.resolve_margin_percent,
+                "cooldown_secs": alerts.cooldown.as_secs(),
+                "webhook_url": alerts.webhook_url.as_deref().map(mask_secret),
+            })
+        });
+        let mut config = serde_json::json!({
+            "pinecone_api_key": mask_secret(&self.pinecone_api_key),
+            "pinecone_assistant_host": self.pinecone_assistant_host,
+            "pinecone_failover_hosts": self.pinecone_failover_hosts,
+            "pinecone_control_plane_host": self.pinecone_control_plane_host,
+            "log_level": self.log_level,
+            "privacy_mode": self.privacy_mode,
+            "default_assistant": self.default_assistant,
+            "assistant_descriptions": self.assistant_descriptions,
+            "assistant_aliases": self.assistant_aliases,
+            "assistant_api_keys": api_keys,
+            "allow_api_key_argument": self.allow_api_key_argument,
+            "server_instructions": self.server_instructions,
+            "tool_descriptions": self.tool_descriptions,
+            "chat_session_dir": self.chat_session_dir,
+            "session_idle_ttl_secs": secs(self.session_idle_ttl),
+            "max_concurrent_requests": self.max_concurrent_requests,
+            "tool_calls": {
+                "max_concurrent": self.max_concurrent_tool_calls,
+                "queue_depth": self.tool_call_queue_depth,
+            },
+            "max_timeout_ms": self.max_timeout.as_millis() as u64,
+            "upload_chunk_size": self.upload_chunk_size,
+            "upload_state_dir": self.upload_state_dir,
+            "max_upload_bytes": self.max_upload_bytes,
+            "allowed_upload_types": self.allowed_upload_types,
+            "snapshot_dir": self.snapshot_dir,
+            "usage_dir": self.usage_dir,
+            "retrieval_cache_ttl_secs": secs(self.retrieval_cache_ttl),
+            "retrieval_memory": self.retrieval_memory,
+            "assistant_inventory_ttl_secs": self.assistant_inventory_ttl.as_secs(),
+            "file_poll_interval_secs": secs(self.file_poll_interval),
+            "file_status_interval_secs": secs(self.file_status_interval),
+            "scrub_patterns": self.scrubber.patterns(),
+            "semantic_cache": self.semantic_cache.as_ref().map(|cache| serde_json::json!({
+                "embedding_url": cache.embedding_url,
+                "embedding_model": cache.embedding_model,
+                "api_key": cache.api_key.as_deref().map(mask_secret),
+                "threshold": cache.threshold,
+                "ttl_secs": cache.ttl.as_secs(),
+                "max_entries": cache.max_entries,
+            })),
+            "max_response_bytes": self.max_response_bytes,
+            "http": {
+                "version": format!("{:?}", self.http.version).to_ascii_lowercase(),
+                "pool_max_idle_per_host": self.http.pool_max_idle_per_host,
+                "pool_idle_timeout_secs": secs(self.http.pool_idle_timeout),
+                "keep_alive_secs": secs(self.http.keep_alive_interval),
+                "connect_timeout_secs": secs(self.http.connect_timeout),
+                "request_timeout_secs": secs(self.http.request_timeout),
+            },
+            "chat": {
+                "model": self.chat_model,
+                "allowed_models": self.allowed_chat_models,
+                "temperature": self.chat_temperature,
+                "max_temperature": self.max_chat_temperature,
+            },
+            "retry": {
+                "statuses": self.retry.statuses,
+                "max_retries": self.retry.max_retries,
+                "budget_percent": self.retry.budget_percent,
+                "budget_window_secs": self.retry.budget_window.as_secs(),
+            },
+            "startup_check": format!("{:?}", self.startup_check).to_ascii_lowercase(),
+            "pricing_usd": {
+                "context_tokens_per_million": self.pricing.context_tokens,
+                "chat_input_tokens_per_million": self.pricing.chat_input_tokens,
+                "chat_output_tokens_per_million": self.pricing.chat_output_tokens,
+                "per_request": self.pricing.request,
+            },
+        });
+        // Added apart, as more entries overflow the `json!` recursion limit.
+        config["error_alerts"] = error_alerts.into();
+        config["serve_stale_secs"] = secs(self.serve_stale).into();
+        config["upload_roots"] = serde_json::json!(self.upload_roots);
+        config["export_dir"] = serde_json::json!(self.export_dir);
+        config["snapshot_max_count"] = self.snapshot_max_count.into();
+        config["snapshot_max_age_days"] = self
+            .snapshot_max_age
+            .map(|age| age.as_secs() / (24 * 60 * 60))
+            .into();
+        config["upload_spool"] = serde_json::json!({
+            "dir": self.upload_spool_dir,
+            "retry_interval_secs": self.upload_spool_retry_interval.as_secs(),
+        });
+        config
+    }
+}
+
+/// Masks a secret, keeping its last four characters so keys can be told
+/// apart.
+fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("****{tail}")
+}
+
+const DEFAULT_MAX_UPLOAD_BYTES: u64 = 100 * 1024 * 1024;
+
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+const DEFAULT_SNAPSHOT_MAX_COUNT: usize = 10_000;
+
+const DEFAULT_SNAPSHOT_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const DEFAULT_MAX_CHAT_TEMPERATURE: f32 = 1.0;
+
+const DEFAULT_TOOL_CALL_QUEUE_DEPTH: usize = 64;
+
+/// The document formats assistants accept.
+fn default_upload_types() -> Vec<String> {
+    parse_upload_types("pdf,txt,md,json,docx")
+}
+
+/// Parses comma-separated extensions and MIME types, normalizing `.PDF` to `pdf`.
+fn parse_upload_types(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// Parses comma-separated HTTP statuses, skipping anything else.
+fn parse_statuses(value: &str) -> Vec<u16> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| match entry.parse::<u16>() {
+            Ok(status @ 100..=599) => Some(status),
+            _ => {
+                tracing::warn!("Ignoring invalid HTTP status in configuration: {entry:?}");
+                None
+            }
+        })
+        .collect()
+}
+
+fn parse_pairs(value: &str, separator: char) -> Vec<(String, String)> {
+    value
+        .split(separator)
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let (key, value) = entry.split_once('=')?;
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() || value.is_empty() {
+                tracing::warn!("Ignoring malformed configuration entry: {entry:?}");
+                return None;
+            }
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_names_apply_the_prefix_and_mapping() {
+        let names = EnvNames::default();
+        assert_eq!(names.name("PINECONE_API_KEY"), "PINECONE_API_KEY");
+
+        let names = EnvNames::with_prefix("ACME_").with_mapping("PINECONE_API_KEY", "VAULT_KEY");
+        assert_eq!(names.name("PINECONE_API_KEY"), "VAULT_KEY");
+        assert_eq!(names.name("LOG_LEVEL"), "ACME_LOG_LEVEL");
     }
 }
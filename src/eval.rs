@@ -0,0 +1,426 @@
+//! Offline evaluation of retrieval quality: runs a query set against the
+//! assistants and measures how well the expected documents are retrieved,
+//! so assistant configurations can be compared.
+
+use std::path::Path;
+use std::time::Instant;
+
+use assistant_mcp::PineconeAssistantRouter;
+use mcp_server::Router;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value, json};
+
+/// One query of a query set, read from a line of JSON.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Case {
+    pub query: String,
+    /// Assistant to query instead of the command's.
+    pub assistant: Option<String>,
+    /// Names or ids of the files the query should retrieve.
+    #[serde(default)]
+    pub expected: Vec<String>,
+    pub top_k: Option<u32>,
+    pub filter: Option<Map<String, Value>>,
+}
+
+/// Reads a query set, or the records of an earlier run, one JSON object
+/// per line; blank lines are skipped.
+pub fn read_lines<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| format!("{}:{}: {e}", path.display(), index + 1))
+        })
+        .collect()
+}
+
+/// Runs the cases one after the other, so latencies are not skewed by
+/// concurrency, and records what each retrieved.
+pub async fn run(
+    router: &PineconeAssistantRouter,
+    cases: &[Case],
+    assistant: Option<&str>,
+    top_k: Option<u32>,
+) -> Vec<Value> {
+    let mut records = Vec::with_capacity(cases.len());
+    for case in cases {
+        let mut arguments = json!({"query": case.query, "response_format": "json"});
+        let assistant = case.assistant.as_deref().or(assistant);
+        if let Some(assistant) = assistant {
+            arguments["assistant_name"] = assistant.into();
+        }
+        if let Some(top_k) = case.top_k.or(top_k) {
+            arguments["top_k"] = top_k.into();
+        }
+        if let Some(filter) = &case.filter {
+            arguments["filter"] = Value::Object(filter.clone());
+        }
+
+        let (latency_ms, result) = retrieve(router, arguments).await;
+        let mut record = json!({
+            "query": case.query,
+            "assistant": assistant,
+            "expected": case.expected,
+            "latency_ms": latency_ms,
+        });
+        match result {
+            Ok(document) => record["documents"] = documents(&document["snippets"]).into(),
+            Err(e) => record["error"] = e.into(),
+        }
+        records.push(record);
+    }
+    records
+}
+
+/// Calls `assistant_context` with JSON output and times it. A result
+/// without snippets, such as a recoverable error, is an error.
+pub async fn retrieve(
+    router: &PineconeAssistantRouter,
+    arguments: Value,
+) -> (f64, Result<Value, String>) {
+    let started = Instant::now();
+    let result = router.call_tool("assistant_context", arguments).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let result = match result {
+        Ok(contents) => {
+            let text = contents
+                .first()
+                .and_then(|c| c.as_text())
+                .unwrap_or_default();
+            match serde_json::from_str::<Value>(text) {
+                Ok(document) if document["snippets"].is_array() => Ok(document),
+                _ => Err(text.to_string()),
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    (latency_ms, result)
+}
+
+/// The files of the snippets, in rank order, with their best score.
+fn documents(snippets: &Value) -> Vec<Value> {
+    let mut documents: Vec<Value> = Vec::new();
+    for snippet in snippets.as_array().into_iter().flatten() {
+        let file = &snippet["reference"]["file"];
+        let name = file["name"].as_str().unwrap_or_default();
+        let id = file["id"].as_str().unwrap_or(name);
+        let score = snippet["score"].as_f64();
+        match documents.iter_mut().find(|document| document["id"] == id) {
+            Some(document) => {
+                if score > document["score"].as_f64() {
+                    document["score"] = score.into();
+                }
+            }
+            None => documents.push(json!({"id": id, "name": name, "score": score})),
+        }
+    }
+    documents
+}
+
+/// Whether a retrieved document is one of `expected`, by name or id.
+fn matches(document: &Value, expected: &[Value]) -> bool {
+    expected
+        .iter()
+        .any(|expected| *expected == document["name"] || *expected == document["id"])
+}
+
+/// Aggregates records: hit rate (a query retrieved at least one expected
+/// document), mean reciprocal rank of the first hit and recall of the
+/// expected documents, over the queries that expect some, and latency
+/// percentiles over all queries.
+pub fn summarize(records: &[Value]) -> Value {
+    let failed = records.iter().filter(|r| r["error"].is_string()).count();
+    let mut judged = 0;
+    let mut hits = 0;
+    let mut reciprocal_ranks = 0.0;
+    let mut recall = 0.0;
+    for record in records.iter().filter(|r| !r["error"].is_string()) {
+        let expected = record["expected"].as_array().map_or(&[][..], Vec::as_slice);
+        if expected.is_empty() {
+            continue;
+        }
+        judged += 1;
+        let documents = record["documents"]
+            .as_array()
+            .map_or(&[][..], Vec::as_slice);
+        if let Some(rank) = documents.iter().position(|d| matches(d, expected)) {
+            hits += 1;
+            reciprocal_ranks += 1.0 / (rank + 1) as f64;
+        }
+        let found = expected
+            .iter()
+            .filter(|e| {
+                documents
+                    .iter()
+                    .any(|d| matches(d, std::slice::from_ref(e)))
+            })
+            .count();
+        recall += found as f64 / expected.len() as f64;
+    }
+    let mut latencies: Vec<f64> = records
+        .iter()
+        .filter_map(|r| r["latency_ms"].as_f64())
+        .collect();
+    latencies.sort_by(f64::total_cmp);
+    let ratio = |total: f64| (judged > 0).then(|| total / judged as f64);
+
+    json!({
+        "queries": records.len(),
+        "failed": failed,
+        "judged": judged,
+        "hit_rate": ratio(hits as f64),
+        "mrr": ratio(reciprocal_ranks),
+        "recall": ratio(recall),
+        "latency_ms": latency_summary(&latencies),
+    })
+}
+
+/// Percentiles of sorted latencies, in milliseconds.
+pub fn latency_summary(sorted: &[f64]) -> Value {
+    json!({
+        "p50": percentile(sorted, 50.0),
+        "p90": percentile(sorted, 90.0),
+        "p95": percentile(sorted, 95.0),
+        "p99": percentile(sorted, 99.0),
+        "max": sorted.last(),
+    })
+}
+
+/// The nearest-rank percentile of sorted values.
+pub fn percentile(sorted: &[f64], percent: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Compares the documents of a run with a snapshot, a run recorded with
+/// `--output`, matching queries by text and assistant. Reports, per query
+/// that changed, the documents that are new, missing, or whose score moved
+/// by more than `tolerance`. Queries that failed or are not in both runs
+/// are not compared.
+pub fn compare(snapshot: &[Value], records: &[Value], tolerance: f64) -> Vec<Value> {
+    let mut diffs = Vec::new();
+    for record in records.iter().filter(|r| r["documents"].is_array()) {
+        let Some(before) = snapshot.iter().find(|s| {
+            s["query"] == record["query"]
+                && s["assistant"] == record["assistant"]
+                && s["documents"].is_array()
+        }) else {
+            continue;
+        };
+        let before = before["documents"]
+            .as_array()
+            .map_or(&[][..], Vec::as_slice);
+        let after = record["documents"]
+            .as_array()
+            .map_or(&[][..], Vec::as_slice);
+        let find = |documents: &[Value], id: &Value| -> Option<Value> {
+            documents.iter().find(|d| d["id"] == *id).cloned()
+        };
+
+        let new: Vec<&Value> = after
+            .iter()
+            .filter(|d| find(before, &d["id"]).is_none())
+            .map(|d| &d["name"])
+            .collect();
+        let missing: Vec<&Value> = before
+            .iter()
+            .filter(|d| find(after, &d["id"]).is_none())
+            .map(|d| &d["name"])
+            .collect();
+        let shifted: Vec<Value> = after
+            .iter()
+            .filter_map(|d| {
+                let old = find(before, &d["id"])?["score"].as_f64()?;
+                let score = d["score"].as_f64()?;
+                ((score - old).abs() > tolerance)
+                    .then(|| json!({"name": d["name"], "before": old, "after": score}))
+            })
+            .collect();
+        if !new.is_empty() || !missing.is_empty() || !shifted.is_empty() {
+            diffs.push(json!({
+                "query": record["query"],
+                "assistant": record["assistant"],
+                "new": new,
+                "missing": missing,
+                "shifted": shifted,
+            }));
+        }
+    }
+    diffs
+}
+
+/// Whether a diff lost documents or scores, as opposed to only gaining.
+pub fn is_regression(diff: &Value) -> bool {
+    let lost = diff["missing"].as_array().is_some_and(|m| !m.is_empty());
+    let dropped = diff["shifted"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|s| s["after"].as_f64() < s["before"].as_f64());
+    lost || dropped
+}
+
+/// Renders the diffs against a snapshot for reading in a terminal.
+pub fn format_diffs(diffs: &[Value]) -> String {
+    let mut out = String::new();
+    let names = |value: &Value| -> String {
+        let names: Vec<&str> = value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        names.join(", ")
+    };
+    for diff in diffs {
+        out.push_str(&format!(
+            "changed: {}\n",
+            diff["query"].as_str().unwrap_or_default()
+        ));
+        if diff["new"].as_array().is_some_and(|n| !n.is_empty()) {
+            out.push_str(&format!("    new: {}\n", names(&diff["new"])));
+        }
+        if diff["missing"].as_array().is_some_and(|m| !m.is_empty()) {
+            out.push_str(&format!("    missing: {}\n", names(&diff["missing"])));
+        }
+        for shift in diff["shifted"].as_array().into_iter().flatten() {
+            out.push_str(&format!(
+                "    score: {} {:.3} -> {:.3}\n",
+                shift["name"].as_str().unwrap_or_default(),
+                shift["before"].as_f64().unwrap_or_default(),
+                shift["after"].as_f64().unwrap_or_default(),
+            ));
+        }
+    }
+    let regressions = diffs.iter().filter(|diff| is_regression(diff)).count();
+    out.push_str(&format!(
+        "{} query(ies) changed since the snapshot, {regressions} regressed\n",
+        diffs.len()
+    ));
+    out
+}
+
+/// Renders a summary, and the failed queries, for reading in a terminal.
+pub fn format_summary(summary: &Value, records: &[Value]) -> String {
+    let mut out = String::new();
+    for record in records.iter().filter(|r| r["error"].is_string()) {
+        out.push_str(&format!(
+            "failed: {}: {}\n",
+            record["query"].as_str().unwrap_or_default(),
+            record["error"].as_str().unwrap_or_default()
+        ));
+    }
+    out.push_str(&format!(
+        "{} queries, {} failed\n",
+        summary["queries"], summary["failed"]
+    ));
+    let percent = |value: &Value| value.as_f64().map(|v| format!("{:.1}%", v * 100.0));
+    match percent(&summary["hit_rate"]) {
+        Some(hit_rate) => out.push_str(&format!(
+            "hit rate {hit_rate}, MRR {:.3}, recall {} over {} queries with expected documents\n",
+            summary["mrr"].as_f64().unwrap_or_default(),
+            percent(&summary["recall"]).unwrap_or_default(),
+            summary["judged"]
+        )),
+        None => out.push_str("no queries with expected documents\n"),
+    }
+    let latency = &summary["latency_ms"];
+    if let Some(max) = latency["max"].as_f64() {
+        out.push_str(&format!(
+            "latency p50 {:.0} ms, p90 {:.0} ms, p99 {:.0} ms, max {max:.0} ms\n",
+            latency["p50"].as_f64().unwrap_or_default(),
+            latency["p90"].as_f64().unwrap_or_default(),
+            latency["p99"].as_f64().unwrap_or_default(),
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_is_nearest_rank() {
+        let sorted: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(percentile(&sorted, 50.0), Some(5.0));
+        assert_eq!(percentile(&sorted, 99.0), Some(10.0));
+        assert_eq!(percentile(&sorted, 0.0), Some(1.0));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_summarize_scores_expected_documents() {
+        let document = |name: &str| json!({"id": format!("id-{name}"), "name": name, "score": 0.5});
+        let records = vec![
+            json!({"query": "a", "expected": ["guide.pdf"], "latency_ms": 100.0,
+                "documents": [document("guide.pdf"), document("faq.md")]}),
+            json!({"query": "b", "expected": ["id-faq.md", "notes.md"], "latency_ms": 300.0,
+                "documents": [document("guide.pdf"), document("faq.md")]}),
+            json!({"query": "c", "expected": ["notes.md"], "latency_ms": 200.0,
+                "documents": [document("guide.pdf")]}),
+            json!({"query": "d", "expected": [], "latency_ms": 400.0, "documents": []}),
+            json!({"query": "e", "expected": ["guide.pdf"], "latency_ms": 50.0,
+                "error": "timed out"}),
+        ];
+
+        let summary = summarize(&records);
+
+        assert_eq!(summary["queries"], 5);
+        assert_eq!(summary["failed"], 1);
+        assert_eq!(summary["judged"], 3);
+        assert_eq!(summary["hit_rate"], 2.0 / 3.0);
+        assert_eq!(summary["mrr"], 1.5 / 3.0);
+        assert_eq!(summary["recall"], 1.5 / 3.0);
+        assert_eq!(summary["latency_ms"]["p50"], 200.0);
+        assert_eq!(summary["latency_ms"]["max"], 400.0);
+    }
+
+    #[test]
+    fn test_compare_reports_changed_documents() {
+        let record = |query: &str, documents: Value| json!({"query": query, "assistant": null, "documents": documents});
+        let document = |name: &str, score: f64| json!({"id": name, "name": name, "score": score});
+        let snapshot = vec![
+            record(
+                "a",
+                json!([document("guide.pdf", 0.9), document("faq.md", 0.5)]),
+            ),
+            record("b", json!([document("guide.pdf", 0.9)])),
+            record("c", json!([document("notes.md", 0.7)])),
+        ];
+        let records = vec![
+            record(
+                "a",
+                json!([document("guide.pdf", 0.88), document("notes.md", 0.4)]),
+            ),
+            record("b", json!([document("guide.pdf", 0.97)])),
+            record("c", json!([document("notes.md", 0.6)])),
+            record("d", json!([document("notes.md", 0.6)])),
+            json!({"query": "c", "assistant": "other", "documents": []}),
+        ];
+
+        let diffs = compare(&snapshot, &records, 0.05);
+
+        assert_eq!(
+            diffs,
+            vec![
+                json!({"query": "a", "assistant": null, "new": ["notes.md"],
+                    "missing": ["faq.md"], "shifted": []}),
+                json!({"query": "b", "assistant": null, "new": [], "missing": [],
+                    "shifted": [{"name": "guide.pdf", "before": 0.9, "after": 0.97}]}),
+                json!({"query": "c", "assistant": null, "new": [], "missing": [],
+                    "shifted": [{"name": "notes.md", "before": 0.7, "after": 0.6}]}),
+            ]
+        );
+        let regressed: Vec<bool> = diffs.iter().map(is_regression).collect();
+        assert_eq!(regressed, [true, false, true]);
+    }
+}
@@ -0,0 +1,169 @@
+//! Health tracking of the data-plane hosts a client can fail over between.
+//!
+//! Requests go to the first healthy host in configuration order. A host is
+//! marked down on a connection error or after sustained 5xx responses; once
+//! it has been down for a while it is tried again, so traffic falls back to
+//! the preferred host as soon as it recovers.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Consecutive 5xx responses after which a host is marked down.
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// How long a host stays down before it is tried again.
+const FAILBACK_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Default)]
+struct Health {
+    consecutive_failures: u32,
+    down_since: Option<Instant>,
+}
+
+pub struct HostPool {
+    hosts: Vec<String>,
+    health: Mutex<Vec<Health>>,
+}
+
+/// How a request to a host ended, as far as its health is concerned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    /// A 5xx response: the host is up but may be degraded.
+    ServerError,
+    /// No response at all.
+    Unreachable,
+}
+
+impl HostPool {
+    /// Creates a pool preferring hosts in the given order.
+    pub fn new(hosts: Vec<String>) -> Self {
+        let health = Mutex::new(vec![Health::default(); hosts.len()]);
+        Self { hosts, health }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// The host requests should go to now.
+    pub fn active(&self) -> &str {
+        self.active_at(Instant::now())
+    }
+
+    fn active_at(&self, now: Instant) -> &str {
+        let health = self.lock();
+        let available = |h: &Health| h.down_since.is_none_or(|t| now - t >= FAILBACK_AFTER);
+        // With every host down, the one down the longest is the best bet.
+        let index = health.iter().position(available).unwrap_or_else(|| {
+            (0..health.len())
+                .min_by_key(|&i| health[i].down_since)
+                .unwrap_or(0)
+        });
+        &self.hosts[index]
+    }
+
+    /// Records how a request to `host` ended.
+    pub fn record(&self, host: &str, outcome: Outcome) {
+        self.record_at(host, outcome, Instant::now());
+    }
+
+    fn record_at(&self, host: &str, outcome: Outcome, now: Instant) {
+        let Some(index) = self.hosts.iter().position(|h| h == host) else {
+            return;
+        };
+        let mut health = self.lock();
+        let entry = &mut health[index];
+        match outcome {
+            Outcome::Success => {
+                if entry.down_since.take().is_some() {
+                    tracing::info!("Pinecone host {host} recovered");
+                }
+                entry.consecutive_failures = 0;
+            }
+            Outcome::ServerError | Outcome::Unreachable => {
+                entry.consecutive_failures += 1;
+                // A host being probed after a failover goes straight back down.
+                let down = outcome == Outcome::Unreachable
+                    || entry.down_since.is_some()
+                    || entry.consecutive_failures >= FAILURE_THRESHOLD;
+                if down {
+                    if entry.down_since.is_none() && self.hosts.len() > 1 {
+                        tracing::warn!("Pinecone host {host} is failing; failing over");
+                    }
+                    entry.down_since = Some(now);
+                }
+            }
+        }
+    }
+
+    /// Health of each host, in order of preference, for diagnostics.
+    pub fn status(&self) -> Vec<serde_json::Value> {
+        let now = Instant::now();
+        let health = self.lock();
+        self.hosts
+            .iter()
+            .zip(health.iter())
+            .map(|(host, health)| {
+                let state = match health.down_since {
+                    None => "up",
+                    Some(since) if now - since >= FAILBACK_AFTER => "probing",
+                    Some(_) => "down",
+                };
+                serde_json::json!({
+                    "host": host,
+                    "state": state,
+                    "consecutive_failures": health.consecutive_failures,
+                })
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Health>> {
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fails_over_and_back() {
+        let pool = HostPool::new(vec!["https://a".to_string(), "https://b".to_string()]);
+        let start = Instant::now();
+
+        pool.record_at("https://a", Outcome::ServerError, start);
+        pool.record_at("https://a", Outcome::ServerError, start);
+        assert_eq!(pool.active_at(start), "https://a");
+        pool.record_at("https://a", Outcome::ServerError, start);
+        assert_eq!(pool.active_at(start), "https://b");
+
+        // The preferred host is probed again after a while; a failed probe
+        // sends traffic back to the other host.
+        let later = start + FAILBACK_AFTER;
+        assert_eq!(pool.active_at(later), "https://a");
+        pool.record_at("https://a", Outcome::ServerError, later);
+        assert_eq!(pool.active_at(later), "https://b");
+
+        let recovered = later + FAILBACK_AFTER;
+        pool.record_at("https://a", Outcome::Success, recovered);
+        assert_eq!(pool.active_at(recovered), "https://a");
+    }
+
+    #[test]
+    fn test_connection_errors_fail_over_at_once() {
+        let pool = HostPool::new(vec!["https://a".to_string(), "https://b".to_string()]);
+        let now = Instant::now();
+
+        pool.record_at("https://a", Outcome::Unreachable, now);
+        pool.record_at(
+            "https://b",
+            Outcome::Unreachable,
+            now + Duration::from_secs(1),
+        );
+
+        // With both down, the host down the longest is tried.
+        assert_eq!(pool.active_at(now + Duration::from_secs(2)), "https://a");
+    }
+}
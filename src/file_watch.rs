@@ -0,0 +1,428 @@
+//! The files of the assistants clients work with, listed as resources and
+//! relisted periodically so clients showing a resource browser learn when
+//! documents were added or removed, and the processing of uploads, followed
+//! until it ends so the uploading client is told of each status change.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::backend::ContextBackend;
+use crate::peer::Peer;
+use crate::pinecone::AssistantFile;
+
+/// Notification sent to the uploading client when a file's processing
+/// status changes.
+pub const FILE_STATUS_NOTIFICATION: &str = "notifications/pinecone/file_status";
+
+struct Watched {
+    backend: Arc<dyn ContextBackend>,
+    /// The files as last listed; `None` until the first listing.
+    files: Option<Vec<AssistantFile>>,
+}
+
+/// An uploaded file still being processed.
+struct Followed {
+    assistant_name: String,
+    backend: Arc<dyn ContextBackend>,
+    file_id: String,
+    name: String,
+    status: String,
+    /// The client that uploaded the file.
+    peer: Peer,
+}
+
+#[derive(Default)]
+struct State {
+    assistants: BTreeMap<String, Watched>,
+    /// Clients that listed resources, by connection.
+    subscribers: HashMap<u64, Peer>,
+    polling: bool,
+    followed: Vec<Followed>,
+    following: bool,
+}
+
+/// The watched assistants' files and the clients to notify when they change.
+#[derive(Clone)]
+pub struct FileWatch {
+    interval: Option<Duration>,
+    status_interval: Option<Duration>,
+    state: Arc<Mutex<State>>,
+}
+
+impl FileWatch {
+    /// Polls every `interval` while clients are subscribed; with `None`
+    /// files are only relisted on demand. Uploads are followed every
+    /// `status_interval`, if set.
+    pub fn new(interval: Option<Duration>, status_interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            status_interval,
+            state: Arc::default(),
+        }
+    }
+
+    /// A watch of no assistant and with no subscribers, that follows uploads
+    /// like this one.
+    pub fn detached(&self) -> Self {
+        Self::new(None, self.status_interval)
+    }
+
+    /// Starts watching an assistant's files.
+    pub fn watch(&self, assistant_name: &str, backend: Arc<dyn ContextBackend>) {
+        let mut state = self.lock();
+        if state.assistants.contains_key(assistant_name) {
+            return;
+        }
+        tracing::debug!("Watching the files of assistant {assistant_name}");
+        state.assistants.insert(
+            assistant_name.to_string(),
+            Watched {
+                backend,
+                files: None,
+            },
+        );
+    }
+
+    /// Notifies the client when the watched files change, until it
+    /// disconnects.
+    pub fn subscribe(&self, peer: &Peer) {
+        let unlisted = {
+            let mut state = self.lock();
+            state.subscribers.insert(peer.id(), peer.clone());
+            state
+                .assistants
+                .values()
+                .any(|watched| watched.files.is_none())
+        };
+        if unlisted {
+            self.check_in_background();
+        }
+        self.poll();
+    }
+
+    /// The watched files as last listed, with the assistant holding them.
+    pub fn files(&self) -> Vec<(String, AssistantFile)> {
+        self.lock()
+            .assistants
+            .iter()
+            .flat_map(|(assistant_name, watched)| {
+                watched
+                    .files
+                    .iter()
+                    .flatten()
+                    .map(move |file| (assistant_name.clone(), file.clone()))
+            })
+            .collect()
+    }
+
+    /// Relists the watched files and notifies subscribers if files were added
+    /// or removed. Returns whether they were.
+    pub async fn check(&self) -> bool {
+        let assistants: Vec<(String, Arc<dyn ContextBackend>)> = self
+            .lock()
+            .assistants
+            .iter()
+            .map(|(name, watched)| (name.clone(), watched.backend.clone()))
+            .collect();
+        let mut changed = false;
+        for (assistant_name, backend) in assistants {
+            let files = match backend.list_files(&assistant_name, None).await {
+                Ok(files) => files,
+                Err(e) => {
+                    tracing::debug!("Failed to list the files of {assistant_name}: {e}");
+                    continue;
+                }
+            };
+            let mut state = self.lock();
+            let Some(watched) = state.assistants.get_mut(&assistant_name) else {
+                continue;
+            };
+            if ids(watched.files.as_deref()) != ids(Some(&files)) {
+                tracing::debug!("The files of assistant {assistant_name} changed");
+                changed = true;
+            }
+            watched.files = Some(files);
+        }
+        if changed {
+            self.notify_subscribers();
+        }
+        changed
+    }
+
+    /// Relists the watched files without waiting, e.g. after an upload.
+    pub fn check_in_background(&self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let watch = self.clone();
+        runtime.spawn(async move {
+            watch.check().await;
+        });
+    }
+
+    /// Tells the client that uploaded a file of its status now and whenever
+    /// it changes, until processing ends.
+    pub fn follow(
+        &self,
+        assistant_name: &str,
+        backend: Arc<dyn ContextBackend>,
+        file: &AssistantFile,
+        peer: &Peer,
+    ) {
+        if self.status_interval.is_none() {
+            return;
+        }
+        let followed = Followed {
+            assistant_name: assistant_name.to_string(),
+            backend,
+            file_id: file.id.clone(),
+            name: file.name.clone(),
+            status: file.status.clone().unwrap_or_else(|| "Pending".to_string()),
+            peer: peer.clone(),
+        };
+        followed.notify(file.percent_done);
+        if is_final(&followed.status) {
+            return;
+        }
+        self.lock().followed.push(followed);
+        self.follow_in_background();
+    }
+
+    /// Checks the status of the files being followed, notifying their
+    /// clients of changes. Returns how many are still being processed.
+    pub async fn update_followed(&self) -> usize {
+        let assistants: BTreeMap<String, Arc<dyn ContextBackend>> = self
+            .lock()
+            .followed
+            .iter()
+            .map(|followed| (followed.assistant_name.clone(), followed.backend.clone()))
+            .collect();
+        for (assistant_name, backend) in assistants {
+            let files = match backend.list_files(&assistant_name, None).await {
+                Ok(files) => files,
+                Err(e) => {
+                    tracing::debug!("Failed to check processing in {assistant_name}: {e}");
+                    continue;
+                }
+            };
+            self.lock().followed.retain_mut(|followed| {
+                if followed.peer.is_closed() {
+                    return false;
+                }
+                if followed.assistant_name != assistant_name {
+                    return true;
+                }
+                let Some(file) = files.iter().find(|file| file.id == followed.file_id) else {
+                    tracing::debug!("File {} is gone; no longer following it", followed.file_id);
+                    return false;
+                };
+                let status = file.status.as_deref().unwrap_or("Pending");
+                if status != followed.status {
+                    followed.status = status.to_string();
+                    followed.notify(file.percent_done);
+                }
+                !is_final(status)
+            });
+        }
+        self.lock().followed.len()
+    }
+
+    fn follow_in_background(&self) {
+        let Some(interval) = self.status_interval else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        {
+            let mut state = self.lock();
+            if state.following {
+                return;
+            }
+            state.following = true;
+        }
+        let watch = self.clone();
+        runtime.spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if watch.update_followed().await == 0 {
+                    let mut state = watch.lock();
+                    // A file may have been followed while checking.
+                    if state.followed.is_empty() {
+                        state.following = false;
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    fn notify_subscribers(&self) {
+        let subscribers: Vec<Peer> = {
+            let mut state = self.lock();
+            state.subscribers.retain(|_, peer| !peer.is_closed());
+            state.subscribers.values().cloned().collect()
+        };
+        for peer in subscribers {
+            peer.notify("notifications/resources/list_changed", json!({}));
+        }
+    }
+
+    /// Relists the files every interval until no subscriber is left.
+    fn poll(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        {
+            let mut state = self.lock();
+            if state.polling {
+                return;
+            }
+            state.polling = true;
+        }
+        let watch = self.clone();
+        runtime.spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                {
+                    let mut state = watch.lock();
+                    state.subscribers.retain(|_, peer| !peer.is_closed());
+                    if state.subscribers.is_empty() {
+                        state.polling = false;
+                        return;
+                    }
+                }
+                watch.check().await;
+            }
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Followed {
+    fn notify(&self, percent_done: Option<f64>) {
+        tracing::debug!("File {} is {}", self.file_id, self.status);
+        let mut params = json!({
+            "assistant_name": self.assistant_name,
+            "file_id": self.file_id,
+            "name": self.name,
+            "status": self.status,
+        });
+        if let Some(percent_done) = percent_done {
+            params["percent_done"] = json!(percent_done);
+        }
+        self.peer.notify(FILE_STATUS_NOTIFICATION, params);
+    }
+}
+
+/// Whether processing ended, successfully or not.
+fn is_final(status: &str) -> bool {
+    matches!(status, "Available" | "ProcessingFailed")
+}
+
+fn ids(files: Option<&[AssistantFile]>) -> BTreeSet<&str> {
+    files
+        .into_iter()
+        .flatten()
+        .map(|file| file.id.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendFuture;
+    use crate::pinecone::AssistantContextResponse;
+    use serde_json::Value;
+    use tokio::sync::mpsc;
+
+    /// Serves the file ids it is given, with their status.
+    #[derive(Default)]
+    struct Files(Mutex<Vec<&'static str>>, Mutex<&'static str>);
+
+    impl ContextBackend for Files {
+        fn assistant_context<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            _query: &'a str,
+            _top_k: Option<u32>,
+        ) -> BackendFuture<'a, AssistantContextResponse> {
+            unreachable!()
+        }
+
+        fn list_files<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            _filter: Option<Value>,
+        ) -> BackendFuture<'a, Vec<AssistantFile>> {
+            let files = self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|id| {
+                    let status = *self.1.lock().unwrap();
+                    serde_json::from_value(json!({"id": id, "name": id, "status": status})).unwrap()
+                })
+                .collect();
+            Box::pin(async move { Ok(files) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_are_notified_when_files_are_added_or_removed() {
+        let backend = Arc::new(Files::default());
+        *backend.0.lock().unwrap() = vec!["a.pdf"];
+        let watch = FileWatch::new(None, None);
+        watch.watch("docs", backend.clone());
+        assert!(watch.check().await);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        watch.subscribe(&Peer::new(tx));
+        assert!(!watch.check().await);
+        assert!(rx.try_recv().is_err());
+
+        backend.0.lock().unwrap().push("b.pdf");
+        assert!(watch.check().await);
+        assert_eq!(
+            rx.try_recv().unwrap()["method"],
+            "notifications/resources/list_changed"
+        );
+        assert_eq!(watch.files().len(), 2);
+        assert_eq!(watch.files()[1].0, "docs");
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_followed_until_processed() {
+        let backend = Arc::new(Files::default());
+        *backend.0.lock().unwrap() = vec!["a.pdf"];
+        *backend.1.lock().unwrap() = "Processing";
+        let watch = FileWatch::new(None, Some(Duration::from_secs(3600)));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let file: AssistantFile =
+            serde_json::from_value(json!({"id": "a.pdf", "name": "a.pdf"})).unwrap();
+
+        watch.follow("docs", backend.clone(), &file, &Peer::new(tx));
+        assert_eq!(rx.try_recv().unwrap()["params"]["status"], "Pending");
+        assert_eq!(watch.update_followed().await, 1);
+        assert_eq!(rx.try_recv().unwrap()["params"]["status"], "Processing");
+        assert_eq!(watch.update_followed().await, 1);
+        assert!(rx.try_recv().is_err());
+
+        *backend.1.lock().unwrap() = "Available";
+        assert_eq!(watch.update_followed().await, 0);
+        let notification = rx.try_recv().unwrap();
+        assert_eq!(notification["method"], FILE_STATUS_NOTIFICATION);
+        assert_eq!(notification["params"]["status"], "Available");
+        assert_eq!(notification["params"]["assistant_name"], "docs");
+    }
+}
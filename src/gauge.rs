@@ -0,0 +1,73 @@
+//! Gauges of work in progress, such as outbound requests or tool calls, for
+//! capacity planning.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::{Value, json};
+
+/// Counts operations in progress and the most seen at once. Clones share
+/// the counts.
+#[derive(Clone, Debug, Default)]
+pub struct Gauge {
+    counts: Arc<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+/// Keeps an operation counted until dropped.
+pub struct GaugeGuard {
+    counts: Arc<Counts>,
+}
+
+impl Gauge {
+    /// Counts an operation as started.
+    pub fn enter(&self) -> GaugeGuard {
+        let current = self.counts.current.fetch_add(1, Ordering::Relaxed) + 1;
+        self.counts.peak.fetch_max(current, Ordering::Relaxed);
+        GaugeGuard {
+            counts: self.counts.clone(),
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.counts.current.load(Ordering::Relaxed)
+    }
+
+    /// The current and peak counts.
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "current": self.current(),
+            "peak": self.counts.peak.load(Ordering::Relaxed),
+        })
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.counts.current.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_until_dropped_and_keeps_the_peak() {
+        let gauge = Gauge::default();
+        let first = gauge.enter();
+        let second = gauge.clone().enter();
+        assert_eq!(gauge.current(), 2);
+
+        drop(first);
+        drop(second);
+        let _third = gauge.enter();
+
+        assert_eq!(gauge.snapshot(), json!({"current": 1, "peak": 2}));
+    }
+}
@@ -0,0 +1,189 @@
+//! The project's assistants, listed once and then kept for a while, so the
+//! features that need them (name suggestions, picking the only assistant)
+//! don't add a Pinecone call to every tool call.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::backend::ContextBackend;
+use crate::pinecone::Assistant;
+
+#[derive(Default)]
+struct State {
+    assistants: Option<Arc<Vec<Assistant>>>,
+    /// When the list was last fetched, or a fetch last failed.
+    fetched: Option<Instant>,
+    refreshing: bool,
+}
+
+/// The assistant list, cached for a TTL. A stale list is still served while
+/// it is refreshed in the background.
+#[derive(Clone)]
+pub struct AssistantInventory {
+    backend: Arc<dyn ContextBackend>,
+    ttl: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl AssistantInventory {
+    pub fn new(backend: Arc<dyn ContextBackend>, ttl: Duration) -> Self {
+        Self {
+            backend,
+            ttl,
+            state: Arc::default(),
+        }
+    }
+
+    /// The assistants, listing them first if they never were. `None` if the
+    /// backend cannot list them.
+    pub async fn get(&self) -> Option<Arc<Vec<Assistant>>> {
+        let (assistants, fetched) = {
+            let state = self.lock();
+            (state.assistants.clone(), state.fetched)
+        };
+        match assistants {
+            Some(assistants) => {
+                if self.is_stale(fetched) {
+                    self.refresh_in_background();
+                }
+                Some(assistants)
+            }
+            // A failed listing is only retried once the TTL has passed.
+            None if self.is_stale(fetched) => self.refresh().await,
+            None => None,
+        }
+    }
+
+    /// The assistants as last listed, without waiting for Pinecone. A
+    /// missing or stale list is refreshed in the background.
+    pub fn cached(&self) -> Option<Arc<Vec<Assistant>>> {
+        let (assistants, fetched) = {
+            let state = self.lock();
+            (state.assistants.clone(), state.fetched)
+        };
+        if self.is_stale(fetched) {
+            self.refresh_in_background();
+        }
+        assistants
+    }
+
+    /// Replaces the list, e.g. with one listed by the startup check.
+    pub fn set(&self, assistants: Vec<Assistant>) -> Arc<Vec<Assistant>> {
+        let assistants = Arc::new(assistants);
+        let mut state = self.lock();
+        state.assistants = Some(assistants.clone());
+        state.fetched = Some(Instant::now());
+        assistants
+    }
+
+    /// Marks the list stale, e.g. after an assistant was created.
+    pub fn invalidate(&self) {
+        self.lock().fetched = None;
+    }
+
+    /// Lists the assistants now. A failure keeps the previous list.
+    pub async fn refresh(&self) -> Option<Arc<Vec<Assistant>>> {
+        match self.backend.list_assistants().await {
+            Ok(assistants) => {
+                tracing::debug!(
+                    "Refreshed assistant inventory: {} assistant(s)",
+                    assistants.len()
+                );
+                Some(self.set(assistants))
+            }
+            Err(e) => {
+                tracing::debug!("Failed to refresh assistant inventory: {e}");
+                let mut state = self.lock();
+                state.fetched = Some(Instant::now());
+                state.assistants.clone()
+            }
+        }
+    }
+
+    fn refresh_in_background(&self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        {
+            let mut state = self.lock();
+            if state.refreshing {
+                return;
+            }
+            state.refreshing = true;
+        }
+        let inventory = self.clone();
+        runtime.spawn(async move {
+            inventory.refresh().await;
+            inventory.lock().refreshing = false;
+        });
+    }
+
+    fn is_stale(&self, fetched: Option<Instant>) -> bool {
+        fetched.is_none_or(|fetched| fetched.elapsed() >= self.ttl)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendFuture;
+    use crate::pinecone::AssistantContextResponse;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the listings, each adding an assistant.
+    #[derive(Default)]
+    struct Listings(AtomicUsize);
+
+    impl ContextBackend for Listings {
+        fn assistant_context<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            _query: &'a str,
+            _top_k: Option<u32>,
+        ) -> BackendFuture<'a, AssistantContextResponse> {
+            unreachable!()
+        }
+
+        fn list_assistants(&self) -> BackendFuture<'_, Vec<Assistant>> {
+            let count = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move {
+                Ok((0..count)
+                    .map(|i| serde_json::from_value(json!({"name": format!("kb-{i}")})).unwrap())
+                    .collect())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lists_once_within_the_ttl() {
+        let backend = Arc::new(Listings::default());
+        let inventory = AssistantInventory::new(backend.clone(), Duration::from_secs(300));
+
+        assert!(inventory.cached().is_none());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(inventory.get().await.unwrap().len(), 1);
+        assert_eq!(inventory.get().await.unwrap().len(), 1);
+        assert_eq!(backend.0.load(Ordering::SeqCst), 1);
+
+        inventory.invalidate();
+        assert_eq!(inventory.get().await.unwrap().len(), 1);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(inventory.get().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stale_lists_are_served_while_refreshing() {
+        let backend = Arc::new(Listings::default());
+        let inventory = AssistantInventory::new(backend.clone(), Duration::ZERO);
+
+        assert_eq!(inventory.get().await.unwrap().len(), 1);
+        assert_eq!(inventory.get().await.unwrap().len(), 1);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(backend.0.load(Ordering::SeqCst), 2);
+    }
+}
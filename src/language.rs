@@ -0,0 +1,240 @@
+//! Language of snippets, for multilingual knowledge bases where the agent
+//! only wants passages in one language.
+//!
+//! A `language` field in the upload metadata of the snippet's file wins;
+//! otherwise the language is detected from the text, by script for
+//! non-Latin scripts and by common function words for Latin ones. Text too
+//! short or ambiguous to tell is left undetermined.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Upload metadata field holding the ISO 639-1 code of a file's language.
+pub const METADATA_LANGUAGE: &str = "language";
+
+/// What to do with snippets in another language.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LanguageMode {
+    /// Drop them; snippets of undetermined language are kept.
+    #[default]
+    Filter,
+    /// Keep every snippet, adding its language, if determined.
+    Flag,
+}
+
+/// Common function words of the Latin-script languages that are detected.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "is", "in", "that", "for", "with", "are", "this", "be", "on",
+            "not", "or", "by", "from", "it", "you", "your",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "mit", "den", "ein", "eine", "zu", "auf",
+            "für", "sie", "von", "wird", "werden", "sich", "dem", "auch",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "est", "des", "une", "un", "du", "pour", "dans", "que", "qui",
+            "pas", "sur", "avec", "sont", "vous", "nous", "au",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "es", "una", "del", "para", "con", "que", "por", "como",
+            "está", "son", "pero", "su", "al", "se", "lo", "más",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "di", "che", "è", "gli", "della", "per", "una", "sono", "con", "non", "del",
+            "alla", "nel", "questo", "anche", "come", "le", "si", "più",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "e", "é", "do", "da", "das", "dos", "uma", "para", "com", "não", "que",
+            "em", "no", "na", "por", "mais", "como", "seu",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "is", "van", "niet", "dat", "op", "te", "zijn", "voor",
+            "met", "worden", "wordt", "ook", "bij", "naar", "deze", "je",
+        ],
+    ),
+];
+
+/// Function words a text needs before a Latin-script language is assumed.
+const MIN_STOPWORD_HITS: usize = 2;
+
+/// Normalizes a requested language, e.g. `EN` or `en-US`, to its ISO 639-1
+/// code.
+pub fn normalize(language: &str) -> String {
+    let language = language.trim().to_ascii_lowercase();
+    match language.split_once(['-', '_']) {
+        Some((code, _)) => code.to_string(),
+        None => language,
+    }
+}
+
+/// The language of a snippet: from its file's metadata, else detected.
+pub fn of_snippet(snippet: &Value) -> Option<String> {
+    let metadata = &snippet["reference"]["file"]["metadata"];
+    if let Some(language) = metadata.get(METADATA_LANGUAGE).and_then(Value::as_str) {
+        return Some(normalize(language));
+    }
+    match &snippet["content"] {
+        Value::String(text) => detect(text).map(str::to_string),
+        Value::Array(blocks) => {
+            let text: Vec<&str> = blocks
+                .iter()
+                .filter_map(|block| block["text"].as_str())
+                .collect();
+            detect(&text.join(" ")).map(str::to_string)
+        }
+        _ => None,
+    }
+}
+
+/// Keeps only snippets in `language` (and those of undetermined language),
+/// or flags each snippet with its language. Returns the snippets kept and
+/// how many were dropped.
+pub fn apply(snippets: Vec<Value>, language: &str, mode: LanguageMode) -> (Vec<Value>, usize) {
+    let language = normalize(language);
+    let total = snippets.len();
+    let kept: Vec<Value> = snippets
+        .into_iter()
+        .filter_map(|mut snippet| {
+            let detected = of_snippet(&snippet);
+            match mode {
+                LanguageMode::Filter => detected
+                    .is_none_or(|detected| detected == language)
+                    .then_some(snippet),
+                LanguageMode::Flag => {
+                    if let (Some(object), Some(detected)) = (snippet.as_object_mut(), detected) {
+                        object.insert("language".to_string(), detected.into());
+                    }
+                    Some(snippet)
+                }
+            }
+        })
+        .collect();
+    let dropped = total - kept.len();
+    (kept, dropped)
+}
+
+/// Detects the language of a text, if it is clear enough.
+pub fn detect(text: &str) -> Option<&'static str> {
+    if let Some(language) = detect_script(text) {
+        return Some(language);
+    }
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut scores: Vec<(&str, usize)> = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|word| stopwords.contains(&word.as_str()))
+                .count();
+            (*language, hits)
+        })
+        .collect();
+    scores.sort_by_key(|&(_, hits)| std::cmp::Reverse(hits));
+    let (best, hits) = scores[0];
+    // A tie means the text is too short or mixed to tell.
+    (hits >= MIN_STOPWORD_HITS && hits > scores[1].1).then_some(best)
+}
+
+/// Detects languages written in their own script, when most letters are in
+/// it.
+fn detect_script(text: &str) -> Option<&'static str> {
+    let mut letters = 0;
+    let mut counts = [0usize; 8];
+    const LANGUAGES: [&str; 8] = ["ja", "zh", "ko", "ru", "el", "ar", "he", "hi"];
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        let index = match c as u32 {
+            0x3040..=0x30FF => 0,
+            0x4E00..=0x9FFF => 1,
+            0xAC00..=0xD7AF | 0x1100..=0x11FF => 2,
+            0x0400..=0x04FF => 3,
+            0x0370..=0x03FF => 4,
+            0x0600..=0x06FF => 5,
+            0x0590..=0x05FF => 6,
+            0x0900..=0x097F => 7,
+            _ => continue,
+        };
+        counts[index] += 1;
+    }
+    // Japanese mixes kana with Han characters.
+    if counts[0] > 0 {
+        counts[0] += counts[1];
+    }
+    let (index, count) = counts.iter().enumerate().max_by_key(|(_, count)| **count)?;
+    (*count > 0 && *count * 2 > letters).then_some(LANGUAGES[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detects_common_languages() {
+        assert_eq!(
+            detect("The refund is issued to the card used for the purchase."),
+            Some("en")
+        );
+        assert_eq!(
+            detect("Die Erstattung wird auf die Karte gebucht, mit der bezahlt wurde."),
+            Some("de")
+        );
+        assert_eq!(
+            detect("Le remboursement est effectué sur la carte utilisée pour l'achat."),
+            Some("fr")
+        );
+        assert_eq!(detect("Возврат средств производится на карту."), Some("ru"));
+        assert_eq!(
+            detect("払い戻しは購入に使用したカードに行われます。"),
+            Some("ja")
+        );
+        assert_eq!(detect("Refund policy"), None);
+    }
+
+    #[test]
+    fn test_filter_keeps_matching_and_undetermined_snippets() {
+        let snippets = vec![
+            json!({"content": "The refund is issued to the card used for the purchase."}),
+            json!({"content": "Die Erstattung wird auf die Karte gebucht, mit der bezahlt wurde."}),
+            json!({"content": "SKU-1234"}),
+            json!({"content": "x", "reference": {"file": {"metadata": {"language": "de-DE"}}}}),
+        ];
+
+        let (kept, dropped) = apply(snippets.clone(), "EN", LanguageMode::Filter);
+        let (flagged, _) = apply(snippets, "en", LanguageMode::Flag);
+
+        assert_eq!(dropped, 2);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[1]["content"], "SKU-1234");
+        assert_eq!(flagged.len(), 4);
+        assert_eq!(flagged[1]["language"], "de");
+        assert!(flagged[2].get("language").is_none());
+        assert_eq!(flagged[3]["language"], "de");
+    }
+}
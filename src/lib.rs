@@ -1,6 +1,61 @@
+//! Pinecone Assistant MCP server and client library.
+//!
+//! The items re-exported from the crate root form the public API and follow
+//! semantic versioning: [`PineconeClient`] and its request/response models,
+//! the [`ContextBackend`] extension point, [`PineconeAssistantRouter`] with
+//! its [`Config`], and the [`Prefixed`]/[`Merged`] combinators for embedding
+//! the router in a larger MCP server. Error enums and [`Config`] are
+//! `#[non_exhaustive]` so new variants and options can be added in minor
+//! releases. Everything else is an implementation detail.
+
+pub(crate) mod admission;
+pub(crate) mod alerting;
+pub mod backend;
+pub mod compose;
 pub mod config;
+pub(crate) mod failover;
+pub(crate) mod file_watch;
+pub(crate) mod gauge;
+pub(crate) mod inventory;
+pub(crate) mod language;
+pub mod logging;
+pub(crate) mod manifest;
+pub(crate) mod merge;
+pub(crate) mod names;
+pub(crate) mod options;
+pub(crate) mod output;
+pub(crate) mod pagination;
+pub(crate) mod params;
+pub(crate) mod payloads;
+pub mod peer;
 pub mod pinecone;
+pub(crate) mod privacy;
+pub(crate) mod query;
+pub mod reporting;
+pub(crate) mod retry_budget;
 pub mod router;
+pub(crate) mod routing;
+pub(crate) mod scrub;
+pub(crate) mod semantic_cache;
+pub mod server;
+pub(crate) mod session;
+pub(crate) mod snapshots;
+pub(crate) mod spool;
+pub(crate) mod tenants;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub(crate) mod uploads;
+pub mod usage;
 
-pub use pinecone::PineconeClient;
-pub use router::PineconeAssistantRouter;
+pub use alerting::ErrorAlertConfig;
+pub use backend::{BackendFuture, ContextBackend};
+pub use compose::{Merged, Prefixed};
+pub use config::{Config, ConfigError, EnvNames, StartupCheck};
+pub use pinecone::{
+    Assistant, AssistantChat, AssistantChatResponse, AssistantContext, AssistantContextResponse,
+    AssistantFile, AssistantSettings, ChatDeltas, HttpOptions, HttpVersion, Message,
+    PineconeClient, PineconeError, Region, RetryPolicy,
+};
+pub use router::{PineconeAssistantRouter, RouterError};
+pub use semantic_cache::SemanticCacheConfig;
+pub use usage::Pricing;
@@ -0,0 +1,433 @@
+//! Log outputs besides stderr: a file rotated by day or size, for MCP hosts
+//! that discard the server's stderr, and the system log when run as a
+//! service.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::config::EnvNames;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    /// Start a new file each day (UTC); old files get the date as suffix.
+    Daily,
+    /// Start a new file once the current one exceeds this many bytes; old
+    /// files get the suffixes `.1` (newest) to `.N`.
+    Size(u64),
+    Never,
+}
+
+#[derive(Clone, Debug)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub rotation: Rotation,
+    /// Rotated files kept besides the current one.
+    pub max_files: usize,
+}
+
+impl LogFileConfig {
+    /// Reads the log file settings, if a log file is configured. Read apart
+    /// from [`crate::Config`] because logging starts before it is loaded.
+    pub fn from_env() -> Option<Self> {
+        const LOG_FILE: &str = "LOG_FILE";
+        const LOG_FILE_ROTATION: &str = "LOG_FILE_ROTATION";
+        const LOG_FILE_MAX_BYTES: &str = "LOG_FILE_MAX_BYTES";
+        const LOG_FILE_MAX_FILES: &str = "LOG_FILE_MAX_FILES";
+
+        let names = EnvNames::from_env();
+
+        let path = names
+            .var(LOG_FILE)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from)?;
+
+        let max_bytes = names
+            .var(LOG_FILE_MAX_BYTES)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(10 * 1024 * 1024);
+
+        let rotation = match names
+            .var(LOG_FILE_ROTATION)
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "size" => Rotation::Size(max_bytes),
+            "never" => Rotation::Never,
+            _ => Rotation::Daily,
+        };
+
+        let max_files = names
+            .var(LOG_FILE_MAX_FILES)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(7);
+
+        Some(Self {
+            path,
+            rotation,
+            max_files,
+        })
+    }
+}
+
+/// Name logs are tagged with in the system log.
+#[cfg(any(feature = "journald", feature = "syslog"))]
+const SYSLOG_IDENTIFIER: &std::ffi::CStr = c"assistant-mcp";
+
+/// System log service to send logs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemLog {
+    Journald,
+    Syslog,
+}
+
+impl SystemLog {
+    pub fn from_env() -> Option<Self> {
+        const LOG_SYSTEM: &str = "LOG_SYSTEM";
+
+        let names = EnvNames::from_env();
+
+        match names
+            .var(LOG_SYSTEM)
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "journald" => Some(Self::Journald),
+            "syslog" => Some(Self::Syslog),
+            _ => None,
+        }
+    }
+
+    /// A layer sending logs to the service. Fails if the service can't be
+    /// reached or support for it was not compiled in (features `journald`
+    /// and `syslog`).
+    pub fn layer(self) -> io::Result<Box<dyn Layer<Registry> + Send + Sync>> {
+        match self {
+            Self::Journald => journald_layer(),
+            Self::Syslog => syslog_layer(),
+        }
+    }
+}
+
+#[cfg(feature = "journald")]
+fn journald_layer() -> io::Result<Box<dyn Layer<Registry> + Send + Sync>> {
+    let layer = tracing_journald::layer()?
+        .with_syslog_identifier(SYSLOG_IDENTIFIER.to_string_lossy().into_owned());
+    Ok(Box::new(layer))
+}
+
+#[cfg(not(feature = "journald"))]
+fn journald_layer() -> io::Result<Box<dyn Layer<Registry> + Send + Sync>> {
+    Err(not_built_with("journald"))
+}
+
+#[cfg(feature = "syslog")]
+fn syslog_layer() -> io::Result<Box<dyn Layer<Registry> + Send + Sync>> {
+    // SAFETY: the identifier is a 'static C string, as openlog keeps a
+    // pointer to it rather than a copy.
+    unsafe { libc::openlog(SYSLOG_IDENTIFIER.as_ptr(), libc::LOG_PID, libc::LOG_DAEMON) };
+    // syslog records the time and program itself.
+    let layer = tracing_subscriber::fmt::layer()
+        .without_time()
+        .with_ansi(false)
+        .with_writer(syslog::Syslog);
+    Ok(Box::new(layer))
+}
+
+#[cfg(feature = "syslog")]
+mod syslog {
+    use std::ffi::CString;
+    use std::io::{self, Write};
+
+    use tracing::{Level, Metadata};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// Writes each formatted event as one syslog message.
+    pub struct Syslog;
+
+    pub struct SyslogWriter {
+        priority: libc::c_int,
+    }
+
+    impl Write for SyslogWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let text = String::from_utf8_lossy(buf);
+            let message = CString::new(text.trim_end().replace('\0', " "))?;
+            // SAFETY: both arguments are valid C strings, and the message is
+            // passed as an argument rather than as the format.
+            unsafe { libc::syslog(self.priority, c"%s".as_ptr(), message.as_ptr()) };
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Syslog {
+        type Writer = SyslogWriter;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            SyslogWriter {
+                priority: libc::LOG_INFO,
+            }
+        }
+
+        fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+            let priority = match *meta.level() {
+                Level::ERROR => libc::LOG_ERR,
+                Level::WARN => libc::LOG_WARNING,
+                Level::INFO => libc::LOG_INFO,
+                Level::DEBUG | Level::TRACE => libc::LOG_DEBUG,
+            };
+            SyslogWriter { priority }
+        }
+    }
+}
+
+#[cfg(not(feature = "syslog"))]
+fn syslog_layer() -> io::Result<Box<dyn Layer<Registry> + Send + Sync>> {
+    Err(not_built_with("syslog"))
+}
+
+#[cfg(not(all(feature = "journald", feature = "syslog")))]
+fn not_built_with(feature: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("built without the {feature} feature"),
+    )
+}
+
+/// A log file that rotates itself as it is written to. Usable as the writer
+/// of a `tracing_subscriber` fmt layer.
+pub struct RotatingFile {
+    config: LogFileConfig,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    file: File,
+    size: u64,
+    /// Day (since the epoch) the current file's entries were written on.
+    day: u64,
+}
+
+impl RotatingFile {
+    pub fn open(config: LogFileConfig) -> io::Result<Self> {
+        if let Some(dir) = config.path.parent() {
+            if !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let state = Self::open_current(&config.path)?;
+        let file = Self {
+            config,
+            state: Mutex::new(state),
+        };
+        // A file left over from an earlier day is rotated right away.
+        file.rotate_if_due(&mut file.lock(), 0)?;
+        Ok(file)
+    }
+
+    fn open_current(path: &Path) -> io::Result<FileState> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        let day = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or_else(today, |d| d.as_secs() / SECONDS_PER_DAY);
+        Ok(FileState {
+            file,
+            size: metadata.len(),
+            day,
+        })
+    }
+
+    /// Rotates before a write of `incoming` bytes if the current file is
+    /// full or from another day.
+    fn rotate_if_due(&self, state: &mut FileState, incoming: usize) -> io::Result<()> {
+        let path = &self.config.path;
+        match self.config.rotation {
+            Rotation::Never => return Ok(()),
+            Rotation::Daily => {
+                if state.day == today() {
+                    return Ok(());
+                }
+                let rotated = suffixed(path, &civil_date(state.day));
+                std::fs::rename(path, rotated)?;
+                self.prune_dated()?;
+            }
+            Rotation::Size(max_bytes) => {
+                if state.size == 0 || state.size + incoming as u64 <= max_bytes {
+                    return Ok(());
+                }
+                // Shift .1 to .2 and so on, dropping the oldest.
+                for n in (1..=self.config.max_files).rev() {
+                    let from = if n == 1 {
+                        path.clone()
+                    } else {
+                        suffixed(path, &(n - 1).to_string())
+                    };
+                    if from.exists() {
+                        std::fs::rename(&from, suffixed(path, &n.to_string()))?;
+                    }
+                }
+                if self.config.max_files == 0 {
+                    std::fs::remove_file(path)?;
+                }
+            }
+        }
+        *state = Self::open_current(path)?;
+        state.day = today();
+        Ok(())
+    }
+
+    /// Removes the oldest dated files beyond the configured number.
+    fn prune_dated(&self) -> io::Result<()> {
+        let path = &self.config.path;
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            return Ok(());
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let prefix = format!("{}.", name.to_string_lossy());
+        let mut rotated: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|entry| entry.path())
+            .collect();
+        // Dates sort lexically.
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.config.max_files);
+        for old in &rotated[..excess] {
+            std::fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FileState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Write for &RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if let Err(e) = self.rotate_if_due(&mut state, buf.len()) {
+            // Keep logging to the current file rather than losing entries.
+            eprintln!("Failed to rotate log file: {e}");
+        }
+        let written = state.file.write(buf)?;
+        state.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().file.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingFile {
+    type Writer = &'a RotatingFile;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+pub(crate) fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / SECONDS_PER_DAY)
+}
+
+/// Days from the epoch to a date (proleptic Gregorian).
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Howard Hinnant's days_from_civil.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// `YYYY-MM-DD` of a day counted from the epoch (proleptic Gregorian, UTC).
+pub(crate) fn civil_date(days: u64) -> String {
+    // Howard Hinnant's days_from_civil, inverted.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!(civil_date(0), "1970-01-01");
+        assert_eq!(civil_date(11_016), "2000-02-29");
+        assert_eq!(civil_date(20_742), "2026-10-16");
+    }
+
+    #[test]
+    fn test_size_rotation_keeps_max_files() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let path = dir.join("server.log");
+        let file = RotatingFile::open(LogFileConfig {
+            path: path.clone(),
+            rotation: Rotation::Size(10),
+            max_files: 2,
+        })
+        .unwrap();
+        for line in ["first ..\n", "second .\n", "third ..\n", "fourth .\n"] {
+            (&file).write_all(line.as_bytes()).unwrap();
+        }
+        let current = std::fs::read_to_string(&path).unwrap();
+        let newest = std::fs::read_to_string(suffixed(&path, "1")).unwrap();
+        let oldest = std::fs::read_to_string(suffixed(&path, "2")).unwrap();
+        let dropped = suffixed(&path, "3").exists();
+
+        assert_eq!(current, "fourth .\n");
+        assert_eq!(newest, "third ..\n");
+        assert_eq!(oldest, "second .\n");
+        assert!(!dropped);
+    }
+}
@@ -1,46 +1,146 @@
-use assistant_mcp::config::Config;
-use assistant_mcp::router::PineconeAssistantRouter;
+mod bench;
+mod cli;
+mod eval;
+
+use assistant_mcp::logging::{LogFileConfig, RotatingFile, SystemLog};
+use assistant_mcp::reporting::{self, Reporting};
+use assistant_mcp::server::{self, ServerError};
+use assistant_mcp::{Config, ConfigError, PineconeAssistantRouter, StartupCheck};
+use clap::Parser;
+use cli::Cli;
 use is_terminal::IsTerminal;
-use mcp_server::router::RouterService;
-use mcp_server::{ByteTransport, Server, ServerError};
+use std::time::Duration;
 use thiserror::Error;
 use tokio::io::{stdin, stdout};
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, fmt};
+
+/// How long each startup probe of Pinecone may take, so an unreachable API
+/// can't keep the server from starting.
+const STARTUP_PROBE_TIMEOUT: Duration = Duration::from_secs(15);
 
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("Configuration error: {0}")]
+    Config(#[from] ConfigError),
+
     #[error("MCP server error: {0}")]
     Server(#[from] ServerError),
+
+    #[error("Startup check failed: {0}")]
+    StartupCheck(String),
 }
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-            "info,assistant_mcp=debug"
-                .parse()
-                .expect("Invalid default filter")
+    let cli = Cli::parse();
+    // Held until exit so queued error reports are flushed.
+    let (_reporting, reporting_error) = match Reporting::from_env() {
+        Some(Ok(reporting)) => (Some(reporting), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    // Many hosts discard stderr, so logs can also go to a file.
+    let (log_file, log_file_error) = match LogFileConfig::from_env().map(RotatingFile::open) {
+        Some(Ok(file)) => (Some(file), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let (system_log, system_log_error) = match SystemLog::from_env().map(SystemLog::layer) {
+        Some(Ok(layer)) => (Some(layer), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(system_log)
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+            // Commands print their own output; only problems are logged.
+            let default = if cli.command.is_some() {
+                "warn"
+            } else {
+                "info,assistant_mcp=debug"
+            };
+            default.parse().expect("Invalid default filter")
+        }))
+        .with(
+            fmt::layer()
+                .with_target(true)
+                .with_thread_ids(true)
+                .with_file(true)
+                .with_line_number(true)
+                .with_ansi(std::io::stderr().is_terminal())
+                .with_writer(std::io::stderr),
+        )
+        .with(log_file.map(|file| {
+            fmt::layer()
+                .with_target(true)
+                .with_thread_ids(true)
+                .with_file(true)
+                .with_line_number(true)
+                .with_ansi(false)
+                .with_writer(file)
         }))
-        .with_target(true)
-        .with_thread_ids(true)
-        .with_file(true)
-        .with_line_number(true)
-        .with_ansi(std::io::stderr().is_terminal())
-        .with_writer(std::io::stderr)
         .init();
+    if let Some(e) = log_file_error {
+        tracing::warn!("Failed to open log file, logging to stderr only: {e}");
+    }
+    if let Some(e) = system_log_error {
+        tracing::warn!("Failed to connect to the system log: {e}");
+    }
+    if !reporting::telemetry_enabled() {
+        tracing::info!("Telemetry is off; no error reports are sent");
+    }
+    if let Some(e) = reporting_error {
+        tracing::warn!("Failed to start Sentry error reporting: {e}");
+    }
+
+    if let Some(command) = cli.command {
+        if !command.run().await {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     tracing::info!("Starting Pinecone MCP server");
 
-    let config = Config::from_env();
+    let config = Config::try_from_env()?;
     tracing::info!("Configuration loaded successfully");
+    let startup_check = config.startup_check;
 
-    let router = RouterService(PineconeAssistantRouter::new(config));
-    let server = Server::new(router);
-    let transport = ByteTransport::new(stdin(), stdout());
+    let mut router = PineconeAssistantRouter::new(config);
+    if startup_check != StartupCheck::Off {
+        let problems = tokio::time::timeout(STARTUP_PROBE_TIMEOUT, router.self_test())
+            .await
+            .unwrap_or_else(|_| vec![format!("No answer within {STARTUP_PROBE_TIMEOUT:?}")]);
+        for problem in &problems {
+            tracing::error!("Startup check: {problem}");
+        }
+        if problems.is_empty() {
+            tracing::info!("Startup check passed");
+        } else if startup_check == StartupCheck::Strict {
+            return Err(AppError::StartupCheck(problems.join("; ")));
+        }
+    }
+    if tokio::time::timeout(STARTUP_PROBE_TIMEOUT, router.load_assistant_instructions())
+        .await
+        .is_err()
+    {
+        tracing::warn!("Gave up loading the default assistant's instructions");
+    }
+    if tokio::time::timeout(STARTUP_PROBE_TIMEOUT, router.detect_only_assistant())
+        .await
+        .is_err()
+    {
+        tracing::warn!("Gave up listing assistants to find the only one");
+    }
 
     tracing::info!("Server initialized and ready to handle requests");
-    server.run(transport).await.map_err(AppError::from)
+    server::serve(router.clone(), stdin(), stdout()).await?;
+    tracing::info!("Client disconnected, shutting down");
+    router.shutdown().await;
+    Ok(())
 }
@@ -0,0 +1,136 @@
+//! Portable description of an assistant and its files, for backup and
+//! migration between projects.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::pinecone::{Assistant, AssistantFile};
+use crate::uploads::METADATA_SHA256;
+
+/// Format version written to manifests; bumped on incompatible changes.
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Seconds since the epoch.
+    pub exported_at: u64,
+    pub assistant: ManifestAssistant,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestAssistant {
+    pub name: String,
+    #[serde(default)]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub name: String,
+    /// Id of the file in the exported assistant, for reference only.
+    pub id: String,
+    /// SHA-256 of the content, if it was recorded at upload.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Metadata given at upload, without the content hash.
+    #[serde(default)]
+    pub metadata: Map<String, Value>,
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+impl Manifest {
+    /// Reads a manifest written by `export_assistant`.
+    pub async fn read(path: &Path) -> Result<Self, String> {
+        let data = tokio::fs::read(path)
+            .await
+            .map_err(|e| format!("Cannot read manifest {}: {e}", path.display()))?;
+        let manifest: Self = serde_json::from_slice(&data)
+            .map_err(|e| format!("Invalid manifest {}: {e}", path.display()))?;
+        if manifest.version > MANIFEST_VERSION {
+            return Err(format!(
+                "Manifest {} has version {}; this server reads up to version {MANIFEST_VERSION}",
+                path.display(),
+                manifest.version
+            ));
+        }
+        Ok(manifest)
+    }
+
+    pub fn new(assistant: Assistant, files: Vec<AssistantFile>) -> Self {
+        let files = files
+            .into_iter()
+            .map(|file| {
+                let mut metadata = match file.metadata {
+                    Some(Value::Object(metadata)) => metadata,
+                    _ => Map::new(),
+                };
+                let sha256 = match metadata.remove(METADATA_SHA256) {
+                    Some(Value::String(hash)) => Some(hash),
+                    _ => None,
+                };
+                ManifestFile {
+                    name: file.name,
+                    id: file.id,
+                    sha256,
+                    metadata,
+                    status: file.status,
+                }
+            })
+            .collect();
+        Self {
+            version: MANIFEST_VERSION,
+            exported_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            assistant: ManifestAssistant {
+                name: assistant.name,
+                instructions: assistant.instructions,
+                metadata: assistant.metadata,
+            },
+            files,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_manifest_separates_hash_from_metadata() {
+        let assistant: Assistant = serde_json::from_value(json!({
+            "name": "kb",
+            "instructions": "Be brief.",
+            "host": "https://example.pinecone.io"
+        }))
+        .unwrap();
+        let file: AssistantFile = serde_json::from_value(json!({
+            "name": "doc.md",
+            "id": "f1",
+            "metadata": {METADATA_SHA256: "abc", "owner": "support"},
+            "signed_url": "https://example.com/expiring"
+        }))
+        .unwrap();
+
+        let manifest = serde_json::to_value(Manifest::new(assistant, vec![file])).unwrap();
+
+        assert_eq!(manifest["version"], MANIFEST_VERSION);
+        assert_eq!(manifest["assistant"]["instructions"], "Be brief.");
+        assert!(manifest["assistant"].get("host").is_none());
+        assert_eq!(manifest["files"][0]["sha256"], "abc");
+        assert_eq!(
+            manifest["files"][0]["metadata"],
+            json!({"owner": "support"})
+        );
+        assert!(manifest["files"][0].get("signed_url").is_none());
+    }
+}
@@ -0,0 +1,250 @@
+use serde_json::Value;
+
+/// Field added to merged snippets listing every assistant that returned them.
+pub const FIELD_ASSISTANTS: &str = "assistants";
+
+/// Merges the snippets returned by several assistants into one list ordered
+/// by score.
+///
+/// Assistants that share documents return identical snippets; those are
+/// collapsed into a single entry that keeps the highest score and lists all
+/// source assistants under [`FIELD_ASSISTANTS`].
+pub fn merge_snippets(results: Vec<(String, Vec<Value>)>) -> Vec<Value> {
+    let mut merged: Vec<(Value, Vec<String>)> = Vec::new();
+    for (assistant_name, snippets) in results {
+        for snippet in snippets {
+            let key = dedup_key(&snippet);
+            match merged
+                .iter_mut()
+                .find(|(existing, _)| dedup_key(existing) == key)
+            {
+                Some((existing, assistants)) => {
+                    if !assistants.contains(&assistant_name) {
+                        assistants.push(assistant_name.clone());
+                    }
+                    if score(&snippet) > score(existing) {
+                        *existing = snippet;
+                    }
+                }
+                None => merged.push((snippet, vec![assistant_name.clone()])),
+            }
+        }
+    }
+
+    merged.sort_by(|(a, _), (b, _)| score(b).total_cmp(&score(a)));
+    merged
+        .into_iter()
+        .map(|(mut snippet, assistants)| {
+            if let Value::Object(fields) = &mut snippet {
+                fields.insert(FIELD_ASSISTANTS.to_string(), assistants.into());
+            }
+            snippet
+        })
+        .collect()
+}
+
+/// Joins snippets that are contiguous chunks of the same file into one
+/// passage, so overlapping fragments are returned as coherent text.
+///
+/// Chunks of paginated documents are contiguous when their page ranges touch;
+/// other chunks when the end of one repeats the start of the other, which is
+/// how the chunker overlaps them. Merged snippets keep the position and score
+/// of their best-scoring chunk.
+pub fn merge_adjacent(snippets: Vec<Value>) -> Vec<Value> {
+    let mut merged: Vec<Value> = Vec::with_capacity(snippets.len());
+    for mut snippet in snippets {
+        let mut position: Option<usize> = None;
+        // A joined snippet may now touch another one, so keep absorbing.
+        while let Some(index) = merged
+            .iter()
+            .position(|existing| adjacent(existing, &snippet))
+        {
+            snippet = join(merged.remove(index), snippet);
+            position = Some(position.map_or(index, |p| p.min(index)));
+        }
+        match position {
+            Some(index) => merged.insert(index, snippet),
+            None => merged.push(snippet),
+        }
+    }
+    merged
+}
+
+/// Minimum repeated text for two unpaginated chunks to count as contiguous.
+const MIN_OVERLAP: usize = 20;
+/// Longest overlap looked for, bounding the cost of the comparison.
+const MAX_OVERLAP: usize = 2000;
+
+fn file_key(snippet: &Value) -> Option<&str> {
+    let file = &snippet["reference"]["file"];
+    file["id"].as_str().or_else(|| file["name"].as_str())
+}
+
+fn pages(snippet: &Value) -> Option<(u64, u64)> {
+    let pages: Vec<u64> = snippet["reference"]["pages"]
+        .as_array()?
+        .iter()
+        .filter_map(Value::as_u64)
+        .collect();
+    Some((*pages.iter().min()?, *pages.iter().max()?))
+}
+
+fn content(snippet: &Value) -> &str {
+    snippet["content"].as_str().unwrap_or_default()
+}
+
+/// Whether two snippets are contiguous chunks of the same file.
+pub fn adjacent(a: &Value, b: &Value) -> bool {
+    // Multimodal snippets carry structured content that can't be joined.
+    if !a["content"].is_string() || !b["content"].is_string() {
+        return false;
+    }
+    match (file_key(a), file_key(b)) {
+        (Some(x), Some(y)) if x == y => {}
+        _ => return false,
+    }
+    match (pages(a), pages(b)) {
+        (Some((a_first, a_last)), Some((b_first, b_last))) => {
+            a_first <= b_last + 1 && b_first <= a_last + 1
+        }
+        _ => {
+            overlap(content(a), content(b)) >= MIN_OVERLAP
+                || overlap(content(b), content(a)) >= MIN_OVERLAP
+        }
+    }
+}
+
+/// Length of the longest suffix of `first` that is a prefix of `second`.
+fn overlap(first: &str, second: &str) -> usize {
+    let longest = first.len().min(second.len()).min(MAX_OVERLAP);
+    (1..=longest)
+        .rev()
+        .find(|&len| second.is_char_boundary(len) && first.ends_with(&second[..len]))
+        .unwrap_or(0)
+}
+
+/// Combines two contiguous chunks; `best` keeps its other fields.
+fn join(a: Value, b: Value) -> Value {
+    let (best, other) = if score(&b) > score(&a) {
+        (b, a)
+    } else {
+        (a, b)
+    };
+
+    let b_follows = match (pages(&best), pages(&other)) {
+        (Some((best_first, _)), Some((other_first, _))) if best_first != other_first => {
+            other_first > best_first
+        }
+        _ => overlap(content(&best), content(&other)) >= overlap(content(&other), content(&best)),
+    };
+    let (first, second) = if b_follows {
+        (&best, &other)
+    } else {
+        (&other, &best)
+    };
+    let shared = overlap(content(first), content(second));
+    let text = if shared >= MIN_OVERLAP {
+        format!("{}{}", content(first), &content(second)[shared..])
+    } else {
+        format!("{}\n{}", content(first), content(second))
+    };
+
+    let mut joined = best.clone();
+    joined["content"] = text.into();
+    if let (Some((a_first, a_last)), Some((b_first, b_last))) = (pages(&best), pages(&other)) {
+        joined["reference"]["pages"] = (a_first.min(b_first)..=a_last.max(b_last))
+            .collect::<Vec<_>>()
+            .into();
+    }
+    if let Some(assistants) = other[FIELD_ASSISTANTS].as_array() {
+        if let Some(joined_assistants) = joined[FIELD_ASSISTANTS].as_array_mut() {
+            for assistant in assistants {
+                if !joined_assistants.contains(assistant) {
+                    joined_assistants.push(assistant.clone());
+                }
+            }
+        }
+    }
+    joined
+}
+
+/// Relevance score reported by the context API, or 0 if absent.
+pub fn score(snippet: &Value) -> f64 {
+    snippet["score"].as_f64().unwrap_or(0.0)
+}
+
+fn dedup_key(snippet: &Value) -> String {
+    match snippet["content"].as_str() {
+        Some(content) => content.trim().to_string(),
+        None => snippet.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_snippets_dedupes_across_assistants() {
+        let merged = merge_snippets(vec![
+            (
+                "docs".to_string(),
+                vec![
+                    json!({"content": "shared", "score": 0.5}),
+                    json!({"content": "only docs", "score": 0.7}),
+                ],
+            ),
+            (
+                "support".to_string(),
+                vec![json!({"content": "shared ", "score": 0.9})],
+            ),
+        ]);
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0]["content"], "shared ");
+        assert_eq!(merged[0]["score"], 0.9);
+        assert_eq!(merged[0][FIELD_ASSISTANTS], json!(["docs", "support"]));
+        assert_eq!(merged[1]["content"], "only docs");
+        assert_eq!(merged[1][FIELD_ASSISTANTS], json!(["docs"]));
+    }
+
+    #[test]
+    fn test_merge_adjacent_joins_contiguous_chunks() {
+        let file = json!({"id": "f1", "name": "guide.pdf"});
+        let merged = merge_adjacent(vec![
+            json!({"content": "page two", "score": 0.9,
+                "reference": {"file": file, "pages": [2]}}),
+            json!({"content": "elsewhere", "score": 0.8,
+                "reference": {"file": {"id": "f2"}, "pages": [3]}}),
+            json!({"content": "page one", "score": 0.7,
+                "reference": {"file": file, "pages": [1]}}),
+            json!({"content": "page nine", "score": 0.6,
+                "reference": {"file": file, "pages": [9]}}),
+        ]);
+
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0]["content"], "page one\npage two");
+        assert_eq!(merged[0]["score"], 0.9);
+        assert_eq!(merged[0]["reference"]["pages"], json!([1, 2]));
+        assert_eq!(merged[1]["content"], "elsewhere");
+        assert_eq!(merged[2]["content"], "page nine");
+    }
+
+    #[test]
+    fn test_merge_adjacent_stitches_overlapping_text() {
+        let file = json!({"name": "notes.md"});
+        let merged = merge_adjacent(vec![
+            json!({"content": "the overlap region is repeated here. And then more.",
+                "score": 0.5, "reference": {"file": file}}),
+            json!({"content": "Intro text: the overlap region is repeated here.",
+                "score": 0.4, "reference": {"file": file}}),
+        ]);
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(
+            merged[0]["content"],
+            "Intro text: the overlap region is repeated here. And then more."
+        );
+    }
+}
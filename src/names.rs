@@ -0,0 +1,104 @@
+//! Assistant names: normalization to Pinecone's naming rules, and
+//! suggestions for names that don't exist.
+
+use std::borrow::Cow;
+
+/// Longest assistant name Pinecone accepts.
+pub const MAX_NAME_LEN: usize = 63;
+
+/// Trims and lowercases a name, then checks it against Pinecone's rules:
+/// lowercase letters, digits and hyphens, starting and ending with a letter
+/// or digit. Returns why the name is invalid otherwise.
+pub fn normalize(name: &str) -> Result<Cow<'_, str>, String> {
+    let trimmed = name.trim();
+    let name = if trimmed.chars().any(|c| c.is_ascii_uppercase()) {
+        Cow::Owned(trimmed.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(trimmed)
+    };
+    if name.is_empty() {
+        return Err("the assistant name is empty".to_string());
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err(format!(
+            "assistant names are at most {MAX_NAME_LEN} characters long"
+        ));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-'))
+    {
+        return Err(format!(
+            "'{c}' is not allowed; assistant names consist of lowercase letters, digits and hyphens"
+        ));
+    }
+    if name.starts_with('-') || name.ends_with('-') {
+        return Err("assistant names cannot start or end with a hyphen".to_string());
+    }
+    Ok(name)
+}
+
+/// The existing name closest to `name`, if any is close enough to be a
+/// likely typo: a few edits away, or containing or contained in it (for
+/// names of three characters or more).
+pub fn suggest<'a>(name: &str, existing: &'a [String]) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(2);
+    existing
+        .iter()
+        .filter(|candidate| candidate.as_str() != name)
+        .map(|candidate| {
+            let contained = candidate.len().min(name.len()) >= 3
+                && (candidate.contains(name) || name.contains(candidate.as_str()));
+            let distance = if contained {
+                1
+            } else {
+                edit_distance(name, candidate)
+            };
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// Levenshtein distance between two strings, by characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizes_and_validates_names() {
+        assert_eq!(normalize("  Support-Docs ").unwrap(), "support-docs");
+        assert!(matches!(normalize("kb-1"), Ok(Cow::Borrowed("kb-1"))));
+        assert!(normalize("   ").is_err());
+        assert!(normalize("support_docs").unwrap_err().contains("'_'"));
+        assert!(normalize("-docs").is_err());
+        assert!(normalize(&"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_suggests_close_names() {
+        let existing = vec![
+            "support-docs".to_string(),
+            "engineering-wiki".to_string(),
+            "hr".to_string(),
+        ];
+        assert_eq!(suggest("suport-docs", &existing), Some("support-docs"));
+        assert_eq!(suggest("engineering", &existing), Some("engineering-wiki"));
+        assert_eq!(suggest("finance", &existing), None);
+    }
+}
@@ -0,0 +1,649 @@
+//! Description of every environment variable the server reads, for the
+//! `print-config --schema` command, so deployments can be validated and
+//! their documentation generated.
+
+use serde_json::{Value, json};
+
+use crate::config::EnvNames;
+
+/// How a variable's value is parsed.
+#[derive(Clone, Copy)]
+enum Kind {
+    String,
+    Boolean,
+    Integer,
+    Number,
+    /// Comma-separated values.
+    List,
+    /// `key=value` entries, separated by `separator`.
+    Pairs {
+        separator: char,
+    },
+    /// One of the values listed.
+    Enum(&'static [&'static str]),
+}
+
+struct ConfigOption {
+    name: &'static str,
+    kind: Kind,
+    default: Option<&'static str>,
+    required: bool,
+    /// Whether the value is a credential, masked wherever it is shown.
+    secret: bool,
+    /// Whether the variable is read under its own name, whatever the prefix.
+    fixed_name: bool,
+    description: &'static str,
+}
+
+const OPTION: ConfigOption = ConfigOption {
+    name: "",
+    kind: Kind::String,
+    default: None,
+    required: false,
+    secret: false,
+    fixed_name: false,
+    description: "",
+};
+
+const OPTIONS: &[ConfigOption] = &[
+    ConfigOption {
+        name: "ASSISTANT_MCP_ENV_PREFIX",
+        fixed_name: true,
+        description: "Prefix the other variables are read behind",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ASSISTANT_MCP_ENV_MAP",
+        kind: Kind::Pairs { separator: ',' },
+        fixed_name: true,
+        description: "Other names single variables are read from",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PINECONE_API_KEY",
+        required: true,
+        secret: true,
+        description: "Pinecone API key",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PINECONE_ASSISTANT_HOST",
+        kind: Kind::List,
+        default: Some("https://prod-1-data.ke.pinecone.io"),
+        description: "Pinecone Assistant API host; further hosts are failed over to, in order",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PINECONE_REGION",
+        kind: Kind::Enum(&["us", "eu"]),
+        default: Some("us"),
+        description: "Region the assistants were created in, selecting the default host",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "LOG_LEVEL",
+        default: Some("info"),
+        description: "Logging level",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PRIVACY_MODE",
+        kind: Kind::Boolean,
+        default: Some("false"),
+        description: "Never log query text and snippets",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PINECONE_DEFAULT_ASSISTANT",
+        description: "Assistant used when a tool call omits assistant_name",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PINECONE_CONTROL_PLANE_HOST",
+        default: Some("https://api.pinecone.io"),
+        description: "Pinecone control plane host used to look up assistants",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PINECONE_ASSISTANT_DESCRIPTIONS",
+        kind: Kind::Pairs { separator: ';' },
+        description: "Assistant descriptions the assistant of a query is selected by",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PINECONE_ASSISTANT_ALIASES",
+        kind: Kind::Pairs { separator: ',' },
+        description: "Aliases resolved to assistant names before calling Pinecone",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PINECONE_ASSISTANT_API_KEYS",
+        kind: Kind::Pairs { separator: ',' },
+        description: "Variables holding the API keys of assistants in other projects",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ALLOW_API_KEY_ARGUMENT",
+        kind: Kind::Boolean,
+        default: Some("false"),
+        description: "Let tool calls pass their own Pinecone API key",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SERVER_INSTRUCTIONS",
+        description: "Replacement for the generic server instructions",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "TOOL_DESCRIPTIONS",
+        kind: Kind::Pairs { separator: ';' },
+        description: "Replacement tool descriptions, by tool name",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "CHAT_MODEL",
+        description: "Generation model of chat answers when a call names none",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "CHAT_ALLOWED_MODELS",
+        kind: Kind::List,
+        description: "Generation models chat calls may use; any when unset",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "CHAT_TEMPERATURE",
+        kind: Kind::Number,
+        description: "Sampling temperature of chat answers when a call passes none",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "CHAT_MAX_TEMPERATURE",
+        kind: Kind::Number,
+        default: Some("1.0"),
+        description: "Highest sampling temperature chat calls may ask for",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "CHAT_SESSION_DIR",
+        description: "Directory where chat session histories are persisted",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SESSION_IDLE_TTL_SECS",
+        kind: Kind::Integer,
+        default: Some("3600"),
+        description: "Idle seconds after which session state is dropped; 0 keeps it",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "MAX_CONCURRENT_REQUESTS",
+        kind: Kind::Integer,
+        default: Some("8"),
+        description: "Tool calls the batch tool runs concurrently",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "MAX_CONCURRENT_TOOL_CALLS",
+        kind: Kind::Integer,
+        description: "Tool calls handled concurrently; unlimited if unset",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "TOOL_CALL_QUEUE_DEPTH",
+        kind: Kind::Integer,
+        default: Some("64"),
+        description: "Tool calls waiting for a slot before further ones are rejected",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "MAX_TIMEOUT_MS",
+        kind: Kind::Integer,
+        default: Some("120000"),
+        description: "Largest timeout_ms accepted",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "UPLOAD_CHUNK_SIZE",
+        kind: Kind::Integer,
+        default: Some("1048576"),
+        description: "Bytes of the chunks uploads are streamed in",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "UPLOAD_STATE_DIR",
+        description: "Directory where the upload journal is kept",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "UPLOAD_SPOOL_DIR",
+        description: "Directory where uploads that fail while offline are queued and retried; off when unset",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "UPLOAD_SPOOL_RETRY_SECS",
+        kind: Kind::Integer,
+        default: Some("60"),
+        description: "Seconds between background retries of the queued uploads",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "MAX_UPLOAD_BYTES",
+        kind: Kind::Integer,
+        default: Some("104857600"),
+        description: "Largest file the upload tools accept",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "UPLOAD_ALLOWED_TYPES",
+        kind: Kind::List,
+        default: Some("pdf,txt,md,json,docx"),
+        description: "File extensions and MIME types the upload tools accept",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "UPLOAD_ROOTS",
+        kind: Kind::List,
+        description: "Directories whose files tool calls may upload, verify and sync, besides the client's roots",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "EXPORT_DIR",
+        description: "Directory where export_assistant may write manifests; inline only when unset",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "LOG_FILE",
+        description: "File to write logs to in addition to stderr",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "LOG_FILE_ROTATION",
+        kind: Kind::Enum(&["daily", "size", "never"]),
+        default: Some("daily"),
+        description: "When a new log file is started",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "LOG_FILE_MAX_BYTES",
+        kind: Kind::Integer,
+        default: Some("10485760"),
+        description: "Size at which size rotation starts a new log file",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "LOG_FILE_MAX_FILES",
+        kind: Kind::Integer,
+        default: Some("7"),
+        description: "Rotated log files kept",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "LOG_SYSTEM",
+        kind: Kind::Enum(&["journald", "syslog"]),
+        description: "System log to also send logs to",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SENTRY_DSN",
+        secret: true,
+        description: "Sentry DSN to report panics and Pinecone API errors to",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "TELEMETRY",
+        kind: Kind::Enum(&["on", "off"]),
+        default: Some("on"),
+        description: "off to keep every error report and other telemetry in the server",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "MAX_RESPONSE_BYTES",
+        kind: Kind::Integer,
+        default: Some("1048576"),
+        description: "Cap on the snippet bytes of one assistant_context call; 0 disables it",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "HTTP_VERSION",
+        kind: Kind::Enum(&["auto", "http1", "http2"]),
+        default: Some("auto"),
+        description: "HTTP version used to talk to Pinecone",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "HTTP_POOL_MAX_IDLE",
+        kind: Kind::Integer,
+        default: Some("32"),
+        description: "Idle connections kept open per host",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "HTTP_POOL_IDLE_TIMEOUT_SECS",
+        kind: Kind::Integer,
+        default: Some("90"),
+        description: "Seconds an idle connection is kept; 0 keeps it indefinitely",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "HTTP_KEEP_ALIVE_SECS",
+        kind: Kind::Integer,
+        default: Some("30"),
+        description: "Interval of keep-alive probes and pings; 0 disables them",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "HTTP_CONNECT_TIMEOUT_SECS",
+        kind: Kind::Integer,
+        default: Some("10"),
+        description: "Seconds connecting to Pinecone may take; 0 waits indefinitely",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "HTTP_REQUEST_TIMEOUT_SECS",
+        kind: Kind::Integer,
+        default: Some("300"),
+        description: "Seconds a whole request to Pinecone may take; 0 waits indefinitely",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "RETRY_STATUSES",
+        kind: Kind::List,
+        default: Some("429,502,503,504"),
+        description: "HTTP statuses on which reads and context queries are retried with exponential backoff",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "MAX_RETRIES",
+        kind: Kind::Integer,
+        default: Some("2"),
+        description: "Retries of a request after its first attempt",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "RETRY_BUDGET_PERCENT",
+        kind: Kind::Integer,
+        default: Some("20"),
+        description: "Retries allowed as a percentage of the requests over the budget window",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "RETRY_BUDGET_WINDOW_SECS",
+        kind: Kind::Integer,
+        default: Some("60"),
+        description: "Window over which the retry budget counts requests and retries",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "STARTUP_CHECK",
+        kind: Kind::Enum(&["off", "warn", "strict"]),
+        default: Some("off"),
+        description: "Whether the configured assistants are checked at startup",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PRICE_CONTEXT_TOKENS",
+        kind: Kind::Number,
+        default: Some("5"),
+        description: "US dollars per million context tokens",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PRICE_CHAT_INPUT_TOKENS",
+        kind: Kind::Number,
+        default: Some("8"),
+        description: "US dollars per million chat input tokens",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PRICE_CHAT_OUTPUT_TOKENS",
+        kind: Kind::Number,
+        default: Some("15"),
+        description: "US dollars per million chat output tokens",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "PRICE_PER_REQUEST",
+        kind: Kind::Number,
+        default: Some("0"),
+        description: "US dollars added per API call",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "USAGE_DIR",
+        description: "Directory where daily usage rollups are written",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SEMANTIC_CACHE_EMBEDDING_URL",
+        description: "Embedding endpoint that turns on the semantic cache",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SEMANTIC_CACHE_EMBEDDING_MODEL",
+        description: "Model named in embedding requests",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SEMANTIC_CACHE_API_KEY",
+        secret: true,
+        description: "Bearer token sent with embedding requests",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SEMANTIC_CACHE_THRESHOLD",
+        kind: Kind::Number,
+        default: Some("0.95"),
+        description: "Cosine similarity from which a cached result is reused",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SEMANTIC_CACHE_TTL_SECS",
+        kind: Kind::Integer,
+        default: Some("3600"),
+        description: "Seconds semantically cached results are reused",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SEMANTIC_CACHE_MAX_ENTRIES",
+        kind: Kind::Integer,
+        default: Some("1000"),
+        description: "Semantically cached results kept",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ALERT_ERROR_RATE_PERCENT",
+        kind: Kind::Integer,
+        description: "Error rate of a tool, in percent, that raises an alert; off when unset",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ALERT_WINDOW_SECS",
+        kind: Kind::Integer,
+        default: Some("300"),
+        description: "Window over which error rates are measured",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ALERT_MIN_CALLS",
+        kind: Kind::Integer,
+        default: Some("20"),
+        description: "Calls in the window below which a tool never alerts",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ALERT_RESOLVE_MARGIN_PERCENT",
+        kind: Kind::Integer,
+        default: Some("10"),
+        description: "Percentage points below the threshold at which an alert resolves",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ALERT_COOLDOWN_SECS",
+        kind: Kind::Integer,
+        default: Some("600"),
+        description: "Seconds after resolving before a tool can alert again",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ALERT_WEBHOOK_URL",
+        secret: true,
+        description: "URL receiving a JSON POST when an error-rate alert fires or resolves",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SCRUB_PII",
+        kind: Kind::List,
+        description: "Kinds of personal data redacted from snippets: email, phone",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SCRUB_PATTERNS",
+        kind: Kind::Pairs { separator: ';' },
+        description: "Named regular expressions redacted from snippets",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "RETRIEVAL_CACHE_TTL_SECS",
+        kind: Kind::Integer,
+        default: Some("300"),
+        description: "Seconds a client's repeated retrieval is reused; 0 turns this off",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "RETRIEVAL_MEMORY_QUERIES",
+        kind: Kind::Integer,
+        default: Some("3"),
+        description: "Latest queries of a client that qualify its follow-up queries",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SERVE_STALE_SECS",
+        kind: Kind::Integer,
+        description: "Age up to which earlier results are served, marked stale, while Pinecone is down; off when unset",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "FILE_POLL_INTERVAL_SECS",
+        kind: Kind::Integer,
+        default: Some("60"),
+        description: "Seconds between relistings of the files browsed as resources; 0 turns this off",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "FILE_STATUS_INTERVAL_SECS",
+        kind: Kind::Integer,
+        default: Some("5"),
+        description: "Seconds between checks of uploads' processing status; 0 turns this off",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "ASSISTANT_INVENTORY_TTL_SECS",
+        kind: Kind::Integer,
+        default: Some("300"),
+        description: "Seconds the project's assistant list is reused",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SNAPSHOT_DIR",
+        description: "Directory where every assistant_context call is recorded",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SNAPSHOT_MAX_COUNT",
+        kind: Kind::Integer,
+        default: Some("10000"),
+        description: "Snapshots kept before the oldest are deleted; 0 keeps all",
+        ..OPTION
+    },
+    ConfigOption {
+        name: "SNAPSHOT_MAX_AGE_DAYS",
+        kind: Kind::Integer,
+        default: Some("30"),
+        description: "Days after which snapshots are deleted; 0 keeps them forever",
+        ..OPTION
+    },
+];
+
+/// The options as a JSON document: for each, the variable it is read from
+/// under `names`, its type, default and description.
+pub fn schema(names: &EnvNames) -> Value {
+    let options: Vec<Value> = OPTIONS
+        .iter()
+        .map(|option| {
+            let variable = if option.fixed_name {
+                option.name.into()
+            } else {
+                names.name(option.name)
+            };
+            let mut value = json!({
+                "name": option.name,
+                "variable": variable,
+                "type": option.kind.type_name(),
+                "required": option.required,
+                "secret": option.secret,
+                "default": option.default,
+                "description": option.description,
+            });
+            match option.kind {
+                Kind::Enum(values) => value["enum"] = json!(values),
+                Kind::Pairs { separator } => value["separator"] = json!(separator.to_string()),
+                Kind::List => value["separator"] = json!(","),
+                _ => {}
+            }
+            value
+        })
+        .collect();
+    json!({ "options": options })
+}
+
+impl Kind {
+    fn type_name(self) -> &'static str {
+        match self {
+            Self::String | Self::Enum(_) => "string",
+            Self::Boolean => "boolean",
+            Self::Integer => "integer",
+            Self::Number => "number",
+            Self::List => "list",
+            Self::Pairs { .. } => "pairs",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// The variables the README documents, from its bullets.
+    fn documented() -> BTreeSet<&'static str> {
+        let readme = include_str!("../README.md");
+        let section = readme
+            .split("### Environment Variables")
+            .nth(1)
+            .and_then(|rest| rest.split("\n## ").next())
+            .unwrap();
+        section
+            .lines()
+            .filter_map(|line| line.strip_prefix("- `"))
+            .filter_map(|line| line.split(" (").next())
+            .flat_map(|names| names.split(", "))
+            .map(|name| name.trim_matches('`'))
+            .collect()
+    }
+
+    #[test]
+    fn test_schema_covers_the_documented_variables() {
+        let described: BTreeSet<&str> = OPTIONS
+            .iter()
+            .filter(|option| !option.fixed_name)
+            .map(|option| option.name)
+            .collect();
+        assert_eq!(described, documented());
+
+        let schema = schema(&EnvNames::with_prefix("ACME_"));
+        assert_eq!(schema["options"][0]["variable"], "ASSISTANT_MCP_ENV_PREFIX");
+        assert_eq!(schema["options"][2]["variable"], "ACME_PINECONE_API_KEY");
+        assert_eq!(schema["options"][4]["enum"], json!(["us", "eu"]));
+    }
+}
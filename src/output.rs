@@ -0,0 +1,513 @@
+//! Rendering of retrieval results into MCP tool content.
+
+use std::time::Duration;
+
+use mcp_spec::content::Content;
+use mcp_spec::resource::ResourceContents;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::merge;
+
+/// URI under which the JSON schema of `response_format: "json"` results is
+/// published as a resource.
+pub const ASSISTANT_CONTEXT_SCHEMA_URI: &str = "pinecone://schemas/assistant_context";
+
+/// URI prefix of the resources describing files cited in snippets.
+pub const FILE_RESOURCE_PREFIX: &str = "pinecone://files/";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    /// One text content item per snippet.
+    #[default]
+    Text,
+    /// A single text content item holding a JSON document that conforms to
+    /// [`assistant_context_schema`].
+    Json,
+}
+
+/// How the citations of chat answers are rendered.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CitationStyle {
+    /// The answer as written, followed by a text content item holding the
+    /// citations as JSON.
+    #[default]
+    List,
+    /// Numeric markers like `[1]` where the answer cites a source, and the
+    /// numbered sources after the answer.
+    Inline,
+    /// Markdown footnote markers like `[^1]`, and the footnotes naming the
+    /// sources after the answer.
+    Footnotes,
+}
+
+/// JSON schema describing `assistant_context` results in JSON format.
+pub fn assistant_context_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "type": "object",
+        "properties": {
+            "snippets": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "type": { "type": "string" },
+                        "content": { "type": "string" },
+                        "score": { "type": "number" },
+                        "reference": {
+                            "type": "object",
+                            "description": "Source of the snippet: the file and, for paginated documents, the pages"
+                        },
+                        "assistants": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Assistants that returned the snippet (multi-assistant queries only)"
+                        },
+                        "language": {
+                            "type": "string",
+                            "description": "ISO 639-1 code of the snippet's language, when flagged with language_mode 'flag' and determined"
+                        },
+                        "highlights": {
+                            "description": "Spans of the snippet matching the query, as returned by Pinecone (include_highlights only)"
+                        }
+                    },
+                    "required": ["content"]
+                }
+            },
+            "usage": {
+                "type": "object",
+                "description": "Token usage reported by Pinecone (single-assistant queries only)"
+            },
+            "omitted": {
+                "type": "integer",
+                "description": "Snippets left out to fit context_budget_chars"
+            },
+            "truncated": {
+                "type": "object",
+                "description": "Present when the lowest-scoring snippets were dropped to stay under the response size limit",
+                "properties": {
+                    "dropped_snippets": { "type": "integer" },
+                    "reason": { "type": "string" }
+                }
+            },
+            "snapshot": {
+                "type": "string",
+                "description": "URI of the resource recording this retrieval (when snapshots are enabled)"
+            },
+            "next_cursor": {
+                "type": "string",
+                "description": "Pass as 'cursor' to get the next page of snippets (paged results only)"
+            },
+            "stale": {
+                "type": "object",
+                "description": "Present when Pinecone is unavailable and the snippets of an earlier identical retrieval are served instead",
+                "properties": {
+                    "age_secs": { "type": "integer" },
+                    "reason": { "type": "string" }
+                }
+            }
+        },
+        "required": ["snippets"]
+    })
+}
+
+/// Moves the image data out of a multimodal snippet into image content,
+/// leaving the caption and MIME type in place.
+fn take_images(snippet: &mut Value) -> Vec<Content> {
+    let Some(blocks) = snippet["content"].as_array_mut() else {
+        return Vec::new();
+    };
+    let mut images = Vec::new();
+    for block in blocks {
+        let image = &mut block["image"];
+        if let (Some(data), Some(mime_type)) = (image["data"].as_str(), image["mime_type"].as_str())
+        {
+            images.push(Content::image(data, mime_type));
+            if let Value::Object(fields) = image {
+                fields.remove("data");
+            }
+        }
+    }
+    images
+}
+
+/// Keeps the snippets that give the most relevance per character within
+/// `budget` characters of content, preserving their order. Returns the kept
+/// snippets and how many were left out.
+pub fn pack(snippets: Vec<Value>, budget: usize) -> (Vec<Value>, usize) {
+    let chars = |snippet: &Value| snippet["content"].as_str().map_or(0, |c| c.chars().count());
+    let mut by_value: Vec<usize> = (0..snippets.len()).collect();
+    by_value.sort_by(|&a, &b| {
+        let density = |i: usize| merge::score(&snippets[i]) / chars(&snippets[i]).max(1) as f64;
+        density(b).total_cmp(&density(a))
+    });
+
+    let mut keep = vec![false; snippets.len()];
+    let mut remaining = budget;
+    for index in by_value {
+        let size = chars(&snippets[index]);
+        if size <= remaining {
+            remaining -= size;
+            keep[index] = true;
+        }
+    }
+    let omitted = keep.iter().filter(|kept| !**kept).count();
+    let kept = snippets
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(snippet, kept)| kept.then_some(snippet))
+        .collect();
+    (kept, omitted)
+}
+
+/// Drops the lowest-scoring snippets until the rest, as serialized, fit in
+/// `max_bytes`, preserving their order. Returns the kept snippets and how
+/// many were dropped.
+pub fn fit_bytes(snippets: Vec<Value>, max_bytes: usize) -> (Vec<Value>, usize) {
+    let sizes: Vec<usize> = snippets.iter().map(|s| s.to_string().len()).collect();
+    let mut total: usize = sizes.iter().sum();
+    if total <= max_bytes {
+        return (snippets, 0);
+    }
+    let mut by_score: Vec<usize> = (0..snippets.len()).collect();
+    by_score.sort_by(|&a, &b| merge::score(&snippets[a]).total_cmp(&merge::score(&snippets[b])));
+
+    let mut keep = vec![true; snippets.len()];
+    for index in by_score {
+        if total <= max_bytes {
+            break;
+        }
+        keep[index] = false;
+        total -= sizes[index];
+    }
+    let dropped = keep.iter().filter(|kept| !**kept).count();
+    let kept = snippets
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(snippet, kept)| kept.then_some(snippet))
+        .collect();
+    (kept, dropped)
+}
+
+/// Distinct files referenced by `snippets`, keyed by their resource URI.
+pub fn cited_files(snippets: &[Value]) -> Vec<(String, Value)> {
+    let mut files: Vec<(String, Value)> = Vec::new();
+    for snippet in snippets {
+        let file = &snippet["reference"]["file"];
+        let Some(id) = file["id"].as_str() else {
+            continue;
+        };
+        let uri = format!("{FILE_RESOURCE_PREFIX}{id}");
+        if !files.iter().any(|(existing, _)| *existing == uri) {
+            files.push((uri, file.clone()));
+        }
+    }
+    files
+}
+
+/// Links a cited file's resource, carrying its metadata (name, signed URL)
+/// so hosts can show the source document without a further read.
+///
+/// `resource_link` content is not available in mcp-spec 0.1, so the link is an
+/// embedded resource whose URI is also served by `resources/read`.
+pub fn file_link(uri: &str, file: &Value) -> Content {
+    Content::resource(ResourceContents::TextResourceContents {
+        uri: uri.to_string(),
+        mime_type: Some("application/json".to_string()),
+        text: file.to_string(),
+    })
+}
+
+/// What a retrieval result says besides its snippets.
+#[derive(Debug, Default)]
+pub struct Notes<'a> {
+    /// Snippets left out to fit a context budget.
+    pub omitted: usize,
+    /// Snippets dropped to fit the response size limit.
+    pub dropped: usize,
+    /// URI of the snapshot recording the retrieval.
+    pub snapshot: Option<&'a str>,
+    /// Cursor to the next page of snippets.
+    pub next_cursor: Option<&'a str>,
+    /// Age of snippets served from an earlier retrieval because Pinecone
+    /// is unavailable.
+    pub stale: Option<Duration>,
+}
+
+/// Renders snippets (and, when known, the API usage) in the requested format,
+/// followed by the `notes` that apply.
+pub fn render(
+    snippets: Vec<Value>,
+    usage: Option<Value>,
+    notes: &Notes,
+    format: ResponseFormat,
+) -> Vec<Content> {
+    let Notes {
+        omitted,
+        dropped,
+        snapshot,
+        next_cursor,
+        stale,
+    } = *notes;
+    match format {
+        ResponseFormat::Text => {
+            let mut contents: Vec<Content> = Vec::with_capacity(snippets.len());
+            for mut snippet in snippets {
+                let images = take_images(&mut snippet);
+                contents.push(Content::text(snippet.to_string()));
+                contents.extend(images);
+            }
+            if omitted > 0 {
+                contents.push(Content::text(format!(
+                    "{omitted} more snippets omitted to fit the context budget"
+                )));
+            }
+            if dropped > 0 {
+                contents.push(Content::text(
+                    json!({ "truncated": truncation(dropped) }).to_string(),
+                ));
+            }
+            if let Some(snapshot) = snapshot {
+                contents.push(Content::text(json!({ "snapshot": snapshot }).to_string()));
+            }
+            if let Some(cursor) = next_cursor {
+                contents.push(Content::text(json!({ "next_cursor": cursor }).to_string()));
+            }
+            if let Some(age) = stale {
+                contents.push(Content::text(
+                    json!({ "stale": staleness(age) }).to_string(),
+                ));
+            }
+            contents
+        }
+        ResponseFormat::Json => {
+            let mut document = json!({ "snippets": snippets });
+            if let Some(usage) = usage {
+                document["usage"] = usage;
+            }
+            if omitted > 0 {
+                document["omitted"] = omitted.into();
+            }
+            if dropped > 0 {
+                document["truncated"] = truncation(dropped);
+            }
+            if let Some(snapshot) = snapshot {
+                document["snapshot"] = snapshot.into();
+            }
+            if let Some(cursor) = next_cursor {
+                document["next_cursor"] = cursor.into();
+            }
+            if let Some(age) = stale {
+                document["stale"] = staleness(age);
+            }
+            vec![Content::text(document.to_string())]
+        }
+    }
+}
+
+/// Renders a chat answer with its citations in the requested style.
+pub fn render_answer(answer: String, citations: Vec<Value>, style: CitationStyle) -> Vec<Content> {
+    if citations.is_empty() {
+        return vec![Content::text(answer)];
+    }
+    match style {
+        CitationStyle::List => vec![
+            Content::text(answer),
+            Content::text(json!({ "citations": citations }).to_string()),
+        ],
+        CitationStyle::Inline | CitationStyle::Footnotes => {
+            vec![Content::text(annotate(&answer, &citations, style))]
+        }
+    }
+}
+
+/// A file cited by an answer, with the pages cited.
+struct Source {
+    key: String,
+    name: String,
+    pages: Vec<u64>,
+}
+
+/// Inserts a marker where each citation points into the answer, numbering
+/// the files cited in order of first citation, and lists them after it.
+fn annotate(answer: &str, citations: &[Value], style: CitationStyle) -> String {
+    let marker = |number: usize| match style {
+        CitationStyle::Footnotes => format!("[^{number}]"),
+        _ => format!("[{number}]"),
+    };
+    let mut citations: Vec<&Value> = citations.iter().collect();
+    citations.sort_by_key(|citation| citation["position"].as_u64().unwrap_or(u64::MAX));
+
+    let mut sources: Vec<Source> = Vec::new();
+    let mut markers: Vec<(usize, String)> = Vec::with_capacity(citations.len());
+    for citation in citations {
+        let mut markup = String::new();
+        for reference in citation["references"].as_array().into_iter().flatten() {
+            let file = &reference["file"];
+            let name = file["name"].as_str().unwrap_or("unnamed file");
+            let key = file["id"].as_str().unwrap_or(name);
+            let index = match sources.iter().position(|source| source.key == key) {
+                Some(index) => index,
+                None => {
+                    sources.push(Source {
+                        key: key.to_string(),
+                        name: name.to_string(),
+                        pages: Vec::new(),
+                    });
+                    sources.len() - 1
+                }
+            };
+            let pages = reference["pages"].as_array().into_iter().flatten();
+            for page in pages.filter_map(Value::as_u64) {
+                if !sources[index].pages.contains(&page) {
+                    sources[index].pages.push(page);
+                }
+            }
+            let marker = marker(index + 1);
+            if !markup.contains(&marker) {
+                markup.push_str(&marker);
+            }
+        }
+        // Positions count characters; citations past the end go at the end.
+        let offset = citation["position"]
+            .as_u64()
+            .and_then(|position| answer.char_indices().nth(position as usize))
+            .map_or(answer.len(), |(offset, _)| offset);
+        markers.push((offset, markup));
+    }
+
+    // From the end, so the offsets of earlier markers stay valid.
+    let mut annotated = answer.to_string();
+    for (offset, markup) in markers.iter().rev() {
+        annotated.insert_str(*offset, markup);
+    }
+    annotated.push_str(match style {
+        CitationStyle::Footnotes => "\n",
+        _ => "\n\nSources:",
+    });
+    for (index, source) in sources.iter_mut().enumerate() {
+        source.pages.sort_unstable();
+        let pages: Vec<String> = source.pages.iter().map(u64::to_string).collect();
+        let pages = match pages.len() {
+            0 => String::new(),
+            1 => format!(", page {}", pages[0]),
+            _ => format!(", pages {}", pages.join(", ")),
+        };
+        let marker = marker(index + 1);
+        match style {
+            CitationStyle::Footnotes => {
+                annotated.push_str(&format!("\n{marker}: {}{pages}", source.name))
+            }
+            _ => annotated.push_str(&format!("\n{marker} {}{pages}", source.name)),
+        }
+    }
+    annotated
+}
+
+fn staleness(age: Duration) -> Value {
+    json!({
+        "age_secs": age.as_secs(),
+        "reason": "Pinecone is unavailable; these snippets were retrieved earlier for the same query and may be outdated"
+    })
+}
+
+fn truncation(dropped: usize) -> Value {
+    json!({
+        "dropped_snippets": dropped,
+        "reason": "The lowest-scoring snippets were dropped to stay under the response size limit"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_prefers_score_per_char() {
+        let snippets = vec![
+            json!({"content": "long but relevant snippet", "score": 0.9}),
+            json!({"content": "short", "score": 0.5}),
+            json!({"content": "tiny", "score": 0.4}),
+        ];
+        let (kept, omitted) = pack(snippets, 12);
+        assert_eq!(omitted, 1);
+        assert_eq!(kept[0]["content"], "short");
+        assert_eq!(kept[1]["content"], "tiny");
+    }
+
+    #[test]
+    fn test_render_returns_images_as_image_content() {
+        let snippet = json!({"type": "multimodal", "score": 0.8, "content": [
+            {"type": "text", "text": "Revenue by quarter"},
+            {"type": "image", "caption": "bar chart",
+                "image": {"type": "base64", "mime_type": "image/png", "data": "aGk="}}
+        ]});
+        let contents = render(vec![snippet], None, &Notes::default(), ResponseFormat::Text);
+
+        assert_eq!(contents.len(), 2);
+        assert!(!contents[0].as_text().unwrap().contains("aGk="));
+        assert_eq!(contents[1].as_image(), Some(("aGk=", "image/png")));
+    }
+
+    #[test]
+    fn test_fit_bytes_drops_lowest_scores_first() {
+        let snippets = vec![
+            json!({"content": "a".repeat(100), "score": 0.9}),
+            json!({"content": "b".repeat(100), "score": 0.2}),
+            json!({"content": "c".repeat(100), "score": 0.5}),
+        ];
+        let (kept, dropped) = fit_bytes(snippets.clone(), 300);
+        assert_eq!(dropped, 1);
+        assert_eq!(kept, vec![snippets[0].clone(), snippets[2].clone()]);
+
+        let (kept, dropped) = fit_bytes(snippets, 10_000);
+        assert_eq!((kept.len(), dropped), (3, 0));
+    }
+
+    #[test]
+    fn test_cited_files_are_distinct() {
+        let file = json!({"id": "f1", "name": "guide.pdf"});
+        let files = cited_files(&[
+            json!({"content": "a", "reference": {"file": file}}),
+            json!({"content": "b", "reference": {"file": file}}),
+            json!({"content": "c"}),
+        ]);
+        assert_eq!(files, vec![("pinecone://files/f1".to_string(), file)]);
+    }
+
+    #[test]
+    fn test_citation_styles() {
+        let guide = json!({"id": "f1", "name": "guide.pdf"});
+        let faq = json!({"id": "f2", "name": "faq.md"});
+        let citations = vec![
+            json!({"position": 24, "references": [{"file": faq}]}),
+            json!({"position": 11, "references": [{"file": guide, "pages": [4, 2]}]}),
+            json!({"position": 24, "references": [{"file": guide, "pages": [2]}]}),
+        ];
+        let answer = "Refunds é 5; returns 30.";
+        let text = |contents: Vec<Content>| contents[0].as_text().unwrap().to_string();
+
+        assert_eq!(
+            text(render_answer(
+                answer.to_string(),
+                citations.clone(),
+                CitationStyle::Inline
+            )),
+            "Refunds é 5[1]; returns 30.[2][1]\n\nSources:\n[1] guide.pdf, pages 2, 4\n[2] faq.md"
+        );
+        assert_eq!(
+            text(render_answer(
+                answer.to_string(),
+                citations.clone(),
+                CitationStyle::Footnotes
+            )),
+            "Refunds é 5[^1]; returns 30.[^2][^1]\n\n[^1]: guide.pdf, pages 2, 4\n[^2]: faq.md"
+        );
+        let contents = render_answer(answer.to_string(), citations, CitationStyle::List);
+        assert_eq!(contents.len(), 2);
+        assert_eq!(text(contents), answer);
+    }
+}
@@ -0,0 +1,231 @@
+//! Cursor-based paging of large tool results.
+//!
+//! The first call computes the full result and returns its first page; the
+//! rest is kept in memory and served by later calls passing the cursor, so
+//! pages stay consistent even when a fresh query would return other results.
+//! Cursors are opaque to clients and can be retried. They name the result by
+//! a random id and only work on the connection that got them, so one client
+//! can neither guess nor reuse another's.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use serde_json::Value;
+
+/// How long the rest of a paged result stays available after its last use.
+const RESULT_TTL: Duration = Duration::from_secs(600);
+
+/// Paged results kept at a time; the least recently used one is dropped.
+const MAX_STORED_RESULTS: usize = 16;
+
+/// A page of results and, if more remain, the cursor to the next one.
+#[derive(Debug, PartialEq)]
+pub struct Page {
+    pub items: Vec<Value>,
+    pub next_cursor: Option<String>,
+}
+
+struct StoredResult {
+    /// Tool the result belongs to, so cursors can't cross tools.
+    tool: &'static str,
+    /// Connection the result was computed for.
+    connection: Option<u64>,
+    items: Arc<Vec<Value>>,
+    last_used: Instant,
+}
+
+#[derive(Clone, Default)]
+pub struct ResultPages {
+    results: Arc<Mutex<HashMap<String, StoredResult>>>,
+}
+
+impl ResultPages {
+    /// Returns the first page of `items` for `connection`, keeping the rest
+    /// for later pages. A page has at most `page_size` items and, past its
+    /// first, no more than `max_bytes` of them.
+    pub fn first(
+        &self,
+        tool: &'static str,
+        connection: Option<u64>,
+        items: Vec<Value>,
+        page_size: usize,
+        max_bytes: Option<usize>,
+    ) -> Page {
+        let end = page_end(&items, 0, page_size, max_bytes);
+        if end == items.len() {
+            return Page {
+                items,
+                next_cursor: None,
+            };
+        }
+        let id = new_result_id();
+        let page = Page {
+            items: items[..end].to_vec(),
+            next_cursor: Some(encode_cursor(&id, end)),
+        };
+
+        let mut results = self.lock();
+        evict(&mut results);
+        if results.len() >= MAX_STORED_RESULTS {
+            let oldest = results
+                .iter()
+                .min_by_key(|(_, result)| result.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                results.remove(&oldest);
+            }
+        }
+        results.insert(
+            id,
+            StoredResult {
+                tool,
+                connection,
+                items: Arc::new(items),
+                last_used: Instant::now(),
+            },
+        );
+        page
+    }
+
+    /// Returns the page a cursor from an earlier call of `tool` on the same
+    /// connection points to, sized like [`ResultPages::first`].
+    pub fn next(
+        &self,
+        tool: &'static str,
+        connection: Option<u64>,
+        cursor: &str,
+        page_size: usize,
+        max_bytes: Option<usize>,
+    ) -> Result<Page, String> {
+        let invalid = || format!("Invalid or expired cursor: {cursor}");
+        let (id, offset) = decode_cursor(cursor).ok_or_else(invalid)?;
+
+        let mut results = self.lock();
+        evict(&mut results);
+        let result = results
+            .get_mut(&id)
+            .filter(|result| result.tool == tool && result.connection == connection)
+            .ok_or_else(invalid)?;
+        result.last_used = Instant::now();
+        let items = result.items.clone();
+        drop(results);
+
+        let offset = offset.min(items.len());
+        let end = page_end(&items, offset, page_size, max_bytes);
+        Ok(Page {
+            items: items[offset..end].to_vec(),
+            next_cursor: (end < items.len()).then(|| encode_cursor(&id, end)),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, StoredResult>> {
+        self.results.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn evict(results: &mut HashMap<String, StoredResult>) {
+    results.retain(|_, result| result.last_used.elapsed() <= RESULT_TTL);
+}
+
+/// Where the page of `items` starting at `offset` ends: after `page_size`
+/// items, or before the first one taking it past `max_bytes`. A page always
+/// has an item, if any are left, so paging moves on however large they are.
+fn page_end(items: &[Value], offset: usize, page_size: usize, max_bytes: Option<usize>) -> usize {
+    let end = offset.saturating_add(page_size.max(1)).min(items.len());
+    let Some(max_bytes) = max_bytes else {
+        return end;
+    };
+    let mut bytes = 0;
+    for (index, item) in items.iter().enumerate().take(end).skip(offset) {
+        bytes += item.to_string().len();
+        if bytes > max_bytes && index > offset {
+            return index;
+        }
+    }
+    end
+}
+
+/// An id no client can guess, as knowing a cursor is enough to page through
+/// its result.
+fn new_result_id() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("No source of randomness");
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn encode_cursor(id: &str, offset: usize) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(format!("{id}:{offset}"))
+}
+
+fn decode_cursor(cursor: &str) -> Option<(String, usize)> {
+    let decoded = BASE64_URL_SAFE_NO_PAD.decode(cursor.trim()).ok()?;
+    let (id, offset) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+    Some((id.to_string(), offset.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pages_follow_cursors() {
+        let pages = ResultPages::default();
+        let items: Vec<Value> = (0..5).map(|i| json!(i)).collect();
+
+        let first = pages.first("list", Some(1), items, 2, None);
+        let cursor = first.next_cursor.unwrap();
+        let second = pages.next("list", Some(1), &cursor, 2, None).unwrap();
+        let retried = pages.next("list", Some(1), &cursor, 2, None).unwrap();
+        let last = pages
+            .next(
+                "list",
+                Some(1),
+                second.next_cursor.as_ref().unwrap(),
+                2,
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(first.items, vec![json!(0), json!(1)]);
+        assert_eq!(second.items, vec![json!(2), json!(3)]);
+        assert_eq!(retried, second);
+        assert_eq!(last.items, vec![json!(4)]);
+        assert_eq!(last.next_cursor, None);
+        assert!(pages.next("other", Some(1), &cursor, 2, None).is_err());
+        assert!(pages.next("list", Some(2), &cursor, 2, None).is_err());
+        assert!(pages.next("list", Some(1), "garbage", 2, None).is_err());
+    }
+
+    #[test]
+    fn test_pages_stay_within_the_byte_limit() {
+        let pages = ResultPages::default();
+        let items: Vec<Value> = ["aaaa", "bbbb", "cccccccccccc", "d"]
+            .into_iter()
+            .map(Value::from)
+            .collect();
+
+        // Each of the first two is 6 bytes of JSON.
+        let first = pages.first("list", None, items, 10, Some(12));
+        assert_eq!(first.items, vec![json!("aaaa"), json!("bbbb")]);
+        let cursor = first.next_cursor.unwrap();
+        let second = pages.next("list", None, &cursor, 10, Some(12)).unwrap();
+        assert_eq!(second.items, vec![json!("cccccccccccc")]);
+        let last = pages
+            .next("list", None, &second.next_cursor.unwrap(), 10, Some(12))
+            .unwrap();
+        assert_eq!(last.items, vec![json!("d")]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn test_small_results_are_not_stored() {
+        let pages = ResultPages::default();
+        let page = pages.first("list", None, vec![json!(1)], 10, None);
+        assert_eq!(page.next_cursor, None);
+        assert!(pages.lock().is_empty());
+    }
+}
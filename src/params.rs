@@ -0,0 +1,355 @@
+//! Typed arguments of the tools served by the router.
+//!
+//! Unknown fields are rejected rather than silently ignored so a misspelled
+//! parameter surfaces as an error the caller can correct.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use mcp_spec::tool::Tool;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::language::LanguageMode;
+use crate::output::{CitationStyle, ResponseFormat};
+use crate::router::RouterError;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssistantContextParams {
+    pub assistant_name: Option<String>,
+    pub assistant_names: Option<Vec<String>>,
+    /// Not needed when `cursor` is given.
+    #[serde(default)]
+    pub query: String,
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    #[serde(default)]
+    pub rewrite_query: bool,
+    pub sub_queries: Option<u32>,
+    pub merge_adjacent: Option<bool>,
+    pub expand_context: Option<u32>,
+    #[serde(default)]
+    pub use_query_history: bool,
+    pub context_budget_chars: Option<usize>,
+    #[serde(default)]
+    pub include_images: bool,
+    #[serde(default)]
+    pub include_highlights: bool,
+    pub timeout_ms: Option<u64>,
+    pub filter: Option<Map<String, Value>>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub file_types: Option<Vec<String>>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub language_mode: LanguageMode,
+    pub page_size: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetDefaultAssistantParams {
+    pub assistant_name: String,
+    pub top_k: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StartChatSessionParams {
+    pub assistant_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChatInSessionParams {
+    pub session_id: String,
+    pub message: String,
+    #[serde(default)]
+    pub json_response: bool,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub citation_style: CitationStyle,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnswerWithSourcesParams {
+    pub assistant_name: Option<String>,
+    pub query: String,
+    pub top_k: Option<u32>,
+    pub filter: Option<Map<String, Value>>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndChatSessionParams {
+    pub session_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResetSessionParams {}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadFileParams {
+    pub assistant_name: Option<String>,
+    pub file_path: PathBuf,
+    pub metadata: Option<Map<String, Value>>,
+}
+
+/// How the content of `upload_content` is encoded.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentEncoding {
+    #[default]
+    Text,
+    Base64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadContentParams {
+    pub assistant_name: Option<String>,
+    pub file_name: String,
+    pub content: String,
+    #[serde(default)]
+    pub encoding: ContentEncoding,
+    pub metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyFileParams {
+    pub assistant_name: Option<String>,
+    pub file_paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadDirectoryParams {
+    pub assistant_name: Option<String>,
+    pub directory: PathBuf,
+    pub include: Option<Vec<String>>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExportAssistantParams {
+    pub assistant_name: Option<String>,
+    pub output_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncAssistantParams {
+    pub assistant_name: Option<String>,
+    pub manifest_path: PathBuf,
+    pub directory: PathBuf,
+    #[serde(default)]
+    pub delete_extras: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IngestFromRootsParams {
+    pub assistant_name: Option<String>,
+    pub roots: Option<Vec<String>>,
+    pub include: Option<Vec<String>>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListFilesParams {
+    pub assistant_name: Option<String>,
+    pub filter: Option<Map<String, Value>>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub file_types: Option<Vec<String>>,
+    /// Called `page_size` by the other paged tools, which is accepted too.
+    #[serde(alias = "page_size")]
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub sort_by: Option<FileSort>,
+}
+
+/// Order of the files listed by `list_files`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSort {
+    /// By name, A to Z.
+    Name,
+    /// Largest first.
+    Size,
+    /// Newest first.
+    Created,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SearchFilesParams {
+    pub assistant_name: Option<String>,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub filter: Option<Map<String, Value>>,
+}
+
+/// Arguments of `list_uploads` and `resume_uploads`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadsParams {
+    pub assistant_name: Option<String>,
+}
+
+/// Arguments of `upload_queue`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadQueueParams {
+    pub assistant_name: Option<String>,
+    #[serde(default)]
+    pub retry: bool,
+    #[serde(default)]
+    pub discard: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthParams {
+    pub assistant_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DebugConfigParams {}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EstimateCostParams {
+    pub assistant_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UsageStatsParams {
+    pub assistant_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchParams {
+    pub calls: Vec<BatchCall>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchCall {
+    pub tool: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+/// Validators of the tools' declared input schemas. Arguments are checked
+/// against them before they are parsed, so every mismatch is reported at
+/// once, with its path, whichever tool is called.
+#[derive(Clone, Default)]
+pub struct SchemaValidators {
+    validators: Arc<HashMap<String, (Value, jsonschema::Validator)>>,
+}
+
+impl SchemaValidators {
+    pub fn new(tools: &[Tool]) -> Self {
+        let validators = tools
+            .iter()
+            .filter_map(|tool| match jsonschema::validator_for(&tool.input_schema) {
+                Ok(validator) => Some((tool.name.clone(), (tool.input_schema.clone(), validator))),
+                Err(e) => {
+                    tracing::warn!("Not validating arguments of {}: {e}", tool.name);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            validators: Arc::new(validators),
+        }
+    }
+
+    pub fn validate(&self, tool_name: &str, arguments: &Value) -> Result<(), RouterError> {
+        let Some((schema, validator)) = self.validators.get(tool_name) else {
+            return Ok(());
+        };
+        let empty = Value::Object(Map::new());
+        let arguments = if arguments.is_null() {
+            &empty
+        } else {
+            arguments
+        };
+        let errors: Vec<String> = validator
+            .iter_errors(arguments)
+            .map(|error| {
+                let path = error.instance_path.to_string();
+                let path = if path.is_empty() { "/" } else { path.as_str() };
+                match required_alternatives(schema, &error) {
+                    Some(alternatives) => format!("{path}: one of {alternatives} is required"),
+                    None => format!("{path}: {error}"),
+                }
+            })
+            .collect();
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(RouterError::InvalidParameters(format!(
+            "Invalid arguments for {tool_name}: {}",
+            errors.join("; ")
+        )))
+    }
+}
+
+/// Names the alternatives of a failed `anyOf` of `required` lists, such as
+/// `query` or `cursor`, which the validator's own message leaves out.
+fn required_alternatives(schema: &Value, error: &jsonschema::ValidationError) -> Option<String> {
+    if !matches!(error.kind, jsonschema::error::ValidationErrorKind::AnyOf) {
+        return None;
+    }
+    let alternatives = schema.pointer(&error.schema_path.to_string())?.as_array()?;
+    let names: Vec<String> = alternatives
+        .iter()
+        .map(|alternative| {
+            let required = alternative.get("required")?.as_array()?;
+            let names: Vec<String> = required
+                .iter()
+                .map(|name| name.as_str().map(|name| format!("'{name}'")))
+                .collect::<Option<_>>()?;
+            Some(names.join(" and "))
+        })
+        .collect::<Option<_>>()?;
+    Some(names.join(", "))
+}
+
+/// Deserializes tool arguments, treating absent arguments as an empty object.
+pub fn parse<T: DeserializeOwned>(arguments: Value) -> Result<T, RouterError> {
+    let arguments = match arguments {
+        Value::Null => Value::Object(Default::default()),
+        arguments => arguments,
+    };
+    serde_json::from_value(arguments).map_err(|e| RouterError::InvalidParameters(e.to_string()))
+}
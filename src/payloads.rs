@@ -0,0 +1,87 @@
+//! Payload sizes of tool calls, to understand the bandwidth the server uses
+//! and spot unexpectedly large responses.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::Serialize;
+use serde_json::{Value, json};
+
+/// Totals of one tool, in bytes of serialized JSON.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+struct ToolSizes {
+    calls: u64,
+    request_bytes: u64,
+    response_bytes: u64,
+    max_response_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    tools: BTreeMap<String, ToolSizes>,
+    snippets: u64,
+}
+
+/// Request and response sizes per tool, and the snippets returned. Clones
+/// share the totals.
+#[derive(Clone, Debug, Default)]
+pub struct PayloadSizes {
+    state: Arc<Mutex<State>>,
+}
+
+impl PayloadSizes {
+    /// Adds a tool call; `response_bytes` is `None` if it failed.
+    pub fn record(&self, tool_name: &str, request_bytes: usize, response_bytes: Option<usize>) {
+        let mut state = self.lock();
+        let sizes = state.tools.entry(tool_name.to_string()).or_default();
+        sizes.calls += 1;
+        sizes.request_bytes += request_bytes as u64;
+        if let Some(response_bytes) = response_bytes {
+            sizes.response_bytes += response_bytes as u64;
+            sizes.max_response_bytes = sizes.max_response_bytes.max(response_bytes as u64);
+        }
+    }
+
+    /// Adds snippets returned to a client.
+    pub fn record_snippets(&self, count: usize) {
+        self.lock().snippets += count as u64;
+    }
+
+    /// The totals per tool and the snippets returned.
+    pub fn snapshot(&self) -> Value {
+        let state = self.lock();
+        json!({"tools": state.tools, "snippets": state.snippets})
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals_per_tool() {
+        let sizes = PayloadSizes::default();
+        sizes.record("assistant_context", 40, Some(1000));
+        sizes.clone().record("assistant_context", 60, Some(3000));
+        sizes.record("assistant_context", 50, None);
+        sizes.record("health", 2, Some(100));
+        sizes.record_snippets(8);
+
+        assert_eq!(
+            sizes.snapshot(),
+            json!({
+                "tools": {
+                    "assistant_context": {"calls": 3, "request_bytes": 150,
+                        "response_bytes": 4000, "max_response_bytes": 3000},
+                    "health": {"calls": 1, "request_bytes": 2,
+                        "response_bytes": 100, "max_response_bytes": 100},
+                },
+                "snippets": 8,
+            })
+        );
+    }
+}
@@ -0,0 +1,430 @@
+//! Handle for talking back to the connected MCP client.
+//!
+//! Tool handlers reach the client (sampling, progress notifications) through
+//! the [`RequestContext`] of the request they are serving.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+
+/// A request to the client, dropped from the pending ones once the server
+/// stops waiting for it, even if the call awaiting it is cancelled.
+struct Awaiting<'a> {
+    peer: &'a Peer,
+    id: u64,
+}
+
+impl Drop for Awaiting<'_> {
+    fn drop(&mut self) {
+        // Still pending if no response arrived.
+        if self.peer.lock_pending().remove(&self.id).is_some() {
+            self.peer.notify(
+                "notifications/cancelled",
+                json!({"requestId": self.id, "reason": "The server stopped waiting"}),
+            );
+        }
+    }
+}
+
+/// Progress a keep-alive adds, a fraction of a step so exact in binary that
+/// repeated ticks never stop adding up.
+const KEEP_ALIVE_STEP: f64 = 0.0625;
+
+/// How long a request to the client may go unanswered. Sampling may wait
+/// for the user to approve it, so this is generous.
+const CLIENT_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Error, Debug)]
+pub enum PeerError {
+    #[error("Client does not support {0}")]
+    Unsupported(&'static str),
+
+    #[error("Client error: {code} - {message}")]
+    Client { code: i64, message: String },
+
+    #[error("Unexpected response from client: {0}")]
+    InvalidResponse(String),
+
+    #[error("Connection to the client closed")]
+    Closed,
+
+    #[error("Client did not answer {0} within {1:?}")]
+    Timeout(String, Duration),
+}
+
+/// A directory the client has shared with the server.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Root {
+    pub uri: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl Root {
+    /// Local path of a `file://` root; other schemes have none.
+    pub fn path(&self) -> Option<PathBuf> {
+        let rest = self.uri.strip_prefix("file://")?;
+        // Only local files: the authority must be empty or localhost.
+        let path = rest.strip_prefix("localhost").unwrap_or(rest);
+        if !path.starts_with('/') {
+            return None;
+        }
+        percent_decode(path).map(PathBuf::from)
+    }
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+type PendingRequests = HashMap<u64, oneshot::Sender<Result<Value, PeerError>>>;
+
+#[derive(Clone)]
+pub struct Peer {
+    /// Unique per connection, to keep the state of different clients apart.
+    id: u64,
+    outgoing: mpsc::UnboundedSender<Value>,
+    pending: Arc<Mutex<PendingRequests>>,
+    next_id: Arc<AtomicU64>,
+    client_capabilities: Arc<RwLock<Value>>,
+}
+
+impl Peer {
+    pub(crate) fn new(outgoing: mpsc::UnboundedSender<Value>) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            outgoing,
+            pending: Arc::default(),
+            next_id: Arc::new(AtomicU64::new(1)),
+            client_capabilities: Arc::new(RwLock::new(Value::Null)),
+        }
+    }
+
+    /// Identifies the connection to the client; no two connections served
+    /// by the process share an id.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn set_client_capabilities(&self, capabilities: Value) {
+        *self
+            .client_capabilities
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = capabilities;
+    }
+
+    /// Whether the client declared the given capability (e.g. `sampling`).
+    pub fn supports(&self, capability: &str) -> bool {
+        self.client_capabilities
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(capability)
+            .is_some_and(|c| !c.is_null())
+    }
+
+    /// Whether the client disconnected.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.outgoing.is_closed()
+    }
+
+    /// Sends a message to the client as-is.
+    pub(crate) fn send(&self, message: Value) -> Result<(), PeerError> {
+        self.outgoing.send(message).map_err(|_| PeerError::Closed)
+    }
+
+    pub fn notify(&self, method: &str, params: Value) {
+        let notification = json!({"jsonrpc": "2.0", "method": method, "params": params});
+        if self.send(notification).is_err() {
+            tracing::debug!("Dropping {method} notification: client disconnected");
+        }
+    }
+
+    /// Issues a request to the client and waits for its response, for at
+    /// most [`CLIENT_REQUEST_TIMEOUT`]; the client is told when the server
+    /// stops waiting, whether it timed out or the call was cancelled.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value, PeerError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.lock_pending().insert(id, tx);
+        let _awaiting = Awaiting { peer: self, id };
+
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        self.send(request)?;
+        match tokio::time::timeout(CLIENT_REQUEST_TIMEOUT, rx).await {
+            Ok(result) => result.unwrap_or(Err(PeerError::Closed)),
+            Err(_) => Err(PeerError::Timeout(
+                method.to_string(),
+                CLIENT_REQUEST_TIMEOUT,
+            )),
+        }
+    }
+
+    /// Routes a response from the client to the request waiting for it.
+    pub(crate) fn resolve(&self, id: &Value, result: Result<Value, PeerError>) {
+        let waiter = id.as_u64().and_then(|id| self.lock_pending().remove(&id));
+        match waiter {
+            Some(waiter) => {
+                let _ = waiter.send(result);
+            }
+            None => tracing::warn!("Received response for unknown request id: {id}"),
+        }
+    }
+
+    /// Fails all outstanding requests, e.g. once the client disconnected.
+    pub(crate) fn close(&self) {
+        for (_, waiter) in self.lock_pending().drain() {
+            let _ = waiter.send(Err(PeerError::Closed));
+        }
+    }
+
+    /// Asks the client's LLM for a completion via MCP sampling and returns
+    /// the text of its reply.
+    pub async fn create_message(
+        &self,
+        system_prompt: &str,
+        user_message: &str,
+        max_tokens: u32,
+    ) -> Result<String, PeerError> {
+        if !self.supports("sampling") {
+            return Err(PeerError::Unsupported("sampling"));
+        }
+        let result = self
+            .request(
+                "sampling/createMessage",
+                json!({
+                    "messages": [{
+                        "role": "user",
+                        "content": {"type": "text", "text": user_message}
+                    }],
+                    "systemPrompt": system_prompt,
+                    "includeContext": "thisServer",
+                    "temperature": 0,
+                    "maxTokens": max_tokens
+                }),
+            )
+            .await?;
+        result["content"]["text"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| PeerError::InvalidResponse(result.to_string()))
+    }
+
+    /// The directories the client has shared, via MCP roots.
+    pub async fn list_roots(&self) -> Result<Vec<Root>, PeerError> {
+        if !self.supports("roots") {
+            return Err(PeerError::Unsupported("roots"));
+        }
+        let result = self.request("roots/list", json!({})).await?;
+        serde_json::from_value(result["roots"].clone())
+            .map_err(|_| PeerError::InvalidResponse(result.to_string()))
+    }
+
+    fn lock_pending(&self) -> std::sync::MutexGuard<'_, PendingRequests> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+tokio::task_local! {
+    static CONTEXT: RequestContext;
+}
+
+/// The client request currently being served.
+#[derive(Clone)]
+pub struct RequestContext {
+    pub peer: Peer,
+    pub progress_token: Option<Value>,
+    /// When the client will give up on the request, if it said so.
+    pub deadline: Option<Instant>,
+    /// Last progress value sent, as progress must only increase.
+    last_progress: Arc<Mutex<f64>>,
+}
+
+impl RequestContext {
+    pub(crate) fn new(
+        peer: Peer,
+        progress_token: Option<Value>,
+        deadline: Option<Instant>,
+    ) -> Self {
+        Self {
+            peer,
+            progress_token,
+            deadline,
+            last_progress: Arc::default(),
+        }
+    }
+
+    /// The context of the request the calling task is serving, if any.
+    pub fn current() -> Option<Self> {
+        CONTEXT.try_with(Clone::clone).ok()
+    }
+
+    pub(crate) async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTEXT.scope(self, future).await
+    }
+
+    /// Reports progress if the client asked for it by sending a progress token.
+    pub fn notify_progress(&self, progress: f64, total: Option<f64>, message: Option<&str>) {
+        let Some(token) = &self.progress_token else {
+            return;
+        };
+        {
+            let mut last = self.last_progress.lock().unwrap_or_else(|e| e.into_inner());
+            if progress <= *last {
+                return;
+            }
+            *last = progress;
+        }
+        self.send_progress(token, progress, total, message, None);
+    }
+
+    /// Reports the next step of the request, numbered on from the progress
+    /// sent so far, which other steps and keep-alives share. `remaining` is
+    /// the number of steps still to come, if known; `partial` is a result
+    /// of the step, sent under the notification's `_meta`.
+    pub fn notify_step(
+        &self,
+        remaining: Option<usize>,
+        message: Option<&str>,
+        partial: Option<Value>,
+    ) {
+        let Some(token) = &self.progress_token else {
+            return;
+        };
+        let progress = {
+            let mut last = self.last_progress.lock().unwrap_or_else(|e| e.into_inner());
+            *last = last.floor() + 1.0;
+            *last
+        };
+        let total = remaining.map(|remaining| progress + remaining as f64);
+        self.send_progress(token, progress, total, message, partial);
+    }
+
+    fn send_progress(
+        &self,
+        token: &Value,
+        progress: f64,
+        total: Option<f64>,
+        message: Option<&str>,
+        partial: Option<Value>,
+    ) {
+        let mut params = json!({"progressToken": token, "progress": progress});
+        if let Some(total) = total {
+            params["total"] = json!(total);
+        }
+        if let Some(message) = message {
+            params["message"] = json!(message);
+        }
+        if let Some(partial) = partial {
+            params["_meta"] = json!({"partial": partial});
+        }
+        self.peer.notify("notifications/progress", params);
+    }
+
+    /// Reports that a long request is still being worked on, so clients with
+    /// idle timeouts keep waiting. Progress moves on by a fixed fraction of a
+    /// step, however many ticks there are, as the steps the handler reports
+    /// are numbered on from wherever it got to.
+    pub(crate) fn keep_alive(&self, elapsed: Duration) {
+        let last = *self.last_progress.lock().unwrap_or_else(|e| e.into_inner());
+        let message = format!("Still working ({}s)", elapsed.as_secs());
+        self.notify_progress(last + KEEP_ALIVE_STEP, None, Some(&message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_paths() {
+        let root = |uri: &str| Root {
+            uri: uri.to_string(),
+            name: None,
+        };
+        assert_eq!(
+            root("file:///home/me/My%20Docs").path(),
+            Some(PathBuf::from("/home/me/My Docs"))
+        );
+        assert_eq!(
+            root("file://localhost/srv/kb").path(),
+            Some(PathBuf::from("/srv/kb"))
+        );
+        assert_eq!(root("file://server/share").path(), None);
+        assert_eq!(root("https://example.com/kb").path(), None);
+        assert_eq!(root("file:///bad%2").path(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unanswered_requests_time_out() {
+        let (outgoing, mut messages) = mpsc::unbounded_channel();
+        let peer = Peer::new(outgoing);
+
+        let result = peer.request("roots/list", json!({})).await;
+
+        assert!(matches!(result, Err(PeerError::Timeout(method, _)) if method == "roots/list"));
+        assert!(peer.lock_pending().is_empty());
+        let request = messages.try_recv().unwrap();
+        let cancelled = messages.try_recv().unwrap();
+        assert_eq!(cancelled["method"], "notifications/cancelled");
+        assert_eq!(cancelled["params"]["requestId"], request["id"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_requests_are_no_longer_pending() {
+        let (outgoing, mut messages) = mpsc::unbounded_channel();
+        let peer = Peer::new(outgoing);
+        let request = tokio::spawn({
+            let peer = peer.clone();
+            async move { peer.request("roots/list", json!({})).await }
+        });
+        let sent = messages.recv().await.unwrap();
+
+        request.abort();
+        assert!(request.await.unwrap_err().is_cancelled());
+        assert!(peer.lock_pending().is_empty());
+        let cancelled = messages.try_recv().unwrap();
+        assert_eq!(cancelled["params"]["requestId"], sent["id"]);
+    }
+
+    #[test]
+    fn test_keep_alives_and_steps_keep_progressing() {
+        let (outgoing, mut notifications) = mpsc::unbounded_channel();
+        let context = RequestContext::new(Peer::new(outgoing), Some(json!("p")), None);
+        for tick in 0..200 {
+            context.keep_alive(Duration::from_secs(tick));
+            if tick == 100 {
+                context.notify_step(None, Some("piece"), None);
+            }
+        }
+
+        let mut progress = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            progress.push(notification["params"]["progress"].as_f64().unwrap());
+        }
+        assert_eq!(progress.len(), 201);
+        assert!(progress.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(progress[101], 7.0);
+    }
+}
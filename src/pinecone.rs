@@ -1,11 +1,66 @@
-use reqwest::{Client, Error as ReqwestError};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::TryStreamExt;
+use reqwest::multipart::{Form, Part};
+use reqwest::{
+    Body, Client, Error as ReqwestError, Method, Request, RequestBuilder, Response, Url,
+};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio_util::io::{ReaderStream, StreamReader, SyncIoBridge};
+
+use crate::failover::{HostPool, Outcome};
+use crate::gauge::Gauge;
+use crate::reporting;
+use crate::retry_budget::RetryBudget;
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum PineconeError {
     #[error("HTTP request error: {0}")]
-    Request(#[from] ReqwestError),
+    Request(ReqwestError),
+
+    #[error("Cannot resolve {host}: check the host URL and DNS settings ({source})")]
+    Dns {
+        host: String,
+        #[source]
+        source: ReqwestError,
+    },
+
+    #[error(
+        "TLS handshake with {host} failed: check for proxies intercepting HTTPS and the \
+        system's CA certificates ({source})"
+    )]
+    Tls {
+        host: String,
+        #[source]
+        source: ReqwestError,
+    },
+
+    #[error("Connecting to {host} timed out: check network, firewall and proxy settings")]
+    ConnectTimeout {
+        host: String,
+        #[source]
+        source: ReqwestError,
+    },
+
+    #[error("Cannot connect to {host}: check network, firewall and proxy settings ({source})")]
+    Connect {
+        host: String,
+        #[source]
+        source: ReqwestError,
+    },
+
+    #[error("{host} did not respond in time; the request may still have been processed")]
+    ReadTimeout {
+        host: String,
+        #[source]
+        source: ReqwestError,
+    },
 
     #[error("API error: {status} - {message}")]
     Api { status: u16, message: String },
@@ -13,8 +68,68 @@ pub enum PineconeError {
     #[error("API error: {resource} not found")]
     NotFound { resource: String },
 
+    /// HTTP 402: the project's plan limits are used up.
+    #[error(
+        "Assistant quota exceeded: upgrade the Pinecone plan or reduce usage; \
+        retrying will not help until the quota resets ({message})"
+    )]
+    QuotaExceeded { message: String },
+
+    /// HTTP 403: the API key may not perform the operation.
+    #[error(
+        "Permission denied for {resource}: the API key's project or role does not allow \
+        this operation; use a key with access rather than retrying ({message})"
+    )]
+    Forbidden { resource: String, message: String },
+
     #[error("JSON deserialization error: {0}")]
     Json(#[from] serde_json::Error),
+
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<ReqwestError> for PineconeError {
+    /// Tells apart the failures that need different fixes: the host name,
+    /// TLS interception, the network path, or a slow server.
+    fn from(source: ReqwestError) -> Self {
+        let host = source
+            .url()
+            .and_then(|url| url.host_str())
+            .unwrap_or("the Pinecone API")
+            .to_string();
+        if source.is_timeout() {
+            return if source.is_connect() {
+                Self::ConnectTimeout { host, source }
+            } else {
+                Self::ReadTimeout { host, source }
+            };
+        }
+        if !source.is_connect() {
+            return Self::Request(source);
+        }
+        // The connector reports the cause only in its error chain's messages.
+        let mut causes = String::new();
+        let mut cause: Option<&dyn std::error::Error> = Some(&source);
+        while let Some(error) = cause {
+            causes.push_str(&error.to_string().to_ascii_lowercase());
+            causes.push('\n');
+            cause = error.source();
+        }
+        if causes.contains("dns error") || causes.contains("failed to lookup address") {
+            Self::Dns { host, source }
+        } else if ["tls", "ssl", "certificate", "handshake"]
+            .iter()
+            .any(|word| causes.contains(word))
+        {
+            Self::Tls { host, source }
+        } else {
+            Self::Connect { host, source }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -22,84 +137,1020 @@ pub struct PineconeClient {
     client: Client,
     api_key: String,
     base_url: String,
+    control_plane_url: String,
+    upload_chunk_size: usize,
+    retry: RetryPolicy,
+    /// Shared with the other clients of a router.
+    retry_budget: Arc<RetryBudget>,
+    /// Data-plane hosts in order of preference, starting with `base_url`.
+    hosts: Arc<HostPool>,
+    rate_limit: Arc<Mutex<RateLimitState>>,
+    /// Requests sent and awaiting their response, retries included.
+    in_flight: Gauge,
+}
+
+#[derive(Default)]
+struct RateLimitState {
+    /// When Pinecone last answered 429 Too Many Requests.
+    last_limited: Option<Instant>,
+    /// Rate-limit and quota headers of the latest response carrying any,
+    /// and when it arrived.
+    headers: Option<(Instant, BTreeMap<String, String>)>,
+}
+
+/// Version of the Pinecone API requests are made against.
+pub const API_VERSION: &str = "2025-04";
+
+/// How long after a 429 response the client counts as rate limited.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Default host of the Pinecone control plane, which manages assistants.
+pub const DEFAULT_CONTROL_PLANE_URL: &str = "https://api.pinecone.io";
+
+/// Region an assistant's data is stored and processed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Us,
+    Eu,
+}
+
+impl Region {
+    /// Data-plane host serving the region's assistants.
+    pub fn assistant_host(self) -> &'static str {
+        match self {
+            Self::Us => "https://prod-1-data.ke.pinecone.io",
+            Self::Eu => "https://prod-eu-data.ke.pinecone.io",
+        }
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "us" => Ok(Self::Us),
+            "eu" => Ok(Self::Eu),
+            other => Err(format!("unknown region: {other}")),
+        }
+    }
+}
+
+/// Responses up to this size are buffered whole before parsing; larger ones,
+/// or ones of unknown size, are parsed as they arrive so a high `top_k` or
+/// large snippets don't hold the raw body and its parsed form in memory at
+/// once.
+const STREAM_PARSE_THRESHOLD: u64 = 256 * 1024;
+
+/// Default size of the chunks in which uploads are read from disk.
+pub const DEFAULT_UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Which HTTP version requests are sent with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/2 when the server offers it during the TLS handshake, else HTTP/1.1.
+    #[default]
+    Auto,
+    Http1,
+    /// HTTP/2 without negotiation, so every request shares one connection.
+    Http2,
+}
+
+impl std::str::FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "1" | "1.1" | "http1" => Ok(Self::Http1),
+            "2" | "http2" => Ok(Self::Http2),
+            other => Err(format!("unknown HTTP version: {other}")),
+        }
+    }
+}
+
+/// Connection settings of the HTTP client, tunable for concurrent load.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HttpOptions {
+    pub version: HttpVersion,
+    /// Idle connections kept open per host for reuse.
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle connection is kept; `None` keeps it indefinitely.
+    pub pool_idle_timeout: Option<Duration>,
+    /// Interval of TCP keep-alive probes and HTTP/2 pings on open
+    /// connections, so proxies and load balancers don't drop them.
+    pub keep_alive_interval: Option<Duration>,
+    /// How long connecting may take; `None` waits indefinitely.
+    pub connect_timeout: Option<Duration>,
+    /// How long a whole request may take, response body included; `None`
+    /// waits indefinitely.
+    pub request_timeout: Option<Duration>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            version: HttpVersion::Auto,
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            keep_alive_interval: Some(Duration::from_secs(30)),
+            connect_timeout: Some(Duration::from_secs(10)),
+            request_timeout: Some(Duration::from_secs(300)),
+        }
+    }
+}
+
+/// Which failed responses are retried, and how often.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RetryPolicy {
+    /// HTTP statuses treated as transient.
+    pub statuses: Vec<u16>,
+    /// Retries after the first attempt; 0 disables retries.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each further one unless the
+    /// response sets `Retry-After`.
+    pub initial_backoff: Duration,
+    /// Retries allowed, as a percentage of the requests over
+    /// `budget_window`; further failures are returned without retrying.
+    pub budget_percent: u32,
+    pub budget_window: Duration,
+}
+
+/// Statuses retried unless configured otherwise.
+pub const DEFAULT_RETRY_STATUSES: [u16; 4] = [429, 502, 503, 504];
+
+/// Longest wait before a retry, whatever `Retry-After` asks for.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            statuses: DEFAULT_RETRY_STATUSES.to_vec(),
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            budget_percent: 20,
+            budget_window: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn budget(&self) -> Arc<RetryBudget> {
+        Arc::new(RetryBudget::new(self.budget_percent, self.budget_window))
+    }
+
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after
+            .unwrap_or_else(|| {
+                self.initial_backoff
+                    .saturating_mul(2u32.saturating_pow(attempt))
+            })
+            .min(MAX_BACKOFF)
+    }
 }
 
-#[derive(Debug, Serialize)]
+/// Collects the rate-limit and remaining-quota headers of a response, such
+/// as `x-ratelimit-remaining`.
+fn rate_limit_headers(response: &Response) -> BTreeMap<String, String> {
+    response
+        .headers()
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            name.contains("ratelimit") || name.contains("rate-limit") || name.contains("quota")
+        })
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Whether sending `request` again has the same effect as sending it once,
+/// so a failed attempt may be retried: reads, and context queries, which only
+/// retrieve. A retried chat could be answered and billed twice, and a write
+/// applied twice.
+fn is_idempotent(request: &Request) -> bool {
+    request.method() == Method::GET
+        || (request.method() == Method::POST && request.url().path().ends_with("/context"))
+}
+
+/// Parses a `Retry-After` header given in seconds; dates are ignored.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?;
+    let secs = value.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+impl HttpOptions {
+    fn build_client(&self) -> Client {
+        let mut builder = Client::builder()
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.keep_alive_interval);
+        builder = match self.version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+        if let Some(interval) = self.keep_alive_interval {
+            builder = builder
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_while_idle(true);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        // Only fails if the TLS backend can't be initialized, like `Client::new`.
+        builder
+            .build()
+            .expect("failed to initialize the HTTP client")
+    }
+}
+
+/// Request body of the assistant context API.
+#[derive(Clone, Debug, Default, Serialize)]
 pub struct AssistantContext {
+    /// What to retrieve context for; unused when `messages` is given.
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub query: String,
 
+    /// A conversation to retrieve context for instead of a single query: its
+    /// last message asks for the context, the earlier ones qualify it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub messages: Option<Vec<Message>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_k: Option<u32>,
+
+    /// Return snippets of images and figures as well as text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multimodal: Option<bool>,
+
+    /// Include the image data of multimodal snippets (base64), not just captions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_binary_content: Option<bool>,
+
+    /// Mark the spans of each snippet that match the query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_highlights: Option<bool>,
+
+    /// Only retrieve from files whose metadata matches this filter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Response of the assistant context API. Snippets are kept as raw JSON so
+/// fields added by newer API versions are passed through untouched.
+#[derive(Clone, Debug, Deserialize)]
 pub struct AssistantContextResponse {
     pub snippets: Vec<serde_json::Value>,
     pub usage: serde_json::Value,
 }
 
+/// A chat message exchanged with an assistant.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// Request body of the assistant chat API.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AssistantChat {
+    pub messages: Vec<Message>,
+    pub stream: bool,
+
+    /// Answer with a JSON document instead of prose.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_response: Option<bool>,
+
+    /// Generation model of the answer; Pinecone's default when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// Sampling temperature; lower values give more deterministic answers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Nucleus sampling: only the most likely tokens whose probabilities add
+    /// up to this value are considered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+}
+
+/// Response of the assistant chat API.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AssistantChatResponse {
+    pub message: Message,
+
+    #[serde(default)]
+    pub id: Option<String>,
+
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+
+    #[serde(default)]
+    pub model: Option<String>,
+
+    #[serde(default)]
+    pub citations: Vec<serde_json::Value>,
+
+    #[serde(default)]
+    pub usage: serde_json::Value,
+}
+
+/// Receives the text of a streamed chat answer as it is generated.
+pub type ChatDeltas<'a> = dyn Fn(&str) + Send + Sync + 'a;
+
+/// A streamed chat answer, assembled from the events of the stream.
+struct StreamedChat(AssistantChatResponse);
+
+impl StreamedChat {
+    fn new() -> Self {
+        Self(AssistantChatResponse {
+            message: Message {
+                role: "assistant".to_string(),
+                content: String::new(),
+            },
+            id: None,
+            finish_reason: None,
+            model: None,
+            citations: Vec::new(),
+            usage: serde_json::Value::Null,
+        })
+    }
+
+    /// Applies one line of the stream, returning the text it adds, if any.
+    fn apply(&mut self, line: &[u8]) -> Result<Option<String>, PineconeError> {
+        let line = String::from_utf8_lossy(line);
+        let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+            return Ok(None);
+        };
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let event: serde_json::Value = serde_json::from_str(data)?;
+        if let Some(id) = event["id"].as_str() {
+            self.0.id = Some(id.to_string());
+        }
+        if let Some(model) = event["model"].as_str() {
+            self.0.model = Some(model.to_string());
+        }
+        match event["type"].as_str() {
+            Some("content_chunk") => {
+                let delta = event["delta"]["content"].as_str().unwrap_or_default();
+                self.0.message.content.push_str(delta);
+                return Ok(Some(delta.to_string()).filter(|delta| !delta.is_empty()));
+            }
+            Some("citation") => self.0.citations.push(event["citation"].clone()),
+            Some("message_end") => {
+                self.0.finish_reason = event["finish_reason"].as_str().map(str::to_string);
+                self.0.usage = event["usage"].clone();
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+}
+
+/// A file uploaded to an assistant.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AssistantFile {
+    pub name: String,
+    pub id: String,
+
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+
+    #[serde(default)]
+    pub created_on: Option<String>,
+
+    #[serde(default)]
+    pub updated_on: Option<String>,
+
+    /// Size of the file in bytes.
+    #[serde(default)]
+    pub size: Option<u64>,
+
+    /// `Processing`, `Available`, `Deleting` or `ProcessingFailed`.
+    #[serde(default)]
+    pub status: Option<String>,
+
+    #[serde(default)]
+    pub percent_done: Option<f64>,
+
+    #[serde(default)]
+    pub signed_url: Option<String>,
+
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListFilesResponse {
+    #[serde(default)]
+    files: Vec<AssistantFile>,
+}
+
+/// An assistant as described by the control plane.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Assistant {
+    pub name: String,
+
+    #[serde(default)]
+    pub instructions: Option<String>,
+
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+
+    #[serde(default)]
+    pub status: Option<String>,
+
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListAssistantsResponse {
+    #[serde(default)]
+    assistants: Vec<Assistant>,
+}
+
+/// Settings of an assistant that can be given on creation or changed later.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AssistantSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct CreateAssistant<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    settings: &'a AssistantSettings,
+}
+
 impl PineconeClient {
     pub fn new(api_key: String, base_url: String) -> Self {
         Self {
             client: Client::new(),
             api_key,
+            hosts: Arc::new(HostPool::new(vec![base_url.clone()])),
+            rate_limit: Arc::default(),
+            in_flight: Gauge::default(),
             base_url,
+            control_plane_url: DEFAULT_CONTROL_PLANE_URL.to_string(),
+            upload_chunk_size: DEFAULT_UPLOAD_CHUNK_SIZE,
+            retry_budget: RetryPolicy::default().budget(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets which failed responses are retried. Requests with streamed
+    /// bodies, like uploads, are never retried.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry_budget = retry.budget();
+        self.retry = retry;
+        self
+    }
+
+    /// Shares a retry budget with other clients, so it caps their retries
+    /// together.
+    pub(crate) fn with_retry_budget(mut self, retry_budget: Arc<RetryBudget>) -> Self {
+        self.retry_budget = retry_budget;
+        self
+    }
+
+    /// Sets the size of the chunks in which uploads are streamed from disk,
+    /// which bounds the memory an upload holds at a time.
+    pub fn with_upload_chunk_size(mut self, upload_chunk_size: usize) -> Self {
+        self.upload_chunk_size = upload_chunk_size.max(1);
+        self
+    }
+
+    /// Rebuilds the HTTP client with the given connection settings.
+    pub fn with_http_options(mut self, options: &HttpOptions) -> Self {
+        self.client = options.build_client();
+        self
+    }
+
+    /// Adds data-plane hosts to fail over to, in order of preference, when
+    /// the base host is unreachable or keeps returning server errors.
+    /// Requests return to the base host once it recovers.
+    pub fn with_failover_hosts(mut self, failover_hosts: Vec<String>) -> Self {
+        let mut hosts = vec![self.base_url.clone()];
+        for host in failover_hosts {
+            let host = host.trim_end_matches('/').to_string();
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        self.hosts = Arc::new(HostPool::new(hosts));
+        self
+    }
+
+    /// Overrides the control plane host used for assistant management calls.
+    pub fn with_control_plane_url(mut self, control_plane_url: String) -> Self {
+        self.control_plane_url = control_plane_url;
+        self
+    }
+
+    /// A client for another project: same hosts, settings and connection
+    /// pool, but its own API key and rate-limit state.
+    pub fn with_api_key(&self, api_key: String) -> Self {
+        Self {
+            api_key,
+            rate_limit: Arc::default(),
+            ..self.clone()
         }
     }
 
+    pub async fn describe_assistant(
+        &self,
+        assistant_name: &str,
+    ) -> Result<Assistant, PineconeError> {
+        let url = format!(
+            "{}/assistant/assistants/{}",
+            self.control_plane_url, assistant_name
+        );
+
+        self.send(self.client.get(&url), assistant_name).await
+    }
+
+    /// Lists the assistants of the project the API key belongs to.
+    pub async fn list_assistants(&self) -> Result<Vec<Assistant>, PineconeError> {
+        let url = format!("{}/assistant/assistants", self.control_plane_url);
+
+        let response: ListAssistantsResponse = self.send(self.client.get(&url), "").await?;
+        Ok(response.assistants)
+    }
+
+    /// Creates an assistant. It can only be used once its status is `Ready`.
+    pub async fn create_assistant(
+        &self,
+        assistant_name: &str,
+        settings: &AssistantSettings,
+    ) -> Result<Assistant, PineconeError> {
+        let url = format!("{}/assistant/assistants", self.control_plane_url);
+        let request_body = CreateAssistant {
+            name: assistant_name,
+            settings,
+        };
+
+        self.post(&url, &request_body, assistant_name).await
+    }
+
+    /// Changes the given settings of an assistant, leaving the others as they are.
+    pub async fn update_assistant(
+        &self,
+        assistant_name: &str,
+        settings: &AssistantSettings,
+    ) -> Result<Assistant, PineconeError> {
+        let url = format!(
+            "{}/assistant/assistants/{}",
+            self.control_plane_url, assistant_name
+        );
+
+        self.send(self.client.patch(&url).json(settings), assistant_name)
+            .await
+    }
+
     pub async fn assistant_context(
         &self,
         assistant_name: &str,
         query: &str,
         top_k: Option<u32>,
+    ) -> Result<AssistantContextResponse, PineconeError> {
+        let request = AssistantContext {
+            query: query.to_string(),
+            top_k,
+            ..AssistantContext::default()
+        };
+
+        self.assistant_context_with(assistant_name, &request).await
+    }
+
+    /// Retrieves context with every option of the context API.
+    pub async fn assistant_context_with(
+        &self,
+        assistant_name: &str,
+        request: &AssistantContext,
     ) -> Result<AssistantContextResponse, PineconeError> {
         let url = format!(
             "{}/assistant/chat/{}/context",
             self.base_url, assistant_name
         );
 
-        let request_body = AssistantContext {
-            query: query.to_string(),
-            top_k,
+        self.post(&url, request, assistant_name).await
+    }
+
+    pub async fn assistant_chat(
+        &self,
+        assistant_name: &str,
+        messages: Vec<Message>,
+    ) -> Result<AssistantChatResponse, PineconeError> {
+        let request = AssistantChat {
+            messages,
+            ..AssistantChat::default()
         };
 
-        let response = self
+        self.assistant_chat_with(assistant_name, &request).await
+    }
+
+    /// Generates an answer with every option of the chat API.
+    pub async fn assistant_chat_with(
+        &self,
+        assistant_name: &str,
+        request: &AssistantChat,
+    ) -> Result<AssistantChatResponse, PineconeError> {
+        let url = format!("{}/assistant/chat/{}", self.base_url, assistant_name);
+
+        self.post(&url, request, assistant_name).await
+    }
+
+    /// Generates an answer as a stream, passing each piece of text to
+    /// `on_delta` as it arrives, and returns the whole answer once complete.
+    pub async fn assistant_chat_stream(
+        &self,
+        assistant_name: &str,
+        request: &AssistantChat,
+        on_delta: &ChatDeltas<'_>,
+    ) -> Result<AssistantChatResponse, PineconeError> {
+        let url = format!("{}/assistant/chat/{}", self.base_url, assistant_name);
+
+        let request_body = AssistantChat {
+            stream: true,
+            ..request.clone()
+        };
+        let request = self
             .client
             .post(&url)
-            .header("Api-Key", &self.api_key)
-            .header("accept", "application/json")
             .header("Content-Type", "application/json")
-            .header("X-Pinecone-API-Version", "2025-04")
-            .json(&request_body)
-            .send()
+            .json(&request_body);
+        let response = self.send_raw(request, assistant_name).await?;
+
+        let mut answer = StreamedChat::new();
+        let mut body = response.bytes_stream();
+        let mut pending = Vec::new();
+        while let Some(bytes) = body.try_next().await? {
+            pending.extend_from_slice(&bytes);
+            while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                if let Some(delta) = answer.apply(&line)? {
+                    on_delta(&delta);
+                }
+            }
+        }
+        if let Some(delta) = answer.apply(&pending)? {
+            on_delta(&delta);
+        }
+        Ok(answer.0)
+    }
+
+    /// Uploads a file to an assistant, attaching `metadata` when given. The
+    /// file is streamed from disk in chunks, so large documents are never held
+    /// in memory as a whole.
+    pub async fn upload_file(
+        &self,
+        assistant_name: &str,
+        path: &Path,
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<AssistantFile, PineconeError> {
+        let url = format!("{}/assistant/files/{}", self.base_url, assistant_name);
+
+        let file = tokio::fs::File::open(path).await?;
+        let length = file.metadata().await?.len();
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "upload".to_string());
+        let body = Body::wrap_stream(ReaderStream::with_capacity(file, self.upload_chunk_size));
+        let part = Part::stream_with_length(body, length)
+            .file_name(file_name)
+            .mime_str(mime_type(path))?;
+        let form = Form::new().part("file", part);
+
+        let mut request = self.client.post(&url).multipart(form);
+        if let Some(metadata) = metadata {
+            request = request.query(&[("metadata", metadata.to_string())]);
+        }
+        self.send(request, assistant_name).await
+    }
+
+    /// Lists an assistant's files, optionally only those whose metadata
+    /// matches `filter`.
+    pub async fn list_files(
+        &self,
+        assistant_name: &str,
+        filter: Option<&serde_json::Value>,
+    ) -> Result<Vec<AssistantFile>, PineconeError> {
+        let url = format!("{}/assistant/files/{}", self.base_url, assistant_name);
+
+        let mut request = self.client.get(&url);
+        if let Some(filter) = filter {
+            request = request.query(&[("filter", filter.to_string())]);
+        }
+        let response: ListFilesResponse = self.send(request, assistant_name).await?;
+        Ok(response.files)
+    }
+
+    pub async fn delete_file(
+        &self,
+        assistant_name: &str,
+        file_id: &str,
+    ) -> Result<(), PineconeError> {
+        let url = format!(
+            "{}/assistant/files/{}/{}",
+            self.base_url, assistant_name, file_id
+        );
+
+        self.send_raw(self.client.delete(&url), assistant_name)
             .await?;
+        Ok(())
+    }
+
+    async fn post<B: Serialize, R: DeserializeOwned + Send + 'static>(
+        &self,
+        url: &str,
+        request_body: &B,
+        assistant_name: &str,
+    ) -> Result<R, PineconeError> {
+        let request = self
+            .client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .json(request_body);
+        self.send(request, assistant_name).await
+    }
+
+    async fn send<R: DeserializeOwned + Send + 'static>(
+        &self,
+        request: RequestBuilder,
+        assistant_name: &str,
+    ) -> Result<R, PineconeError> {
+        let response = self.send_raw(request, assistant_name).await?;
+        if response
+            .content_length()
+            .is_some_and(|len| len <= STREAM_PARSE_THRESHOLD)
+        {
+            return Ok(response.json::<R>().await?);
+        }
+        let body = response.bytes_stream().map_err(std::io::Error::other);
+        let reader = std::io::BufReader::new(SyncIoBridge::new(StreamReader::new(body)));
+        tokio::task::spawn_blocking(move || serde_json::from_reader(reader))
+            .await
+            .map_err(std::io::Error::other)?
+            .map_err(PineconeError::from)
+    }
+
+    /// Sends a request, retrying transient failures and turning unsuccessful
+    /// responses into errors.
+    async fn send_raw(
+        &self,
+        request: RequestBuilder,
+        assistant_name: &str,
+    ) -> Result<Response, PineconeError> {
+        let _in_flight = self.in_flight.enter();
+        let mut request = request
+            .header("Api-Key", &self.api_key)
+            .header("accept", "application/json")
+            .header("X-Pinecone-API-Version", API_VERSION)
+            .build()?;
+        let method = request.method().clone();
+        let path = request.url().path().to_string();
+        let retryable = is_idempotent(&request);
+        self.retry_budget.record_request();
+        let mut attempt = 0;
+        let mut failovers = 0;
+        let response = loop {
+            let host = self.route(&mut request);
+            let next = request.try_clone();
+            let response = match self.client.execute(request).await {
+                Ok(response) => response,
+                Err(e) => {
+                    // Unreachable hosts are skipped right away, without backoff.
+                    if let (Some(host), true) = (&host, e.is_connect()) {
+                        self.hosts.record(host, Outcome::Unreachable);
+                        if let Some(next) = next.filter(|_| failovers + 1 < self.hosts.len()) {
+                            failovers += 1;
+                            request = next;
+                            continue;
+                        }
+                    }
+                    return Err(e.into());
+                }
+            };
+            let status = response.status().as_u16();
+            let headers = rate_limit_headers(&response);
+            if !headers.is_empty() {
+                tracing::debug!("{method} {path} rate limits: {headers:?}");
+            }
+            if status == 429 || !headers.is_empty() {
+                let now = Instant::now();
+                let mut rate_limit = self.lock_rate_limit();
+                if status == 429 {
+                    rate_limit.last_limited = Some(now);
+                }
+                if !headers.is_empty() {
+                    rate_limit.headers = Some((now, headers));
+                }
+            }
+            if let Some(host) = &host {
+                let outcome = if response.status().is_server_error() {
+                    Outcome::ServerError
+                } else {
+                    Outcome::Success
+                };
+                self.hosts.record(host, outcome);
+            }
+            match next.filter(|_| retryable && attempt < self.retry.max_retries) {
+                Some(next) if self.retry.statuses.contains(&status) => {
+                    if !self.retry_budget.try_retry() {
+                        tracing::warn!(
+                            "{method} {path} returned {status}; not retrying, the retry budget is spent"
+                        );
+                        break response;
+                    }
+                    let delay = self.retry.backoff(attempt, retry_after(&response));
+                    tracing::warn!("{method} {path} returned {status}; retrying in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    request = next;
+                    attempt += 1;
+                }
+                _ => break response,
+            }
+        };
 
         let status = response.status();
         if !status.is_success() {
             let error_text = response.text().await?;
-            match status.as_u16() {
-                404 => {
-                    return Err(PineconeError::NotFound {
-                        resource: format!("assistant \"{assistant_name}\""),
-                    });
-                }
-                s => {
-                    return Err(PineconeError::Api {
-                        status: s,
-                        message: error_text,
-                    });
-                }
+            let resource = if assistant_name.is_empty() {
+                "assistants".to_string()
+            } else {
+                format!("assistant \"{assistant_name}\"")
+            };
+            if status.as_u16() == 404 {
+                return Err(PineconeError::NotFound { resource });
             }
+            reporting::api_error(
+                status.as_u16(),
+                method.as_str(),
+                &path,
+                &error_text,
+                assistant_name,
+            );
+            return Err(match status.as_u16() {
+                402 => PineconeError::QuotaExceeded {
+                    message: error_text,
+                },
+                403 => PineconeError::Forbidden {
+                    resource,
+                    message: error_text,
+                },
+                s => PineconeError::Api {
+                    status: s,
+                    message: error_text,
+                },
+            });
+        }
+
+        Ok(response)
+    }
+
+    /// Checks that the control plane, and the data plane of `assistant_name`
+    /// if given, are reachable and accept the API key, and reports the state
+    /// of the data-plane hosts and of rate limiting.
+    pub async fn health(&self, assistant_name: Option<&str>) -> serde_json::Value {
+        let control_plane = probe(self.list_assistants()).await;
+        let data_plane = match assistant_name {
+            Some(assistant_name) => Some(probe(self.list_files(assistant_name, None)).await),
+            None => None,
+        };
+        let status = [Some(&control_plane), data_plane.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|probe| probe["status"].as_str().unwrap_or("error"))
+            .find(|status| *status != "ok")
+            .unwrap_or("ok");
+        let (last_rate_limited, rate_limit_headers) = {
+            let rate_limit = self.lock_rate_limit();
+            (
+                rate_limit.last_limited.map(|at| at.elapsed()),
+                rate_limit.headers.clone(),
+            )
+        };
+        serde_json::json!({
+            "status": status,
+            "host": self.base_url,
+            "control_plane_host": self.control_plane_url,
+            "api_version": API_VERSION,
+            "control_plane": control_plane,
+            "data_plane": data_plane,
+            "hosts": self.hosts.status(),
+            "rate_limit": {
+                "limited": last_rate_limited.is_some_and(|elapsed| elapsed < RATE_LIMIT_WINDOW),
+                "last_429_secs_ago": last_rate_limited.map(|elapsed| elapsed.as_secs()),
+                "headers": rate_limit_headers.as_ref().map(|(_, headers)| headers),
+                "headers_secs_ago": rate_limit_headers.map(|(at, _)| at.elapsed().as_secs()),
+            },
+            "in_flight_requests": self.in_flight.snapshot(),
+            "retry": {
+                "statuses": self.retry.statuses,
+                "max_retries": self.retry.max_retries,
+                "budget": self.retry_budget.snapshot(),
+            },
+        })
+    }
+
+    fn lock_rate_limit(&self) -> std::sync::MutexGuard<'_, RateLimitState> {
+        self.rate_limit.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Points a data-plane request at the currently active host, returning
+    /// that host; control-plane requests are left alone.
+    fn route(&self, request: &mut Request) -> Option<String> {
+        let rest = request
+            .url()
+            .as_str()
+            .strip_prefix(self.base_url.trim_end_matches('/'))?
+            .to_string();
+        let host = self.hosts.active().to_string();
+        if host != self.base_url {
+            *request.url_mut() = Url::parse(&format!("{host}{rest}")).ok()?;
+        }
+        Some(host)
+    }
+}
+
+/// Times a health check call and classifies its failure.
+async fn probe<T>(
+    call: impl std::future::Future<Output = Result<T, PineconeError>>,
+) -> serde_json::Value {
+    let started = Instant::now();
+    let result = call.await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let status = match &result {
+        Ok(_) => "ok",
+        Err(PineconeError::Api { status: 401, .. } | PineconeError::Forbidden { .. }) => {
+            "unauthorized"
+        }
+        Err(PineconeError::Api { status: 429, .. } | PineconeError::QuotaExceeded { .. }) => {
+            "rate_limited"
         }
+        Err(PineconeError::NotFound { .. }) => "not_found",
+        Err(
+            PineconeError::Dns { .. }
+            | PineconeError::Tls { .. }
+            | PineconeError::Connect { .. }
+            | PineconeError::ConnectTimeout { .. }
+            | PineconeError::ReadTimeout { .. },
+        ) => "unreachable",
+        Err(_) => "error",
+    };
+    serde_json::json!({
+        "status": status,
+        "latency_ms": latency_ms,
+        "error": result.err().map(|e| e.to_string()),
+    })
+}
 
-        Ok(response.json::<AssistantContextResponse>().await?)
+/// MIME type of the document formats assistants accept, by extension.
+pub(crate) fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("json") => "application/json",
+        Some("docx") => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        _ => "application/octet-stream",
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::temp_dir;
     use mockito::Server;
+    use serde_json::json;
 
     #[tokio::test]
     async fn test_somke() {
@@ -123,6 +1174,471 @@ mod tests {
         assert_eq!(response.snippets[1]["text"], "snippet 2");
     }
 
+    #[tokio::test]
+    async fn test_large_responses_are_parsed_as_they_stream() {
+        let snippet = json!({"content": "x".repeat(1024), "score": 0.5});
+        let body = json!({"snippets": vec![snippet; 512], "usage": {"total_tokens": 100}});
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/assistant/chat/kb/context")
+            .with_status(200)
+            .with_body(body.to_string())
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/assistant/chat/truncated/context")
+            .with_status(200)
+            .with_chunked_body(|w| w.write_all(br#"{"snippets": [{"content""#))
+            .create_async()
+            .await;
+        let client = PineconeClient::new("test-api-key".to_string(), server.url());
+
+        let response = client.assistant_context("kb", "q", None).await.unwrap();
+        let truncated = client.assistant_context("truncated", "q", None).await;
+
+        assert_eq!(response.snippets.len(), 512);
+        assert_eq!(response.usage["total_tokens"], 100);
+        assert!(matches!(truncated, Err(PineconeError::Json(_))));
+    }
+
+    #[tokio::test]
+    async fn test_upload_file_streams_multipart() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/assistant/files/test-assistant")
+            .match_header(
+                "content-type",
+                mockito::Matcher::Regex("^multipart/form-data".to_string()),
+            )
+            .match_body(mockito::Matcher::Regex("(?s)notes.md.*# Notes".to_string()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"name": "notes.md", "id": "f1", "status": "Processing"}"#)
+            .create();
+
+        let temp = temp_dir();
+        let dir = temp.path();
+        let path = dir.join("notes.md");
+        std::fs::write(&path, "# Notes\n".repeat(100)).unwrap();
+
+        let client = PineconeClient::new("test-api-key".to_string(), server.url())
+            .with_upload_chunk_size(16);
+        let result = client.upload_file("test-assistant", &path, None).await;
+
+        mock.assert();
+        let file = result.unwrap();
+        assert_eq!(file.id, "f1");
+        assert_eq!(file.status.as_deref(), Some("Processing"));
+    }
+
+    #[tokio::test]
+    async fn test_list_files_with_filter() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/assistant/files/test-assistant")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "filter".to_string(),
+                r#"{"content_sha256":"abc"}"#.to_string(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"files": [{"name": "a.pdf", "id": "f1", "metadata": {"content_sha256": "abc"}}]}"#)
+            .create();
+
+        let client = PineconeClient::new("test-api-key".to_string(), server.url());
+        let files = client
+            .list_files(
+                "test-assistant",
+                Some(&serde_json::json!({"content_sha256": "abc"})),
+            )
+            .await
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].id, "f1");
+    }
+
+    #[tokio::test]
+    async fn test_create_assistant_and_delete_file() {
+        let mut server = Server::new_async().await;
+        let create = server
+            .mock("POST", "/assistant/assistants")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "name": "test-assistant",
+                "instructions": "Be brief."
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"name": "test-assistant", "status": "Initializing"}"#)
+            .create();
+        let delete = server
+            .mock("DELETE", "/assistant/files/test-assistant/f1")
+            .with_status(200)
+            .create();
+
+        let client = PineconeClient::new("test-api-key".to_string(), server.url())
+            .with_control_plane_url(server.url());
+        let settings = AssistantSettings {
+            instructions: Some("Be brief.".to_string()),
+            ..AssistantSettings::default()
+        };
+        let assistant = client
+            .create_assistant("test-assistant", &settings)
+            .await
+            .unwrap();
+        client.delete_file("test-assistant", "f1").await.unwrap();
+
+        create.assert();
+        delete.assert();
+        assert_eq!(assistant.status.as_deref(), Some("Initializing"));
+    }
+
+    #[tokio::test]
+    async fn test_assistant_chat() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/assistant/chat/test-assistant")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "messages": [{"role": "user", "content": "hi"}],
+                "stream": false
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": "1", "finish_reason": "stop", "message": {"role": "assistant", "content": "hello"}, "model": "gpt-4o", "citations": [], "usage": {"total_tokens": 10}}"#)
+            .create();
+
+        let client = PineconeClient::new("test-api-key".to_string(), server.url());
+
+        let result = client
+            .assistant_chat("test-assistant", vec![Message::user("hi")])
+            .await;
+
+        mock.assert();
+        let response = result.unwrap();
+        assert_eq!(response.message.content, "hello");
+        assert_eq!(response.model.as_deref(), Some("gpt-4o"));
+    }
+
+    #[tokio::test]
+    async fn test_assistant_chat_stream() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/assistant/chat/test-assistant")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"stream": true})))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data:{\"type\":\"message_start\",\"id\":\"1\",\"model\":\"gpt-4o\",\"role\":\"assistant\"}\n\n",
+                "data:{\"type\":\"content_chunk\",\"id\":\"1\",\"delta\":{\"content\":\"Hel\"}}\n\n",
+                "data:{\"type\":\"content_chunk\",\"id\":\"1\",\"delta\":{\"content\":\"lo\"}}\n\n",
+                "data:{\"type\":\"citation\",\"id\":\"1\",\"citation\":{\"position\":5,\"references\":[]}}\n\n",
+                "data:{\"type\":\"message_end\",\"id\":\"1\",\"finish_reason\":\"stop\",\"usage\":{\"total_tokens\":10}}"
+            ))
+            .create();
+
+        let client = PineconeClient::new("test-api-key".to_string(), server.url());
+        let deltas = Mutex::new(Vec::new());
+        let request = AssistantChat {
+            messages: vec![Message::user("hi")],
+            ..AssistantChat::default()
+        };
+
+        let result = client
+            .assistant_chat_stream("test-assistant", &request, &|delta| {
+                deltas.lock().unwrap().push(delta.to_string())
+            })
+            .await;
+
+        mock.assert();
+        let response = result.unwrap();
+        assert_eq!(*deltas.lock().unwrap(), ["Hel", "lo"]);
+        assert_eq!(response.message.content, "Hello");
+        assert_eq!(response.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(response.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(response.citations.len(), 1);
+        assert_eq!(response.usage["total_tokens"], 10);
+    }
+
+    #[tokio::test]
+    async fn test_describe_assistant() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/assistant/assistants/test-assistant")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"name": "test-assistant", "instructions": "Answer politely", "metadata": {}, "status": "Ready", "host": "https://example.com"}"#)
+            .create();
+
+        let client = PineconeClient::new("test-api-key".to_string(), "unused".to_string())
+            .with_control_plane_url(server.url());
+
+        let assistant = client.describe_assistant("test-assistant").await.unwrap();
+
+        mock.assert();
+        assert_eq!(assistant.instructions.as_deref(), Some("Answer politely"));
+        assert_eq!(assistant.status.as_deref(), Some("Ready"));
+    }
+
+    #[tokio::test]
+    async fn test_quota_and_permission_errors() {
+        let mut server = Server::new_async().await;
+        let quota = server
+            .mock("POST", "/assistant/chat/kb/context")
+            .with_status(402)
+            .with_body(r#"{"error": "Monthly context token limit reached"}"#)
+            .create();
+        let forbidden = server
+            .mock("GET", "/assistant/files/kb")
+            .with_status(403)
+            .with_body(r#"{"error": "Forbidden"}"#)
+            .create();
+        let client = PineconeClient::new("test-api-key".to_string(), server.url());
+
+        let context = client.assistant_context("kb", "q", None).await;
+        let files = client.list_files("kb", None).await;
+
+        quota.assert();
+        forbidden.assert();
+        let context = context.unwrap_err();
+        assert!(matches!(context, PineconeError::QuotaExceeded { .. }));
+        assert!(context.to_string().contains("upgrade the Pinecone plan"));
+        assert!(matches!(
+            files,
+            Err(PineconeError::Forbidden { resource, .. }) if resource == "assistant \"kb\""
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_http_options_apply_to_requests() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/assistant/assistants")
+            .with_status(200)
+            .with_body(r#"{"assistants": []}"#)
+            .create_async()
+            .await;
+        let options = HttpOptions {
+            version: HttpVersion::Http1,
+            pool_max_idle_per_host: 0,
+            pool_idle_timeout: None,
+            keep_alive_interval: None,
+            connect_timeout: None,
+            request_timeout: None,
+        };
+        let client = PineconeClient::new("test-api-key".to_string(), server.url())
+            .with_control_plane_url(server.url())
+            .with_http_options(&options);
+
+        assert!(client.list_assistants().await.unwrap().is_empty());
+        mock.assert_async().await;
+        assert_eq!("2".parse(), Ok(HttpVersion::Http2));
+        assert_eq!("HTTP1".parse(), Ok(HttpVersion::Http1));
+        assert!("3".parse::<HttpVersion>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retryable_statuses_are_retried() {
+        let mut server = Server::new_async().await;
+        let gateway_error = server
+            .mock("GET", "/assistant/assistants")
+            .with_status(520)
+            .with_header("retry-after", "0")
+            .expect(1)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("GET", "/assistant/assistants")
+            .with_status(200)
+            .with_body(r#"{"assistants": []}"#)
+            .create_async()
+            .await;
+        let retry = |statuses: Vec<u16>| RetryPolicy {
+            statuses,
+            max_retries: 1,
+            initial_backoff: Duration::ZERO,
+            ..RetryPolicy::default()
+        };
+        let client = PineconeClient::new("test-api-key".to_string(), server.url())
+            .with_control_plane_url(server.url());
+
+        let retried = client.clone().with_retry_policy(retry(vec![520]));
+        assert!(retried.list_assistants().await.unwrap().is_empty());
+        gateway_error.assert_async().await;
+        ok.assert_async().await;
+
+        // Statuses outside the policy fail on the first attempt.
+        let gateway_error = server
+            .mock("GET", "/assistant/assistants")
+            .with_status(520)
+            .expect(1)
+            .create_async()
+            .await;
+        let not_retried = client.with_retry_policy(retry(DEFAULT_RETRY_STATUSES.to_vec()));
+        assert!(matches!(
+            not_retried.list_assistants().await,
+            Err(PineconeError::Api { status: 520, .. })
+        ));
+        gateway_error.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_only_idempotent_requests_are_retried() {
+        let mut server = Server::new_async().await;
+        let context = server
+            .mock("POST", "/assistant/chat/kb/context")
+            .with_status(503)
+            .expect(2)
+            .create_async()
+            .await;
+        let chat = server
+            .mock("POST", "/assistant/chat/kb")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let client = PineconeClient::new("test-api-key".to_string(), server.url())
+            .with_retry_policy(RetryPolicy {
+                max_retries: 1,
+                initial_backoff: Duration::ZERO,
+                ..RetryPolicy::default()
+            });
+
+        assert!(client.assistant_context("kb", "q", None).await.is_err());
+        assert!(
+            client
+                .assistant_chat("kb", vec![Message::user("hi")])
+                .await
+                .is_err()
+        );
+        context.assert_async().await;
+        chat.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_when_the_budget_is_spent() {
+        let mut server = Server::new_async().await;
+        // One request and the budget's floor of three retries.
+        let unavailable = server
+            .mock("GET", "/assistant/assistants")
+            .with_status(503)
+            .expect(4)
+            .create_async()
+            .await;
+        let client = PineconeClient::new("test-api-key".to_string(), server.url())
+            .with_control_plane_url(server.url())
+            .with_retry_policy(RetryPolicy {
+                max_retries: 10,
+                initial_backoff: Duration::ZERO,
+                budget_percent: 0,
+                ..RetryPolicy::default()
+            });
+
+        assert!(matches!(
+            client.list_assistants().await,
+            Err(PineconeError::Api { status: 503, .. })
+        ));
+        unavailable.assert_async().await;
+        let health = client.health(None).await;
+        assert_eq!(health["retry"]["budget"]["exhausted"], true);
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_and_honors_retry_after() {
+        let retry = RetryPolicy::default();
+        assert_eq!(retry.backoff(0, None), Duration::from_millis(500));
+        assert_eq!(retry.backoff(2, None), Duration::from_secs(2));
+        assert_eq!(
+            retry.backoff(0, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            retry.backoff(0, Some(Duration::from_secs(600))),
+            MAX_BACKOFF
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_auth_and_rate_limits() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/assistant/assistants")
+            .with_status(401)
+            .with_body("Invalid API key")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/assistant/files/kb")
+            .with_status(429)
+            .with_header("x-ratelimit-remaining", "0")
+            .with_header("x-ratelimit-reset", "12")
+            .create_async()
+            .await;
+        let client = PineconeClient::new("test-api-key".to_string(), server.url())
+            .with_control_plane_url(server.url())
+            .with_retry_policy(RetryPolicy {
+                max_retries: 0,
+                ..RetryPolicy::default()
+            });
+
+        let health = client.health(Some("kb")).await;
+
+        assert_eq!(health["status"], "unauthorized");
+        assert_eq!(health["control_plane"]["status"], "unauthorized");
+        assert_eq!(health["data_plane"]["status"], "rate_limited");
+        assert_eq!(health["rate_limit"]["limited"], true);
+        assert_eq!(
+            health["rate_limit"]["headers"],
+            serde_json::json!({"x-ratelimit-remaining": "0", "x-ratelimit-reset": "12"})
+        );
+        assert_eq!(health["hosts"][0]["state"], "up");
+        assert_eq!(health["in_flight_requests"]["current"], 0);
+        assert_eq!(health["in_flight_requests"]["peak"], 1);
+        assert_eq!(health["api_version"], API_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_the_next_host() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/assistant/files/kb")
+            .with_status(200)
+            .with_body(r#"{"files": []}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let client =
+            PineconeClient::new("test-api-key".to_string(), "http://127.0.0.1:1".to_string())
+                .with_failover_hosts(vec![server.url()]);
+
+        assert!(client.list_files("kb", None).await.unwrap().is_empty());
+        // The unreachable host is now skipped.
+        assert!(client.list_files("kb", None).await.unwrap().is_empty());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_connection_failures_are_classified() {
+        let unresolvable = PineconeClient::new(
+            "test-api-key".to_string(),
+            "http://assistant.invalid".to_string(),
+        );
+        let refused =
+            PineconeClient::new("test-api-key".to_string(), "http://127.0.0.1:1".to_string());
+
+        let dns = unresolvable.list_files("kb", None).await.unwrap_err();
+        let connect = refused.list_files("kb", None).await.unwrap_err();
+
+        assert!(
+            matches!(&dns, PineconeError::Dns { host, .. } if host == "assistant.invalid"),
+            "{dns:?}"
+        );
+        assert!(
+            matches!(&connect, PineconeError::Connect { host, .. } if host == "127.0.0.1"),
+            "{connect:?}"
+        );
+    }
+
     #[tokio::test]
     async fn test_query_assistant_error() {
         let mut server = Server::new_async().await;
@@ -0,0 +1,35 @@
+use sha2::{Digest, Sha256};
+
+/// Renders user-supplied text (queries, snippets) for logging.
+///
+/// In privacy mode the text itself never reaches the log; only a short
+/// SHA-256 prefix and the byte length are emitted, which is enough to
+/// correlate repeated queries without exposing their content.
+pub fn loggable(text: &str, privacy_mode: bool) -> String {
+    if privacy_mode {
+        let digest = Sha256::digest(text.as_bytes());
+        let hex = format!("{digest:x}");
+        format!("sha256:{} ({} bytes)", &hex[..12], text.len())
+    } else {
+        format!("{text:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loggable_hides_text_in_privacy_mode() {
+        let rendered = loggable("my secret question", true);
+        assert!(!rendered.contains("secret"));
+        assert!(rendered.starts_with("sha256:"));
+        assert!(rendered.ends_with("(18 bytes)"));
+        assert_eq!(rendered, loggable("my secret question", true));
+    }
+
+    #[test]
+    fn test_loggable_passes_text_through_by_default() {
+        assert_eq!(loggable("hello", false), "\"hello\"");
+    }
+}
@@ -0,0 +1,170 @@
+//! Query transformations applied before retrieval.
+
+use crate::peer::{Peer, PeerError};
+
+const REWRITE_SYSTEM_PROMPT: &str = "You rewrite user questions into standalone search queries \
+for a document retrieval system. Resolve pronouns and references such as \"the other one\" \
+using the conversation so far, keep the key terms, and drop filler words. \
+Reply with the rewritten query only, without quotes or explanation.";
+
+const REWRITE_MAX_TOKENS: u32 = 200;
+
+const EXPAND_SYSTEM_PROMPT: &str = "You break broad questions into focused search queries \
+for a document retrieval system. Each query must be self-contained and cover a different \
+aspect of the question. Reply with one query per line and nothing else.";
+
+const EXPAND_MAX_TOKENS: u32 = 400;
+
+/// Upper bound on sub-queries per call, to bound the fan-out.
+pub const MAX_SUB_QUERIES: usize = 5;
+
+/// Phrases that usually join independent parts of a question.
+const SPLIT_PHRASES: &[&str] = &[
+    " as well as ",
+    " and also ",
+    " and how ",
+    " and what ",
+    " and when ",
+    " and where ",
+    " and which ",
+    " and who ",
+    " and why ",
+];
+
+/// Asks the client's LLM to turn `query` into a retrieval-friendly,
+/// self-contained query.
+pub async fn rewrite(peer: &Peer, query: &str) -> Result<String, PeerError> {
+    let reply = peer
+        .create_message(REWRITE_SYSTEM_PROMPT, query, REWRITE_MAX_TOKENS)
+        .await?;
+    let rewritten = clean_reply(&reply);
+    if rewritten.is_empty() {
+        return Err(PeerError::InvalidResponse("empty rewrite".to_string()));
+    }
+    Ok(rewritten.to_string())
+}
+
+/// Asks the client's LLM to decompose `query` into at most `max` sub-queries.
+pub async fn expand(peer: &Peer, query: &str, max: usize) -> Result<Vec<String>, PeerError> {
+    let prompt = format!("Write at most {max} search queries for: {query}");
+    let reply = peer
+        .create_message(EXPAND_SYSTEM_PROMPT, &prompt, EXPAND_MAX_TOKENS)
+        .await?;
+    let sub_queries = dedup(
+        reply
+            .lines()
+            .map(|line| clean_reply(strip_list_marker(line.trim())))
+            .filter(|line| !line.is_empty()),
+        max,
+    );
+    if sub_queries.is_empty() {
+        return Err(PeerError::InvalidResponse("no sub-queries".to_string()));
+    }
+    Ok(sub_queries)
+}
+
+/// Splits `query` into at most `max` sub-queries at sentence boundaries and
+/// phrases joining independent questions. Returns a single element when there
+/// is nothing to split.
+pub fn split(query: &str, max: usize) -> Vec<String> {
+    let mut parts = vec![query.to_string()];
+    for phrase in SPLIT_PHRASES {
+        parts = parts
+            .iter()
+            .flat_map(|part| split_keeping_question_word(part, phrase))
+            .collect();
+    }
+    dedup(
+        parts
+            .iter()
+            .flat_map(|part| part.split(['?', ';', '\n']))
+            .map(|part| part.trim().trim_end_matches(['.', ',']).trim())
+            .filter(|part| !part.is_empty()),
+        max,
+    )
+}
+
+/// Splits at " and what " etc. but keeps "what" with the second half.
+fn split_keeping_question_word(text: &str, phrase: &str) -> Vec<String> {
+    let lower = text.to_ascii_lowercase();
+    let Some(at) = lower.find(phrase) else {
+        return vec![text.to_string()];
+    };
+    let kept = phrase.trim_start().strip_prefix("and ").map_or(0, str::len);
+    let second = &text[at + phrase.len() - kept..];
+    let mut parts = vec![text[..at].to_string()];
+    parts.extend(split_keeping_question_word(second, phrase));
+    parts
+}
+
+/// Removes a leading "-", "*" or "1." style list marker.
+fn strip_list_marker(line: &str) -> &str {
+    if let Some(rest) = line.strip_prefix(['-', '*']) {
+        return rest.trim_start();
+    }
+    let digits = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    match digits.strip_prefix(['.', ')']) {
+        Some(rest) if digits.len() < line.len() => rest.trim_start(),
+        _ => line,
+    }
+}
+
+fn dedup<'a>(queries: impl Iterator<Item = &'a str>, max: usize) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for query in queries {
+        if !unique.iter().any(|q| q.eq_ignore_ascii_case(query)) {
+            unique.push(query.to_string());
+        }
+    }
+    unique.truncate(max);
+    unique
+}
+
+/// Strips whitespace and wrapping quotes models tend to add despite being
+/// asked not to.
+fn clean_reply(reply: &str) -> &str {
+    let reply = reply.trim();
+    for quote in ['"', '\'', '`'] {
+        if let Some(inner) = reply
+            .strip_prefix(quote)
+            .and_then(|r| r.strip_suffix(quote))
+        {
+            return inner.trim();
+        }
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_reply() {
+        assert_eq!(clean_reply("  pricing of plan B \n"), "pricing of plan B");
+        assert_eq!(clean_reply("\"pricing of plan B\""), "pricing of plan B");
+        assert_eq!(clean_reply("`x`"), "x");
+        assert_eq!(clean_reply("\"unbalanced"), "\"unbalanced");
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split("How do I rotate API keys and what are the rate limits?", 5),
+            vec!["How do I rotate API keys", "what are the rate limits"]
+        );
+        assert_eq!(
+            split("Pricing of pods as well as serverless; regions", 5),
+            vec!["Pricing of pods", "serverless", "regions"]
+        );
+        assert_eq!(split("salt and pepper", 5), vec!["salt and pepper"]);
+        assert_eq!(split("a? b? c", 2), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_strip_list_marker() {
+        assert_eq!(strip_list_marker("1. pricing"), "pricing");
+        assert_eq!(strip_list_marker("- pricing"), "pricing");
+        assert_eq!(strip_list_marker("2024 pricing"), "2024 pricing");
+    }
+}
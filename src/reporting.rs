@@ -0,0 +1,170 @@
+//! Error reporting to Sentry (feature `sentry`): panics and Pinecone API
+//! failures, so operators of many deployments are alerted on error spikes.
+//! Queries, snippets and credentials are never sent.
+//!
+//! `TELEMETRY=off` disables every exporter, whatever else is configured.
+
+use std::io;
+
+use crate::config::EnvNames;
+
+/// Keeps error reporting active; pending events are flushed on drop.
+pub struct Reporting {
+    #[cfg(feature = "sentry")]
+    _guard: sentry::ClientInitGuard,
+}
+
+/// Whether telemetry may leave the process. Anything that exports data in
+/// the background must check this before starting.
+pub fn telemetry_enabled() -> bool {
+    const TELEMETRY: &str = "TELEMETRY";
+
+    EnvNames::from_env()
+        .var(TELEMETRY)
+        .map_or(true, |v| !is_off(&v))
+}
+
+fn is_off(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "off" | "0" | "false" | "no" | "disabled"
+    )
+}
+
+impl Reporting {
+    /// Starts reporting to the DSN in `SENTRY_DSN`, if set and telemetry is
+    /// enabled. Fails if the DSN is invalid or support was not compiled in.
+    pub fn from_env() -> Option<io::Result<Self>> {
+        const SENTRY_DSN: &str = "SENTRY_DSN";
+
+        if !telemetry_enabled() {
+            return None;
+        }
+        let dsn = EnvNames::from_env()
+            .var(SENTRY_DSN)
+            .ok()
+            .filter(|v| !v.trim().is_empty())?;
+        Some(init(dsn.trim()))
+    }
+}
+
+#[cfg(feature = "sentry")]
+fn init(dsn: &str) -> io::Result<Reporting> {
+    let dsn = dsn
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid DSN: {e}")))?;
+    // SENTRY_ENVIRONMENT and SENTRY_RELEASE are picked up by the client.
+    let guard = sentry::init(sentry::ClientOptions {
+        dsn: Some(dsn),
+        release: sentry::release_name!(),
+        send_default_pii: false,
+        ..Default::default()
+    });
+    Ok(Reporting { _guard: guard })
+}
+
+#[cfg(not(feature = "sentry"))]
+fn init(_dsn: &str) -> io::Result<Reporting> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "built without the sentry feature",
+    ))
+}
+
+/// Reports a failed Pinecone API call. The assistant name and anything
+/// shaped like an API key are redacted from the path and message.
+#[cfg(feature = "sentry")]
+pub(crate) fn api_error(status: u16, method: &str, path: &str, message: &str, assistant: &str) {
+    if sentry::Hub::current().client().is_none() {
+        return;
+    }
+    let path = redact(path, assistant);
+    let message = redact(message, assistant);
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("status", status);
+            scope.set_tag("method", method);
+            scope.set_extra("path", path.into());
+            scope.set_extra("response", message.into());
+        },
+        || {
+            sentry::capture_message(
+                &format!("Pinecone API error {status}"),
+                sentry::Level::Error,
+            )
+        },
+    );
+}
+
+#[cfg(not(feature = "sentry"))]
+pub(crate) fn api_error(
+    _status: u16,
+    _method: &str,
+    _path: &str,
+    _message: &str,
+    _assistant: &str,
+) {
+}
+
+/// Longest response body sent with a report.
+#[cfg(feature = "sentry")]
+const MAX_REPORTED_MESSAGE: usize = 1000;
+
+#[cfg(feature = "sentry")]
+fn redact(text: &str, assistant: &str) -> String {
+    let text = if assistant.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(assistant, "{assistant}")
+    };
+    let mut redacted: String = text
+        .split_inclusive(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+        .map(|word| match word.find("pcsk_") {
+            Some(start) => {
+                let end = word
+                    .find(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+                    .unwrap_or(word.len());
+                format!("{}[redacted]{}", &word[..start], &word[end..])
+            }
+            None => word.to_string(),
+        })
+        .collect();
+    if redacted.len() > MAX_REPORTED_MESSAGE {
+        let mut end = MAX_REPORTED_MESSAGE;
+        while !redacted.is_char_boundary(end) {
+            end -= 1;
+        }
+        redacted.truncate(end);
+        redacted.push('…');
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_telemetry_switch_values() {
+        assert!(is_off("off"));
+        assert!(is_off(" OFF "));
+        assert!(is_off("false"));
+        assert!(!is_off("on"));
+        assert!(!is_off(""));
+    }
+
+    #[cfg(feature = "sentry")]
+    #[test]
+    fn test_redact_hides_assistant_and_keys() {
+        let redacted = redact(
+            "Assistant kb-prod rejected key \"pcsk_abc123\" for /assistant/chat/kb-prod",
+            "kb-prod",
+        );
+
+        assert_eq!(
+            redacted,
+            "Assistant {assistant} rejected key \"[redacted]\" for /assistant/chat/{assistant}"
+        );
+        assert!(redact(&"x".repeat(5000), "kb").len() <= MAX_REPORTED_MESSAGE + 3);
+    }
+}
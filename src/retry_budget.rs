@@ -0,0 +1,164 @@
+//! A budget capping retries at a share of recent requests, so that during
+//! an outage retrying doesn't multiply the load on Pinecone.
+//!
+//! Requests and retries are counted in one-second buckets over a sliding
+//! window. A retry is allowed while the retries in the window stay below
+//! the budget's percentage of the requests, or below a small floor so an
+//! idle server can still retry the occasional transient failure.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+/// Retries allowed in a window whatever the request count.
+const MIN_RETRIES_PER_WINDOW: u64 = 3;
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    /// Seconds since the budget was created.
+    second: u64,
+    requests: u64,
+    retries: u64,
+}
+
+pub struct RetryBudget {
+    percent: u32,
+    window: Duration,
+    started: Instant,
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+impl RetryBudget {
+    /// Allows retries up to `percent` of the requests over `window`.
+    pub fn new(percent: u32, window: Duration) -> Self {
+        Self {
+            percent,
+            window,
+            started: Instant::now(),
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Counts a request's first attempt.
+    pub fn record_request(&self) {
+        self.record_request_at(Instant::now());
+    }
+
+    /// Whether a retry is within the budget, counting it if so.
+    pub fn try_retry(&self) -> bool {
+        self.try_retry_at(Instant::now())
+    }
+
+    /// The budget and the requests and retries in the current window.
+    pub fn snapshot(&self) -> Value {
+        let (requests, retries) = self.totals(Instant::now());
+        json!({
+            "percent": self.percent,
+            "window_secs": self.window.as_secs(),
+            "requests": requests,
+            "retries": retries,
+            "exhausted": retries >= self.allowed(requests),
+        })
+    }
+
+    fn record_request_at(&self, now: Instant) {
+        self.bucket(now, |bucket| bucket.requests += 1);
+    }
+
+    fn try_retry_at(&self, now: Instant) -> bool {
+        let (requests, retries) = self.totals(now);
+        let allowed = retries < self.allowed(requests);
+        if allowed {
+            self.bucket(now, |bucket| bucket.retries += 1);
+        }
+        allowed
+    }
+
+    fn allowed(&self, requests: u64) -> u64 {
+        (requests * u64::from(self.percent) / 100).max(MIN_RETRIES_PER_WINDOW)
+    }
+
+    /// Requests and retries in the window ending at `now`.
+    fn totals(&self, now: Instant) -> (u64, u64) {
+        let mut buckets = self.lock();
+        self.prune(&mut buckets, now);
+        buckets.iter().fold((0, 0), |(requests, retries), bucket| {
+            (requests + bucket.requests, retries + bucket.retries)
+        })
+    }
+
+    fn bucket(&self, now: Instant, update: impl FnOnce(&mut Bucket)) {
+        let second = self.second(now);
+        let mut buckets = self.lock();
+        self.prune(&mut buckets, now);
+        if buckets.back().is_none_or(|bucket| bucket.second != second) {
+            buckets.push_back(Bucket {
+                second,
+                requests: 0,
+                retries: 0,
+            });
+        }
+        if let Some(bucket) = buckets.back_mut() {
+            update(bucket);
+        }
+    }
+
+    fn prune(&self, buckets: &mut VecDeque<Bucket>, now: Instant) {
+        let oldest = self
+            .second(now)
+            .saturating_sub(self.window.as_secs().max(1) - 1);
+        while buckets.front().is_some_and(|bucket| bucket.second < oldest) {
+            buckets.pop_front();
+        }
+    }
+
+    fn second(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.started).as_secs()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Bucket>> {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retries_are_capped_at_a_share_of_requests() {
+        let budget = RetryBudget::new(20, Duration::from_secs(10));
+        let now = budget.started;
+        for _ in 0..50 {
+            budget.record_request_at(now);
+        }
+
+        let retried = (0..20).filter(|_| budget.try_retry_at(now)).count();
+
+        assert_eq!(retried, 10);
+        assert_eq!(budget.snapshot()["exhausted"], true);
+    }
+
+    #[test]
+    fn test_idle_budgets_allow_a_few_retries() {
+        let budget = RetryBudget::new(0, Duration::from_secs(10));
+        let now = budget.started;
+        budget.record_request_at(now);
+
+        let retried = (0..10).filter(|_| budget.try_retry_at(now)).count();
+
+        assert_eq!(retried as u64, MIN_RETRIES_PER_WINDOW);
+    }
+
+    #[test]
+    fn test_the_window_slides() {
+        let budget = RetryBudget::new(0, Duration::from_secs(10));
+        let start = budget.started;
+        while budget.try_retry_at(start) {}
+
+        assert!(!budget.try_retry_at(start + Duration::from_secs(9)));
+        assert!(budget.try_retry_at(start + Duration::from_secs(10)));
+    }
+}
@@ -1,28 +1,529 @@
+use crate::admission::{Admission, Refusal};
+use crate::alerting::ErrorRates;
+use crate::backend::{BackendFuture, ContextBackend};
 use crate::config::Config;
-use crate::pinecone::{PineconeClient, PineconeError};
+use crate::file_watch::FileWatch;
+use crate::gauge::Gauge;
+use crate::inventory::AssistantInventory;
+use crate::language::{self, METADATA_LANGUAGE};
+use crate::logging;
+use crate::manifest::Manifest;
+use crate::merge::{self, FIELD_ASSISTANTS, merge_adjacent, merge_snippets};
+use crate::names;
+use crate::output::{
+    self, ASSISTANT_CONTEXT_SCHEMA_URI, FILE_RESOURCE_PREFIX, Notes, ResponseFormat,
+};
+use crate::pagination::ResultPages;
+use crate::params::{
+    self, AnswerWithSourcesParams, AssistantContextParams, BatchParams, ChatInSessionParams,
+    ContentEncoding, DebugConfigParams, EndChatSessionParams, EstimateCostParams,
+    ExportAssistantParams, FileSort, HealthParams, IngestFromRootsParams, ListFilesParams,
+    ResetSessionParams, SchemaValidators, SearchFilesParams, SetDefaultAssistantParams,
+    StartChatSessionParams, SyncAssistantParams, UploadContentParams, UploadDirectoryParams,
+    UploadFileParams, UploadQueueParams, UploadsParams, UsageStatsParams, VerifyFileParams,
+};
+use crate::payloads::PayloadSizes;
+use crate::peer::{PeerError, RequestContext, Root};
+use crate::pinecone::{
+    API_VERSION, AssistantChat, AssistantContext, AssistantFile, AssistantSettings, Message,
+    PineconeClient, PineconeError,
+};
+use crate::privacy::loggable;
+use crate::query::{self, MAX_SUB_QUERIES};
+use crate::retry_budget::RetryBudget;
+use crate::routing::{AssistantRoute, QueryRouter};
+use crate::scrub::Scrubber;
+use crate::semantic_cache::{Lookup, SemanticCache};
+use crate::session::{Session, SessionDefaults};
+use crate::snapshots::{SNAPSHOT_RESOURCE_PREFIX, SnapshotStore};
+use crate::spool::{SpoolStatus, SpooledUpload, UploadSpool};
+use crate::tenants::Tenants;
+use crate::uploads::{
+    self, METADATA_CREATED_AT, METADATA_FILE_TYPE, METADATA_SHA256, METADATA_TAGS, TempFile,
+    UploadJournal, UploadLimits, UploadRecord, UploadStatus,
+};
+use crate::usage::{self, Pricing, UsageLedger};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use futures::{FutureExt, StreamExt};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use mcp_server::router::CapabilitiesBuilder;
 use mcp_spec::content::Content;
 use mcp_spec::handler::{PromptError, ResourceError, ToolError};
 use mcp_spec::prompt::Prompt;
 use mcp_spec::{protocol::ServerCapabilities, resource::Resource, tool::Tool};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
-const TOOL_ASSISTANT_CONTEXT: &'static str = "assistant_context";
+const TOOL_ASSISTANT_CONTEXT: &str = "assistant_context";
+const TOOL_SET_DEFAULT_ASSISTANT: &str = "set_default_assistant";
+const TOOL_START_CHAT_SESSION: &str = "start_chat_session";
+const TOOL_CHAT_IN_SESSION: &str = "chat_in_session";
+const TOOL_END_CHAT_SESSION: &str = "end_chat_session";
+const TOOL_ANSWER_WITH_SOURCES: &str = "answer_with_sources";
+const TOOL_RESET_SESSION: &str = "reset_session";
+const TOOL_UPLOAD_FILE: &str = "upload_file";
+const TOOL_UPLOAD_CONTENT: &str = "upload_content";
+const TOOL_UPLOAD_DIRECTORY: &str = "upload_directory";
+const TOOL_INGEST_FROM_ROOTS: &str = "ingest_from_roots";
+const TOOL_VERIFY_FILE: &str = "verify_file";
+const TOOL_LIST_FILES: &str = "list_files";
+const TOOL_SEARCH_FILES: &str = "search_files";
+const TOOL_EXPORT_ASSISTANT: &str = "export_assistant";
+const TOOL_SYNC_ASSISTANT: &str = "sync_assistant";
+const TOOL_LIST_UPLOADS: &str = "list_uploads";
+const TOOL_RESUME_UPLOADS: &str = "resume_uploads";
+const TOOL_UPLOAD_QUEUE: &str = "upload_queue";
+const TOOL_HEALTH: &str = "health";
+const TOOL_DEBUG_CONFIG: &str = "debug_config";
+const TOOL_ESTIMATE_COST: &str = "estimate_cost";
+const TOOL_USAGE_STATS: &str = "usage_stats";
+const TOOL_BATCH: &str = "batch";
 
-const PARAM_ASSISTANT_NAME: &'static str = "assistant_name";
-const PARAM_QUERY: &'static str = "query";
-const PARAM_TOP_K: &'static str = "top_k";
+const PARAM_ASSISTANT_NAME: &str = "assistant_name";
+const PARAM_ASSISTANT_NAMES: &str = "assistant_names";
+const PARAM_QUERY: &str = "query";
+const PARAM_TOP_K: &str = "top_k";
+const PARAM_RESPONSE_FORMAT: &str = "response_format";
+const PARAM_REWRITE_QUERY: &str = "rewrite_query";
+const PARAM_SUB_QUERIES: &str = "sub_queries";
+const PARAM_MERGE_ADJACENT: &str = "merge_adjacent";
+const PARAM_EXPAND_CONTEXT: &str = "expand_context";
+const PARAM_USE_QUERY_HISTORY: &str = "use_query_history";
+const PARAM_CONTEXT_BUDGET_CHARS: &str = "context_budget_chars";
+const PARAM_INCLUDE_IMAGES: &str = "include_images";
+const PARAM_INCLUDE_HIGHLIGHTS: &str = "include_highlights";
+const PARAM_JSON_RESPONSE: &str = "json_response";
+const PARAM_MODEL: &str = "model";
+const PARAM_TEMPERATURE: &str = "temperature";
+const PARAM_TOP_P: &str = "top_p";
+const PARAM_CITATION_STYLE: &str = "citation_style";
+const PARAM_TIMEOUT_MS: &str = "timeout_ms";
+const PARAM_SESSION_ID: &str = "session_id";
+const PARAM_MESSAGE: &str = "message";
+const PARAM_FILE_PATH: &str = "file_path";
+const PARAM_FILE_PATHS: &str = "file_paths";
+const PARAM_DIRECTORY: &str = "directory";
+const PARAM_INCLUDE: &str = "include";
+const PARAM_EXCLUDE: &str = "exclude";
+const PARAM_METADATA: &str = "metadata";
+const PARAM_FILE_NAME: &str = "file_name";
+const PARAM_CONTENT: &str = "content";
+const PARAM_ENCODING: &str = "encoding";
+const PARAM_OUTPUT_PATH: &str = "output_path";
+const PARAM_MANIFEST_PATH: &str = "manifest_path";
+const PARAM_DELETE_EXTRAS: &str = "delete_extras";
+const PARAM_DRY_RUN: &str = "dry_run";
+const PARAM_ROOTS: &str = "roots";
+const PARAM_FILTER: &str = "filter";
+const PARAM_NAME: &str = "name";
+const PARAM_TAGS: &str = "tags";
+const PARAM_CREATED_AFTER: &str = "created_after";
+const PARAM_CREATED_BEFORE: &str = "created_before";
+const PARAM_FILE_TYPES: &str = "file_types";
+const PARAM_LANGUAGE: &str = "language";
+const PARAM_LANGUAGE_MODE: &str = "language_mode";
+const PARAM_PAGE_SIZE: &str = "page_size";
+const PARAM_LIMIT: &str = "limit";
+const PARAM_SORT_BY: &str = "sort_by";
+const PARAM_CURSOR: &str = "cursor";
+const PARAM_CALLS: &str = "calls";
+const PARAM_API_KEY: &str = "api_key";
+const PARAM_RETRY: &str = "retry";
+const PARAM_DISCARD: &str = "discard";
+
+/// Upper bound on the calls in one batch.
+const MAX_BATCH_CALLS: usize = 32;
+
+/// Upper bound on the files one upload_directory call may upload.
+const MAX_DIRECTORY_FILES: usize = 1000;
+
+/// Files upload_directory picks up when no include patterns are given: the
+/// formats assistants accept.
+const DEFAULT_INCLUDE: &[&str] = &["**/*.pdf", "**/*.txt", "**/*.md", "**/*.json", "**/*.docx"];
+
+/// Called as each file of a multi-file upload completes, with the number of
+/// files done, the total and the file's entry in the upload report.
+pub type UploadProgress<'a> = dyn Fn(usize, usize, &Value) + Sync + 'a;
+
+/// Reports upload progress to the client of the current request, if any.
+fn notify_progress(done: usize, total: usize, file: &Value) {
+    if let Some(context) = RequestContext::current() {
+        let message = file[PARAM_FILE_PATH].as_str();
+        context.notify_step(Some(total.saturating_sub(done)), message, None);
+    }
+}
+
+/// Sends a partial result of a multi-part call to the client of the current
+/// request, if it asked for progress, so it can read results as they arrive.
+fn notify_partial(done: usize, total: usize, message: &str, partial: Value) {
+    if let Some(context) = RequestContext::current() {
+        context.notify_step(
+            Some(total.saturating_sub(done)),
+            Some(message),
+            Some(partial),
+        );
+    }
+}
+
+/// A question with the snippets to answer it from, numbered so the answer
+/// can refer to them.
+fn pinned_question(question: &str, snippets: &[Value]) -> String {
+    let mut message = String::from(
+        "Answer the question from the sources below, citing them by number. \
+        Say so if they do not contain the answer.\n\nSources:",
+    );
+    for (index, snippet) in snippets.iter().enumerate() {
+        let content = match &snippet["content"] {
+            Value::String(content) => content.clone(),
+            content => content.to_string(),
+        };
+        let file = snippet["reference"]["file"]["name"]
+            .as_str()
+            .unwrap_or("unnamed file");
+        message.push_str(&format!("\n\n[{}] ({file})\n{content}", index + 1));
+    }
+    message.push_str(&format!("\n\nQuestion: {question}"));
+    message
+}
+
+/// Narrows a metadata filter to files whose `created_at` metadata falls in
+/// `[after, before)`.
+fn with_date_range(
+    filter: Option<Value>,
+    after: Option<&str>,
+    before: Option<&str>,
+) -> Result<Option<Value>, RouterError> {
+    let mut range = Map::new();
+    for (param, value, operator) in [
+        (PARAM_CREATED_AFTER, after, "$gte"),
+        (PARAM_CREATED_BEFORE, before, "$lt"),
+    ] {
+        let Some(value) = value else {
+            continue;
+        };
+        let secs = parse_timestamp(value).ok_or_else(|| {
+            RouterError::InvalidParameters(format!(
+                "{param} must be a date (YYYY-MM-DD) or a UTC time (YYYY-MM-DDTHH:MM:SSZ), \
+                got {value:?}"
+            ))
+        })?;
+        range.insert(operator.to_string(), secs.into());
+    }
+    if range.is_empty() {
+        return Ok(filter);
+    }
+    Ok(Some(and_filter(
+        filter,
+        serde_json::json!({ METADATA_CREATED_AT: range }),
+    )))
+}
+
+/// Narrows a metadata filter to files of the given types, such as `pdf` or
+/// `.MD`.
+fn with_file_types(filter: Option<Value>, file_types: Option<&[String]>) -> Option<Value> {
+    let file_types: Vec<String> = file_types
+        .unwrap_or_default()
+        .iter()
+        .map(|file_type| {
+            file_type
+                .trim()
+                .trim_start_matches('.')
+                .to_ascii_lowercase()
+        })
+        .filter(|file_type| !file_type.is_empty())
+        .collect();
+    if file_types.is_empty() {
+        return filter;
+    }
+    Some(and_filter(
+        filter,
+        serde_json::json!({ METADATA_FILE_TYPE: { "$in": file_types } }),
+    ))
+}
+
+/// Requires every tag to be among a file's tags.
+fn with_tags(filter: Option<Value>, tags: &[String]) -> Option<Value> {
+    tags.iter()
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .fold(filter, |filter, tag| {
+            Some(and_filter(
+                filter,
+                serde_json::json!({ METADATA_TAGS: { "$in": [tag] } }),
+            ))
+        })
+}
+
+/// Orders files for `list_files`; files without a size or creation time come
+/// last.
+fn sort_files(mut files: Vec<AssistantFile>, sort_by: FileSort) -> Vec<AssistantFile> {
+    match sort_by {
+        FileSort::Name => files.sort_by_cached_key(|file| file.name.to_lowercase()),
+        FileSort::Size => files.sort_by_key(|file| std::cmp::Reverse(file.size)),
+        // RFC 3339 times in UTC sort chronologically as strings.
+        FileSort::Created => files.sort_by(|a, b| b.created_on.cmp(&a.created_on)),
+    }
+    files
+}
+
+/// Whether a file name contains every word of `name`, ignoring case and
+/// separators, so "q3 security review" finds `Q3_Security-Review.pdf`.
+fn name_matches(file_name: &str, name: &str) -> bool {
+    let file_name = file_name.to_lowercase();
+    name.split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|word| !word.is_empty())
+        .all(|word| file_name.contains(&word.to_lowercase()))
+}
+
+/// Combines a metadata filter, if any, with another condition.
+fn and_filter(filter: Option<Value>, condition: Value) -> Value {
+    match filter {
+        Some(filter) => serde_json::json!({ "$and": [filter, condition] }),
+        None => condition,
+    }
+}
+
+/// Parses `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SSZ` into Unix seconds.
+fn parse_timestamp(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    let mut secs = logging::days_from_civil(year, month, day) * 86_400;
+    if let Some(time) = time {
+        let time = time.strip_suffix('Z')?;
+        // Fractions of a second are ignored.
+        let time = time.split('.').next()?;
+        let mut parts = time.splitn(3, ':');
+        let hours: i64 = parts.next()?.parse().ok()?;
+        let minutes: i64 = parts.next()?.parse().ok()?;
+        let seconds: i64 = parts.next().unwrap_or("0").parse().ok()?;
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return None;
+        }
+        secs += hours * 3600 + minutes * 60 + seconds;
+    }
+    Some(secs)
+}
+
+/// Days of `month` (1 to 12) in `year` of the proleptic Gregorian calendar.
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Page size of paged results when a cursor is given without one.
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// Most snippets whose neighbouring chunks `expand_context` fetches.
+const MAX_EXPANDED_SNIPPETS: u32 = 5;
+
+/// Characters from each end of a snippet used to find its neighbours.
+const NEIGHBOUR_QUERY_CHARS: usize = 300;
+
+/// How long sync_assistant waits for an assistant it created to become ready.
+const ASSISTANT_READY_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum RouterError {
     #[error("Pinecone error: {0}")]
     Pinecone(#[from] PineconeError),
 
     #[error("Invalid parameters: {0}")]
     InvalidParameters(String),
+
+    #[error("Chat session {0} not found")]
+    SessionNotFound(String),
+
+    #[error(
+        "Assistant {name} not found{}",
+        suggestion.as_ref().map(|s| format!("; did you mean {s}?")).unwrap_or_default()
+    )]
+    UnknownAssistant {
+        name: String,
+        /// The closest existing name, if one is close enough.
+        suggestion: Option<String>,
+    },
+
+    #[error("Request abandoned: the client's deadline leaves no time for it")]
+    DeadlineExceeded,
+
+    #[error("Request timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("Client request failed: {0}")]
+    Client(#[from] PeerError),
+
+    #[error("Server busy, retry in {}s", retry_after.as_secs())]
+    Busy { retry_after: Duration },
+}
+
+/// Time kept back from the client's deadline to deliver the response.
+const DEADLINE_MARGIN: Duration = Duration::from_millis(250);
+
+/// Share of a retrieval's time the semantic cache lookup may take; the rest
+/// is left for Pinecone should the lookup miss.
+const CACHE_LOOKUP_SHARE: u32 = 4;
+
+/// Identifies the client connection of the current request, if any.
+fn connection_id() -> Option<u64> {
+    RequestContext::current().map(|context| context.peer.id())
+}
+
+/// The time the client left for the current request, if it set a deadline.
+fn deadline_remaining() -> Option<Duration> {
+    RequestContext::current()
+        .and_then(|context| context.deadline)
+        .map(|deadline| {
+            deadline
+                .saturating_duration_since(Instant::now())
+                .saturating_sub(DEADLINE_MARGIN)
+        })
+}
+
+/// Runs a backend call within the time the client left for the current
+/// request and the caller's `timeout`, abandoning it (and its connection)
+/// once that is used up rather than finishing work nobody waits for.
+async fn within_deadline<T>(
+    call: BackendFuture<'_, T>,
+    timeout: Option<Duration>,
+) -> Result<T, RouterError> {
+    let remaining = deadline_remaining();
+    let (budget, error) = match (remaining, timeout) {
+        (Some(remaining), Some(timeout)) if timeout < remaining => {
+            (timeout, RouterError::Timeout(timeout))
+        }
+        (Some(remaining), _) => (remaining, RouterError::DeadlineExceeded),
+        (None, Some(timeout)) => (timeout, RouterError::Timeout(timeout)),
+        (None, None) => return Ok(call.await?),
+    };
+    if budget.is_zero() {
+        return Err(error);
+    }
+    match tokio::time::timeout(budget, call).await {
+        Ok(result) => Ok(result?),
+        Err(_) => {
+            tracing::warn!("Abandoned backend request after {budget:?}: {error}");
+            Err(error)
+        }
+    }
+}
+
+/// Whether a failure means Pinecone is down, rather than the request being
+/// wrong: a 5xx response, or no host could be reached.
+fn is_outage(err: &RouterError) -> bool {
+    match err {
+        RouterError::Pinecone(PineconeError::Api { status, .. }) => *status >= 500,
+        RouterError::Pinecone(
+            PineconeError::Connect { .. }
+            | PineconeError::ConnectTimeout { .. }
+            | PineconeError::ReadTimeout { .. },
+        ) => true,
+        _ => false,
+    }
+}
+
+/// Whether a failure means Pinecone couldn't be reached at all, as when the
+/// machine is offline, rather than Pinecone refusing the request.
+fn is_offline(err: &RouterError) -> bool {
+    match err {
+        RouterError::Pinecone(
+            PineconeError::Dns { .. }
+            | PineconeError::Connect { .. }
+            | PineconeError::ConnectTimeout { .. }
+            | PineconeError::ReadTimeout { .. },
+        ) => true,
+        RouterError::Pinecone(PineconeError::Request(e)) => e.is_request(),
+        _ => false,
+    }
+}
+
+/// Outcome of an upload that didn't fail.
+enum Uploaded {
+    /// The file, and whether the assistant already held its content.
+    File(AssistantFile, bool),
+    /// Spooled, to be retried once Pinecone can be reached.
+    Queued(SpooledUpload),
+}
+
+/// The result of an upload tool: the file, flagged if it was a duplicate, or
+/// the queued upload.
+fn uploaded_result(uploaded: Uploaded) -> Value {
+    match uploaded {
+        Uploaded::File(file, existing) => {
+            let mut result = serde_json::json!(file);
+            if existing {
+                result["duplicate"] = true.into();
+            }
+            result
+        }
+        Uploaded::Queued(upload) => serde_json::json!({
+            "status": "queued",
+            "queue_id": upload.id,
+            "name": upload.file_name,
+            "error": upload.error,
+            "hint": format!(
+                "Pinecone couldn't be reached; the upload is retried in the background. \
+                Check on it with {TOOL_UPLOAD_QUEUE}."
+            ),
+        }),
+    }
+}
+
+/// Resolves a local path named by a tool call, symbolic links included, and
+/// checks that it is under one of `roots`, which must be resolved too.
+async fn confine(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, RouterError> {
+    let resolved = tokio::fs::canonicalize(path).await.map_err(|e| {
+        RouterError::InvalidParameters(format!("Cannot read {}: {e}", path.display()))
+    })?;
+    if roots.iter().any(|root| resolved.starts_with(root)) {
+        Ok(resolved)
+    } else {
+        Err(RouterError::InvalidParameters(format!(
+            "{} is outside the directories files may be read from: the upload roots \
+            configured on the server and the roots shared by the client",
+            path.display()
+        )))
+    }
+}
+
+/// Compiles glob patterns, rejecting invalid ones as bad parameters.
+fn glob_set<S: AsRef<str>>(patterns: &[S]) -> Result<GlobSet, RouterError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern.as_ref())
+            .map_err(|e| RouterError::InvalidParameters(format!("Invalid glob pattern: {e}")))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| RouterError::InvalidParameters(format!("Invalid glob patterns: {e}")))
+}
+
+/// The content hash recorded in a file's metadata at upload, if any.
+fn content_hash(file: &AssistantFile) -> Option<&str> {
+    file.metadata.as_ref()?.get(METADATA_SHA256)?.as_str()
 }
 
 impl From<RouterError> for ToolError {
@@ -30,14 +531,162 @@ impl From<RouterError> for ToolError {
         match err {
             RouterError::Pinecone(e) => ToolError::ExecutionError(e.to_string()),
             RouterError::InvalidParameters(msg) => ToolError::InvalidParameters(msg),
+            RouterError::SessionNotFound(_) | RouterError::UnknownAssistant { .. } => {
+                ToolError::InvalidParameters(err.to_string())
+            }
+            RouterError::DeadlineExceeded
+            | RouterError::Timeout(_)
+            | RouterError::Client(_)
+            | RouterError::Busy { .. } => ToolError::ExecutionError(err.to_string()),
         }
     }
 }
 
+/// Turns failures the agent can act on (an unknown assistant, a rate limit,
+/// an outage, a timeout) into a result describing the error, which hosts
+/// show to the model, rather than a protocol error that some hosts treat as
+/// fatal for the whole turn. Other failures stay errors.
+fn recoverable(err: RouterError) -> Result<Vec<Content>, ToolError> {
+    let (kind, retryable, hint) = match &err {
+        RouterError::Pinecone(PineconeError::NotFound { .. })
+        | RouterError::UnknownAssistant { .. } => ("not_found", false, "Check the assistant name"),
+        RouterError::Pinecone(PineconeError::Api { status: 429, .. }) => (
+            "rate_limited",
+            true,
+            "Wait a moment before retrying, and make fewer calls at a time",
+        ),
+        RouterError::Pinecone(PineconeError::QuotaExceeded { .. }) => (
+            "quota_exceeded",
+            false,
+            "Tell the user the Pinecone quota is used up; retrying will not help",
+        ),
+        RouterError::Pinecone(PineconeError::Api { status, .. }) if *status >= 500 => (
+            "unavailable",
+            true,
+            "Pinecone is having trouble; retry shortly",
+        ),
+        RouterError::Pinecone(
+            PineconeError::Connect { .. }
+            | PineconeError::ConnectTimeout { .. }
+            | PineconeError::ReadTimeout { .. },
+        ) => (
+            "unavailable",
+            true,
+            "Pinecone could not be reached; retry shortly",
+        ),
+        RouterError::Timeout(_) => (
+            "timeout",
+            true,
+            "Retry with a larger timeout_ms or a narrower query",
+        ),
+        RouterError::Busy { .. } => (
+            "busy",
+            true,
+            "The server has too many calls in progress; retry after the time given",
+        ),
+        _ => return Err(err.into()),
+    };
+    tracing::warn!("Tool call failed ({kind}): {err}");
+    let error = serde_json::json!({
+        "error": {
+            "kind": kind,
+            "message": err.to_string(),
+            "retryable": retryable,
+            "hint": hint,
+        }
+    });
+    Ok(vec![Content::text(error.to_string())])
+}
+
+/// Whether a tool result describes a failure (see [`recoverable`]), so
+/// servers can flag it with `isError`.
+pub fn is_error_result(content: &[Content]) -> bool {
+    let [content] = content else {
+        return false;
+    };
+    content
+        .as_text()
+        .and_then(|text| serde_json::from_str::<Value>(text).ok())
+        .and_then(|value| {
+            value.as_object().map(|object| {
+                object.len() == 1 && object.get("error").is_some_and(Value::is_object)
+            })
+        })
+        .unwrap_or(false)
+}
+
 #[derive(Clone)]
 pub struct PineconeAssistantRouter {
-    client: PineconeClient,
+    backend: Arc<dyn ContextBackend>,
+    assistant_backends: HashMap<String, Arc<dyn ContextBackend>>,
     tools: Vec<Tool>,
+    privacy_mode: bool,
+    default_assistant: Option<String>,
+    query_router: QueryRouter,
+    assistant_aliases: HashMap<String, String>,
+    session: Session,
+    assistant_instructions: Option<String>,
+    /// Configured replacement of the generic instructions.
+    server_instructions: Option<String>,
+    /// Generation model of chat answers when a call names none.
+    chat_model: Option<String>,
+    /// Models chat calls may use; any model when empty.
+    allowed_chat_models: Vec<String>,
+    /// Sampling temperature of chat answers when a call passes none.
+    chat_temperature: Option<f32>,
+    /// Highest sampling temperature a call may ask for.
+    max_chat_temperature: f32,
+    max_concurrent_requests: usize,
+    max_timeout: Duration,
+    uploads: UploadJournal,
+    upload_limits: UploadLimits,
+    /// Directories whose files tool calls may read, besides the client's
+    /// roots.
+    upload_roots: Vec<PathBuf>,
+    /// Directory where manifests may be exported to, if any.
+    export_dir: Option<PathBuf>,
+    /// Uploads that couldn't reach Pinecone, if they are spooled.
+    spool: Option<UploadSpool>,
+    /// Time between background retries of the spooled uploads.
+    spool_retry_interval: Duration,
+    snapshots: Option<SnapshotStore>,
+    max_response_bytes: Option<usize>,
+    pages: ResultPages,
+    /// The configuration the router was created with, secrets masked.
+    redacted_config: Value,
+    usage: UsageLedger,
+    pricing: Pricing,
+    /// Tool calls being handled, batched calls included.
+    tool_calls: Gauge,
+    /// Limits the tool calls running at a time, if configured.
+    admission: Option<Arc<Admission>>,
+    /// Error rates per tool, tracked when alerts are configured.
+    error_rates: Option<ErrorRates>,
+    payload_sizes: PayloadSizes,
+    /// How long retrievals are reused within a conversation, if at all.
+    retrieval_cache_ttl: Option<Duration>,
+    /// How many of a connection's latest queries are remembered.
+    retrieval_memory: usize,
+    /// How old a retrieval may be to be served while Pinecone is down, if
+    /// that is enabled.
+    serve_stale: Option<Duration>,
+    semantic_cache: Option<SemanticCache>,
+    scrubber: Scrubber,
+    /// Compiled input schemas of the tools, to validate arguments against.
+    schemas: SchemaValidators,
+    /// The project's assistants, to suggest the intended one when a name
+    /// doesn't exist, and to pick the only one when none is named.
+    inventory: AssistantInventory,
+    /// Files of the assistants in use, listed as resources.
+    files: FileWatch,
+    /// Whether calls may pass their own API key.
+    allow_api_key_argument: bool,
+    /// Sessions, paged results and upload journals of calls passing their
+    /// own API key.
+    tenants: Tenants,
+    /// The Pinecone client that calls passing their own API key copy; `None`
+    /// for routers created with another backend.
+    tenant_client: Option<PineconeClient>,
 }
 
 impl PineconeAssistantRouter {
@@ -46,147 +695,6031 @@ impl PineconeAssistantRouter {
             "Creating new PineconeAssistantRouter [Host: {}]",
             config.pinecone_assistant_host
         );
-        let client = PineconeClient::new(config.pinecone_api_key, config.pinecone_assistant_host);
+        // One budget caps the retries of every client.
+        let retry_budget = Arc::new(RetryBudget::new(
+            config.retry.budget_percent,
+            config.retry.budget_window,
+        ));
+        let assistant_backends = config
+            .assistant_api_keys
+            .iter()
+            .map(|(assistant_name, api_key)| {
+                tracing::info!("Using a dedicated API key for assistant: {assistant_name}");
+                let client =
+                    PineconeClient::new(api_key.clone(), config.pinecone_assistant_host.clone())
+                        .with_control_plane_url(config.pinecone_control_plane_host.clone())
+                        .with_upload_chunk_size(config.upload_chunk_size)
+                        .with_http_options(&config.http)
+                        .with_retry_policy(config.retry.clone())
+                        .with_retry_budget(retry_budget.clone())
+                        .with_failover_hosts(config.pinecone_failover_hosts.clone());
+                (
+                    assistant_name.clone(),
+                    Arc::new(client) as Arc<dyn ContextBackend>,
+                )
+            })
+            .collect();
+        let client = PineconeClient::new(
+            config.pinecone_api_key.clone(),
+            config.pinecone_assistant_host.clone(),
+        )
+        .with_control_plane_url(config.pinecone_control_plane_host.clone())
+        .with_upload_chunk_size(config.upload_chunk_size)
+        .with_http_options(&config.http)
+        .with_retry_policy(config.retry.clone())
+        .with_retry_budget(retry_budget)
+        .with_failover_hosts(config.pinecone_failover_hosts.clone());
         tracing::info!("Successfully initialized Pinecone client");
-        Self {
-            client,
-            tools: vec![Tool::new(
-                TOOL_ASSISTANT_CONTEXT.to_string(),
-                "Retrieves relevant document snippets from your Pinecone Assistant knowledge base. \
-                Returns an array of text snippets from the most relevant documents. \
-                You can use the 'top_k' parameter to control result count (default: 15). \
-                Recommended top_k: a few (5-8) for simple/narrow queries, 10-20 for complex/broad topics.".to_string(),
-                serde_json::json!({
-                "type": "object",
-                "properties": {
-                    PARAM_ASSISTANT_NAME: {
-                        "type": "string",
-                        "description": "Name of an existing Pinecone assistant"
-                    },
-                    PARAM_QUERY: {
-                        "type": "string",
-                        "description": "The query to retrieve context for."
-                    },
-                    PARAM_TOP_K: {
-                        "type": "integer",
-                        "description": "The number of context snippets to retrieve. Defaults to 15."
+        let tenant_client = config.allow_api_key_argument.then(|| client.clone());
+        let mut router = Self::with_backends(config, Arc::new(client), assistant_backends);
+        router.tenant_client = tenant_client;
+        router
+    }
+
+    /// Creates a router that serves every assistant from `backend` instead of
+    /// the Pinecone API.
+    pub fn with_backend(config: Config, backend: Arc<dyn ContextBackend>) -> Self {
+        Self::with_backends(config, backend, HashMap::new())
+    }
+
+    fn with_backends(
+        config: Config,
+        backend: Arc<dyn ContextBackend>,
+        assistant_backends: HashMap<String, Arc<dyn ContextBackend>>,
+    ) -> Self {
+        if config.privacy_mode {
+            tracing::info!("Privacy mode enabled: queries and snippets will not be logged");
+        }
+        let redacted_config = config.redacted();
+        let inventory = AssistantInventory::new(backend.clone(), config.assistant_inventory_ttl);
+        let files = FileWatch::new(config.file_poll_interval, config.file_status_interval);
+        let query_router = QueryRouter::new(
+            config
+                .assistant_descriptions
+                .into_iter()
+                .map(|(name, description)| AssistantRoute::new(name, description))
+                .collect(),
+        );
+        let server_selects_assistant =
+            config.default_assistant.is_some() || !query_router.is_empty();
+        let mut assistant_context_description =
+            "Retrieves relevant document snippets from your Pinecone Assistant knowledge base. \
+            Returns an array of text snippets from the most relevant documents. \
+            You can use the 'top_k' parameter to control result count (default: 15). \
+            Recommended top_k: a few (5-8) for simple/narrow queries, 10-20 for complex/broad topics."
+                .to_string();
+        if !query_router.is_empty() {
+            assistant_context_description.push_str(
+                " If 'assistant_name' is omitted, the most relevant of the following assistants is picked based on the query:",
+            );
+            for route in query_router.routes() {
+                assistant_context_description
+                    .push_str(&format!("\n- {}: {}", route.name, route.description));
+            }
+        }
+        if !config.assistant_aliases.is_empty() {
+            let mut aliases: Vec<&String> = config.assistant_aliases.keys().collect();
+            aliases.sort();
+            assistant_context_description.push_str(&format!(
+                " The following short names can be used as 'assistant_name': {}.",
+                aliases
+                    .iter()
+                    .map(|alias| alias.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        let timeout_description = format!(
+            "Give up after this many milliseconds, trading completeness for latency \
+            (at most {}).",
+            config.max_timeout.as_millis()
+        );
+        let created_after_description = format!(
+            "Only files created on or after this date (YYYY-MM-DD) or UTC time \
+            (YYYY-MM-DDTHH:MM:SSZ), per their '{METADATA_CREATED_AT}' metadata: the upload \
+            time unless set at upload, e.g. to the document's own date."
+        );
+        let created_before_description = format!(
+            "Only files created before this date (YYYY-MM-DD) or UTC time \
+            (YYYY-MM-DDTHH:MM:SSZ), per their '{METADATA_CREATED_AT}' metadata."
+        );
+        let mut model_schema = serde_json::json!({
+            "type": "string",
+            "description": format!(
+                "Generation model of the answer, e.g. a cheaper one for simple lookups or a \
+                stronger one for reasoning over several documents. Defaults to {}.",
+                config.chat_model.as_deref().unwrap_or("Pinecone's default model")
+            )
+        });
+        if !config.allowed_chat_models.is_empty() {
+            model_schema["enum"] = serde_json::json!(config.allowed_chat_models);
+        }
+        let temperature_description = format!(
+            "Sampling temperature of the answer; 0 for the most repeatable answers, e.g. in \
+            evaluations. Defaults to {}.",
+            config
+                .chat_temperature
+                .map_or_else(|| "Pinecone's default".to_string(), |t| t.to_string())
+        );
+        let file_types_description = format!(
+            "Only files of these types, by extension, e.g. [\"pdf\", \"md\"], per their \
+            '{METADATA_FILE_TYPE}' metadata, set when uploaded through this server."
+        );
+        let mut router = Self {
+            backend,
+            assistant_backends,
+            redacted_config,
+            privacy_mode: config.privacy_mode,
+            allow_api_key_argument: config.allow_api_key_argument,
+            tenant_client: None,
+            default_assistant: config.default_assistant,
+            query_router,
+            assistant_aliases: config.assistant_aliases,
+            session: Session::new(config.chat_session_dir.clone(), config.session_idle_ttl),
+            tenants: Tenants::new(
+                config.chat_session_dir,
+                config.session_idle_ttl,
+                config.upload_state_dir.clone(),
+            ),
+            assistant_instructions: None,
+            server_instructions: config.server_instructions,
+            chat_model: config.chat_model,
+            allowed_chat_models: config.allowed_chat_models,
+            chat_temperature: config.chat_temperature,
+            max_chat_temperature: config.max_chat_temperature,
+            max_concurrent_requests: config.max_concurrent_requests,
+            max_timeout: config.max_timeout,
+            uploads: UploadJournal::new(config.upload_state_dir),
+            upload_limits: UploadLimits {
+                max_bytes: config.max_upload_bytes,
+                allowed_types: config.allowed_upload_types,
+            },
+            upload_roots: config.upload_roots,
+            export_dir: config.export_dir,
+            spool: config.upload_spool_dir.map(UploadSpool::new),
+            spool_retry_interval: config.upload_spool_retry_interval,
+            snapshots: config.snapshot_dir.map(|dir| {
+                SnapshotStore::new(dir)
+                    .with_retention(config.snapshot_max_count, config.snapshot_max_age)
+            }),
+            usage: UsageLedger::new(config.usage_dir),
+            pricing: config.pricing,
+            tool_calls: Gauge::default(),
+            admission: config
+                .max_concurrent_tool_calls
+                .map(|limit| Arc::new(Admission::new(limit, config.tool_call_queue_depth))),
+            payload_sizes: PayloadSizes::default(),
+            error_rates: config.error_alerts.map(ErrorRates::new),
+            retrieval_cache_ttl: config.retrieval_cache_ttl,
+            retrieval_memory: config.retrieval_memory,
+            serve_stale: config.serve_stale,
+            semantic_cache: config.semantic_cache.map(SemanticCache::new),
+            scrubber: config.scrubber,
+            max_response_bytes: config.max_response_bytes,
+            pages: ResultPages::default(),
+            tools: vec![
+                Tool::new(
+                    TOOL_ASSISTANT_CONTEXT.to_string(),
+                    assistant_context_description,
+                    serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        PARAM_ASSISTANT_NAME: {
+                            "type": "string",
+                            "description": if server_selects_assistant {
+                                "Name of an existing Pinecone assistant. Optional: when omitted, the default set with \
+                                set_default_assistant is used, or else the server selects one"
+                            } else {
+                                "Name of an existing Pinecone assistant. Required unless 'assistant_names' is given \
+                                or a default was set with set_default_assistant"
+                            }
+                        },
+                        PARAM_ASSISTANT_NAMES: {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Query several assistants at once instead of a single 'assistant_name'. \
+                            Results are merged by score and snippets shared between assistants are returned once."
+                        },
+                        PARAM_QUERY: {
+                            "type": "string",
+                            "description": "The query to retrieve context for."
+                        },
+                        PARAM_TOP_K: {
+                            "type": "integer",
+                            "description": "The number of context snippets to retrieve. \
+                            Defaults to the value set with set_default_assistant, or else 15."
+                            },
+                        PARAM_RESPONSE_FORMAT: {
+                            "type": "string",
+                            "enum": ["text", "json"],
+                            "description": format!("'text' (default) returns one content item per snippet. \
+                            'json' returns a single JSON document with snippets, scores and references, \
+                            following the schema published as resource {ASSISTANT_CONTEXT_SCHEMA_URI}.")
+                        },
+                        PARAM_REWRITE_QUERY: {
+                            "type": "boolean",
+                            "description": "Ask your own model (via MCP sampling) to rewrite the query into a \
+                            standalone search query before retrieval. Useful for follow-ups like \
+                            'what about the other one?'. Ignored if the client does not support sampling."
+                        },
+                        PARAM_SUB_QUERIES: {
+                            "type": "integer",
+                            "minimum": 0,
+                            "maximum": MAX_SUB_QUERIES,
+                            "description": "Break a broad, multi-part query into up to this many focused \
+                            sub-queries (using your model via MCP sampling if available, else by splitting \
+                            the question), retrieve for each and merge the results. \
+                            The original query is always retrieved as well."
+                        },
+                        PARAM_MERGE_ADJACENT: {
+                            "type": "boolean",
+                            "description": "Join snippets that are contiguous chunks of the same file into \
+                            one passage. Defaults to true."
+                        },
+                        PARAM_EXPAND_CONTEXT: {
+                            "type": "integer",
+                            "minimum": 0,
+                            "maximum": MAX_EXPANDED_SNIPPETS,
+                            "description": "Also fetch the chunks just before and after this many of the \
+                            best snippets in their document, for more surrounding context of the best hits. \
+                            They are joined to their snippet unless merge_adjacent is false."
+                        },
+                        PARAM_USE_QUERY_HISTORY: {
+                            "type": "boolean",
+                            "description": "Send the latest queries of this conversation along with this \
+                            one, so a follow-up like 'and how do I configure that?' retrieves context for \
+                            the earlier topic. Leave off when the query changes the subject."
+                        },
+                        PARAM_CONTEXT_BUDGET_CHARS: {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Maximum total characters of snippet content to return. \
+                            Snippets with the highest score per character are kept; the result notes \
+                            how many were omitted."
+                        },
+                        PARAM_INCLUDE_IMAGES: {
+                            "type": "boolean",
+                            "description": "Also retrieve figures and page images from documents, \
+                            returned as image content next to their snippet."
+                        },
+                        PARAM_INCLUDE_HIGHLIGHTS: {
+                            "type": "boolean",
+                            "description": "Add the spans of each snippet that match the query, \
+                            as 'highlights', to show why a passage was retrieved."
+                        },
+                        PARAM_FILTER: {
+                            "type": "object",
+                            "description": "Only retrieve from files whose upload metadata matches this \
+                            filter, e.g. {\"owner\": \"support\"}"
+                        },
+                        PARAM_CREATED_AFTER: {
+                            "type": "string",
+                            "description": created_after_description
+                        },
+                        PARAM_CREATED_BEFORE: {
+                            "type": "string",
+                            "description": created_before_description
+                        },
+                        PARAM_FILE_TYPES: {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": file_types_description
+                        },
+                        PARAM_LANGUAGE: {
+                            "type": "string",
+                            "description": format!("Only return snippets in this language, as an ISO 639-1 \
+                            code such as 'en'. The language comes from the '{METADATA_LANGUAGE}' upload \
+                            metadata of the snippet's file, or is detected from its text; snippets whose \
+                            language can't be told are kept.")
+                        },
+                        PARAM_LANGUAGE_MODE: {
+                            "type": "string",
+                            "enum": ["filter", "flag"],
+                            "description": "'filter' (default) drops snippets in other languages; 'flag' \
+                            keeps them, adding the 'language' of each snippet instead."
+                        },
+                        PARAM_TIMEOUT_MS: {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": timeout_description
+                        },
+                        PARAM_PAGE_SIZE: {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Return at most this many snippets, with a 'next_cursor' \
+                            to get the rest. Useful when querying several assistants or sub-queries."
+                        },
+                        PARAM_CURSOR: {
+                            "type": "string",
+                            "description": "'next_cursor' of an earlier call, to get its next page. \
+                            The other parameters are then not needed, except page_size and response_format."
                         }
-                    },
-                    "required": [PARAM_ASSISTANT_NAME, PARAM_QUERY]
-                }),
-            )],
+                        },
+                        "anyOf": [{"required": [PARAM_QUERY]}, {"required": [PARAM_CURSOR]}]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_SET_DEFAULT_ASSISTANT.to_string(),
+                    format!(
+                        "Sets the assistant (and optionally top_k) used by {TOOL_ASSISTANT_CONTEXT} \
+                for the rest of this session whenever those parameters are omitted. \
+                Call it once at the start of a conversation about a single knowledge base."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of an existing Pinecone assistant"
+                            },
+                            PARAM_TOP_K: {
+                                "type": "integer",
+                                "description": "Default number of context snippets to retrieve"
+                            }
+                        },
+                        "required": [PARAM_ASSISTANT_NAME]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_START_CHAT_SESSION.to_string(),
+                    format!(
+                        "Starts a conversation with a Pinecone assistant whose message history is kept \
+                        by the server. Returns a session id to pass to {TOOL_CHAT_IN_SESSION}; \
+                        call {TOOL_END_CHAT_SESSION} when the conversation is over."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of an existing Pinecone assistant. \
+                                Defaults to the session or server default assistant"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_CHAT_IN_SESSION.to_string(),
+                    "Sends a message in a chat session and returns the assistant's grounded answer \
+                    followed by its citations. Earlier messages of the session are sent along automatically. \
+                    When a progress token is given, the answer is also streamed as it is written, each new \
+                    piece of text as the message of a progress notification."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_SESSION_ID: {
+                                "type": "string",
+                                "description": format!("Session id returned by {TOOL_START_CHAT_SESSION}")
+                            },
+                            PARAM_MESSAGE: {
+                                "type": "string",
+                                "description": "The user message to send"
+                            },
+                            PARAM_JSON_RESPONSE: {
+                                "type": "boolean",
+                                "description": "Answer with a JSON document instead of prose, for \
+                                extraction-style questions whose answer is parsed. Describe the \
+                                expected fields in the message."
+                            },
+                            PARAM_MODEL: model_schema,
+                            PARAM_TEMPERATURE: {
+                                "type": "number",
+                                "minimum": 0,
+                                "maximum": config.max_chat_temperature,
+                                "description": temperature_description
+                            },
+                            PARAM_TOP_P: {
+                                "type": "number",
+                                "exclusiveMinimum": 0,
+                                "maximum": 1,
+                                "description": "Only sample from the most likely tokens whose \
+                                probabilities add up to this value."
+                            },
+                            PARAM_CITATION_STYLE: {
+                                "type": "string",
+                                "enum": ["list", "inline", "footnotes"],
+                                "description": "How citations are shown. 'list' (default) returns the \
+                                answer as written, followed by the citations as JSON. 'inline' marks cited \
+                                passages with [1] and lists the numbered sources after the answer. \
+                                'footnotes' uses Markdown footnotes ([^1]) instead."
+                            },
+                            PARAM_TIMEOUT_MS: {
+                                "type": "integer",
+                                "minimum": 1,
+                                "description": timeout_description
+                            }
+                        },
+                        "required": [PARAM_SESSION_ID, PARAM_MESSAGE]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_ANSWER_WITH_SOURCES.to_string(),
+                    format!(
+                        "Answers a question in one call: retrieves context like {TOOL_ASSISTANT_CONTEXT}, \
+                        then has the assistant answer from those snippets. Returns the answer, then a JSON \
+                        document with the snippets it was given and the citations of the answer."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of an existing Pinecone assistant. Optional when a \
+                                default is set or the server selects one"
+                            },
+                            PARAM_QUERY: {
+                                "type": "string",
+                                "description": "The question to answer."
+                            },
+                            PARAM_TOP_K: {
+                                "type": "integer",
+                                "minimum": 1,
+                                "description": "The number of snippets to answer from. Defaults to the \
+                                value set with set_default_assistant, or else 15."
+                            },
+                            PARAM_FILTER: {
+                                "type": "object",
+                                "description": "Only answer from files whose upload metadata matches this \
+                                filter, e.g. {\"team\": \"billing\"}."
+                            },
+                            PARAM_MODEL: model_schema,
+                            PARAM_TEMPERATURE: {
+                                "type": "number",
+                                "minimum": 0,
+                                "maximum": config.max_chat_temperature,
+                                "description": temperature_description
+                            },
+                            PARAM_TIMEOUT_MS: {
+                                "type": "integer",
+                                "minimum": 1,
+                                "description": timeout_description
+                            }
+                        },
+                        "required": [PARAM_QUERY]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_END_CHAT_SESSION.to_string(),
+                    "Ends a chat session and discards its message history.".to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_SESSION_ID: {
+                                "type": "string",
+                                "description": format!("Session id returned by {TOOL_START_CHAT_SESSION}")
+                            }
+                        },
+                        "required": [PARAM_SESSION_ID]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_RESET_SESSION.to_string(),
+                    format!(
+                        "Discards all state this server holds for the session: defaults set with \
+                        {TOOL_SET_DEFAULT_ASSISTANT}, cached retrievals and all chat sessions."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {}
+                    }),
+                ),
+                Tool::new(
+                    TOOL_UPLOAD_FILE.to_string(),
+                    "Uploads a local file (PDF, text, Markdown, JSON or DOCX) to an assistant's \
+                    knowledge base. The file is processed asynchronously after the upload. \
+                    If the assistant already has a file with identical content, that file is \
+                    returned (flagged 'duplicate') instead of uploading again."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant to upload to. Defaults to the session default"
+                            },
+                            PARAM_FILE_PATH: {
+                                "type": "string",
+                                "description": "Path of the file on the server's filesystem, under an upload root or a root shared by the client"
+                            },
+                            PARAM_METADATA: {
+                                "type": "object",
+                                "description": format!("JSON metadata to attach, e.g. source URL, tags or owner. \
+                                '{METADATA_CREATED_AT}' sets the document's date, as YYYY-MM-DD, \
+                                YYYY-MM-DDTHH:MM:SSZ or Unix seconds, in place of the upload time. \
+                                Usable later in the filter of assistant_context and list_files. Pinecone can't \
+                                change it after upload; to reclassify a file, upload it again with new metadata")
+                            }
+                        },
+                        "required": [PARAM_FILE_PATH]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_UPLOAD_CONTENT.to_string(),
+                    format!(
+                        "Uploads a file given inline, as text or base64, to an assistant's knowledge \
+                        base. For clients without access to the server's filesystem; otherwise \
+                        prefer {TOOL_UPLOAD_FILE}. Duplicates are detected as for {TOOL_UPLOAD_FILE}."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant to upload to. Defaults to the session default"
+                            },
+                            PARAM_FILE_NAME: {
+                                "type": "string",
+                                "description": "Name of the file, with an extension giving its type, e.g. notes.md"
+                            },
+                            PARAM_CONTENT: {
+                                "type": "string",
+                                "description": "Content of the file"
+                            },
+                            PARAM_ENCODING: {
+                                "type": "string",
+                                "enum": ["text", "base64"],
+                                "description": "Encoding of the content; use base64 for binary files such as PDF. Defaults to text"
+                            },
+                            PARAM_METADATA: {
+                                "type": "object",
+                                "description": format!("JSON metadata to attach, e.g. source URL, tags or owner. \
+                                '{METADATA_CREATED_AT}' sets the document's date, as YYYY-MM-DD, \
+                                YYYY-MM-DDTHH:MM:SSZ or Unix seconds, in place of the upload time")
+                            }
+                        },
+                        "required": [PARAM_FILE_NAME, PARAM_CONTENT]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_UPLOAD_DIRECTORY.to_string(),
+                    format!(
+                        "Uploads all matching files below a directory to an assistant, several at a \
+                        time, reporting progress as files complete. Returns a per-file status report \
+                        (uploaded, duplicate or failed). At most {MAX_DIRECTORY_FILES} files."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant to upload to. Defaults to the session default"
+                            },
+                            PARAM_DIRECTORY: {
+                                "type": "string",
+                                "description": "Directory on the server's filesystem, under an upload root or a root shared by the client"
+                            },
+                            PARAM_INCLUDE: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": format!("Glob patterns, relative to the directory, of files to upload. \
+                                Defaults to {}", DEFAULT_INCLUDE.join(", "))
+                            },
+                            PARAM_EXCLUDE: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Glob patterns of files to skip, e.g. **/drafts/**"
+                            },
+                            PARAM_METADATA: {
+                                "type": "object",
+                                "description": format!("JSON metadata to attach to every file, e.g. source URL, tags or owner. \
+                                '{METADATA_CREATED_AT}' sets the documents' date, as YYYY-MM-DD, \
+                                YYYY-MM-DDTHH:MM:SSZ or Unix seconds, in place of the upload time. \
+                                Usable later in the filter of assistant_context and list_files")
+                            }
+                        },
+                        "required": [PARAM_DIRECTORY]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_INGEST_FROM_ROOTS.to_string(),
+                    format!(
+                        "Uploads matching files from the directories the user shared with the client \
+                        (MCP roots) to an assistant, as {TOOL_UPLOAD_DIRECTORY} does for one directory. \
+                        Files outside the roots, including through symbolic links, are never read."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant to upload to. Defaults to the session default"
+                            },
+                            PARAM_ROOTS: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Names or URIs of the roots to ingest. Defaults to all roots"
+                            },
+                            PARAM_INCLUDE: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": format!("Glob patterns, relative to each root, of files to upload. \
+                                Defaults to {}", DEFAULT_INCLUDE.join(", "))
+                            },
+                            PARAM_EXCLUDE: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Glob patterns of files to skip"
+                            },
+                            PARAM_METADATA: {
+                                "type": "object",
+                                "description": "JSON metadata to attach to every file"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_VERIFY_FILE.to_string(),
+                    "Checks that local files match the assistant's copies by comparing SHA-256 \
+                    hashes. Reports per file: 'match', 'mismatch', 'missing' (no file of that name \
+                    in the assistant) or 'unknown' (uploaded without a recorded hash)."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant to check against. Defaults to the session default"
+                            },
+                            PARAM_FILE_PATHS: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Paths of the local files on the server's filesystem, under an upload root or a root shared by the client"
+                            }
+                        },
+                        "required": [PARAM_FILE_PATHS]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_LIST_FILES.to_string(),
+                    "Lists the files in an assistant's knowledge base with their metadata and \
+                    processing status, optionally only those whose metadata matches a filter. \
+                    For knowledge bases with many files, use limit and cursor to browse them \
+                    page by page, and sort_by to order them."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant. Defaults to the session default"
+                            },
+                            PARAM_FILTER: {
+                                "type": "object",
+                                "description": "Metadata filter, e.g. {\"tags\": {\"$in\": [\"faq\"]}}"
+                            },
+                            PARAM_CREATED_AFTER: {
+                                "type": "string",
+                                "description": created_after_description
+                            },
+                            PARAM_CREATED_BEFORE: {
+                                "type": "string",
+                                "description": created_before_description
+                            },
+                            PARAM_FILE_TYPES: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": file_types_description
+                            },
+                            PARAM_LIMIT: {
+                                "type": "integer",
+                                "minimum": 1,
+                                "description": "Return at most this many files as {\"files\": [...], \
+                                \"next_cursor\": ...} instead of all of them"
+                            },
+                            PARAM_CURSOR: {
+                                "type": "string",
+                                "description": "'next_cursor' of an earlier call, to get its next page"
+                            },
+                            PARAM_SORT_BY: {
+                                "type": "string",
+                                "enum": ["name", "size", "created"],
+                                "description": "Order of the files: 'name' (A to Z), 'size' (largest \
+                                first) or 'created' (newest first). Default: as Pinecone lists them"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_SEARCH_FILES.to_string(),
+                    "Finds files in an assistant's knowledge base by name, tags or metadata, \
+                    e.g. the Q3 security review, without listing every file. Returns the matching \
+                    files with their metadata and processing status."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant. Defaults to the session default"
+                            },
+                            PARAM_NAME: {
+                                "type": "string",
+                                "description": "Words the file name must contain, in any order and case, \
+                                e.g. \"q3 security review\""
+                            },
+                            PARAM_TAGS: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Tags the file must all have in its 'tags' metadata"
+                            },
+                            PARAM_FILTER: {
+                                "type": "object",
+                                "description": "Metadata filter, e.g. {\"owner\": {\"$eq\": \"security\"}}"
+                            }
+                        },
+                        "anyOf": [
+                            {"required": [PARAM_NAME]},
+                            {"required": [PARAM_TAGS]},
+                            {"required": [PARAM_FILTER]}
+                        ]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_EXPORT_ASSISTANT.to_string(),
+                    "Exports an assistant as a JSON manifest: its instructions and metadata, and \
+                    its files with their metadata and content hashes. For backup and migration; \
+                    the files' content is not included."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant to export. Defaults to the session default"
+                            },
+                            PARAM_OUTPUT_PATH: {
+                                "type": "string",
+                                "description": "Write the manifest under this file name in the server's \
+                                export directory instead of returning it"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_SYNC_ASSISTANT.to_string(),
+                    format!(
+                        "Makes an assistant match a manifest written by {TOOL_EXPORT_ASSISTANT}: \
+                        creates the assistant if it doesn't exist, updates its instructions and \
+                        metadata, uploads the manifest's files that it lacks from a local directory \
+                        and optionally deletes files not in the manifest. Returns a per-file report."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Name of the assistant to sync. Defaults to the name in the manifest"
+                            },
+                            PARAM_MANIFEST_PATH: {
+                                "type": "string",
+                                "description": "Path of the manifest on the server's filesystem, under an upload root or a root shared by the client"
+                            },
+                            PARAM_DIRECTORY: {
+                                "type": "string",
+                                "description": "Directory holding the manifest's files under their names"
+                            },
+                            PARAM_DELETE_EXTRAS: {
+                                "type": "boolean",
+                                "description": "Delete the assistant's files that are not in the manifest. \
+                                    Nothing is deleted if any file fails to sync, nor files without a \
+                                    recorded content hash"
+                            },
+                            PARAM_DRY_RUN: {
+                                "type": "boolean",
+                                "description": "Only report what would change"
+                            }
+                        },
+                        "required": [PARAM_MANIFEST_PATH, PARAM_DIRECTORY]
+                    }),
+                ),
+                Tool::new(
+                    TOOL_LIST_UPLOADS.to_string(),
+                    format!(
+                        "Lists uploads started with {TOOL_UPLOAD_FILE} that did not complete: \
+                        in progress, interrupted (e.g. by a restart) or failed."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Only list uploads to this assistant"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_RESUME_UPLOADS.to_string(),
+                    format!(
+                        "Retries the interrupted and failed uploads listed by {TOOL_LIST_UPLOADS}. \
+                        Files are sent again from the start; files changed or removed since are skipped."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Only resume uploads to this assistant"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_UPLOAD_QUEUE.to_string(),
+                    format!(
+                        "Lists the uploads queued because Pinecone couldn't be reached, e.g. while \
+                        offline. Queued uploads are retried in the background until they go \
+                        through; uploads Pinecone rejected on retry are kept as failed until \
+                        discarded. Available when the server has an upload spool directory; \
+                        {TOOL_UPLOAD_FILE}, {TOOL_UPLOAD_CONTENT} and {TOOL_UPLOAD_DIRECTORY} \
+                        then report such uploads as queued."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Only list uploads to this assistant"
+                            },
+                            PARAM_RETRY: {
+                                "type": "boolean",
+                                "description": "Retry the queued and failed uploads now"
+                            },
+                            PARAM_DISCARD: {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Ids of uploads to remove from the queue without uploading them"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_HEALTH.to_string(),
+                    "Checks the connection to Pinecone: whether it is reachable and accepts the \
+                    API key, the configured hosts and their failover state, recent rate \
+                    limiting, and the payload sizes of tool calls. Use it to find out why calls are failing."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Also check access to this assistant's data. \
+                                Defaults to the default assistant, if any."
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_DEBUG_CONFIG.to_string(),
+                    "Returns the server's effective configuration (hosts, API version, defaults, \
+                    limits, enabled tools) with API keys masked. Include it when reporting \
+                    unexpected behavior."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {}
+                    }),
+                ),
+                Tool::new(
+                    TOOL_ESTIMATE_COST.to_string(),
+                    "Estimates the cost in US dollars of the retrievals and chats made through this \
+                    server since it started, from the token usage Pinecone reported, per assistant \
+                    and in total. Actual billing may differ."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Only estimate the cost of this assistant"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_USAGE_STATS.to_string(),
+                    "Returns usage counters per assistant since the server started and for \
+                    today (UTC): context queries, chat requests, tokens and failed calls."
+                        .to_string(),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_ASSISTANT_NAME: {
+                                "type": "string",
+                                "description": "Only return the counters of this assistant"
+                            }
+                        }
+                    }),
+                ),
+                Tool::new(
+                    TOOL_BATCH.to_string(),
+                    format!(
+                        "Runs several tool calls of this server concurrently and returns their results \
+                        in order, as a JSON array of {{tool, is_error, content | error}}. \
+                        Use it to issue independent retrievals at once, e.g. several \
+                        {TOOL_ASSISTANT_CONTEXT} queries. At most {MAX_BATCH_CALLS} calls. \
+                        When a progress token is given, each result is also sent in the _meta.partial \
+                        field of a progress notification as soon as it completes, with its index."
+                    ),
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            PARAM_CALLS: {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "tool": { "type": "string" },
+                                        "arguments": { "type": "object" }
+                                    },
+                                    "required": ["tool"]
+                                },
+                                "description": "The tool calls to run"
+                            }
+                        },
+                        "required": [PARAM_CALLS]
+                    }),
+                ),
+            ],
+            schemas: SchemaValidators::default(),
+            inventory,
+            files,
+        };
+        for (tool_name, description) in config.tool_descriptions {
+            match router.tools.iter_mut().find(|tool| tool.name == tool_name) {
+                Some(tool) => tool.description = description,
+                None => tracing::warn!("Ignoring description of unknown tool: {tool_name}"),
+            }
+        }
+        if router.allow_api_key_argument {
+            for tool in &mut router.tools {
+                if let Some(Value::Object(properties)) = tool.input_schema.get_mut("properties") {
+                    properties.insert(
+                        PARAM_API_KEY.to_string(),
+                        serde_json::json!({
+                            "type": "string",
+                            "description": "Pinecone API key to use for this call instead of \
+                                the server's, for the project of the caller's tenant"
+                        }),
+                    );
+                }
+            }
+        }
+        router.schemas = SchemaValidators::new(&router.tools);
+        if let Some(name) = router.default_assistant.as_deref() {
+            router.watch_files(router.resolve_alias(name));
+        }
+        // Uploads spooled before a restart.
+        router.retry_spooled_in_background();
+        router
+    }
+
+    /// Lists the project's assistants ahead of the first tool call when the
+    /// only one may be used for calls that don't name one, i.e. when no
+    /// default assistant and no assistant descriptions are configured.
+    pub async fn detect_only_assistant(&self) {
+        if self.default_assistant.is_some() || !self.query_router.is_empty() {
+            return;
+        }
+        self.inventory.get().await;
+        if let Some(name) = self.only_assistant() {
+            tracing::info!("Using the only assistant, {name}, when calls don't name one");
+            self.watch_files(&name);
+        }
+    }
+
+    /// Writes out state kept in memory for later, such as usage counters,
+    /// before the process exits.
+    pub async fn shutdown(&self) {
+        self.usage.flush().await;
+    }
+
+    /// The project's only assistant, if it has exactly one and neither a
+    /// default assistant nor assistant descriptions are configured.
+    fn only_assistant(&self) -> Option<String> {
+        if self.default_assistant.is_some() || !self.query_router.is_empty() {
+            return None;
+        }
+        match self.inventory.cached()?.as_slice() {
+            [assistant] => Some(assistant.name.clone()),
+            _ => None,
+        }
+    }
+
+    /// Fetches the default assistant's instructions so they can be included
+    /// in the server instructions. Failures are logged and leave the generic
+    /// instructions in place.
+    pub async fn load_assistant_instructions(&mut self) {
+        let Some(assistant_name) = self.default_assistant.clone() else {
+            return;
+        };
+        let assistant_name = self.resolve_alias(&assistant_name).to_string();
+        match self
+            .backend_for(&assistant_name)
+            .describe_assistant(&assistant_name)
+            .await
+        {
+            Ok(assistant) => {
+                let description = assistant
+                    .metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata["description"].as_str().map(str::to_string));
+                let parts: Vec<String> = description
+                    .into_iter()
+                    .chain(assistant.instructions)
+                    .filter(|part| !part.trim().is_empty())
+                    .collect();
+                if parts.is_empty() {
+                    tracing::debug!("Assistant {assistant_name} has no instructions to include");
+                    return;
+                }
+                tracing::info!("Loaded instructions of default assistant: {assistant_name}");
+                self.assistant_instructions = Some(format!(
+                    "The default assistant \"{assistant_name}\" is described as follows: {}",
+                    parts.join("\n")
+                ));
+            }
+            Err(e) => {
+                tracing::warn!("Failed to load instructions of assistant {assistant_name}: {e}")
+            }
         }
     }
 
+    /// Checks that every assistant the configuration names (the default,
+    /// alias targets, described assistants) exists and logs an inventory of
+    /// the project's assistants, so a wrong name shows at startup rather than
+    /// mid-conversation. Returns the problems found.
+    pub async fn self_test(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let inventory = match self.backend.list_assistants().await {
+            Ok(assistants) => {
+                let summary: Vec<String> = assistants
+                    .iter()
+                    .map(|assistant| match &assistant.status {
+                        Some(status) => format!("{} ({status})", assistant.name),
+                        None => assistant.name.clone(),
+                    })
+                    .collect();
+                tracing::info!(
+                    "Assistant inventory: {} assistant(s): {}",
+                    assistants.len(),
+                    summary.join(", ")
+                );
+                self.inventory.set(assistants.clone());
+                Some(assistants)
+            }
+            // Assistants are then looked up one by one.
+            Err(PineconeError::Unsupported(_)) => None,
+            Err(e) => {
+                problems.push(format!("Listing assistants failed: {e}"));
+                return problems;
+            }
+        };
+
+        let mut expected: Vec<(String, &str)> = Vec::new();
+        if let Some(name) = &self.default_assistant {
+            expected.push(("Default assistant".to_string(), self.resolve_alias(name)));
+        }
+        for (alias, name) in &self.assistant_aliases {
+            expected.push((format!("Target of alias {alias}"), name));
+        }
+        for route in self.query_router.routes() {
+            expected.push((
+                "Described assistant".to_string(),
+                self.resolve_alias(&route.name),
+            ));
+        }
+        let mut checked = HashSet::new();
+        for (role, name) in expected {
+            if !checked.insert(name) {
+                continue;
+            }
+            let listed = inventory
+                .as_ref()
+                .filter(|_| !self.assistant_backends.contains_key(name))
+                .map(|assistants| assistants.iter().find(|a| a.name == name).cloned());
+            let assistant = match listed {
+                Some(assistant) => assistant,
+                // Assistants with a dedicated key live in another project.
+                None => match self.backend_for(name).describe_assistant(name).await {
+                    Ok(assistant) => Some(assistant),
+                    Err(PineconeError::NotFound { .. }) => None,
+                    Err(PineconeError::Unsupported(_)) => continue,
+                    Err(e) => {
+                        problems.push(format!("{role} {name} cannot be looked up: {e}"));
+                        continue;
+                    }
+                },
+            };
+            match assistant {
+                None => problems.push(format!("{role} {name} does not exist")),
+                Some(assistant)
+                    if assistant
+                        .status
+                        .as_deref()
+                        .is_some_and(|status| status.eq_ignore_ascii_case("failed")) =>
+                {
+                    problems.push(format!("{role} {name} is in a failed state"))
+                }
+                Some(_) => {}
+            }
+        }
+        problems
+    }
+
     async fn handle_assistant_context(
         &self,
         arguments: Value,
     ) -> Result<Vec<Content>, RouterError> {
         tracing::debug!("Processing {TOOL_ASSISTANT_CONTEXT} arguments");
-        let assistant_name = arguments[PARAM_ASSISTANT_NAME].as_str().ok_or_else(|| {
-            RouterError::InvalidParameters(format!("{} must be a string", PARAM_ASSISTANT_NAME))
-        })?;
-        let query = arguments[PARAM_QUERY].as_str().ok_or_else(|| {
-            RouterError::InvalidParameters(format!("{} must be a string", PARAM_QUERY))
-        })?;
-        let top_k = arguments[PARAM_TOP_K].as_u64().map(|v| v as u32);
+        let snapshot_arguments = self.snapshots.is_some().then(|| arguments.clone());
+        let params: AssistantContextParams = params::parse(arguments)?;
+        if let Some(cursor) = &params.cursor {
+            let page_size = params.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+            let page = self
+                .pages
+                .next(
+                    TOOL_ASSISTANT_CONTEXT,
+                    connection_id(),
+                    cursor,
+                    page_size,
+                    self.max_response_bytes,
+                )
+                .map_err(RouterError::InvalidParameters)?;
+            let (snippets, dropped) = self.fit_response(page.items);
+            let notes = Notes {
+                dropped,
+                next_cursor: page.next_cursor.as_deref(),
+                ..Notes::default()
+            };
+            return Ok(self.render_context(snippets, None, &notes, params.response_format));
+        }
+        if params.query.is_empty() {
+            return Err(RouterError::InvalidParameters(format!(
+                "missing field `{PARAM_QUERY}`"
+            )));
+        }
+        let defaults = self.session.defaults();
+        let top_k = params.top_k.or(defaults.top_k);
+        tracing::debug!("Query: {}", loggable(&params.query, self.privacy_mode));
+        let query = if params.rewrite_query {
+            self.rewrite_query(&params.query).await
+        } else {
+            params.query.clone()
+        };
+        let query = query.as_str();
+        let queries = match params.sub_queries {
+            Some(max) if max > 0 => self.expand_query(query, max as usize).await,
+            _ => vec![query.to_string()],
+        };
+        // Remembered by connection, so clients sharing the server don't
+        // qualify each other's queries.
+        let connection = format!("{:?}", connection_id());
+        let mut history = if params.use_query_history {
+            self.session.recent_queries(&connection)
+        } else {
+            Vec::new()
+        };
+        history.retain(|earlier| earlier != query);
+        let messages = (!history.is_empty()).then(|| {
+            history
+                .iter()
+                .map(String::as_str)
+                .chain([query])
+                .map(Message::user)
+                .collect()
+        });
+        let request = AssistantContext {
+            query: if messages.is_some() {
+                String::new()
+            } else {
+                query.to_string()
+            },
+            messages,
+            top_k,
+            multimodal: params.include_images.then_some(true),
+            include_binary_content: params.include_images.then_some(true),
+            include_highlights: params.include_highlights.then_some(true),
+            filter: with_file_types(
+                with_date_range(
+                    params.filter.map(Value::Object),
+                    params.created_after.as_deref(),
+                    params.created_before.as_deref(),
+                )?,
+                params.file_types.as_deref(),
+            ),
+        };
+        let timeout = self.timeout(params.timeout_ms);
 
-        tracing::info!(
-            "Making request to Pinecone API for assistant: {} with top_k: {:?}",
-            assistant_name,
-            top_k
-        );
+        // Merged results name their assistants; others come from `assistant`.
+        let mut stale = None;
+        let (snippets, usage, assistant) = match &params.assistant_names {
+            None if queries.len() > 1 => {
+                let assistant_name =
+                    self.pick_assistant(params.assistant_name.as_deref(), &defaults, query)?;
+                let assistant_names = [assistant_name.to_string()];
+                (
+                    self.fan_out(&assistant_names, &queries, &request, timeout)
+                        .await?,
+                    None,
+                    None,
+                )
+            }
+            None => {
+                let assistant_name =
+                    self.pick_assistant(params.assistant_name.as_deref(), &defaults, query)?;
+                let assistant_name = assistant_name.as_ref();
 
-        let response = self
-            .client
-            .assistant_context(assistant_name, query, top_k)
-            .await?;
+                let scope = SemanticCache::scope(assistant_name, &request);
+                // Keyed by connection too, so that clients sharing the server
+                // never see each other's results.
+                let key = format!("{connection}\n{scope}\n{query}");
+                let cached = self
+                    .retrieval_cache_ttl
+                    .and_then(|ttl| self.session.cached_retrieval(&key, ttl));
+                if let Some(snippets) = cached {
+                    tracing::debug!(
+                        "Reusing the results of the same retrieval in this conversation"
+                    );
+                    (snippets, None, Some(assistant_name.to_string()))
+                } else {
+                    let retrieved = self
+                        .retrieve(assistant_name, query, scope, request.clone(), timeout)
+                        .await;
+                    let fallback = match (&retrieved, self.serve_stale) {
+                        (Err(e), Some(max_age)) if is_outage(e) => {
+                            self.session.fallback(&key, max_age)
+                        }
+                        _ => None,
+                    };
+                    let (snippets, usage) = match (retrieved, fallback) {
+                        (Err(e), Some((snippets, age))) => {
+                            tracing::warn!(
+                                "Serving the snippets of the same retrieval {}s ago: {e}",
+                                age.as_secs()
+                            );
+                            stale = Some(age);
+                            (snippets, None)
+                        }
+                        (retrieved, _) => retrieved?,
+                    };
+                    if stale.is_none() {
+                        if self.retrieval_cache_ttl.is_some() {
+                            self.session.cache_retrieval(key.clone(), snippets.clone());
+                        }
+                        if self.serve_stale.is_some() {
+                            self.session.keep_fallback(key, snippets.clone());
+                        }
+                    }
+                    (snippets, usage, Some(assistant_name.to_string()))
+                }
+            }
+            Some(assistant_names) if assistant_names.is_empty() => {
+                return Err(RouterError::InvalidParameters(format!(
+                    "{PARAM_ASSISTANT_NAMES} must not be empty"
+                )));
+            }
+            Some(assistant_names) => (
+                self.fan_out(assistant_names, &queries, &request, timeout)
+                    .await?,
+                None,
+                None,
+            ),
+        };
 
         tracing::info!("Successfully received response from Pinecone API");
-        Ok(response
-            .snippets
-            .iter()
-            .map(|snippet| Content::text(snippet.to_string()))
-            .collect())
+        if self.retrieval_memory > 0 {
+            self.session
+                .remember_query(connection, query.to_string(), self.retrieval_memory);
+        }
+        let snippets = match params.expand_context {
+            Some(count) if count > 0 => {
+                self.expand_context(
+                    snippets,
+                    count as usize,
+                    assistant.as_deref(),
+                    &request,
+                    timeout,
+                )
+                .await
+            }
+            _ => snippets,
+        };
+        let snippets = match &params.language {
+            Some(language) => {
+                let (snippets, dropped) = language::apply(snippets, language, params.language_mode);
+                if dropped > 0 {
+                    tracing::debug!(
+                        "Dropped {dropped} snippets in languages other than {language}"
+                    );
+                }
+                snippets
+            }
+            None => snippets,
+        };
+        let snippets = if params.merge_adjacent.unwrap_or(true) {
+            merge_adjacent(snippets)
+        } else {
+            snippets
+        };
+        let (snippets, omitted) = match params.context_budget_chars {
+            Some(budget) => output::pack(snippets, budget),
+            None => (snippets, 0),
+        };
+        if omitted > 0 {
+            tracing::debug!("Omitted {omitted} snippets to fit the context budget");
+        }
+        // Paged results are cut to size page by page instead, so snippets
+        // that don't fit one page move to the next rather than being lost.
+        let (snippets, dropped) = match params.page_size {
+            Some(_) => (snippets, 0),
+            None => self.fit_response(snippets),
+        };
+        let snapshot = match (&self.snapshots, snapshot_arguments) {
+            (Some(store), Some(arguments)) => {
+                let snapshot = serde_json::json!({
+                    "tool": TOOL_ASSISTANT_CONTEXT,
+                    "arguments": arguments,
+                    "queries": queries,
+                    "snippets": snippets,
+                    "usage": usage,
+                    "omitted": omitted,
+                    "dropped": dropped,
+                });
+                match store.save(snapshot).await {
+                    Ok(id) => Some(format!("{SNAPSHOT_RESOURCE_PREFIX}{id}")),
+                    Err(e) => {
+                        tracing::warn!("Failed to save retrieval snapshot: {e}");
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let (snippets, dropped, next_cursor) = match params.page_size {
+            Some(page_size) => {
+                let page = self.pages.first(
+                    TOOL_ASSISTANT_CONTEXT,
+                    connection_id(),
+                    snippets,
+                    page_size,
+                    self.max_response_bytes,
+                );
+                let (snippets, dropped) = self.fit_response(page.items);
+                (snippets, dropped, page.next_cursor)
+            }
+            None => (snippets, dropped, None),
+        };
+        let notes = Notes {
+            omitted,
+            dropped,
+            snapshot: snapshot.as_deref(),
+            next_cursor: next_cursor.as_deref(),
+            stale,
+        };
+        Ok(self.render_context(snippets, usage, &notes, params.response_format))
     }
-}
 
-impl mcp_server::Router for PineconeAssistantRouter {
-    fn name(&self) -> String {
-        "pinecone-assistant".to_string()
+    /// Drops the lowest-scoring snippets until the rest fit
+    /// `max_response_bytes`, returning how many were dropped.
+    fn fit_response(&self, snippets: Vec<Value>) -> (Vec<Value>, usize) {
+        let (snippets, dropped) = match self.max_response_bytes {
+            Some(max_bytes) => output::fit_bytes(snippets, max_bytes),
+            None => (snippets, 0),
+        };
+        if dropped > 0 {
+            tracing::warn!("Dropped {dropped} snippets to stay under the response size limit");
+        }
+        (snippets, dropped)
     }
 
-    fn instructions(&self) -> String {
-        format!(
-            "This server connects to an existing Pinecone Assistant,\
-            a RAG system for retrieving relevant document snippets. \
-            Use the {TOOL_ASSISTANT_CONTEXT} tool to access contextual information from its knowledge base"
-        )
+    /// Renders retrieved snippets, linking the files they cite.
+    fn render_context(
+        &self,
+        snippets: Vec<Value>,
+        usage: Option<Value>,
+        notes: &Notes,
+        format: ResponseFormat,
+    ) -> Vec<Content> {
+        let snippet_count = snippets.len();
+        self.payload_sizes.record_snippets(snippet_count);
+        let cited_files = output::cited_files(&snippets);
+        let mut contents = output::render(snippets, usage, notes, format);
+        if format == ResponseFormat::Text {
+            contents.extend(
+                cited_files
+                    .iter()
+                    .map(|(uri, file)| output::file_link(uri, file)),
+            );
+        }
+        self.session.cite_files(cited_files);
+        let texts: Vec<&str> = contents.iter().filter_map(Content::as_text).collect();
+        tracing::debug!(
+            "Returning {} snippets ({} bytes)",
+            snippet_count,
+            texts.iter().map(|text| text.len()).sum::<usize>()
+        );
+        for text in texts {
+            tracing::trace!("Content: {}", loggable(text, self.privacy_mode));
+        }
+        contents
     }
+}
 
-    fn capabilities(&self) -> ServerCapabilities {
-        tracing::debug!("Building server capabilities");
-        CapabilitiesBuilder::new().with_tools(true).build()
+impl PineconeAssistantRouter {
+    async fn handle_set_default_assistant(
+        &self,
+        arguments: Value,
+    ) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_SET_DEFAULT_ASSISTANT} arguments");
+        let params: SetDefaultAssistantParams = params::parse(arguments)?;
+        let assistant_name = self.canonical_name(&params.assistant_name)?.into_owned();
+        tracing::info!(
+            "Setting session defaults: assistant: {} top_k: {:?}",
+            assistant_name,
+            params.top_k
+        );
+
+        self.watch_files(&assistant_name);
+        let mut state = self.session.lock();
+        state.defaults.assistant_name = Some(assistant_name.clone());
+        state.defaults.top_k = params.top_k;
+        Ok(vec![Content::text(match params.top_k {
+            Some(top_k) => format!(
+                "Default assistant set to \"{assistant_name}\" with top_k {top_k} for this session"
+            ),
+            None => format!("Default assistant set to \"{assistant_name}\" for this session"),
+        })])
     }
 
-    fn list_tools(&self) -> Vec<Tool> {
-        tracing::debug!("Listing available tools");
-        self.tools.clone()
+    async fn handle_start_chat_session(
+        &self,
+        arguments: Value,
+    ) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_START_CHAT_SESSION} arguments");
+        let params: StartChatSessionParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+
+        let chat = self.session.start_chat(assistant_name).await;
+        tracing::info!(
+            "Started chat session {} with assistant: {}",
+            chat.id,
+            chat.assistant_name
+        );
+        Ok(vec![Content::text(
+            serde_json::json!({
+                PARAM_SESSION_ID: chat.id,
+                PARAM_ASSISTANT_NAME: chat.assistant_name,
+            })
+            .to_string(),
+        )])
     }
 
-    fn call_tool(
+    async fn handle_chat_in_session(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_CHAT_IN_SESSION} arguments");
+        let params: ChatInSessionParams = params::parse(arguments)?;
+        let chat = self
+            .session
+            .chat(&params.session_id)
+            .await
+            .ok_or_else(|| RouterError::SessionNotFound(params.session_id.clone()))?;
+        tracing::debug!("Message: {}", loggable(&params.message, self.privacy_mode));
+        let model = self.chat_model(params.model)?;
+        let temperature = self.chat_temperature(params.temperature)?;
+
+        let question = Message::user(params.message);
+        let mut messages = chat.messages.clone();
+        messages.push(question.clone());
+        tracing::info!(
+            "Making chat request to Pinecone API for assistant: {} with {} messages",
+            chat.assistant_name,
+            messages.len()
+        );
+        let request = AssistantChat {
+            messages,
+            json_response: params.json_response.then_some(true),
+            model,
+            temperature,
+            top_p: params.top_p,
+            ..AssistantChat::default()
+        };
+        // Clients that asked for progress see the answer as it is written,
+        // unless it is scrubbed, as a match could span the pieces.
+        let context = RequestContext::current()
+            .filter(|c| c.progress_token.is_some() && self.scrubber.is_empty());
+        let on_delta = |delta: &str| {
+            if let Some(context) = &context {
+                context.notify_step(None, Some(delta), None);
+            }
+        };
+        let backend = self.backend_for(&chat.assistant_name);
+        let call = if context.is_some() {
+            backend.assistant_chat_stream(&chat.assistant_name, request, &on_delta)
+        } else {
+            backend.assistant_chat_with(&chat.assistant_name, request)
+        };
+        let response = within_deadline(call, self.timeout(params.timeout_ms)).await;
+        let mut response = self
+            .track(&chat.assistant_name, usage::Kind::Chat, response, |r| {
+                &r.usage
+            })
+            .await?;
+        tracing::info!("Successfully received chat response from Pinecone API");
+        self.scrubber
+            .scrub_answer(&mut response.message.content, &mut response.citations);
+
+        let turn = vec![question, response.message.clone()];
+        if !self.session.append_to_chat(&chat.id, turn).await {
+            tracing::info!("Chat session {} ended while answering", chat.id);
+        }
+
+        Ok(output::render_answer(
+            response.message.content,
+            response.citations,
+            params.citation_style,
+        ))
+    }
+
+    async fn handle_answer_with_sources(
         &self,
-        tool_name: &str,
         arguments: Value,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>> {
-        tracing::info!("Calling tool: {}", tool_name);
-        let router = self.clone();
-        match tool_name {
-            TOOL_ASSISTANT_CONTEXT => Box::pin(async move {
-                router
-                    .handle_assistant_context(arguments)
-                    .await
-                    .map_err(Into::into)
-            }),
-            _ => {
-                tracing::error!("Tool not found: {}", tool_name);
-                let tool_name = tool_name.to_string();
-                Box::pin(async move {
-                    Err(ToolError::NotFound(format!("Tool {} not found", tool_name)))
+    ) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_ANSWER_WITH_SOURCES} arguments");
+        let params: AnswerWithSourcesParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, &params.query)?
+            .to_string();
+        let model = self.chat_model(params.model)?;
+        let temperature = self.chat_temperature(params.temperature)?;
+        tracing::debug!("Question: {}", loggable(&params.query, self.privacy_mode));
+        let timeout = self.timeout(params.timeout_ms);
+
+        let request = AssistantContext {
+            query: params.query.clone(),
+            top_k: params.top_k.or(defaults.top_k),
+            filter: params.filter.map(Value::Object),
+            ..AssistantContext::default()
+        };
+        let scope = SemanticCache::scope(&assistant_name, &request);
+        let (snippets, _) = self
+            .retrieve(&assistant_name, &params.query, scope, request, timeout)
+            .await?;
+        let snippets = merge_adjacent(snippets);
+        self.payload_sizes.record_snippets(snippets.len());
+
+        tracing::info!(
+            "Making chat request to Pinecone API for assistant: {assistant_name} with {} pinned snippets",
+            snippets.len()
+        );
+        let request = AssistantChat {
+            messages: vec![Message::user(pinned_question(&params.query, &snippets))],
+            model,
+            temperature,
+            ..AssistantChat::default()
+        };
+        let response = within_deadline(
+            self.backend_for(&assistant_name)
+                .assistant_chat_with(&assistant_name, request),
+            timeout,
+        )
+        .await;
+        let mut response = self
+            .track(&assistant_name, usage::Kind::Chat, response, |r| &r.usage)
+            .await?;
+        self.scrubber
+            .scrub_answer(&mut response.message.content, &mut response.citations);
+        self.session.cite_files(output::cited_files(&snippets));
+
+        Ok(vec![
+            Content::text(response.message.content),
+            Content::text(
+                serde_json::json!({
+                    "snippets": snippets,
+                    "citations": response.citations,
                 })
+                .to_string(),
+            ),
+        ])
+    }
+
+    /// The generation model of a chat call: the one it asked for or else the
+    /// configured default, which must be allowed.
+    fn chat_model(&self, requested: Option<String>) -> Result<Option<String>, RouterError> {
+        let model = requested.or_else(|| self.chat_model.clone());
+        if let Some(model) = &model {
+            if !self.allowed_chat_models.is_empty() && !self.allowed_chat_models.contains(model) {
+                return Err(RouterError::InvalidParameters(format!(
+                    "Model {model} is not allowed; use one of: {}",
+                    self.allowed_chat_models.join(", ")
+                )));
             }
         }
+        Ok(model)
     }
 
-    fn list_resources(&self) -> Vec<Resource> {
-        vec![]
+    /// The sampling temperature of a chat call: the one it asked for, within
+    /// the configured maximum, or else the configured default.
+    fn chat_temperature(&self, requested: Option<f32>) -> Result<Option<f32>, RouterError> {
+        match requested {
+            Some(temperature) if !(0.0..=self.max_chat_temperature).contains(&temperature) => {
+                Err(RouterError::InvalidParameters(format!(
+                    "{PARAM_TEMPERATURE} must be between 0 and {}",
+                    self.max_chat_temperature
+                )))
+            }
+            Some(temperature) => Ok(Some(temperature)),
+            None => Ok(self.chat_temperature),
+        }
     }
 
-    fn read_resource(
-        &self,
-        _uri: &str,
-    ) -> Pin<Box<dyn Future<Output = Result<String, ResourceError>> + Send + 'static>> {
-        Box::pin(async {
-            Err(ResourceError::NotFound(
-                "No resources available".to_string(),
-            ))
-        })
+    async fn handle_end_chat_session(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_END_CHAT_SESSION} arguments");
+        let params: EndChatSessionParams = params::parse(arguments)?;
+        if !self.session.end_chat(&params.session_id).await {
+            return Err(RouterError::SessionNotFound(params.session_id));
+        }
+        tracing::info!("Ended chat session {}", params.session_id);
+        Ok(vec![Content::text(format!(
+            "Chat session {} ended",
+            params.session_id
+        ))])
     }
 
-    fn list_prompts(&self) -> Vec<Prompt> {
-        vec![]
+    async fn handle_reset_session(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_RESET_SESSION} arguments");
+        params::parse::<ResetSessionParams>(arguments)?;
+        let summary = self.session.reset().await;
+        tracing::info!(
+            "Reset session state [defaults: {}, chats: {}]",
+            summary.had_defaults,
+            summary.chats
+        );
+        Ok(vec![Content::text(format!(
+            "Session reset: {} chat session(s) ended{}",
+            summary.chats,
+            if summary.had_defaults {
+                ", defaults cleared"
+            } else {
+                ""
+            }
+        ))])
     }
 
-    fn get_prompt(
-        &self,
-        prompt_name: &str,
-    ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>> {
-        let prompt_name = prompt_name.to_string();
-        Box::pin(async move {
-            Err(PromptError::NotFound(format!(
-                "Prompt {} not found",
-                prompt_name
-            )))
+    async fn handle_upload_file(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_UPLOAD_FILE} arguments");
+        let params: UploadFileParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+
+        let path = confine(&params.file_path, &self.readable_roots().await).await?;
+
+        tracing::info!(
+            "Uploading {} to assistant: {}",
+            path.display(),
+            assistant_name
+        );
+        let uploaded = self
+            .upload_or_spool(
+                &assistant_name,
+                &path,
+                Some(&path),
+                params.metadata.as_ref(),
+                true,
+            )
+            .await?;
+        Ok(vec![Content::text(uploaded_result(uploaded).to_string())])
+    }
+
+    async fn handle_upload_content(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_UPLOAD_CONTENT} arguments");
+        let params: UploadContentParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+        let content = match params.encoding {
+            ContentEncoding::Text => params.content.into_bytes(),
+            ContentEncoding::Base64 => {
+                BASE64_STANDARD.decode(params.content.trim()).map_err(|e| {
+                    RouterError::InvalidParameters(format!(
+                        "{PARAM_CONTENT} is not valid base64: {e}"
+                    ))
+                })?
+            }
+        };
+        // Checked before staging, so oversized content never reaches disk.
+        let name = Path::new(&params.file_name);
+        self.upload_limits
+            .check_type(name)
+            .and_then(|()| self.upload_limits.check_size(name, content.len() as u64))
+            .map_err(RouterError::InvalidParameters)?;
+        let file = TempFile::write(&params.file_name, &content)
+            .await
+            .map_err(|e| RouterError::InvalidParameters(format!("Cannot stage upload: {e}")))?;
+
+        tracing::info!(
+            "Uploading {} inline bytes as {} to assistant: {}",
+            content.len(),
+            params.file_name,
+            assistant_name
+        );
+        // Not journalled: the staged copy is gone once the call returns, so
+        // there would be nothing to resume from.
+        let uploaded = self
+            .upload_or_spool(
+                &assistant_name,
+                file.path(),
+                None,
+                params.metadata.as_ref(),
+                false,
+            )
+            .await?;
+        Ok(vec![Content::text(uploaded_result(uploaded).to_string())])
+    }
+
+    async fn handle_upload_directory(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_UPLOAD_DIRECTORY} arguments");
+        let params: UploadDirectoryParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+        let include = match &params.include {
+            Some(patterns) => glob_set(patterns)?,
+            None => glob_set(DEFAULT_INCLUDE)?,
+        };
+        let exclude = glob_set(&params.exclude)?;
+        let metadata = params.metadata.as_ref();
+        // Symbolic links are skipped while walking, so nothing outside the
+        // directory is reached.
+        let directory = confine(&params.directory, &self.readable_roots().await).await?;
+        let files = uploads::collect_files(&directory, &include, &exclude, MAX_DIRECTORY_FILES)
+            .await
+            .map_err(|e| {
+                RouterError::InvalidParameters(format!(
+                    "Cannot collect files from {}: {e}",
+                    directory.display()
+                ))
+            })?;
+
+        tracing::info!(
+            "Uploading {} files from {} to assistant: {}",
+            files.len(),
+            directory.display(),
+            assistant_name
+        );
+        let report = self
+            .upload_all(&assistant_name, &files, metadata, &notify_progress)
+            .await;
+        Ok(vec![Content::text(report.to_string())])
+    }
+
+    async fn handle_ingest_from_roots(
+        &self,
+        arguments: Value,
+    ) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_INGEST_FROM_ROOTS} arguments");
+        let params: IngestFromRootsParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+        let include = match &params.include {
+            Some(patterns) => glob_set(patterns)?,
+            None => glob_set(DEFAULT_INCLUDE)?,
+        };
+        let exclude = glob_set(&params.exclude)?;
+
+        let context = RequestContext::current().ok_or(PeerError::Closed)?;
+        let mut roots = context.peer.list_roots().await?;
+        if let Some(selected) = &params.roots {
+            for wanted in selected {
+                if !roots
+                    .iter()
+                    .any(|root| root.uri == *wanted || root.name.as_ref() == Some(wanted))
+                {
+                    return Err(RouterError::InvalidParameters(format!(
+                        "{wanted} is not one of the client's roots"
+                    )));
+                }
+            }
+            roots.retain(|root| {
+                selected
+                    .iter()
+                    .any(|wanted| root.uri == *wanted || root.name.as_ref() == Some(wanted))
+            });
+        }
+
+        let mut files = Vec::new();
+        for root in &roots {
+            let Some(directory) = root.path() else {
+                tracing::debug!("Skipping root {}: not a local directory", root.uri);
+                continue;
+            };
+            // Symbolic links are skipped while walking, so nothing outside
+            // the root is reached.
+            let limit = MAX_DIRECTORY_FILES.saturating_sub(files.len());
+            let found = uploads::collect_files(&directory, &include, &exclude, limit)
+                .await
+                .map_err(|e| {
+                    RouterError::InvalidParameters(format!(
+                        "Cannot collect files from root {}: {e}",
+                        root.uri
+                    ))
+                })?;
+            files.extend(found);
+        }
+
+        tracing::info!(
+            "Uploading {} files from {} roots to assistant: {}",
+            files.len(),
+            roots.len(),
+            assistant_name
+        );
+        let report = self
+            .upload_all(
+                &assistant_name,
+                &files,
+                params.metadata.as_ref(),
+                &notify_progress,
+            )
+            .await;
+        Ok(vec![Content::text(report.to_string())])
+    }
+
+    /// Uploads files and the supported documents in directories, as the
+    /// upload tools do, calling `on_progress` as each file completes.
+    /// Returns the report of [`TOOL_UPLOAD_DIRECTORY`].
+    pub async fn upload_paths(
+        &self,
+        assistant_name: Option<&str>,
+        paths: &[PathBuf],
+        metadata: Option<&Map<String, Value>>,
+        on_progress: &UploadProgress<'_>,
+    ) -> Result<Value, RouterError> {
+        let defaults = self.session.defaults();
+        let assistant_name = self.pick_assistant(assistant_name, &defaults, "")?;
+        let include = glob_set(DEFAULT_INCLUDE)?;
+        let exclude = glob_set::<&str>(&[])?;
+        let mut files = Vec::new();
+        for path in paths {
+            if !path.is_dir() {
+                files.push(path.clone());
+                continue;
+            }
+            let limit = MAX_DIRECTORY_FILES.saturating_sub(files.len());
+            let found = uploads::collect_files(path, &include, &exclude, limit)
+                .await
+                .map_err(|e| {
+                    RouterError::InvalidParameters(format!(
+                        "Cannot collect files from {}: {e}",
+                        path.display()
+                    ))
+                })?;
+            files.extend(found);
+        }
+
+        tracing::info!(
+            "Uploading {} files to assistant: {}",
+            files.len(),
+            assistant_name
+        );
+        Ok(self
+            .upload_all(&assistant_name, &files, metadata, on_progress)
+            .await)
+    }
+
+    /// Uploads files concurrently, calling `on_progress` as each completes,
+    /// and returns a per-file status report.
+    async fn upload_all(
+        &self,
+        assistant_name: &str,
+        files: &[PathBuf],
+        metadata: Option<&Map<String, Value>>,
+        on_progress: &UploadProgress<'_>,
+    ) -> Value {
+        let total = files.len();
+        // Collected into boxed futures up front: a stream over a borrowing
+        // closure trips the compiler's `Send` inference.
+        let uploads: Vec<_> = files
+            .iter()
+            .enumerate()
+            .map(|(index, path)| {
+                async move {
+                    let result = self
+                        .upload_or_spool(assistant_name, path, Some(path), metadata, true)
+                        .await;
+                    (index, result)
+                }
+                .boxed()
+            })
+            .collect();
+        let mut results =
+            futures::stream::iter(uploads).buffer_unordered(self.max_concurrent_requests);
+        let mut report = vec![Value::Null; total];
+        let (mut uploaded, mut duplicates, mut queued, mut failed) = (0, 0, 0, 0);
+        let mut done = 0;
+        while let Some((index, result)) = results.next().await {
+            let path = &files[index];
+            report[index] = match result {
+                Ok(Uploaded::File(file, existing)) => {
+                    if existing {
+                        duplicates += 1;
+                    } else {
+                        uploaded += 1;
+                    }
+                    let status = if existing { "duplicate" } else { "uploaded" };
+                    serde_json::json!({ PARAM_FILE_PATH: path, "status": status, "file_id": file.id })
+                }
+                Ok(Uploaded::Queued(upload)) => {
+                    queued += 1;
+                    serde_json::json!({ PARAM_FILE_PATH: path, "status": "queued", "queue_id": upload.id, "error": upload.error })
+                }
+                Err(e) => {
+                    failed += 1;
+                    serde_json::json!({ PARAM_FILE_PATH: path, "status": "failed", "error": e.to_string() })
+                }
+            };
+            done += 1;
+            on_progress(done, total, &report[index]);
+        }
+        tracing::info!(
+            "Upload finished [uploaded: {uploaded}, duplicates: {duplicates}, queued: {queued}, failed: {failed}]"
+        );
+
+        serde_json::json!({
+            "uploaded": uploaded,
+            "duplicates": duplicates,
+            "queued": queued,
+            "failed": failed,
+            "files": report,
+        })
+    }
+
+    async fn handle_list_files(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_LIST_FILES} arguments");
+        let params: ListFilesParams = params::parse(arguments)?;
+        if let Some(cursor) = &params.cursor {
+            let page_size = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+            let page = self
+                .pages
+                .next(TOOL_LIST_FILES, connection_id(), cursor, page_size, None)
+                .map_err(RouterError::InvalidParameters)?;
+            return Ok(vec![Content::text(
+                serde_json::json!({"files": page.items, "next_cursor": page.next_cursor})
+                    .to_string(),
+            )]);
+        }
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+
+        let filter = with_file_types(
+            with_date_range(
+                params.filter.map(Value::Object),
+                params.created_after.as_deref(),
+                params.created_before.as_deref(),
+            )?,
+            params.file_types.as_deref(),
+        );
+        tracing::info!("Listing files of assistant: {}", assistant_name);
+        let files = within_deadline(
+            self.backend_for(&assistant_name)
+                .list_files(&assistant_name, filter),
+            None,
+        )
+        .await?;
+        let files = match params.sort_by {
+            Some(sort_by) => sort_files(files, sort_by),
+            None => files,
+        };
+        let Some(page_size) = params.limit else {
+            return Ok(vec![Content::text(serde_json::json!(files).to_string())]);
+        };
+        let files = files.iter().map(|file| serde_json::json!(file)).collect();
+        let page = self
+            .pages
+            .first(TOOL_LIST_FILES, connection_id(), files, page_size, None);
+        Ok(vec![Content::text(
+            serde_json::json!({"files": page.items, "next_cursor": page.next_cursor}).to_string(),
+        )])
+    }
+
+    async fn handle_search_files(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_SEARCH_FILES} arguments");
+        let params: SearchFilesParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+
+        let filter = with_tags(params.filter.map(Value::Object), &params.tags);
+        tracing::info!("Searching files of assistant: {}", assistant_name);
+        let files = within_deadline(
+            self.backend_for(&assistant_name)
+                .list_files(&assistant_name, filter),
+            None,
+        )
+        .await?;
+        let files: Vec<AssistantFile> = match params.name.as_deref() {
+            Some(name) => files
+                .into_iter()
+                .filter(|file| name_matches(&file.name, name))
+                .collect(),
+            None => files,
+        };
+        Ok(vec![Content::text(serde_json::json!(files).to_string())])
+    }
+
+    async fn handle_export_assistant(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_EXPORT_ASSISTANT} arguments");
+        let params: ExportAssistantParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+
+        tracing::info!("Exporting assistant: {}", assistant_name);
+        let backend = self.backend_for(&assistant_name);
+        let (assistant, files) = tokio::try_join!(
+            within_deadline(backend.describe_assistant(&assistant_name), None),
+            within_deadline(backend.list_files(&assistant_name, None), None),
+        )?;
+        let manifest = Manifest::new(assistant, files);
+
+        let Some(file_name) = params.output_path else {
+            return Ok(vec![Content::text(serde_json::json!(manifest).to_string())]);
+        };
+        let output_path = self.export_path(&file_name).await?;
+        let data = serde_json::to_vec_pretty(&manifest).unwrap_or_default();
+        tokio::fs::write(&output_path, data).await.map_err(|e| {
+            RouterError::InvalidParameters(format!("Cannot write {}: {e}", output_path.display()))
+        })?;
+        tracing::info!(
+            "Exported {} files of {} to {}",
+            manifest.files.len(),
+            assistant_name,
+            output_path.display()
+        );
+        Ok(vec![Content::text(
+            serde_json::json!({
+                PARAM_OUTPUT_PATH: output_path,
+                "files": manifest.files.len(),
+            })
+            .to_string(),
+        )])
+    }
+
+    /// Where a manifest exported as `file_name` is written: in the export
+    /// directory, as a plain file name, and not through a symbolic link.
+    async fn export_path(&self, file_name: &Path) -> Result<PathBuf, RouterError> {
+        let Some(dir) = &self.export_dir else {
+            return Err(RouterError::InvalidParameters(format!(
+                "{PARAM_OUTPUT_PATH} needs an export directory; set EXPORT_DIR, or omit it to \
+                get the manifest in the result"
+            )));
+        };
+        if file_name.file_name() != Some(file_name.as_os_str()) {
+            return Err(RouterError::InvalidParameters(format!(
+                "{PARAM_OUTPUT_PATH} must be a plain file name, not {}",
+                file_name.display()
+            )));
+        }
+        tokio::fs::create_dir_all(dir).await.map_err(|e| {
+            RouterError::InvalidParameters(format!("Cannot create {}: {e}", dir.display()))
+        })?;
+        let path = dir.join(file_name);
+        match tokio::fs::symlink_metadata(&path).await {
+            Ok(metadata) if !metadata.is_file() => Err(RouterError::InvalidParameters(format!(
+                "{} exists and is not a regular file",
+                path.display()
+            ))),
+            _ => Ok(path),
+        }
+    }
+
+    async fn handle_sync_assistant(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_SYNC_ASSISTANT} arguments");
+        let params: SyncAssistantParams = params::parse(arguments)?;
+        let roots = self.readable_roots().await;
+        let manifest_path = confine(&params.manifest_path, &roots).await?;
+        let directory = confine(&params.directory, &roots).await?;
+        let manifest = Manifest::read(&manifest_path)
+            .await
+            .map_err(RouterError::InvalidParameters)?;
+        let assistant_name = self
+            .resolve_alias(
+                params
+                    .assistant_name
+                    .as_deref()
+                    .unwrap_or(&manifest.assistant.name),
+            )
+            .to_string();
+        let backend = self.backend_for(&assistant_name);
+        let dry_run = params.dry_run;
+
+        tracing::info!(
+            "Syncing assistant {} with {} manifest files{}",
+            assistant_name,
+            manifest.files.len(),
+            if dry_run { " (dry run)" } else { "" }
+        );
+        let settings = AssistantSettings {
+            instructions: manifest.assistant.instructions.clone(),
+            metadata: manifest.assistant.metadata.clone(),
+        };
+        let (created, updated) =
+            match within_deadline(backend.describe_assistant(&assistant_name), None).await {
+                Ok(assistant) => {
+                    let outdated = assistant.instructions.as_deref().unwrap_or_default()
+                        != settings.instructions.as_deref().unwrap_or_default()
+                        || (settings.metadata.is_some() && assistant.metadata != settings.metadata);
+                    if outdated && !dry_run {
+                        within_deadline(backend.update_assistant(&assistant_name, settings), None)
+                            .await?;
+                    }
+                    (false, outdated)
+                }
+                Err(RouterError::Pinecone(PineconeError::NotFound { .. })) => {
+                    if !dry_run {
+                        within_deadline(backend.create_assistant(&assistant_name, settings), None)
+                            .await?;
+                        self.inventory.invalidate();
+                        self.wait_until_ready(&assistant_name).await?;
+                    }
+                    (true, false)
+                }
+                Err(e) => return Err(e),
+            };
+        let existing = if created {
+            Vec::new()
+        } else {
+            within_deadline(backend.list_files(&assistant_name, None), None).await?
+        };
+        let existing_hashes: HashSet<&str> = existing.iter().filter_map(content_hash).collect();
+
+        // Each entry carries the local content hash, if read, to tell which
+        // of the assistant's files the manifest accounts for.
+        let syncs: Vec<_> = manifest
+            .files
+            .iter()
+            .map(|file| {
+                let (assistant_name, directory) = (assistant_name.as_str(), &directory);
+                let (existing_hashes, roots) = (&existing_hashes, &roots);
+                async move {
+                    let (status, detail) = if file
+                        .sha256
+                        .as_deref()
+                        .is_some_and(|hash| existing_hashes.contains(hash))
+                    {
+                        ("present", None)
+                    } else if Path::new(&file.name).file_name() != Some(file.name.as_ref()) {
+                        ("failed", Some("not a plain file name".to_string()))
+                    } else {
+                        let path = directory.join(&file.name);
+                        // The file itself may be a link to elsewhere.
+                        let hashed = match confine(&path, roots).await {
+                            Ok(resolved) => uploads::file_sha256(&resolved)
+                                .await
+                                .map_err(|e| format!("Cannot read {}: {e}", path.display())),
+                            Err(e) => Err(e.to_string()),
+                        };
+                        match hashed {
+                            Err(e) => ("failed", Some(e)),
+                            Ok(hash)
+                                if file
+                                    .sha256
+                                    .as_ref()
+                                    .is_some_and(|expected| *expected != hash) =>
+                            {
+                                (
+                                    "failed",
+                                    Some("local content differs from the manifest".to_string()),
+                                )
+                            }
+                            Ok(hash) if existing_hashes.contains(hash.as_str()) => {
+                                ("present", Some(hash))
+                            }
+                            Ok(hash) if dry_run => ("to_upload", Some(hash)),
+                            Ok(hash) => match self
+                                .upload_tracked(assistant_name, &path, Some(&file.metadata))
+                                .await
+                            {
+                                Ok((_, true)) => ("present", Some(hash)),
+                                Ok((_, false)) => ("uploaded", Some(hash)),
+                                Err(e) => ("failed", Some(e.to_string())),
+                            },
+                        }
+                    };
+                    let mut entry = serde_json::json!({ "name": file.name, "status": status });
+                    let local_hash = match detail {
+                        Some(error) if status == "failed" => {
+                            entry["error"] = error.into();
+                            None
+                        }
+                        hash => hash.or_else(|| file.sha256.clone()),
+                    };
+                    (entry, local_hash)
+                }
+                .boxed()
+            })
+            .collect();
+        let synced: Vec<(Value, Option<String>)> = futures::stream::iter(syncs)
+            .buffered(self.max_concurrent_requests)
+            .collect()
+            .await;
+
+        let wanted_hashes: HashSet<&str> = synced
+            .iter()
+            .filter_map(|(_, hash)| hash.as_deref())
+            .collect();
+        let wanted_names: HashSet<&str> = manifest
+            .files
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect();
+        let mut report: Vec<Value> = synced.iter().map(|(entry, _)| entry.clone()).collect();
+        // Files without a recorded hash can only be told apart by name.
+        let extras = existing.iter().filter(|file| match content_hash(file) {
+            Some(hash) => !wanted_hashes.contains(hash),
+            None => !wanted_names.contains(file.name.as_str()),
+        });
+        // A file that failed to sync may be the copy of an extra, and a file
+        // without a hash may be a renamed copy of a wanted one; either way,
+        // deleting could lose content the manifest wants.
+        let any_failed = synced.iter().any(|(entry, _)| entry["status"] == "failed");
+        for file in extras {
+            let kept = if !params.delete_extras {
+                None
+            } else if any_failed {
+                Some("kept, as some files failed to sync")
+            } else if content_hash(file).is_none() {
+                Some("kept, as its content hash is unknown")
+            } else {
+                None
+            };
+            let status = if !params.delete_extras || kept.is_some() {
+                "extra"
+            } else if dry_run {
+                "to_delete"
+            } else {
+                match within_deadline(backend.delete_file(&assistant_name, &file.id), None).await {
+                    Ok(()) => "deleted",
+                    Err(e) => {
+                        tracing::warn!("Failed to delete file {}: {e}", file.id);
+                        "failed"
+                    }
+                }
+            };
+            let mut entry =
+                serde_json::json!({ "name": file.name, "file_id": file.id, "status": status });
+            if let Some(kept) = kept {
+                entry["note"] = kept.into();
+            }
+            report.push(entry);
+        }
+
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for entry in &report {
+            *counts
+                .entry(entry["status"].as_str().unwrap_or_default())
+                .or_default() += 1;
+        }
+        tracing::info!("Synced assistant {assistant_name}: {counts:?}");
+        Ok(vec![Content::text(
+            serde_json::json!({
+                PARAM_ASSISTANT_NAME: assistant_name,
+                "created": created,
+                "updated": updated,
+                PARAM_DRY_RUN: dry_run,
+                "counts": counts,
+                "files": report,
+            })
+            .to_string(),
+        )])
+    }
+
+    /// Polls a newly created assistant until it can take files.
+    async fn wait_until_ready(&self, assistant_name: &str) -> Result<(), RouterError> {
+        let started = Instant::now();
+        loop {
+            let assistant = within_deadline(
+                self.backend_for(assistant_name)
+                    .describe_assistant(assistant_name),
+                None,
+            )
+            .await?;
+            if assistant
+                .status
+                .as_deref()
+                .is_none_or(|status| status == "Ready")
+            {
+                return Ok(());
+            }
+            if started.elapsed() >= ASSISTANT_READY_TIMEOUT {
+                return Err(RouterError::Timeout(ASSISTANT_READY_TIMEOUT));
+            }
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+
+    async fn handle_verify_file(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_VERIFY_FILE} arguments");
+        let params: VerifyFileParams = params::parse(arguments)?;
+        let defaults = self.session.defaults();
+        let assistant_name = self
+            .pick_assistant(params.assistant_name.as_deref(), &defaults, "")?
+            .to_string();
+
+        tracing::info!(
+            "Verifying {} files against assistant: {}",
+            params.file_paths.len(),
+            assistant_name
+        );
+        let files = within_deadline(
+            self.backend_for(&assistant_name)
+                .list_files(&assistant_name, None),
+            None,
+        )
+        .await?;
+
+        let roots = self.readable_roots().await;
+        let mut report = Vec::with_capacity(params.file_paths.len());
+        for path in &params.file_paths {
+            let hashed = match confine(path, &roots).await {
+                Ok(resolved) => uploads::file_sha256(&resolved)
+                    .await
+                    .map_err(|e| format!("Cannot read file: {e}")),
+                Err(e) => Err(e.to_string()),
+            };
+            let local = match hashed {
+                Ok(hash) => hash,
+                Err(e) => {
+                    report.push(serde_json::json!({
+                        PARAM_FILE_PATH: path,
+                        "status": "error",
+                        "error": e,
+                    }));
+                    continue;
+                }
+            };
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            let stored_hash = |file: &&AssistantFile| {
+                file.metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata[METADATA_SHA256].as_str().map(str::to_string))
+            };
+            // Several files may share a name; prefer the one matching the local copy.
+            let candidates: Vec<&AssistantFile> =
+                files.iter().filter(|file| file.name == name).collect();
+            let matching = candidates
+                .iter()
+                .find(|file| stored_hash(file).as_deref() == Some(local.as_str()))
+                .or(candidates.first());
+            let mut entry = serde_json::json!({ PARAM_FILE_PATH: path, "local_sha256": local });
+            match matching {
+                None => entry["status"] = "missing".into(),
+                Some(file) => {
+                    entry["file_id"] = file.id.clone().into();
+                    entry["status"] = match stored_hash(file) {
+                        Some(stored) if stored == local => "match",
+                        Some(_) => "mismatch",
+                        None => "unknown",
+                    }
+                    .into();
+                    entry["stored_sha256"] = stored_hash(file).into();
+                }
+            }
+            report.push(entry);
+        }
+        Ok(vec![Content::text(Value::from(report).to_string())])
+    }
+
+    async fn handle_list_uploads(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_LIST_UPLOADS} arguments");
+        let params: UploadsParams = params::parse(arguments)?;
+        let assistant_name = params
+            .assistant_name
+            .as_deref()
+            .map(|name| self.resolve_alias(name));
+        let unfinished = self.uploads.unfinished(assistant_name);
+        Ok(vec![Content::text(
+            serde_json::json!(unfinished).to_string(),
+        )])
+    }
+
+    async fn handle_health(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_HEALTH} arguments");
+        let params: HealthParams = params::parse(arguments)?;
+        let assistant_name = params
+            .assistant_name
+            .as_deref()
+            .or(self.default_assistant.as_deref())
+            .map(|name| self.resolve_alias(name));
+        let backend = match assistant_name {
+            Some(name) => self.backend_for(name),
+            None => self.backend.as_ref(),
+        };
+        let mut health = match backend.health(assistant_name).await {
+            Ok(health) => health,
+            Err(PineconeError::Unsupported(_)) => serde_json::json!({"status": "unknown"}),
+            Err(e) => return Err(e.into()),
+        };
+        health["assistant"] = assistant_name.into();
+        health["dedicated_key"] = assistant_name
+            .is_some_and(|name| self.assistant_backends.contains_key(name))
+            .into();
+        health["tool_calls"] = self.tool_calls.snapshot();
+        health["payload_sizes"] = self.payload_sizes.snapshot();
+        health["error_rates"] = self
+            .error_rates
+            .as_ref()
+            .map_or(Value::Null, |rates| rates.snapshot());
+        health["tool_call_queue"] = self
+            .admission
+            .as_ref()
+            .map_or(Value::Null, |admission| admission.snapshot());
+        Ok(vec![Content::text(health.to_string())])
+    }
+
+    async fn handle_debug_config(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_DEBUG_CONFIG} arguments");
+        let DebugConfigParams {} = params::parse(arguments)?;
+        let tools: Vec<&str> = self.tools.iter().map(|tool| tool.name.as_str()).collect();
+        let mut config = self.redacted_config.clone();
+        config["api_version"] = API_VERSION.into();
+        config["server_version"] = env!("CARGO_PKG_VERSION").into();
+        config["enabled_tools"] = tools.into();
+        Ok(vec![Content::text(config.to_string())])
+    }
+
+    async fn handle_estimate_cost(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_ESTIMATE_COST} arguments");
+        let params: EstimateCostParams = params::parse(arguments)?;
+        let assistant_name = params
+            .assistant_name
+            .as_deref()
+            .map(|name| self.resolve_alias(name));
+        let estimate = self.usage.estimate(&self.pricing, assistant_name);
+        Ok(vec![Content::text(estimate.to_string())])
+    }
+
+    async fn handle_usage_stats(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_USAGE_STATS} arguments");
+        let params: UsageStatsParams = params::parse(arguments)?;
+        let assistant_name = params
+            .assistant_name
+            .as_deref()
+            .map(|name| self.resolve_alias(name));
+        let counters = self.usage.counters(assistant_name);
+        Ok(vec![Content::text(counters.to_string())])
+    }
+
+    async fn handle_resume_uploads(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_RESUME_UPLOADS} arguments");
+        let params: UploadsParams = params::parse(arguments)?;
+        let assistant_name = params
+            .assistant_name
+            .as_deref()
+            .map(|name| self.resolve_alias(name));
+        let resumable: Vec<UploadRecord> = self
+            .uploads
+            .unfinished(assistant_name)
+            .into_iter()
+            .filter(|record| record.status != UploadStatus::InProgress)
+            .collect();
+        tracing::info!("Resuming {} uploads", resumable.len());
+
+        let mut report = Vec::with_capacity(resumable.len());
+        for record in resumable {
+            let path = record.path.display().to_string();
+            let result = if record.file_unchanged().await {
+                self.upload_tracked(
+                    &record.assistant_name,
+                    &record.path,
+                    record.metadata.as_ref(),
+                )
+                .await
+                .map(|(file, _)| serde_json::json!({"status": "completed", "file_id": file.id}))
+                .unwrap_or_else(|e| serde_json::json!({"status": "failed", "error": e.to_string()}))
+            } else {
+                serde_json::json!({
+                    "status": "skipped",
+                    "error": "file changed or removed since the upload started"
+                })
+            };
+            let mut entry = serde_json::json!({
+                PARAM_ASSISTANT_NAME: record.assistant_name,
+                PARAM_FILE_PATH: path,
+            });
+            if let (Value::Object(entry), Value::Object(result)) = (&mut entry, result) {
+                entry.extend(result);
+            }
+            report.push(entry);
+        }
+        Ok(vec![Content::text(Value::from(report).to_string())])
+    }
+
+    /// Lists the spooled uploads, after discarding and retrying them as asked.
+    async fn handle_upload_queue(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_UPLOAD_QUEUE} arguments");
+        let params: UploadQueueParams = params::parse(arguments)?;
+        let Some(spool) = &self.spool else {
+            return Err(RouterError::InvalidParameters(format!(
+                "{TOOL_UPLOAD_QUEUE} needs an upload spool directory; set UPLOAD_SPOOL_DIR \
+                to queue uploads while Pinecone can't be reached"
+            )));
+        };
+        let assistant_name = params
+            .assistant_name
+            .as_deref()
+            .map(|name| self.resolve_alias(name));
+
+        if !params.discard.is_empty() {
+            let spooled = spool.entries(None).await;
+            if let Some(id) = params
+                .discard
+                .iter()
+                .find(|id| !spooled.iter().any(|upload| upload.id == **id))
+            {
+                return Err(RouterError::InvalidParameters(format!(
+                    "{id} is not in the upload queue"
+                )));
+            }
+        }
+        let mut discarded = Vec::new();
+        for id in &params.discard {
+            if spool.remove(id).await {
+                discarded.push(id.as_str());
+            }
+        }
+        let retried = if params.retry {
+            Some(self.retry_spooled(spool, assistant_name, true).await)
+        } else {
+            None
+        };
+        let uploads = spool.entries(assistant_name).await;
+        let mut result = serde_json::json!({
+            "dir": spool.dir(),
+            "retry_interval_secs": self.spool_retry_interval.as_secs(),
+            "queued": uploads.iter().filter(|u| u.status == SpoolStatus::Queued).count(),
+            "failed": uploads.iter().filter(|u| u.status == SpoolStatus::Failed).count(),
+            "uploads": uploads,
+        });
+        if !discarded.is_empty() {
+            result["discarded"] = discarded.into();
+        }
+        if let Some(retried) = retried {
+            result["retried"] = retried.into();
+        }
+        Ok(vec![Content::text(result.to_string())])
+    }
+
+    /// Uploads a file as [`Self::upload_deduplicated`] does, spooling it
+    /// instead of failing if Pinecone can't be reached and spooling is on.
+    /// `source` is the file the upload was asked for, if one on disk.
+    async fn upload_or_spool(
+        &self,
+        assistant_name: &str,
+        path: &Path,
+        source: Option<&Path>,
+        metadata: Option<&Map<String, Value>>,
+        journal: bool,
+    ) -> Result<Uploaded, RouterError> {
+        let error = match self
+            .upload_deduplicated(assistant_name, path, metadata, journal)
+            .await
+        {
+            Ok((file, existing)) => return Ok(Uploaded::File(file, existing)),
+            Err(e) => e,
+        };
+        let Some(spool) = self.spool.as_ref().filter(|_| is_offline(&error)) else {
+            return Err(error);
+        };
+        match spool
+            .enqueue(assistant_name, path, source, metadata, &error.to_string())
+            .await
+        {
+            Ok(upload) => {
+                tracing::info!(
+                    "Queued upload of {} to {assistant_name} as {} until Pinecone can be reached",
+                    path.display(),
+                    upload.id
+                );
+                self.retry_spooled_in_background();
+                Ok(Uploaded::Queued(upload))
+            }
+            Err(e) => {
+                tracing::warn!("Failed to spool upload of {}: {e}", path.display());
+                Err(error)
+            }
+        }
+    }
+
+    /// Retries the spooled uploads, optionally only those to one assistant,
+    /// the failed ones too if `failed` is set, and returns how each went.
+    /// Uploaded ones leave the spool. Stops at the first that can't reach
+    /// Pinecone, as the rest wouldn't either.
+    async fn retry_spooled(
+        &self,
+        spool: &UploadSpool,
+        assistant_name: Option<&str>,
+        failed: bool,
+    ) -> Vec<Value> {
+        let _pass = spool.pass().await;
+        let mut report = Vec::new();
+        for mut upload in spool.entries(assistant_name).await {
+            if upload.status == SpoolStatus::Failed && !failed {
+                continue;
+            }
+            let path = spool.file_path(&upload);
+            let result = self
+                .upload_deduplicated(
+                    &upload.assistant_name,
+                    &path,
+                    upload.metadata.as_ref(),
+                    false,
+                )
+                .await;
+            match result {
+                Ok((file, _)) => {
+                    tracing::info!(
+                        "Uploaded queued {} to {} as file {}",
+                        upload.file_name,
+                        upload.assistant_name,
+                        file.id
+                    );
+                    spool.remove(&upload.id).await;
+                    report.push(
+                        serde_json::json!({"id": upload.id, "status": "uploaded", "file_id": file.id}),
+                    );
+                }
+                Err(e) => {
+                    let offline = is_offline(&e);
+                    let status = if offline {
+                        SpoolStatus::Queued
+                    } else {
+                        SpoolStatus::Failed
+                    };
+                    spool.update(&mut upload, status, &e.to_string()).await;
+                    report.push(
+                        serde_json::json!({"id": upload.id, "status": status, "error": e.to_string()}),
+                    );
+                    if offline {
+                        break;
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    /// Retries the queued uploads every interval in the background until
+    /// none is left, unless that is already happening.
+    fn retry_spooled_in_background(&self) {
+        let Some(spool) = self.spool.clone() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        if !spool.start_retrying() {
+            return;
+        }
+        let router = self.clone();
+        runtime.spawn(async move {
+            let has_queued = |uploads: Vec<SpooledUpload>| {
+                uploads
+                    .iter()
+                    .any(|upload| upload.status == SpoolStatus::Queued)
+            };
+            loop {
+                if !has_queued(spool.entries(None).await) {
+                    spool.stop_retrying();
+                    // An upload spooled meanwhile left the retries to this
+                    // task, unless another took over.
+                    if !has_queued(spool.entries(None).await) || !spool.start_retrying() {
+                        break;
+                    }
+                }
+                tokio::time::sleep(router.spool_retry_interval).await;
+                router.retry_spooled(&spool, None, false).await;
+            }
+        });
+    }
+
+    /// Uploads a file, recording the attempt in the upload journal.
+    ///
+    /// Files whose content the assistant already holds are not uploaded
+    /// again; the existing file is returned instead, flagged by `true`.
+    async fn upload_tracked(
+        &self,
+        assistant_name: &str,
+        path: &Path,
+        metadata: Option<&Map<String, Value>>,
+    ) -> Result<(AssistantFile, bool), RouterError> {
+        self.upload_deduplicated(assistant_name, path, metadata, true)
+            .await
+    }
+
+    /// Uploads a file unless the assistant already holds its content,
+    /// recording the attempt in the upload journal if `journal` is set.
+    async fn upload_deduplicated(
+        &self,
+        assistant_name: &str,
+        path: &Path,
+        metadata: Option<&Map<String, Value>>,
+        journal: bool,
+    ) -> Result<(AssistantFile, bool), RouterError> {
+        if metadata.is_some_and(|metadata| metadata.contains_key(METADATA_SHA256)) {
+            return Err(RouterError::InvalidParameters(format!(
+                "{PARAM_METADATA} field {METADATA_SHA256} is reserved"
+            )));
+        }
+        // Date filters compare Unix seconds, so a caller-set date is stored as such.
+        let created_at = match metadata.and_then(|metadata| metadata.get(METADATA_CREATED_AT)) {
+            None => None,
+            Some(Value::Number(secs)) => Some(Value::Number(secs.clone())),
+            Some(Value::String(date)) => {
+                Some(parse_timestamp(date).map(Value::from).ok_or_else(|| {
+                    RouterError::InvalidParameters(format!(
+                        "{PARAM_METADATA} field {METADATA_CREATED_AT} must be a date (YYYY-MM-DD), \
+                        a UTC time (YYYY-MM-DDTHH:MM:SSZ) or Unix seconds, got {date:?}"
+                    ))
+                })?)
+            }
+            Some(other) => {
+                return Err(RouterError::InvalidParameters(format!(
+                    "{PARAM_METADATA} field {METADATA_CREATED_AT} must be a date (YYYY-MM-DD), \
+                    a UTC time (YYYY-MM-DDTHH:MM:SSZ) or Unix seconds, got {other}"
+                )));
+            }
+        };
+        self.upload_limits
+            .check(path)
+            .await
+            .map_err(RouterError::InvalidParameters)?;
+        let hash = uploads::file_sha256(path).await.map_err(|e| {
+            RouterError::InvalidParameters(format!("Cannot read {}: {e}", path.display()))
+        })?;
+        if let Some(existing) = self.find_by_hash(assistant_name, &hash).await {
+            tracing::info!(
+                "{} already uploaded to {assistant_name} as file {}",
+                path.display(),
+                existing.id
+            );
+            return Ok((existing, true));
+        }
+
+        let upload = if journal {
+            let upload = self
+                .uploads
+                .start(assistant_name, path, metadata)
+                .await
+                .map_err(|e| {
+                    RouterError::InvalidParameters(format!("Cannot read {}: {e}", path.display()))
+                })?
+                .ok_or_else(|| {
+                    RouterError::InvalidParameters(format!(
+                        "{} is already being uploaded to {assistant_name}",
+                        path.display()
+                    ))
+                })?;
+            Some(upload)
+        } else {
+            None
+        };
+        let mut metadata = metadata.cloned().unwrap_or_default();
+        metadata.insert(METADATA_SHA256.to_string(), hash.into());
+        if let Some(extension) = path.extension() {
+            let extension = extension.to_string_lossy().to_ascii_lowercase();
+            metadata.insert(METADATA_FILE_TYPE.to_string(), extension.into());
+        }
+        let created_at = created_at.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+                .into()
+        });
+        metadata.insert(METADATA_CREATED_AT.to_string(), created_at);
+        let result = within_deadline(
+            self.backend_for(assistant_name).upload_file(
+                assistant_name,
+                path,
+                Some(Value::Object(metadata)),
+            ),
+            None,
+        )
+        .await;
+        match &result {
+            Ok(file) => {
+                tracing::info!("Uploaded {} as file {}", file.name, file.id);
+                if let Some(upload) = upload {
+                    upload.complete(&file.id).await;
+                }
+                if let Some(context) = RequestContext::current() {
+                    self.files.follow(
+                        assistant_name,
+                        self.shared_backend(assistant_name),
+                        file,
+                        &context.peer,
+                    );
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Upload of {} failed: {e}", path.display());
+                if let Some(upload) = upload {
+                    upload.fail(&e.to_string()).await;
+                }
+            }
+        }
+        result.map(|file| (file, false))
+    }
+
+    /// An assistant file with the given content hash, if the backend can
+    /// tell. Files that failed processing don't count.
+    async fn find_by_hash(&self, assistant_name: &str, hash: &str) -> Option<AssistantFile> {
+        let filter = serde_json::json!({ METADATA_SHA256: hash });
+        let files = match within_deadline(
+            self.backend_for(assistant_name)
+                .list_files(assistant_name, Some(filter)),
+            None,
+        )
+        .await
+        {
+            Ok(files) => files,
+            Err(e) => {
+                tracing::debug!("Skipping duplicate check: {e}");
+                return None;
+            }
+        };
+        files.into_iter().find(|file| {
+            file.metadata
+                .as_ref()
+                .is_some_and(|metadata| metadata[METADATA_SHA256] == hash)
+                && file.status.as_deref() != Some("ProcessingFailed")
         })
     }
+
+    async fn handle_batch(&self, arguments: Value) -> Result<Vec<Content>, RouterError> {
+        tracing::debug!("Processing {TOOL_BATCH} arguments");
+        let params: BatchParams = params::parse(arguments)?;
+        if params.calls.is_empty() || params.calls.len() > MAX_BATCH_CALLS {
+            return Err(RouterError::InvalidParameters(format!(
+                "{PARAM_CALLS} must hold between 1 and {MAX_BATCH_CALLS} calls"
+            )));
+        }
+        if params.calls.iter().any(|call| call.tool == TOOL_BATCH) {
+            return Err(RouterError::InvalidParameters(format!(
+                "{TOOL_BATCH} calls cannot be nested"
+            )));
+        }
+        tracing::info!(
+            "Running {} batched tool calls, at most {} at a time",
+            params.calls.len(),
+            self.max_concurrent_requests
+        );
+
+        let total = params.calls.len();
+        // Batched calls run in the batch's slot rather than queueing behind it.
+        let inner = Self {
+            admission: None,
+            ..self.clone()
+        };
+        let calls = params.calls.into_iter().enumerate().map(|(index, call)| {
+            let result = mcp_server::Router::call_tool(&inner, &call.tool, call.arguments);
+            async move { (index, call.tool, result.await) }
+        });
+        let mut completed =
+            futures::stream::iter(calls).buffer_unordered(self.max_concurrent_requests);
+        let mut results = Vec::with_capacity(total);
+        while let Some((index, tool, result)) = completed.next().await {
+            let result = match result {
+                Ok(content) if is_error_result(&content) => {
+                    serde_json::json!({"tool": tool, "is_error": true, "content": content})
+                }
+                Ok(content) => {
+                    serde_json::json!({"tool": tool, "is_error": false, "content": content})
+                }
+                Err(e) => {
+                    serde_json::json!({"tool": tool, "is_error": true, "error": e.to_string()})
+                }
+            };
+            let mut partial = result.clone();
+            partial["index"] = index.into();
+            let outcome = if partial["is_error"] == true {
+                "failed"
+            } else {
+                "done"
+            };
+            let message = format!("Call {} ({tool}) {outcome}", index + 1);
+            notify_partial(results.len() + 1, total, &message, partial);
+            results.push((index, result));
+        }
+        results.sort_by_key(|(index, _)| *index);
+        let results: Vec<Value> = results.into_iter().map(|(_, result)| result).collect();
+        Ok(vec![Content::text(Value::from(results).to_string())])
+    }
+
+    /// Queries several assistants, each with every query, concurrently and
+    /// merges their snippets. Options other than the query are taken from
+    /// `request`; each request is bounded by `timeout`.
+    ///
+    /// Individual failures are logged and skipped so one unavailable
+    /// assistant doesn't sink the whole call; only if every request fails is
+    /// the first error returned. Each response is also sent to the client as
+    /// a partial result as soon as it arrives.
+    async fn fan_out(
+        &self,
+        assistant_names: &[String],
+        queries: &[String],
+        request: &AssistantContext,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Value>, RouterError> {
+        let mut resolved: Vec<Cow<str>> = Vec::new();
+        for name in assistant_names {
+            let name = self.canonical_name(name)?;
+            if !resolved.contains(&name) {
+                resolved.push(name);
+            }
+        }
+        tracing::info!(
+            "Making requests to Pinecone API for assistants: {:?} with {} queries and top_k: {:?}",
+            resolved,
+            queries.len(),
+            request.top_k
+        );
+
+        let requests: Vec<(&str, &str)> = resolved
+            .iter()
+            .flat_map(|name| {
+                queries
+                    .iter()
+                    .map(move |query| (name.as_ref(), query.as_str()))
+            })
+            .collect();
+        let mut pending: futures::stream::FuturesUnordered<_> = requests
+            .iter()
+            .enumerate()
+            .map(|(index, (name, query))| {
+                let mut request = AssistantContext {
+                    query: query.to_string(),
+                    ..request.clone()
+                };
+                // A follow-up asks for its query last, after the recent ones.
+                if let Some(last) = request.messages.as_mut().and_then(|m| m.last_mut()) {
+                    last.content = std::mem::take(&mut request.query);
+                }
+                let response = within_deadline(
+                    self.backend_for(name).assistant_context_with(name, request),
+                    timeout,
+                );
+                async move { (index, response.await) }
+            })
+            .collect();
+        let mut responses = Vec::with_capacity(requests.len());
+        while let Some((index, response)) = pending.next().await {
+            let (name, query) = requests[index];
+            let mut response = self
+                .track(name, usage::Kind::Context, response, |r| &r.usage)
+                .await;
+            if let Ok(response) = &mut response {
+                self.scrubber.scrub(&mut response.snippets);
+            }
+            let message = match &response {
+                Ok(response) => format!("{name}: {} snippets", response.snippets.len()),
+                Err(_) => format!("{name}: failed"),
+            };
+            let partial = match &response {
+                Ok(response) => serde_json::json!({
+                    PARAM_ASSISTANT_NAME: name,
+                    PARAM_QUERY: query,
+                    "snippets": response.snippets,
+                }),
+                Err(e) => serde_json::json!({
+                    PARAM_ASSISTANT_NAME: name,
+                    PARAM_QUERY: query,
+                    "error": e.to_string(),
+                }),
+            };
+            notify_partial(responses.len() + 1, requests.len(), &message, partial);
+            responses.push((index, response));
+        }
+        // Merge in request order, as if the responses had arrived in order.
+        responses.sort_by_key(|(index, _)| *index);
+
+        let mut results = Vec::new();
+        let mut first_error = None;
+        for (index, response) in responses {
+            let name = requests[index].0;
+            match response {
+                Ok(response) => results.push((name.to_string(), response.snippets)),
+                Err(e) => {
+                    tracing::warn!("Request to assistant {name} failed: {e}");
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) if results.is_empty() => Err(e),
+            _ => Ok(merge_snippets(results)),
+        }
+    }
+
+    /// Retrieves context from a single assistant, through the semantic
+    /// cache if configured, scrubbing the snippets.
+    async fn retrieve(
+        &self,
+        assistant_name: &str,
+        query: &str,
+        scope: String,
+        request: AssistantContext,
+        timeout: Option<Duration>,
+    ) -> Result<(Vec<Value>, Option<Value>), RouterError> {
+        let started = Instant::now();
+        let lookup = match &self.semantic_cache {
+            Some(cache) => {
+                let budget = match (deadline_remaining(), timeout) {
+                    (Some(remaining), Some(timeout)) => Some(remaining.min(timeout)),
+                    (remaining, timeout) => remaining.or(timeout),
+                };
+                match budget {
+                    Some(budget) => {
+                        let budget = budget / CACHE_LOOKUP_SHARE;
+                        tokio::time::timeout(budget, cache.lookup(&scope, query))
+                            .await
+                            .unwrap_or_else(|_| {
+                                tracing::warn!(
+                                    "Bypassing the semantic cache: no embedding within {budget:?}"
+                                );
+                                Lookup::default()
+                            })
+                    }
+                    None => cache.lookup(&scope, query).await,
+                }
+            }
+            None => Lookup::default(),
+        };
+        if let Some(snippets) = lookup.hit {
+            return Ok((snippets, None));
+        }
+        // The lookup counts against the caller's timeout.
+        let timeout = timeout.map(|timeout| timeout.saturating_sub(started.elapsed()));
+        tracing::info!(
+            "Making request to Pinecone API for assistant: {} with top_k: {:?}",
+            assistant_name,
+            request.top_k
+        );
+        let response = within_deadline(
+            self.backend_for(assistant_name)
+                .assistant_context_with(assistant_name, request),
+            timeout,
+        )
+        .await;
+        let mut response = self
+            .track(assistant_name, usage::Kind::Context, response, |r| &r.usage)
+            .await?;
+        self.scrubber.scrub(&mut response.snippets);
+        if let (Some(cache), Some(embedding)) = (&self.semantic_cache, lookup.embedding) {
+            cache.insert(scope, embedding, response.snippets.clone());
+        }
+        Ok((response.snippets, Some(response.usage)))
+    }
+
+    /// Adds the chunks just before and after the `count` best snippets,
+    /// each placed after its snippet so `merge_adjacent` can join them.
+    /// Neighbours are found by querying the snippet's file with the start and
+    /// the end of the snippet, which chunks overlapping it repeat. Expansion
+    /// is best effort: failed queries only leave a snippet as it was.
+    async fn expand_context(
+        &self,
+        snippets: Vec<Value>,
+        count: usize,
+        assistant: Option<&str>,
+        request: &AssistantContext,
+        timeout: Option<Duration>,
+    ) -> Vec<Value> {
+        let count = count.min(MAX_EXPANDED_SNIPPETS as usize);
+        let mut lookups = Vec::new();
+        for (index, snippet) in snippets.iter().enumerate().take(count) {
+            let Some(content) = snippet["content"].as_str() else {
+                continue;
+            };
+            let Some(assistant_name) = snippet[FIELD_ASSISTANTS][0].as_str().or(assistant) else {
+                continue;
+            };
+            // Files uploaded through this server can be singled out by hash.
+            let filter = match snippet["reference"]["file"]["metadata"][METADATA_SHA256].as_str() {
+                Some(hash) => Some(and_filter(
+                    request.filter.clone(),
+                    serde_json::json!({ METADATA_SHA256: hash }),
+                )),
+                None => request.filter.clone(),
+            };
+            let chars: Vec<char> = content.chars().collect();
+            let head: String = chars.iter().take(NEIGHBOUR_QUERY_CHARS).collect();
+            let tail: String = chars[chars.len().saturating_sub(NEIGHBOUR_QUERY_CHARS)..]
+                .iter()
+                .collect();
+            let mut queries = vec![head];
+            if chars.len() > NEIGHBOUR_QUERY_CHARS {
+                queries.push(tail);
+            }
+            for query in queries {
+                let request = AssistantContext {
+                    query,
+                    top_k: Some(5),
+                    filter: filter.clone(),
+                    ..AssistantContext::default()
+                };
+                lookups.push(async move {
+                    let scope = SemanticCache::scope(assistant_name, &request);
+                    let query = request.query.clone();
+                    let result = self
+                        .retrieve(assistant_name, &query, scope, request, timeout)
+                        .await;
+                    (index, result)
+                });
+            }
+        }
+
+        let mut neighbours: Vec<Vec<Value>> = vec![Vec::new(); snippets.len()];
+        let results: Vec<_> = futures::stream::iter(lookups)
+            .buffer_unordered(self.max_concurrent_requests)
+            .collect()
+            .await;
+        for (index, result) in results {
+            let found = match result {
+                Ok((found, _)) => found,
+                Err(e) => {
+                    tracing::debug!("Failed to expand the context of a snippet: {e}");
+                    continue;
+                }
+            };
+            for candidate in found {
+                let known = snippets
+                    .iter()
+                    .any(|s| s["content"] == candidate["content"])
+                    || neighbours
+                        .iter()
+                        .flatten()
+                        .any(|s| s["content"] == candidate["content"]);
+                if !known && merge::adjacent(&snippets[index], &candidate) {
+                    neighbours[index].push(candidate);
+                }
+            }
+        }
+        let added: usize = neighbours.iter().map(Vec::len).sum();
+        tracing::debug!(
+            "Expanded the context of the best snippets with {added} neighbouring chunks"
+        );
+        snippets
+            .into_iter()
+            .zip(neighbours)
+            .flat_map(|(snippet, neighbours)| std::iter::once(snippet).chain(neighbours))
+            .collect()
+    }
+
+    /// Counts a backend call in the usage ledger: its reported usage, or a
+    /// failure.
+    async fn track<T>(
+        &self,
+        assistant_name: &str,
+        kind: usage::Kind,
+        result: Result<T, RouterError>,
+        usage_of: impl Fn(&T) -> &Value,
+    ) -> Result<T, RouterError> {
+        match &result {
+            Ok(response) => self.usage.record(assistant_name, kind, usage_of(response)),
+            Err(_) => self.usage.record_error(assistant_name, kind),
+        }
+        match result {
+            Err(RouterError::Pinecone(PineconeError::NotFound { .. })) => {
+                Err(self.unknown_assistant(assistant_name).await)
+            }
+            result => result,
+        }
+    }
+
+    /// The error for an assistant that doesn't exist, suggesting the closest
+    /// name among the project's assistants.
+    async fn unknown_assistant(&self, assistant_name: &str) -> RouterError {
+        let known: Vec<String> = match self.inventory.get().await {
+            Some(assistants) => assistants.iter().map(|a| a.name.clone()).collect(),
+            None => Vec::new(),
+        };
+        RouterError::UnknownAssistant {
+            name: assistant_name.to_string(),
+            suggestion: names::suggest(assistant_name, &known).map(str::to_string),
+        }
+    }
+
+    /// Decomposes the query into sub-queries, via sampling when the client
+    /// supports it and by splitting the question otherwise. The original
+    /// query always comes first.
+    async fn expand_query(&self, query: &str, max: usize) -> Vec<String> {
+        let max = max.min(MAX_SUB_QUERIES);
+        let sampled = match RequestContext::current() {
+            Some(context) => query::expand(&context.peer, query, max)
+                .await
+                .inspect_err(|e| tracing::debug!("Not expanding query via sampling: {e}"))
+                .ok(),
+            None => None,
+        };
+        let sub_queries = sampled.unwrap_or_else(|| query::split(query, max));
+
+        let mut queries = vec![query.to_string()];
+        for sub_query in sub_queries {
+            if !queries.iter().any(|q| q.eq_ignore_ascii_case(&sub_query)) {
+                queries.push(sub_query);
+            }
+        }
+        for sub_query in &queries[1..] {
+            tracing::debug!("Sub-query: {}", loggable(sub_query, self.privacy_mode));
+        }
+        queries
+    }
+
+    /// Rewrites the query via sampling, falling back to the original query
+    /// whenever the client can't or won't help.
+    async fn rewrite_query(&self, query: &str) -> String {
+        let Some(context) = RequestContext::current() else {
+            tracing::debug!("Not rewriting query: no client connection");
+            return query.to_string();
+        };
+        match query::rewrite(&context.peer, query).await {
+            Ok(rewritten) => {
+                tracing::info!(
+                    "Rewrote query to: {}",
+                    loggable(&rewritten, self.privacy_mode)
+                );
+                rewritten
+            }
+            Err(e) => {
+                tracing::debug!("Not rewriting query: {e}");
+                query.to_string()
+            }
+        }
+    }
+
+    /// The caller's `timeout_ms`, capped at the configured maximum.
+    fn timeout(&self, timeout_ms: Option<u64>) -> Option<Duration> {
+        timeout_ms.map(|ms| Duration::from_millis(ms).min(self.max_timeout))
+    }
+
+    /// The backend holding the API key for the project the assistant lives in.
+    fn backend_for(&self, assistant_name: &str) -> &dyn ContextBackend {
+        self.assistant_backends
+            .get(assistant_name)
+            .unwrap_or(&self.backend)
+            .as_ref()
+    }
+
+    /// A copy of the router that calls Pinecone with the caller's API key.
+    /// Nothing fetched with the key is cached, and the project's assistants
+    /// and files are listed with the key, so no tenant sees another's data.
+    fn for_tenant(&self, api_key: Value) -> Result<Self, RouterError> {
+        let api_key = match api_key {
+            Value::String(api_key) if !api_key.trim().is_empty() => api_key,
+            _ => {
+                return Err(RouterError::InvalidParameters(format!(
+                    "{PARAM_API_KEY} must be a non-empty string"
+                )));
+            }
+        };
+        let Some(client) = &self.tenant_client else {
+            return Err(RouterError::InvalidParameters(format!(
+                "{PARAM_API_KEY} is not supported by this server's backend"
+            )));
+        };
+        tracing::debug!("Using the caller's API key");
+        let tenant = self.tenants.state(&api_key);
+        let backend: Arc<dyn ContextBackend> = Arc::new(client.with_api_key(api_key));
+        let mut router = self.clone();
+        router.session = tenant.session;
+        router.pages = tenant.pages;
+        router.uploads = tenant.uploads;
+        // Snapshots are resources readable without a key.
+        router.snapshots = None;
+        router.inventory = AssistantInventory::new(backend.clone(), Duration::ZERO);
+        router.backend = backend;
+        router.assistant_backends = HashMap::new();
+        router.retrieval_cache_ttl = None;
+        router.semantic_cache = None;
+        router.serve_stale = None;
+        router.files = self.files.detached();
+        // Retried later with the server's key, they would go to its project.
+        router.spool = None;
+        Ok(router)
+    }
+
+    /// The directories whose files tool calls may read: the configured upload
+    /// roots and the client's roots, resolved.
+    async fn readable_roots(&self) -> Vec<PathBuf> {
+        let mut roots = self.upload_roots.clone();
+        if let Some(context) = RequestContext::current() {
+            match context.peer.list_roots().await {
+                Ok(shared) => roots.extend(shared.iter().filter_map(Root::path)),
+                Err(e) => tracing::debug!("Not reading files from client roots: {e}"),
+            }
+        }
+        let mut resolved = Vec::with_capacity(roots.len());
+        for root in roots {
+            match tokio::fs::canonicalize(&root).await {
+                Ok(root) => resolved.push(root),
+                Err(e) => tracing::debug!("Skipping upload root {}: {e}", root.display()),
+            }
+        }
+        resolved
+    }
+
+    /// [`Self::backend_for`], shared with background tasks.
+    fn shared_backend(&self, assistant_name: &str) -> Arc<dyn ContextBackend> {
+        self.assistant_backends
+            .get(assistant_name)
+            .unwrap_or(&self.backend)
+            .clone()
+    }
+
+    /// Lists the assistant's files as resources from now on.
+    fn watch_files(&self, assistant_name: &str) {
+        self.files
+            .watch(assistant_name, self.shared_backend(assistant_name));
+    }
+
+    /// Maps a configured alias to the real assistant name; other names pass through.
+    fn resolve_alias<'a>(&'a self, name: &'a str) -> &'a str {
+        match self.assistant_aliases.get(name) {
+            Some(resolved) => {
+                tracing::debug!("Resolved assistant alias {name} -> {resolved}");
+                resolved
+            }
+            None => name,
+        }
+    }
+
+    /// Maps an alias to the real assistant name and normalizes other names
+    /// to Pinecone's naming rules, rejecting names that break them.
+    fn canonical_name<'a>(&'a self, name: &'a str) -> Result<Cow<'a, str>, RouterError> {
+        let name = name.trim();
+        let resolved = self.resolve_alias(name);
+        if resolved != name {
+            return Ok(Cow::Borrowed(resolved));
+        }
+        let normalized = names::normalize(name).map_err(|reason| {
+            RouterError::InvalidParameters(format!("Invalid assistant name \"{name}\": {reason}"))
+        })?;
+        Ok(match normalized {
+            Cow::Owned(normalized) => match self.assistant_aliases.get(&normalized) {
+                Some(resolved) => Cow::Borrowed(resolved),
+                None => Cow::Owned(normalized),
+            },
+            normalized => normalized,
+        })
+    }
+
+    /// Determines the assistant for a call: the explicitly named one, else the
+    /// session default, else the project's only assistant, else one selected
+    /// for the query.
+    fn pick_assistant<'a>(
+        &'a self,
+        explicit: Option<&'a str>,
+        defaults: &'a SessionDefaults,
+        query: &str,
+    ) -> Result<Cow<'a, str>, RouterError> {
+        let name = match explicit.or(defaults.assistant_name.as_deref()) {
+            Some(name) => name,
+            None => match self.only_assistant() {
+                Some(only) => {
+                    tracing::debug!("Using the only assistant: {only}");
+                    return Ok(Cow::Owned(only));
+                }
+                None => self.select_assistant(query)?,
+            },
+        };
+        self.canonical_name(name)
+    }
+
+    /// Picks an assistant for a query that didn't name one: the best keyword
+    /// match among the configured descriptions, else the configured default.
+    fn select_assistant(&self, query: &str) -> Result<&str, RouterError> {
+        if let Some(route) = self.query_router.select(query) {
+            tracing::info!("Routed query to assistant: {}", route.name);
+            return Ok(&route.name);
+        }
+        if let Some(default_assistant) = &self.default_assistant {
+            tracing::debug!("No route matched, using default assistant: {default_assistant}");
+            return Ok(default_assistant);
+        }
+        if self.query_router.is_empty() {
+            return Err(RouterError::InvalidParameters(format!(
+                "missing field `{PARAM_ASSISTANT_NAME}`"
+            )));
+        }
+        let candidates: Vec<&str> = self
+            .query_router
+            .routes()
+            .iter()
+            .map(|route| route.name.as_str())
+            .collect();
+        Err(RouterError::InvalidParameters(format!(
+            "Could not pick an assistant for this query; specify {PARAM_ASSISTANT_NAME} (one of: {})",
+            candidates.join(", ")
+        )))
+    }
+}
+
+impl mcp_server::Router for PineconeAssistantRouter {
+    fn name(&self) -> String {
+        "pinecone-assistant".to_string()
+    }
+
+    fn instructions(&self) -> String {
+        let instructions = match &self.server_instructions {
+            Some(instructions) => instructions.clone(),
+            None => format!(
+                "This server connects to an existing Pinecone Assistant,\
+                a RAG system for retrieving relevant document snippets. \
+                Use the {TOOL_ASSISTANT_CONTEXT} tool to access contextual information from its knowledge base. \
+                Use {TOOL_SET_DEFAULT_ASSISTANT} to avoid repeating the assistant name on every call"
+            ),
+        };
+        match &self.assistant_instructions {
+            Some(assistant_instructions) => format!("{instructions}\n\n{assistant_instructions}"),
+            None => instructions,
+        }
+    }
+
+    fn capabilities(&self) -> ServerCapabilities {
+        tracing::debug!("Building server capabilities");
+        CapabilitiesBuilder::new()
+            .with_tools(true)
+            .with_resources(false, true)
+            .build()
+    }
+
+    fn list_tools(&self) -> Vec<Tool> {
+        tracing::debug!("Listing available tools");
+        let Some(only) = self.only_assistant() else {
+            return self.tools.clone();
+        };
+        let note = format!(
+            " When omitted, the project's only assistant, \"{only}\", is used, so it can be left out."
+        );
+        let mut tools = self.tools.clone();
+        for tool in &mut tools {
+            let description = tool
+                .input_schema
+                .pointer_mut(&format!("/properties/{PARAM_ASSISTANT_NAME}/description"));
+            if let Some(Value::String(description)) = description {
+                description.push_str(&note);
+            }
+        }
+        tools
+    }
+
+    fn call_tool(
+        &self,
+        tool_name: &str,
+        mut arguments: Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>> {
+        tracing::info!("Calling tool: {}", tool_name);
+        // Taken out before anything could log it.
+        let api_key = arguments
+            .as_object_mut()
+            .filter(|_| self.allow_api_key_argument)
+            .and_then(|arguments| arguments.remove(PARAM_API_KEY));
+        if let Err(e) = self.schemas.validate(tool_name, &arguments) {
+            tracing::debug!("Rejected {tool_name} arguments: {e}");
+            return Box::pin(async move { Err(e.into()) });
+        }
+        let router = match api_key {
+            Some(api_key) => match self.for_tenant(api_key) {
+                Ok(router) => router,
+                Err(e) => return Box::pin(async move { Err(e.into()) }),
+            },
+            None => self.clone(),
+        };
+        let in_flight = self.tool_calls.enter();
+        let request_bytes = arguments.to_string().len();
+        let payload_sizes = self.payload_sizes.clone();
+        let name = tool_name.to_string();
+        let admission = self.admission.clone();
+        let error_rates = self.error_rates.clone();
+        let changes_files = matches!(
+            tool_name,
+            TOOL_UPLOAD_FILE
+                | TOOL_UPLOAD_CONTENT
+                | TOOL_UPLOAD_DIRECTORY
+                | TOOL_INGEST_FROM_ROOTS
+                | TOOL_SYNC_ASSISTANT
+                | TOOL_RESUME_UPLOADS
+                | TOOL_UPLOAD_QUEUE
+        );
+        let files = self.files.clone();
+        let call: Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send>> =
+            match tool_name {
+                TOOL_ASSISTANT_CONTEXT => Box::pin(async move {
+                    router
+                        .handle_assistant_context(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_SET_DEFAULT_ASSISTANT => Box::pin(async move {
+                    router
+                        .handle_set_default_assistant(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_START_CHAT_SESSION => Box::pin(async move {
+                    router
+                        .handle_start_chat_session(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_CHAT_IN_SESSION => Box::pin(async move {
+                    router
+                        .handle_chat_in_session(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_ANSWER_WITH_SOURCES => Box::pin(async move {
+                    router
+                        .handle_answer_with_sources(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_END_CHAT_SESSION => Box::pin(async move {
+                    router
+                        .handle_end_chat_session(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_RESET_SESSION => Box::pin(async move {
+                    router
+                        .handle_reset_session(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_UPLOAD_FILE => Box::pin(async move {
+                    router
+                        .handle_upload_file(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_UPLOAD_CONTENT => Box::pin(async move {
+                    router
+                        .handle_upload_content(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_UPLOAD_DIRECTORY => Box::pin(async move {
+                    router
+                        .handle_upload_directory(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_INGEST_FROM_ROOTS => Box::pin(async move {
+                    router
+                        .handle_ingest_from_roots(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_LIST_FILES => Box::pin(async move {
+                    router
+                        .handle_list_files(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_SEARCH_FILES => Box::pin(async move {
+                    router
+                        .handle_search_files(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_EXPORT_ASSISTANT => Box::pin(async move {
+                    router
+                        .handle_export_assistant(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_SYNC_ASSISTANT => Box::pin(async move {
+                    router
+                        .handle_sync_assistant(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_VERIFY_FILE => Box::pin(async move {
+                    router
+                        .handle_verify_file(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_LIST_UPLOADS => Box::pin(async move {
+                    router
+                        .handle_list_uploads(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_RESUME_UPLOADS => Box::pin(async move {
+                    router
+                        .handle_resume_uploads(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_UPLOAD_QUEUE => Box::pin(async move {
+                    router
+                        .handle_upload_queue(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_HEALTH => {
+                    Box::pin(
+                        async move { router.handle_health(arguments).await.or_else(recoverable) },
+                    )
+                }
+                TOOL_DEBUG_CONFIG => Box::pin(async move {
+                    router
+                        .handle_debug_config(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_ESTIMATE_COST => Box::pin(async move {
+                    router
+                        .handle_estimate_cost(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_USAGE_STATS => Box::pin(async move {
+                    router
+                        .handle_usage_stats(arguments)
+                        .await
+                        .or_else(recoverable)
+                }),
+                TOOL_BATCH => {
+                    Box::pin(
+                        async move { router.handle_batch(arguments).await.or_else(recoverable) },
+                    )
+                }
+                _ => {
+                    tracing::error!("Tool not found: {}", tool_name);
+                    let tool_name = tool_name.to_string();
+                    Box::pin(async move {
+                        Err(ToolError::NotFound(format!("Tool {} not found", tool_name)))
+                    })
+                }
+            };
+        Box::pin(async move {
+            let _in_flight = in_flight;
+            let deadline = RequestContext::current().and_then(|context| context.deadline);
+            let _slot = match &admission {
+                Some(admission) => match admission.admit(deadline).await {
+                    Ok(slot) => Some(slot),
+                    Err(Refusal::Full(retry_after)) => {
+                        tracing::warn!("Rejected {name} call: the tool call queue is full");
+                        return recoverable(RouterError::Busy { retry_after });
+                    }
+                    Err(Refusal::Deadline) => {
+                        tracing::warn!("Gave up on queued {name} call at its deadline");
+                        return Err(RouterError::DeadlineExceeded.into());
+                    }
+                },
+                None => None,
+            };
+            let result = call.await;
+            let response_bytes = result
+                .as_ref()
+                .ok()
+                .and_then(|contents| serde_json::to_vec(contents).ok())
+                .map(|json| json.len());
+            tracing::debug!(
+                "{name}: {request_bytes} request bytes, {} response bytes",
+                response_bytes.map_or("no".to_string(), |bytes| bytes.to_string())
+            );
+            payload_sizes.record(&name, request_bytes, response_bytes);
+            if let Some(error_rates) = &error_rates {
+                let failed = result.as_ref().is_ok_and(|c| is_error_result(c)) || result.is_err();
+                error_rates.record(&name, failed);
+            }
+            if changes_files {
+                files.check_in_background();
+            }
+            result
+        })
+    }
+
+    fn list_resources(&self) -> Vec<Resource> {
+        let schema = Resource::new(
+            ASSISTANT_CONTEXT_SCHEMA_URI,
+            Some("text".to_string()),
+            Some("assistant_context output schema".to_string()),
+        )
+        .map(|resource| {
+            resource
+                .with_mime_type("application/schema+json")
+                .with_description(format!(
+                    "JSON schema of {TOOL_ASSISTANT_CONTEXT} results with response_format 'json'"
+                ))
+        });
+        // Clients that list resources are told when the list changes.
+        if let Some(context) = RequestContext::current() {
+            self.files.subscribe(&context.peer);
+        }
+        let state = self.session.lock();
+        let watched: Vec<(String, AssistantFile)> = self
+            .files
+            .files()
+            .into_iter()
+            .filter(|(_, file)| {
+                !state
+                    .cited_files
+                    .contains_key(&format!("{FILE_RESOURCE_PREFIX}{}", file.id))
+            })
+            .collect();
+        let watched = watched.iter().map(|(assistant_name, file)| {
+            Resource::new(
+                format!("{FILE_RESOURCE_PREFIX}{}", file.id),
+                Some("text".to_string()),
+                Some(file.name.clone()),
+            )
+            .map(|resource| {
+                resource
+                    .with_mime_type("application/json")
+                    .with_description(format!("File of assistant {assistant_name}"))
+            })
+        });
+        let files = state.cited_files.iter().map(|(uri, file)| {
+            Resource::new(
+                uri,
+                Some("text".to_string()),
+                file["name"].as_str().map(str::to_string),
+            )
+            .map(|resource| {
+                resource
+                    .with_mime_type("application/json")
+                    .with_description("Source file cited in retrieved context")
+            })
+        });
+        let snapshots = self
+            .snapshots
+            .iter()
+            .flat_map(SnapshotStore::recent)
+            .map(|id| {
+                Resource::new(
+                    format!("{SNAPSHOT_RESOURCE_PREFIX}{id}"),
+                    Some("text".to_string()),
+                    Some(format!("Retrieval snapshot {id}")),
+                )
+                .map(|resource| {
+                    resource
+                        .with_mime_type("application/json")
+                        .with_description(
+                            "Query, parameters and snippets of an assistant_context call",
+                        )
+                })
+            });
+        std::iter::once(schema)
+            .chain(files)
+            .chain(watched)
+            .chain(snapshots)
+            .filter_map(Result::ok)
+            .collect()
+    }
+
+    fn read_resource(
+        &self,
+        uri: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ResourceError>> + Send + 'static>> {
+        let uri = uri.to_string();
+        let cited_file = uri.strip_prefix(FILE_RESOURCE_PREFIX).and_then(|id| {
+            self.session
+                .lock()
+                .cited_files
+                .get(&uri)
+                .cloned()
+                .or_else(|| {
+                    self.files
+                        .files()
+                        .into_iter()
+                        .find(|(_, file)| file.id == id)
+                        .and_then(|(_, file)| serde_json::to_value(file).ok())
+                })
+        });
+        let snapshots = self.snapshots.clone();
+        Box::pin(async move {
+            if let Some(file) = cited_file {
+                return Ok(file.to_string());
+            }
+            if let (Some(id), Some(store)) = (uri.strip_prefix(SNAPSHOT_RESOURCE_PREFIX), snapshots)
+            {
+                return store.load(id).await.map_err(|e| {
+                    ResourceError::NotFound(format!("Resource {uri} not found: {e}"))
+                });
+            }
+            match uri.as_str() {
+                ASSISTANT_CONTEXT_SCHEMA_URI => Ok(output::assistant_context_schema().to_string()),
+                _ => Err(ResourceError::NotFound(format!(
+                    "Resource {} not found",
+                    uri
+                ))),
+            }
+        })
+    }
+
+    fn list_prompts(&self) -> Vec<Prompt> {
+        vec![]
+    }
+
+    fn get_prompt(
+        &self,
+        prompt_name: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>> {
+        let prompt_name = prompt_name.to_string();
+        Box::pin(async move {
+            Err(PromptError::NotFound(format!(
+                "Prompt {} not found",
+                prompt_name
+            )))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::Peer;
+    use crate::pinecone::{Assistant, AssistantChatResponse, AssistantContextResponse, ChatDeltas};
+    use crate::test_support::temp_dir;
+    use mcp_server::Router;
+    use serde_json::json;
+    use std::sync::atomic::Ordering;
+
+    /// Answers every query with one snippet naming the assistant, plus one
+    /// snippet shared by all assistants.
+    struct MockBackend;
+
+    impl ContextBackend for MockBackend {
+        fn assistant_context<'a>(
+            &'a self,
+            assistant_name: &'a str,
+            _query: &'a str,
+            _top_k: Option<u32>,
+        ) -> BackendFuture<'a, AssistantContextResponse> {
+            Box::pin(async move {
+                if assistant_name == "missing" {
+                    return Err(PineconeError::NotFound {
+                        resource: format!("assistant \"{assistant_name}\""),
+                    });
+                }
+                Ok(AssistantContextResponse {
+                    snippets: vec![
+                        json!({"content": format!("from {assistant_name}"), "score": 0.5}),
+                        json!({"content": "shared", "score": 0.9}),
+                    ],
+                    usage: json!({}),
+                })
+            })
+        }
+
+        fn assistant_chat<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            messages: Vec<Message>,
+        ) -> BackendFuture<'a, AssistantChatResponse> {
+            Box::pin(async move { Ok(Self::chat_response(&messages)) })
+        }
+
+        fn describe_assistant<'a>(
+            &'a self,
+            assistant_name: &'a str,
+        ) -> BackendFuture<'a, Assistant> {
+            Box::pin(async move {
+                Ok(serde_json::from_value(json!({
+                    "name": assistant_name,
+                    "instructions": "Answer from the docs."
+                }))
+                .unwrap())
+            })
+        }
+
+        fn update_assistant<'a>(
+            &'a self,
+            assistant_name: &'a str,
+            settings: AssistantSettings,
+        ) -> BackendFuture<'a, Assistant> {
+            Box::pin(async move {
+                Ok(serde_json::from_value(json!({
+                    "name": assistant_name,
+                    "instructions": settings.instructions
+                }))
+                .unwrap())
+            })
+        }
+
+        /// Accepts any upload as file f2.
+        fn upload_file<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            path: &'a Path,
+            metadata: Option<Value>,
+        ) -> BackendFuture<'a, AssistantFile> {
+            Box::pin(async move {
+                Ok(serde_json::from_value(json!({
+                    "name": path.file_name().unwrap().to_string_lossy(),
+                    "id": "f2",
+                    "metadata": metadata
+                }))
+                .unwrap())
+            })
+        }
+
+        fn delete_file<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            _file_id: &'a str,
+        ) -> BackendFuture<'a, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        /// Holds a single file, doc.md, with content "abc".
+        fn list_files<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            _filter: Option<Value>,
+        ) -> BackendFuture<'a, Vec<AssistantFile>> {
+            Box::pin(async move {
+                Ok(vec![serde_json::from_value(json!({
+                    "name": "doc.md",
+                    "id": "f1",
+                    "metadata": {METADATA_SHA256: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"}
+                }))
+                .unwrap()])
+            })
+        }
+    }
+
+    impl MockBackend {
+        /// Echoes the number of messages received, to observe the history.
+        fn chat_response(messages: &[Message]) -> AssistantChatResponse {
+            serde_json::from_value(json!({
+                "message": {"role": "assistant", "content": format!("seen {}", messages.len())},
+                "citations": [{"position": 1, "references": []}]
+            }))
+            .unwrap()
+        }
+    }
+
+    /// Answers with the options of the chat request.
+    struct EchoOptions;
+
+    impl ContextBackend for EchoOptions {
+        fn assistant_context<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            _query: &'a str,
+            _top_k: Option<u32>,
+        ) -> BackendFuture<'a, AssistantContextResponse> {
+            unreachable!()
+        }
+
+        fn assistant_chat_with<'a>(
+            &'a self,
+            _assistant_name: &'a str,
+            request: AssistantChat,
+        ) -> BackendFuture<'a, AssistantChatResponse> {
+            let mut options = serde_json::to_value(&request).unwrap();
+            options.as_object_mut().unwrap().remove("messages");
+            Box::pin(async move {
+                Ok(serde_json::from_value(json!({
+                    "message": {"role": "assistant", "content": options.to_string()}
+                }))
+                .unwrap())
+            })
+        }
+    }
+
+    fn router(config: Config) -> PineconeAssistantRouter {
+        PineconeAssistantRouter::with_backend(config, Arc::new(MockBackend))
+    }
+
+    fn texts(contents: &[Content]) -> Vec<Value> {
+        contents
+            .iter()
+            .map(|c| serde_json::from_str(c.as_text().unwrap()).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_assistant_context_resolves_aliases() {
+        let config = Config {
+            assistant_aliases: HashMap::from([("docs".to_string(), "prod-docs-v3".to_string())]),
+            ..Config::default()
+        };
+        let result = router(config)
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "docs", "query": "q"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(texts(&result)[0]["content"], "from prod-docs-v3");
+    }
+
+    #[test]
+    fn test_files_sort_by_name_size_and_creation() {
+        let files: Vec<AssistantFile> = serde_json::from_value(json!([
+            {"name": "b.md", "id": "1", "size": 10, "created_on": "2024-03-01T00:00:00Z"},
+            {"name": "C.md", "id": "2"},
+            {"name": "a.md", "id": "3", "size": 200, "created_on": "2024-05-01T00:00:00Z"}
+        ]))
+        .unwrap();
+        let ids = |sort_by| -> Vec<String> {
+            sort_files(files.clone(), sort_by)
+                .into_iter()
+                .map(|file| file.id)
+                .collect()
+        };
+
+        assert_eq!(ids(FileSort::Name), ["3", "1", "2"]);
+        assert_eq!(ids(FileSort::Size), ["3", "1", "2"]);
+        assert_eq!(ids(FileSort::Created), ["3", "1", "2"]);
+    }
+
+    #[test]
+    fn test_date_range_and_file_types_narrow_the_filter() {
+        assert_eq!(parse_timestamp("1970-01-02"), Some(86_400));
+        assert_eq!(parse_timestamp("2024-03-01T12:30:00Z"), Some(1_709_296_200));
+        assert_eq!(parse_timestamp("2024-13-01"), None);
+        assert_eq!(parse_timestamp("2024-02-29"), Some(1_709_164_800));
+        assert_eq!(parse_timestamp("2023-02-29"), None);
+        assert_eq!(parse_timestamp("1900-02-29"), None);
+        assert_eq!(parse_timestamp("2000-02-29"), Some(951_782_400));
+        assert_eq!(parse_timestamp("2024-02-31"), None);
+        assert_eq!(parse_timestamp("2024-04-31"), None);
+        assert_eq!(parse_timestamp("2024-03-01T12:30:60Z"), None);
+        assert_eq!(parse_timestamp("2024-03-01T12:30:00+02:00"), None);
+
+        let filter = with_date_range(
+            Some(json!({"owner": "hr"})),
+            Some("2025-01-01"),
+            Some("2025-02-01T00:00:00Z"),
+        )
+        .unwrap();
+        assert_eq!(
+            filter,
+            Some(json!({"$and": [
+                {"owner": "hr"},
+                {"created_at": {"$gte": 1_735_689_600, "$lt": 1_738_368_000}}
+            ]}))
+        );
+        assert_eq!(with_date_range(None, None, None).unwrap(), None);
+        assert_eq!(
+            with_file_types(None, Some(&[".PDF".to_string(), "md".to_string()])),
+            Some(json!({"file_type": {"$in": ["pdf", "md"]}}))
+        );
+        assert!(with_date_range(None, Some("January"), None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_language_filter_keeps_undetermined_snippets() {
+        let result = router(Config::default())
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "a", "query": "q", "language": "en", "language_mode": "filter"}),
+            )
+            .await
+            .unwrap();
+        // The mock's snippets are too short to tell their language.
+        let snippets = texts(&result);
+        assert_eq!(snippets.len(), 2);
+        assert!(snippets[0].get("language").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_assistant_context_fan_out_merges_results() {
+        let result = router(Config::default())
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_names": ["a", "b", "missing"], "query": "q"}),
+            )
+            .await
+            .unwrap();
+        let snippets = texts(&result);
+        assert_eq!(snippets.len(), 3);
+        assert_eq!(snippets[0]["content"], "shared");
+        assert_eq!(snippets[0]["assistants"], json!(["a", "b"]));
+    }
+
+    #[tokio::test]
+    async fn test_self_test_reports_unknown_assistants() {
+        /// Lists a single assistant, and no others exist.
+        struct Inventory;
+
+        impl ContextBackend for Inventory {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                unreachable!()
+            }
+
+            fn list_assistants(&self) -> BackendFuture<'_, Vec<Assistant>> {
+                Box::pin(async {
+                    Ok(vec![
+                        serde_json::from_value(json!({"name": "kb", "status": "Ready"})).unwrap(),
+                    ])
+                })
+            }
+        }
+
+        let config = Config {
+            default_assistant: Some("docs".to_string()),
+            assistant_aliases: HashMap::from([
+                ("docs".to_string(), "kb".to_string()),
+                ("old".to_string(), "retired".to_string()),
+            ]),
+            ..Config::default()
+        };
+        let router = PineconeAssistantRouter::with_backend(config, Arc::new(Inventory));
+
+        assert_eq!(
+            router.self_test().await,
+            vec!["Target of alias old retired does not exist".to_string()]
+        );
+        assert!(
+            PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Inventory))
+                .self_test()
+                .await
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assistant_names_are_normalized_and_checked() {
+        /// Lists a single assistant, and no others exist.
+        struct Inventory;
+
+        impl ContextBackend for Inventory {
+            fn assistant_context<'a>(
+                &'a self,
+                assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                Box::pin(async move {
+                    match assistant_name {
+                        "support-docs" => Ok(AssistantContextResponse {
+                            snippets: vec![],
+                            usage: json!({}),
+                        }),
+                        _ => Err(PineconeError::NotFound {
+                            resource: format!("assistant \"{assistant_name}\""),
+                        }),
+                    }
+                })
+            }
+
+            fn list_assistants(&self) -> BackendFuture<'_, Vec<Assistant>> {
+                Box::pin(async {
+                    Ok(vec![
+                        serde_json::from_value(json!({"name": "support-docs"})).unwrap(),
+                    ])
+                })
+            }
+        }
+
+        let router = PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Inventory));
+        let call = |name: &str| {
+            router.call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": name, "query": "q"}),
+            )
+        };
+
+        assert!(call(" Support-Docs ").await.is_ok());
+        let typo = call("suport-docs").await.unwrap();
+        assert_eq!(
+            texts(&typo)[0]["error"]["message"],
+            "Assistant suport-docs not found; did you mean support-docs?"
+        );
+        assert!(matches!(
+            call("support_docs").await,
+            Err(ToolError::InvalidParameters(msg)) if msg.contains("'_' is not allowed")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_the_only_assistant_is_used_when_none_is_named() {
+        /// Lists a single assistant and echoes the assistant queried.
+        struct Single;
+
+        impl ContextBackend for Single {
+            fn assistant_context<'a>(
+                &'a self,
+                assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                Box::pin(async move {
+                    Ok(AssistantContextResponse {
+                        snippets: vec![json!({"content": format!("from {assistant_name}")})],
+                        usage: json!({}),
+                    })
+                })
+            }
+
+            fn list_assistants(&self) -> BackendFuture<'_, Vec<Assistant>> {
+                Box::pin(async { Ok(vec![serde_json::from_value(json!({"name": "kb"})).unwrap()]) })
+            }
+        }
+
+        let router = PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Single));
+        router.detect_only_assistant().await;
+
+        let result = router
+            .call_tool(TOOL_ASSISTANT_CONTEXT, json!({"query": "q"}))
+            .await
+            .unwrap();
+        assert_eq!(texts(&result)[0]["content"], "from kb");
+        let tools = router.list_tools();
+        let description = tools[0].input_schema["properties"]["assistant_name"]["description"]
+            .as_str()
+            .unwrap();
+        assert!(description.contains("the project's only assistant, \"kb\", is used"));
+
+        let with_default = PineconeAssistantRouter::with_backend(
+            Config {
+                default_assistant: Some("docs".to_string()),
+                ..Config::default()
+            },
+            Arc::new(Single),
+        );
+        with_default.detect_only_assistant().await;
+        assert!(with_default.only_assistant().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expand_context_adds_neighbouring_chunks() {
+        /// One document of pages 1 to 9; a query matches the pages whose text
+        /// starts with it, or page 2.
+        struct Pages;
+
+        impl ContextBackend for Pages {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                let page = |number: u64, file: &str| {
+                    json!({
+                        "content": format!("Page {number} of {file}."),
+                        "score": 0.5,
+                        "reference": {"file": {"id": file, "name": file}, "pages": [number]}
+                    })
+                };
+                let snippets = if query == "refunds" {
+                    vec![page(2, "guide")]
+                } else {
+                    vec![
+                        page(3, "guide"),
+                        page(9, "guide"),
+                        page(3, "faq"),
+                        page(2, "guide"),
+                    ]
+                };
+                Box::pin(async move {
+                    Ok(AssistantContextResponse {
+                        snippets,
+                        usage: json!({}),
+                    })
+                })
+            }
+        }
+
+        let router = PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Pages));
+        let arguments = json!({"assistant_name": "kb", "query": "refunds", "expand_context": 1,
+            "response_format": "json"});
+        let result = router
+            .call_tool(TOOL_ASSISTANT_CONTEXT, arguments.clone())
+            .await
+            .unwrap();
+        let snippets = texts(&result)[0]["snippets"].clone();
+        assert_eq!(snippets.as_array().unwrap().len(), 1);
+        assert_eq!(snippets[0]["content"], "Page 2 of guide.\nPage 3 of guide.");
+        assert_eq!(snippets[0]["reference"]["pages"], json!([2, 3]));
+
+        let mut arguments = arguments;
+        arguments["merge_adjacent"] = json!(false);
+        let result = router
+            .call_tool(TOOL_ASSISTANT_CONTEXT, arguments)
+            .await
+            .unwrap();
+        let contents: Vec<Value> = texts(&result)[0]["snippets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|snippet| snippet["content"].clone())
+            .collect();
+        assert_eq!(contents, ["Page 2 of guide.", "Page 3 of guide."]);
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_queries_carry_the_query_history() {
+        /// Answers with the conversation it was asked to retrieve for.
+        struct Conversation;
+
+        impl ContextBackend for Conversation {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                unreachable!()
+            }
+
+            fn assistant_context_with<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                request: AssistantContext,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                let asked = json!({"query": request.query, "messages": request.messages});
+                Box::pin(async move {
+                    Ok(AssistantContextResponse {
+                        snippets: vec![json!({"content": asked.to_string()})],
+                        usage: json!({}),
+                    })
+                })
+            }
+        }
+
+        let router =
+            PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Conversation));
+        let mut asked = Vec::new();
+        for (query, use_query_history) in [
+            ("What is the refund policy?", false),
+            ("And in the EU?", true),
+            ("Who founded the company?", false),
+        ] {
+            let result = router
+                .call_tool(
+                    TOOL_ASSISTANT_CONTEXT,
+                    json!({"assistant_name": "kb", "query": query,
+                        "use_query_history": use_query_history}),
+                )
+                .await
+                .unwrap();
+            let content = texts(&result)[0]["content"].as_str().unwrap().to_string();
+            asked.push(serde_json::from_str::<Value>(&content).unwrap());
+        }
+        assert_eq!(
+            asked[0],
+            json!({"query": "What is the refund policy?", "messages": null})
+        );
+        assert_eq!(
+            asked[1]["messages"],
+            json!([
+                {"role": "user", "content": "What is the refund policy?"},
+                {"role": "user", "content": "And in the EU?"}
+            ])
+        );
+        assert_eq!(asked[2]["query"], "Who founded the company?");
+    }
+
+    #[tokio::test]
+    async fn test_include_highlights_is_passed_to_pinecone() {
+        struct Highlights;
+
+        impl ContextBackend for Highlights {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                unreachable!()
+            }
+
+            fn assistant_context_with<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                request: AssistantContext,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                let mut snippet = json!({"content": "Refunds take 5 days"});
+                if request.include_highlights == Some(true) {
+                    snippet["highlights"] = json!([{"type": "text", "content": "5 days"}]);
+                }
+                Box::pin(async move {
+                    Ok(AssistantContextResponse {
+                        snippets: vec![snippet],
+                        usage: json!({}),
+                    })
+                })
+            }
+        }
+
+        let router = PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Highlights));
+        let result = router
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "kb", "query": "refunds", "include_highlights": true,
+                    "response_format": "json"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            texts(&result)[0]["snippets"][0]["highlights"][0]["content"],
+            "5 days"
+        );
+    }
+
+    #[test]
+    fn test_instructions_and_tool_descriptions_can_be_overridden() {
+        let router = router(Config {
+            server_instructions: Some("Search the HR handbook first.".to_string()),
+            tool_descriptions: HashMap::from([
+                (TOOL_HEALTH.to_string(), "Checks the handbook".to_string()),
+                ("missing".to_string(), "Ignored".to_string()),
+            ]),
+            ..Config::default()
+        });
+
+        assert_eq!(router.instructions(), "Search the HR handbook first.");
+        let tools = router.list_tools();
+        let health = tools.iter().find(|tool| tool.name == TOOL_HEALTH).unwrap();
+        assert_eq!(health.description, "Checks the handbook");
+        assert!(tools.iter().all(|tool| tool.name != "missing"));
+    }
+
+    #[tokio::test]
+    async fn test_health_names_the_checked_assistant() {
+        let config = Config {
+            default_assistant: Some("docs".to_string()),
+            assistant_aliases: HashMap::from([("docs".to_string(), "kb".to_string())]),
+            ..Config::default()
+        };
+
+        let router = router(config);
+        let first = router.call_tool(TOOL_HEALTH, json!({})).await.unwrap();
+        let result = router.call_tool(TOOL_HEALTH, json!({})).await.unwrap();
+
+        let health = &texts(&result)[0];
+        assert_eq!(health["status"], "unknown");
+        assert_eq!(health["assistant"], "kb");
+        assert_eq!(health["dedicated_key"], false);
+        // The health call itself is in flight.
+        assert_eq!(health["tool_calls"], json!({"current": 1, "peak": 1}));
+        // Sizes of the calls that completed before.
+        let sizes = &health["payload_sizes"]["tools"][TOOL_HEALTH];
+        assert_eq!(sizes["calls"], 1);
+        assert_eq!(sizes["request_bytes"], 2);
+        assert_eq!(
+            sizes["response_bytes"],
+            serde_json::to_vec(&first).unwrap().len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_debug_config_masks_api_keys() {
+        let config = Config {
+            pinecone_api_key: "pcsk_secret_1234".to_string(),
+            assistant_api_keys: HashMap::from([("kb".to_string(), "short".to_string())]),
+            ..Config::default()
+        };
+
+        let result = router(config)
+            .call_tool(TOOL_DEBUG_CONFIG, json!({}))
+            .await
+            .unwrap();
+
+        let text = result[0].as_text().unwrap();
+        assert!(
+            !text.contains("secret") && !text.contains("short"),
+            "{text}"
+        );
+        let config = &texts(&result)[0];
+        assert_eq!(config["pinecone_api_key"], "****1234");
+        assert_eq!(config["assistant_api_keys"]["kb"], "*****");
+        assert_eq!(config["api_version"], API_VERSION);
+        assert!(
+            config["enabled_tools"]
+                .as_array()
+                .unwrap()
+                .contains(&json!(TOOL_DEBUG_CONFIG))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repeated_retrievals_are_reused_per_connection() {
+        let router = router(Config::default());
+        let (outgoing, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let first = RequestContext::new(Peer::new(outgoing.clone()), None, None);
+        let second = RequestContext::new(Peer::new(outgoing), None, None);
+        for context in [&first, &first, &second] {
+            context
+                .clone()
+                .scope(router.call_tool(
+                    TOOL_ASSISTANT_CONTEXT,
+                    json!({"assistant_name": "a", "query": "q"}),
+                ))
+                .await
+                .unwrap();
+        }
+
+        let result = router
+            .call_tool(TOOL_USAGE_STATS, json!({"assistant_name": "a"}))
+            .await
+            .unwrap();
+        assert_eq!(
+            texts(&result)[0]["since_start"]["a"]["context"]["requests"],
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_estimate_cost_totals_reported_usage() {
+        let router = router(Config {
+            retrieval_cache_ttl: None,
+            ..Config::default()
+        });
+        for assistant_name in ["a", "b", "a"] {
+            router
+                .call_tool(
+                    TOOL_ASSISTANT_CONTEXT,
+                    json!({"assistant_name": assistant_name, "query": "q"}),
+                )
+                .await
+                .unwrap();
+        }
+
+        let result = router
+            .call_tool(TOOL_ESTIMATE_COST, json!({"assistant_name": "a"}))
+            .await
+            .unwrap();
+
+        let estimate = &texts(&result)[0];
+        assert_eq!(estimate["assistants"]["a"]["context"]["requests"], 2);
+        let missing = router
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "missing", "query": "q"}),
+            )
+            .await
+            .unwrap();
+        assert!(is_error_result(&missing));
+        let stats = router.call_tool(TOOL_USAGE_STATS, json!({})).await.unwrap();
+        let stats = &texts(&stats)[0];
+        assert_eq!(stats["since_start"]["b"]["context"]["requests"], 1);
+        assert_eq!(stats["since_start"]["missing"]["context"]["errors"], 1);
+        assert_eq!(stats["today"]["assistants"]["a"]["context"]["requests"], 2);
+        assert!(estimate["assistants"].get("b").is_none());
+        assert_eq!(estimate["total_cost_usd"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_recoverable_failures_are_error_results() {
+        let router = router(Config::default());
+
+        let missing = router
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "missing", "query": "q"}),
+            )
+            .await
+            .unwrap();
+        let invalid = router
+            .call_tool(TOOL_ASSISTANT_CONTEXT, json!({"assistant_name": "a"}))
+            .await;
+
+        assert!(is_error_result(&missing));
+        let error = &texts(&missing)[0]["error"];
+        assert_eq!(error["kind"], "not_found");
+        assert_eq!(error["retryable"], false);
+        assert!(matches!(invalid, Err(ToolError::InvalidParameters(_))));
+    }
+
+    #[tokio::test]
+    async fn test_batch_runs_calls_in_order() {
+        let result = router(Config::default())
+            .call_tool(
+                TOOL_BATCH,
+                json!({"calls": [
+                    {"tool": TOOL_ASSISTANT_CONTEXT, "arguments": {"assistant_name": "a", "query": "q"}},
+                    {"tool": TOOL_ASSISTANT_CONTEXT, "arguments": {"assistant_name": "missing", "query": "q"}},
+                    {"tool": TOOL_ASSISTANT_CONTEXT, "arguments": {"assistant_name": "b", "query": "q"}}
+                ]}),
+            )
+            .await
+            .unwrap();
+        let results = &texts(&result)[0];
+        assert_eq!(results.as_array().unwrap().len(), 3);
+        assert_eq!(results[0]["is_error"], false);
+        assert!(
+            results[0]["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("from a")
+        );
+        assert_eq!(results[1]["is_error"], true);
+        assert_eq!(
+            serde_json::from_str::<Value>(results[1]["content"][0]["text"].as_str().unwrap())
+                .unwrap()["error"]["kind"],
+            "not_found"
+        );
+        assert!(
+            results[2]["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("from b")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stale_results_are_served_during_an_outage() {
+        /// Answers once, then fails like an unavailable Pinecone.
+        #[derive(Default)]
+        struct Outage(std::sync::atomic::AtomicUsize);
+
+        impl ContextBackend for Outage {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                let calls = self.0.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move {
+                    if calls > 0 {
+                        return Err(PineconeError::Api {
+                            status: 503,
+                            message: "unavailable".to_string(),
+                        });
+                    }
+                    Ok(AssistantContextResponse {
+                        snippets: vec![json!({"content": format!("about {query}")})],
+                        usage: json!({}),
+                    })
+                })
+            }
+        }
+
+        let config = Config {
+            retrieval_cache_ttl: None,
+            serve_stale: Some(Duration::from_secs(60)),
+            ..Config::default()
+        };
+        let router = PineconeAssistantRouter::with_backend(config, Arc::new(Outage::default()));
+        let call = |query: &str| {
+            router.call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "kb", "query": query, "response_format": "json"}),
+            )
+        };
+
+        let fresh = &texts(&call("a").await.unwrap())[0];
+        assert!(fresh.get("stale").is_none());
+
+        let stale = &texts(&call("a").await.unwrap())[0];
+        assert_eq!(stale["snippets"][0]["content"], "about a");
+        assert_eq!(stale["stale"]["age_secs"], 0);
+
+        // Queries never answered still fail.
+        let failed = &texts(&call("b").await.unwrap())[0];
+        assert_eq!(failed["error"]["kind"], "unavailable");
+    }
+
+    #[tokio::test]
+    async fn test_assistants_are_queried_with_their_mapped_api_keys() {
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for (assistant_name, key) in [("kb", "kb-key"), ("other", "server-key")] {
+            let mock = server
+                .mock(
+                    "POST",
+                    format!("/assistant/chat/{assistant_name}/context").as_str(),
+                )
+                .match_header("Api-Key", key)
+                .with_body(r#"{"snippets": [{"content": "answer"}], "usage": {}}"#)
+                .expect(1)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+        let router = PineconeAssistantRouter::new(Config {
+            pinecone_api_key: "server-key".to_string(),
+            pinecone_assistant_host: server.url(),
+            assistant_api_keys: HashMap::from([("kb".to_string(), "kb-key".to_string())]),
+            ..Config::default()
+        });
+
+        for assistant_name in ["kb", "other"] {
+            let result = router
+                .call_tool(
+                    TOOL_ASSISTANT_CONTEXT,
+                    json!({"assistant_name": assistant_name, "query": "q", "response_format": "json"}),
+                )
+                .await;
+            assert_eq!(
+                texts(&result.unwrap())[0]["snippets"][0]["content"],
+                "answer"
+            );
+        }
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_results_are_not_served_to_calls_with_their_own_key() {
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for key in ["server-key", "tenant-key"] {
+            let mock = server
+                .mock("POST", "/assistant/chat/kb/context")
+                .match_header("Api-Key", key)
+                .with_body(r#"{"snippets": [{"content": "answer"}], "usage": {}}"#)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+        let router = PineconeAssistantRouter::new(Config {
+            pinecone_api_key: "server-key".to_string(),
+            pinecone_assistant_host: server.url(),
+            allow_api_key_argument: true,
+            retrieval_cache_ttl: None,
+            serve_stale: Some(Duration::from_secs(60)),
+            retry: crate::pinecone::RetryPolicy {
+                max_retries: 0,
+                ..Default::default()
+            },
+            ..Config::default()
+        });
+        let call = |api_key: Option<&str>| {
+            let mut arguments =
+                json!({"assistant_name": "kb", "query": "q", "response_format": "json"});
+            if let Some(api_key) = api_key {
+                arguments[PARAM_API_KEY] = api_key.into();
+            }
+            router.call_tool(TOOL_ASSISTANT_CONTEXT, arguments)
+        };
+        call(None).await.unwrap();
+        call(Some("tenant-key")).await.unwrap();
+
+        mocks.pop().unwrap().remove_async().await;
+        server
+            .mock("POST", "/assistant/chat/kb/context")
+            .match_header("Api-Key", "tenant-key")
+            .with_status(503)
+            .create_async()
+            .await;
+        let failed = &texts(&call(Some("tenant-key")).await.unwrap())[0];
+        assert_eq!(failed["error"]["kind"], "unavailable");
+    }
+
+    #[tokio::test]
+    async fn test_calls_beyond_the_queue_are_rejected_as_busy() {
+        /// Takes a while to answer.
+        struct Slow;
+
+        impl ContextBackend for Slow {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                Box::pin(async move {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    Ok(AssistantContextResponse {
+                        snippets: vec![json!({"content": format!("about {query}")})],
+                        usage: json!({}),
+                    })
+                })
+            }
+        }
+
+        let config = Config {
+            max_concurrent_tool_calls: Some(1),
+            tool_call_queue_depth: 1,
+            ..Config::default()
+        };
+        let router = PineconeAssistantRouter::with_backend(config, Arc::new(Slow));
+        let call = |query: &str| {
+            router.call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "kb", "query": query}),
+            )
+        };
+
+        let (running, queued, rejected) = tokio::join!(call("a"), call("b"), call("c"));
+
+        assert!(texts(&running.unwrap())[0].to_string().contains("about a"));
+        assert!(texts(&queued.unwrap())[0].to_string().contains("about b"));
+        let rejected = &texts(&rejected.unwrap())[0]["error"];
+        assert_eq!(rejected["kind"], "busy");
+        assert_eq!(rejected["retryable"], true);
+        assert_eq!(rejected["message"], "Server busy, retry in 2s");
+
+        // A batch's calls run in its slot.
+        let batch = router
+            .call_tool(
+                TOOL_BATCH,
+                json!({"calls": [
+                    {"tool": TOOL_ASSISTANT_CONTEXT, "arguments": {"assistant_name": "kb", "query": "d"}},
+                    {"tool": TOOL_ASSISTANT_CONTEXT, "arguments": {"assistant_name": "kb", "query": "e"}}
+                ]}),
+            )
+            .await
+            .unwrap();
+        let results = &texts(&batch)[0];
+        assert_eq!(results[0]["is_error"], false);
+        assert_eq!(results[1]["is_error"], false);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_cache_lookup_stays_within_the_timeout() {
+        // Accepts embedding requests but never answers them.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/embed", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+        let config = Config {
+            semantic_cache: Some(crate::SemanticCacheConfig::new(url)),
+            ..Config::default()
+        };
+
+        let started = Instant::now();
+        let result = router(config)
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "kb", "query": "q", "timeout_ms": 400}),
+            )
+            .await
+            .unwrap();
+        assert!(!is_error_result(&result));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_semantic_cache_skips_pinecone_for_similar_queries() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/embed")
+            .with_body(r#"{"data": [{"values": [0.6, 0.8]}]}"#)
+            .create_async()
+            .await;
+        let config = Config {
+            semantic_cache: Some(crate::SemanticCacheConfig::new(format!(
+                "{}/embed",
+                server.url()
+            ))),
+            ..Config::default()
+        };
+        let router = router(config);
+        for query in ["reset password", "how to reset a password"] {
+            let result = router
+                .call_tool(
+                    TOOL_ASSISTANT_CONTEXT,
+                    json!({"assistant_name": "a", "query": query}),
+                )
+                .await
+                .unwrap();
+            assert!(texts(&result)[0].to_string().contains("from a"));
+        }
+
+        let stats = router.call_tool(TOOL_USAGE_STATS, json!({})).await.unwrap();
+        assert_eq!(
+            texts(&stats)[0]["since_start"]["a"]["context"]["requests"],
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_calls_may_pass_their_own_api_key() {
+        let mut server = mockito::Server::new_async().await;
+        let tenant = server
+            .mock("POST", "/assistant/chat/kb/context")
+            .match_header("Api-Key", "tenant-key")
+            .with_body(r#"{"snippets": [{"content": "tenant"}], "usage": {}}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let config = Config {
+            pinecone_api_key: "server-key".to_string(),
+            pinecone_assistant_host: server.url(),
+            allow_api_key_argument: true,
+            ..Config::default()
+        };
+        let shared = PineconeAssistantRouter::new(config);
+        let arguments = json!({"assistant_name": "kb", "query": "q", "api_key": "tenant-key"});
+        for _ in 0..2 {
+            let result = shared
+                .call_tool(TOOL_ASSISTANT_CONTEXT, arguments.clone())
+                .await
+                .unwrap();
+            assert!(texts(&result)[0].to_string().contains("tenant"));
+        }
+        tenant.assert_async().await;
+
+        let result = router(Config::default())
+            .call_tool(TOOL_ASSISTANT_CONTEXT, arguments)
+            .await;
+        assert!(result.unwrap_err().to_string().contains("api_key"));
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_cannot_see_each_others_cursors_or_chats() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/assistant/files/kb")
+            .with_body(
+                json!({"files": [
+                    {"id": "1", "name": "a.md", "status": "Available"},
+                    {"id": "2", "name": "b.md", "status": "Available"},
+                ]})
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let shared = PineconeAssistantRouter::new(Config {
+            pinecone_api_key: "server-key".to_string(),
+            pinecone_assistant_host: server.url(),
+            allow_api_key_argument: true,
+            ..Config::default()
+        });
+
+        let result = shared
+            .call_tool(
+                TOOL_LIST_FILES,
+                json!({"assistant_name": "kb", "limit": 1, "api_key": "key-a"}),
+            )
+            .await
+            .unwrap();
+        let cursor = texts(&result)[0]["next_cursor"].clone();
+        let next_page = |api_key: &str| {
+            let mut arguments = json!({"cursor": cursor, "limit": 1, "api_key": api_key});
+            if api_key.is_empty() {
+                arguments.as_object_mut().unwrap().remove("api_key");
+            }
+            shared.call_tool(TOOL_LIST_FILES, arguments)
+        };
+        assert!(next_page("key-b").await.is_err());
+        assert!(next_page("").await.is_err());
+        assert_eq!(next_page("key-a").await.unwrap().len(), 1);
+
+        let result = shared
+            .call_tool(
+                TOOL_START_CHAT_SESSION,
+                json!({"assistant_name": "kb", "api_key": "key-a"}),
+            )
+            .await
+            .unwrap();
+        let session_id = texts(&result)[0]["session_id"].clone();
+        let end = |api_key: &str| {
+            shared.call_tool(
+                TOOL_END_CHAT_SESSION,
+                json!({"session_id": session_id, "api_key": api_key}),
+            )
+        };
+        assert!(end("key-b").await.is_err());
+        assert!(
+            shared
+                .call_tool(TOOL_END_CHAT_SESSION, json!({"session_id": session_id}))
+                .await
+                .is_err()
+        );
+        assert!(end("key-a").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_sends_partial_results_as_progress() {
+        let (outgoing, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let context = RequestContext::new(crate::peer::Peer::new(outgoing), Some(json!(7)), None);
+        // Steps are numbered on from progress already sent.
+        context.keep_alive(Duration::from_secs(30));
+        notifications.try_recv().unwrap();
+        let result = context
+            .scope(router(Config::default()).call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_names": ["a", "missing"], "query": "q"}),
+            ))
+            .await
+            .unwrap();
+
+        let mut partials = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            let params = &notification["params"];
+            assert_eq!(params["progress"], partials.len() as f64 + 1.0);
+            assert_eq!(params["total"], 2.0);
+            assert!(params["message"].as_str().unwrap().len() < 40);
+            partials.push(params["_meta"]["partial"].clone());
+        }
+        partials.sort_by_key(|partial| partial["assistant_name"].to_string());
+        assert_eq!(partials.len(), 2);
+        assert_eq!(partials[0]["snippets"][0]["content"], "from a");
+        assert!(partials[1]["error"].as_str().unwrap().contains("not found"));
+        assert!(!is_error_result(&result));
+    }
+
+    #[tokio::test]
+    async fn test_backend_call_is_abandoned_past_the_deadline() {
+        let (outgoing, _) = tokio::sync::mpsc::unbounded_channel();
+        let context = RequestContext::new(
+            crate::peer::Peer::new(outgoing),
+            None,
+            Some(Instant::now() + Duration::from_millis(100)),
+        );
+        let result = context
+            .scope(router(Config::default()).call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "a", "query": "q"}),
+            ))
+            .await;
+        assert!(matches!(result, Err(ToolError::ExecutionError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_verify_file_compares_hashes() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        std::fs::write(dir.join("doc.md"), "abc").unwrap();
+        std::fs::write(dir.join("other.md"), "abc").unwrap();
+        let config = Config {
+            upload_roots: vec![dir.to_path_buf()],
+            ..Config::default()
+        };
+
+        let result = router(config)
+            .call_tool(
+                TOOL_VERIFY_FILE,
+                json!({"assistant_name": "kb", "file_paths": [dir.join("doc.md"), dir.join("other.md")]}),
+            )
+            .await;
+
+        let report = &texts(&result.unwrap())[0];
+        assert_eq!(report[0]["status"], "match");
+        assert_eq!(report[0]["file_id"], "f1");
+        assert_eq!(report[1]["status"], "missing");
+    }
+
+    #[tokio::test]
+    async fn test_list_files_exposes_metadata() {
+        let result = router(Config::default())
+            .call_tool(
+                TOOL_LIST_FILES,
+                json!({"assistant_name": "kb", "filter": {"owner": "support"}}),
+            )
+            .await
+            .unwrap();
+        let files = &texts(&result)[0];
+        assert_eq!(files[0]["id"], "f1");
+        assert!(files[0]["metadata"][METADATA_SHA256].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_search_files_by_name_and_tags() {
+        /// Holds two files, and records the filter of the last listing.
+        #[derive(Default)]
+        struct Files {
+            filter: std::sync::Mutex<Option<Value>>,
+        }
+
+        impl ContextBackend for Files {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                unreachable!()
+            }
+
+            fn list_files<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                filter: Option<Value>,
+            ) -> BackendFuture<'a, Vec<AssistantFile>> {
+                *self.filter.lock().unwrap() = filter;
+                Box::pin(async {
+                    Ok(vec![
+                        serde_json::from_value(
+                            json!({"name": "Q3_Security-Review.pdf", "id": "f1"}),
+                        )
+                        .unwrap(),
+                        serde_json::from_value(json!({"name": "q3-roadmap.md", "id": "f2"}))
+                            .unwrap(),
+                    ])
+                })
+            }
+        }
+
+        let backend = Arc::new(Files::default());
+        let router = PineconeAssistantRouter::with_backend(Config::default(), backend.clone());
+        let result = router
+            .call_tool(
+                TOOL_SEARCH_FILES,
+                json!({"assistant_name": "kb", "name": "q3 security review", "tags": ["security", "audit"]}),
+            )
+            .await
+            .unwrap();
+        let missing_criteria = router
+            .call_tool(TOOL_SEARCH_FILES, json!({"assistant_name": "kb"}))
+            .await;
+
+        let files = &texts(&result)[0];
+        assert_eq!(files.as_array().unwrap().len(), 1);
+        assert_eq!(files[0]["id"], "f1");
+        assert_eq!(
+            *backend.filter.lock().unwrap(),
+            Some(json!({"$and": [
+                {"tags": {"$in": ["security"]}},
+                {"tags": {"$in": ["audit"]}}
+            ]}))
+        );
+        assert!(matches!(
+            missing_criteria,
+            Err(ToolError::InvalidParameters(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_upload_content_detects_duplicates() {
+        let result = router(Config::default())
+            .call_tool(
+                TOOL_UPLOAD_CONTENT,
+                json!({
+                    "assistant_name": "kb",
+                    "file_name": "copy.md",
+                    "content": "YWJj",
+                    "encoding": "base64"
+                }),
+            )
+            .await
+            .unwrap();
+        let file = &texts(&result)[0];
+        assert_eq!(file["id"], "f1");
+        assert_eq!(file["duplicate"], true);
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_queued_while_offline() {
+        /// Can't be reached until put online.
+        #[derive(Default)]
+        struct Offline(std::sync::atomic::AtomicBool);
+
+        impl ContextBackend for Offline {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                unreachable!()
+            }
+
+            fn upload_file<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                path: &'a Path,
+                _metadata: Option<Value>,
+            ) -> BackendFuture<'a, AssistantFile> {
+                let online = self.0.load(Ordering::SeqCst);
+                Box::pin(async move {
+                    if !online {
+                        let refused = reqwest::get("http://127.0.0.1:1").await.unwrap_err();
+                        return Err(refused.into());
+                    }
+                    Ok(serde_json::from_value(json!({
+                        "name": path.file_name().unwrap().to_string_lossy(),
+                        "id": "f2"
+                    }))
+                    .unwrap())
+                })
+            }
+        }
+
+        let temp = temp_dir();
+        let dir = temp.path();
+        let config = Config {
+            upload_spool_dir: Some(dir.to_path_buf()),
+            upload_spool_retry_interval: Duration::from_millis(20),
+            ..Config::default()
+        };
+        let backend = Arc::new(Offline::default());
+        let router = PineconeAssistantRouter::with_backend(config, backend.clone());
+        let queue = || router.call_tool(TOOL_UPLOAD_QUEUE, json!({"assistant_name": "kb"}));
+
+        let result = router
+            .call_tool(
+                TOOL_UPLOAD_CONTENT,
+                json!({"assistant_name": "kb", "file_name": "notes.md", "content": "offline"}),
+            )
+            .await
+            .unwrap();
+        let queued = &texts(&result)[0];
+        assert_eq!(queued["status"], "queued");
+        let listed = &texts(&queue().await.unwrap())[0];
+        assert_eq!(listed["queued"], 1);
+        assert_eq!(listed["uploads"][0]["file_name"], "notes.md");
+        assert_eq!(listed["uploads"][0]["id"], queued["queue_id"]);
+
+        backend.0.store(true, Ordering::SeqCst);
+        let mut remaining = Value::Null;
+        for _ in 0..100 {
+            remaining = texts(&queue().await.unwrap())[0]["uploads"].clone();
+            if remaining == json!([]) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(remaining, json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_export_assistant_writes_manifest() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let config = Config {
+            export_dir: Some(dir.to_path_buf()),
+            ..Config::default()
+        };
+        let exporter = router(config);
+        let export = |output_path: Value| {
+            exporter.call_tool(
+                TOOL_EXPORT_ASSISTANT,
+                json!({"assistant_name": "kb", "output_path": output_path}),
+            )
+        };
+        let result = export(json!("manifest.json")).await.unwrap();
+        let escaping = export(json!("../manifest.json")).await;
+        let absolute = export(json!(dir.join("other.json"))).await;
+        let manifest: Value =
+            serde_json::from_slice(&std::fs::read(dir.join("manifest.json")).unwrap()).unwrap();
+        let unconfigured = router(Config::default())
+            .call_tool(
+                TOOL_EXPORT_ASSISTANT,
+                json!({"assistant_name": "kb", "output_path": "manifest.json"}),
+            )
+            .await;
+
+        for rejected in [escaping, absolute, unconfigured] {
+            assert!(matches!(rejected, Err(ToolError::InvalidParameters(_))));
+        }
+        assert_eq!(texts(&result)[0]["files"], 1);
+        assert_eq!(
+            manifest["assistant"]["instructions"],
+            "Answer from the docs."
+        );
+        assert_eq!(manifest["files"][0]["name"], "doc.md");
+        assert_eq!(
+            manifest["files"][0]["sha256"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_assistant_uploads_missing_and_deletes_extras() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        std::fs::write(dir.join("new.md"), "new").unwrap();
+        let manifest_path = dir.join("manifest.json");
+        let router = router(Config {
+            upload_roots: vec![dir.to_path_buf()],
+            ..Config::default()
+        });
+        let sync = |files: Value| {
+            std::fs::write(
+                &manifest_path,
+                json!({
+                    "version": 1,
+                    "exported_at": 0,
+                    "assistant": {"name": "kb", "instructions": "Be brief."},
+                    "files": files
+                })
+                .to_string(),
+            )
+            .unwrap();
+            router.call_tool(
+                TOOL_SYNC_ASSISTANT,
+                json!({"manifest_path": manifest_path, "directory": dir, "delete_extras": true}),
+            )
+        };
+        let statuses = |report: &Value| -> Vec<String> {
+            report["files"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|file| format!("{}: {}", file["name"], file["status"]).replace('"', ""))
+                .collect()
+        };
+        let new = json!({"name": "new.md", "id": "old", "metadata": {"owner": "support"}});
+
+        let partial = sync(json!([new, {"name": "gone.md", "id": "old2"}])).await;
+        let complete = sync(json!([new])).await;
+
+        let report = &texts(&partial.unwrap())[0];
+        assert_eq!(report["assistant_name"], "kb");
+        assert_eq!(report["created"], false);
+        assert_eq!(report["updated"], true);
+        // Nothing is deleted while a file failed to sync.
+        assert_eq!(
+            statuses(report),
+            ["new.md: uploaded", "gone.md: failed", "doc.md: extra"]
+        );
+        let report = &texts(&complete.unwrap())[0];
+        assert_eq!(statuses(report), ["new.md: uploaded", "doc.md: deleted"]);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_from_roots_uploads_shared_files() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        std::fs::write(dir.join("a.md"), "a").unwrap();
+        std::fs::write(dir.join("b.exe"), "b").unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let peer = Peer::new(tx);
+        peer.set_client_capabilities(json!({"roots": {}}));
+        let client = peer.clone();
+        let root_uri = format!("file://{}", dir.display());
+        tokio::spawn(async move {
+            while let Some(request) = rx.recv().await {
+                if request["method"] == "roots/list" {
+                    let roots = json!({"roots": [{"uri": root_uri, "name": "docs"}]});
+                    client.resolve(&request["id"], Ok(roots));
+                }
+            }
+        });
+        let context = RequestContext::new(peer, None, None);
+        let router = router(Config::default());
+        let result = context
+            .clone()
+            .scope(router.call_tool(
+                TOOL_INGEST_FROM_ROOTS,
+                json!({"assistant_name": "kb", "roots": ["docs"]}),
+            ))
+            .await;
+        let unknown = context
+            .scope(router.call_tool(
+                TOOL_INGEST_FROM_ROOTS,
+                json!({"assistant_name": "kb", "roots": ["elsewhere"]}),
+            ))
+            .await;
+
+        let report = &texts(&result.unwrap())[0];
+        assert_eq!(report["uploaded"], 1);
+        assert_eq!(report["files"][0]["file_path"], json!(dir.join("a.md")));
+        assert!(matches!(unknown, Err(ToolError::InvalidParameters(_))));
+    }
+
+    #[tokio::test]
+    async fn test_upload_rejects_disallowed_files() {
+        let config = Config {
+            max_upload_bytes: 2,
+            ..Config::default()
+        };
+        let router = router(config);
+        let binary = router
+            .call_tool(
+                TOOL_UPLOAD_CONTENT,
+                json!({"assistant_name": "kb", "file_name": "tool.exe", "content": "x"}),
+            )
+            .await;
+        let large = router
+            .call_tool(
+                TOOL_UPLOAD_CONTENT,
+                json!({"assistant_name": "kb", "file_name": "doc.md", "content": "abc"}),
+            )
+            .await;
+        let encoded = router
+            .call_tool(
+                TOOL_UPLOAD_CONTENT,
+                json!({"assistant_name": "kb", "file_name": "doc.md", "content": "YWJj", "encoding": "base64"}),
+            )
+            .await;
+        assert!(matches!(binary, Err(ToolError::InvalidParameters(e)) if e.contains("file type")));
+        assert!(matches!(large, Err(ToolError::InvalidParameters(e)) if e.contains("3 bytes")));
+        // Judged by the decoded size, not the 4 characters sent.
+        assert!(matches!(encoded, Err(ToolError::InvalidParameters(e)) if e.contains("3 bytes")));
+    }
+
+    #[tokio::test]
+    async fn test_upload_paths_expands_directories_and_reports_progress() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        std::fs::write(dir.join("docs/a.md"), "a").unwrap();
+        std::fs::write(dir.join("docs/skip.exe"), "b").unwrap();
+        std::fs::write(dir.join("notes.txt"), "c").unwrap();
+
+        let progress = std::sync::Mutex::new(Vec::new());
+        let on_progress = |done: usize, total: usize, _: &Value| {
+            progress.lock().unwrap().push((done, total));
+        };
+        let report = router(Config::default())
+            .upload_paths(
+                Some("kb"),
+                &[dir.join("docs"), dir.join("notes.txt")],
+                None,
+                &on_progress,
+            )
+            .await;
+
+        let report = report.unwrap();
+        assert_eq!(report["uploaded"], 2);
+        assert_eq!(
+            report["files"][0]["file_path"],
+            json!(dir.join("docs/a.md"))
+        );
+        assert_eq!(*progress.lock().unwrap(), vec![(1, 2), (2, 2)]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_uploads_only_read_files_under_the_roots() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::write(dir.join("root/new.md"), "new").unwrap();
+        std::fs::write(dir.join("secret.md"), "secret").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.md"), dir.join("root/link.md")).unwrap();
+        let config = Config {
+            upload_roots: vec![dir.join("root")],
+            ..Config::default()
+        };
+        let router = router(config);
+        let upload = |path: PathBuf| {
+            router.call_tool(
+                TOOL_UPLOAD_FILE,
+                json!({"assistant_name": "kb", "file_path": path}),
+            )
+        };
+
+        let inside = upload(dir.join("root/new.md")).await;
+        let outside = upload(dir.join("secret.md")).await;
+        let escaping = upload(dir.join("root/../secret.md")).await;
+        let linked = upload(dir.join("root/link.md")).await;
+        let directory = router
+            .call_tool(
+                TOOL_UPLOAD_DIRECTORY,
+                json!({"assistant_name": "kb", "directory": dir}),
+            )
+            .await;
+
+        assert_eq!(texts(&inside.unwrap())[0]["id"], "f2");
+        for rejected in [outside, escaping, linked, directory] {
+            assert!(
+                matches!(rejected, Err(ToolError::InvalidParameters(e)) if e.contains("outside"))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_rejects_reserved_metadata() {
+        let result = router(Config::default())
+            .call_tool(
+                TOOL_UPLOAD_FILE,
+                json!({
+                    "assistant_name": "kb",
+                    "file_path": "doc.md",
+                    "metadata": {METADATA_SHA256: "forged"}
+                }),
+            )
+            .await;
+        assert!(matches!(result, Err(ToolError::InvalidParameters(_))));
+    }
+
+    #[tokio::test]
+    async fn test_uploaded_dates_match_date_filters() {
+        /// Keeps uploads and lists them by their `created_at` range.
+        #[derive(Default)]
+        struct Stored(std::sync::Mutex<Vec<AssistantFile>>);
+
+        impl ContextBackend for Stored {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                unreachable!()
+            }
+
+            fn upload_file<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                path: &'a Path,
+                metadata: Option<Value>,
+            ) -> BackendFuture<'a, AssistantFile> {
+                let file: AssistantFile = serde_json::from_value(json!({
+                    "name": path.file_name().unwrap().to_string_lossy(),
+                    "id": "f2",
+                    "metadata": metadata
+                }))
+                .unwrap();
+                self.0.lock().unwrap().push(file.clone());
+                Box::pin(async move { Ok(file) })
+            }
+
+            fn list_files<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                filter: Option<Value>,
+            ) -> BackendFuture<'a, Vec<AssistantFile>> {
+                let range = filter.map_or(Value::Null, |f| f[METADATA_CREATED_AT].clone());
+                let files = self
+                    .0
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|file| {
+                        let created_at = &file.metadata.as_ref().unwrap()[METADATA_CREATED_AT];
+                        let created_at = created_at.as_i64().unwrap();
+                        range["$gte"]
+                            .as_i64()
+                            .is_none_or(|after| created_at >= after)
+                            && range["$lt"]
+                                .as_i64()
+                                .is_none_or(|before| created_at < before)
+                    })
+                    .cloned()
+                    .collect();
+                Box::pin(async move { Ok(files) })
+            }
+        }
+
+        let router =
+            PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Stored::default()));
+        let upload = |created_at: Value| {
+            router.call_tool(
+                TOOL_UPLOAD_CONTENT,
+                json!({
+                    "assistant_name": "kb",
+                    "file_name": "report.md",
+                    "content": "March report",
+                    "metadata": {METADATA_CREATED_AT: created_at}
+                }),
+            )
+        };
+        upload(json!("2024-03-01")).await.unwrap();
+        let list = |after: &str, before: &str| {
+            router.call_tool(
+                TOOL_LIST_FILES,
+                json!({"assistant_name": "kb", "created_after": after, "created_before": before}),
+            )
+        };
+        let march = list("2024-03-01", "2024-04-01").await.unwrap();
+        let april = list("2024-04-01", "2024-05-01").await.unwrap();
+
+        assert_eq!(
+            texts(&march)[0][0]["metadata"][METADATA_CREATED_AT],
+            1_709_251_200
+        );
+        assert_eq!(texts(&april)[0], json!([]));
+        for rejected in [json!("March 2024"), json!(true)] {
+            assert!(matches!(
+                upload(rejected).await,
+                Err(ToolError::InvalidParameters(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_results_are_paged_by_cursor() {
+        let router = router(Config::default());
+        let first = router
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({
+                    "assistant_names": ["a", "b"],
+                    "query": "q",
+                    "page_size": 2,
+                    "response_format": "json"
+                }),
+            )
+            .await
+            .unwrap();
+        let first = &texts(&first)[0];
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let rest = router
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"cursor": cursor, "page_size": 2, "response_format": "json"}),
+            )
+            .await
+            .unwrap();
+        let rest = &texts(&rest)[0];
+        let missing_query = router
+            .call_tool(TOOL_ASSISTANT_CONTEXT, json!({"assistant_name": "a"}))
+            .await;
+
+        assert_eq!(first["snippets"].as_array().unwrap().len(), 2);
+        assert_eq!(first["snippets"][0]["content"], "shared");
+        assert_eq!(rest["snippets"].as_array().unwrap().len(), 1);
+        assert!(rest.get("next_cursor").is_none());
+        assert!(
+            matches!(missing_query, Err(ToolError::InvalidParameters(e)) if e.contains("query"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_default_assistant_is_used_when_omitted() {
+        let router = router(Config::default());
+        router
+            .call_tool(
+                TOOL_SET_DEFAULT_ASSISTANT,
+                json!({"assistant_name": "kb", "top_k": 3}),
+            )
+            .await
+            .unwrap();
+        let result = router
+            .call_tool(TOOL_ASSISTANT_CONTEXT, json!({"query": "q"}))
+            .await
+            .unwrap();
+        assert_eq!(texts(&result)[0]["content"], "from kb");
+    }
+
+    #[tokio::test]
+    async fn test_chat_session_keeps_history() {
+        let router = router(Config::default());
+        let started = router
+            .call_tool(TOOL_START_CHAT_SESSION, json!({"assistant_name": "kb"}))
+            .await
+            .unwrap();
+        let session_id = texts(&started)[0][PARAM_SESSION_ID].clone();
+
+        for expected in ["seen 1", "seen 3"] {
+            let result = router
+                .call_tool(
+                    TOOL_CHAT_IN_SESSION,
+                    json!({"session_id": session_id, "message": "hi"}),
+                )
+                .await
+                .unwrap();
+            assert_eq!(result[0].as_text(), Some(expected));
+        }
+
+        router
+            .call_tool(TOOL_END_CHAT_SESSION, json!({"session_id": session_id}))
+            .await
+            .unwrap();
+        let result = router
+            .call_tool(
+                TOOL_CHAT_IN_SESSION,
+                json!({"session_id": session_id, "message": "hi"}),
+            )
+            .await;
+        assert!(matches!(result, Err(ToolError::InvalidParameters(_))));
+    }
+
+    #[tokio::test]
+    async fn test_chat_json_response_is_passed_to_pinecone() {
+        let router =
+            PineconeAssistantRouter::with_backend(Config::default(), Arc::new(EchoOptions));
+        let session_id = start_chat(&router).await;
+
+        let result = router
+            .call_tool(
+                TOOL_CHAT_IN_SESSION,
+                json!({"session_id": session_id, "message": "List the fees", "json_response": true}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            texts(&result)[0],
+            json!({"stream": false, "json_response": true})
+        );
+
+        let result = router
+            .call_tool(
+                TOOL_CHAT_IN_SESSION,
+                json!({"session_id": session_id, "message": "Explain them"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(texts(&result)[0], json!({"stream": false}));
+    }
+
+    #[tokio::test]
+    async fn test_chat_model_defaults_and_allowlist() {
+        let router = PineconeAssistantRouter::with_backend(
+            Config {
+                chat_model: Some("gpt-4o-mini".to_string()),
+                allowed_chat_models: vec!["gpt-4o-mini".to_string(), "gpt-4o".to_string()],
+                ..Config::default()
+            },
+            Arc::new(EchoOptions),
+        );
+        let session_id = start_chat(&router).await;
+
+        for (arguments, model) in [
+            (
+                json!({"session_id": session_id, "message": "hi"}),
+                "gpt-4o-mini",
+            ),
+            (
+                json!({"session_id": session_id, "message": "hi", "model": "gpt-4o"}),
+                "gpt-4o",
+            ),
+        ] {
+            let result = router
+                .call_tool(TOOL_CHAT_IN_SESSION, arguments)
+                .await
+                .unwrap();
+            assert_eq!(texts(&result)[0]["model"], model);
+        }
+
+        let result = router
+            .call_tool(
+                TOOL_CHAT_IN_SESSION,
+                json!({"session_id": session_id, "message": "hi", "model": "o1"}),
+            )
+            .await;
+        assert!(matches!(result, Err(ToolError::InvalidParameters(_))));
+    }
+
+    #[tokio::test]
+    async fn test_chat_generation_parameters_are_bounded() {
+        let router = PineconeAssistantRouter::with_backend(
+            Config {
+                chat_temperature: Some(0.0),
+                max_chat_temperature: 0.5,
+                ..Config::default()
+            },
+            Arc::new(EchoOptions),
+        );
+        let session_id = start_chat(&router).await;
+
+        let result = router
+            .call_tool(
+                TOOL_CHAT_IN_SESSION,
+                json!({"session_id": session_id, "message": "hi", "top_p": 0.5}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(texts(&result)[0]["temperature"], 0.0);
+        assert_eq!(texts(&result)[0]["top_p"], 0.5);
+
+        let result = router
+            .call_tool(
+                TOOL_CHAT_IN_SESSION,
+                json!({"session_id": session_id, "message": "hi", "temperature": 0.9}),
+            )
+            .await;
+        assert!(matches!(result, Err(ToolError::InvalidParameters(_))));
+        // Enforced by the handlers too, not only the schema.
+        let result = router
+            .handle_chat_in_session(
+                json!({"session_id": session_id, "message": "hi", "temperature": 0.9}),
+            )
+            .await;
+        assert!(matches!(result, Err(RouterError::InvalidParameters(_))));
+        let result = router
+            .handle_answer_with_sources(
+                json!({"assistant_name": "kb", "query": "q", "temperature": 0.9}),
+            )
+            .await;
+        assert!(matches!(result, Err(RouterError::InvalidParameters(_))));
+    }
+
+    #[tokio::test]
+    async fn test_chat_answers_stream_as_progress() {
+        /// Writes its answer in two pieces.
+        struct Streaming;
+
+        impl ContextBackend for Streaming {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                unreachable!()
+            }
+
+            fn assistant_chat_stream<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _request: AssistantChat,
+                on_delta: &'a ChatDeltas<'a>,
+            ) -> BackendFuture<'a, AssistantChatResponse> {
+                Box::pin(async move {
+                    on_delta("Refunds take ");
+                    // A keep-alive tick in between doesn't hold back later pieces.
+                    let context = RequestContext::current().unwrap();
+                    context.keep_alive(Duration::from_secs(30));
+                    on_delta("5 days.");
+                    Ok(serde_json::from_value(json!({
+                        "message": {"role": "assistant", "content": "Refunds take 5 days."}
+                    }))
+                    .unwrap())
+                })
+            }
+        }
+
+        let router = PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Streaming));
+        let session_id = start_chat(&router).await;
+        let (outgoing, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let context = RequestContext::new(crate::peer::Peer::new(outgoing), Some(json!(7)), None);
+        let result = context
+            .scope(router.call_tool(
+                TOOL_CHAT_IN_SESSION,
+                json!({"session_id": session_id, "message": "How long do refunds take?"}),
+            ))
+            .await
+            .unwrap();
+
+        let mut pieces = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            let message = notification["params"]["message"].as_str().unwrap();
+            if !message.starts_with("Still working") {
+                pieces.push(message.to_string());
+            }
+        }
+        assert_eq!(pieces, ["Refunds take ", "5 days."]);
+        assert_eq!(result[0].as_text(), Some("Refunds take 5 days."));
+    }
+
+    #[tokio::test]
+    async fn test_answer_with_sources_pins_the_retrieved_snippets() {
+        /// Retrieves one snippet and answers with the question it was asked.
+        struct Parrot;
+
+        impl ContextBackend for Parrot {
+            fn assistant_context<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                _query: &'a str,
+                _top_k: Option<u32>,
+            ) -> BackendFuture<'a, AssistantContextResponse> {
+                Box::pin(async move {
+                    Ok(AssistantContextResponse {
+                        snippets: vec![json!({
+                            "content": "Refunds take 5 days.",
+                            "score": 0.9,
+                            "reference": {"file": {"id": "f1", "name": "guide.pdf"}}
+                        })],
+                        usage: json!({}),
+                    })
+                })
+            }
+
+            fn assistant_chat<'a>(
+                &'a self,
+                _assistant_name: &'a str,
+                messages: Vec<Message>,
+            ) -> BackendFuture<'a, AssistantChatResponse> {
+                Box::pin(async move {
+                    Ok(serde_json::from_value(json!({
+                        "message": messages[0],
+                        "citations": [{"position": 3, "references": []}]
+                    }))
+                    .unwrap())
+                })
+            }
+        }
+
+        let router = PineconeAssistantRouter::with_backend(Config::default(), Arc::new(Parrot));
+        let result = router
+            .call_tool(
+                TOOL_ANSWER_WITH_SOURCES,
+                json!({"assistant_name": "kb", "query": "How long do refunds take?"}),
+            )
+            .await
+            .unwrap();
+        let answer = result[0].as_text().unwrap();
+        assert!(answer.contains("[1] (guide.pdf)\nRefunds take 5 days."));
+        assert!(answer.ends_with("Question: How long do refunds take?"));
+        let sources = &texts(&result[1..])[0];
+        assert_eq!(sources["snippets"][0]["content"], "Refunds take 5 days.");
+        assert_eq!(sources["citations"][0]["position"], 3);
+    }
+
+    async fn start_chat(router: &PineconeAssistantRouter) -> Value {
+        let started = router
+            .call_tool(TOOL_START_CHAT_SESSION, json!({"assistant_name": "kb"}))
+            .await
+            .unwrap();
+        texts(&started)[0][PARAM_SESSION_ID].clone()
+    }
+
+    #[tokio::test]
+    async fn test_assistant_context_json_format() {
+        let result = router(Config::default())
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "a", "query": "q", "response_format": "json"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        let document = &texts(&result)[0];
+        assert_eq!(document["snippets"].as_array().unwrap().len(), 2);
+        assert_eq!(document["usage"], json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_assistant_context_snapshots_are_resources() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let router = router(Config {
+            snapshot_dir: Some(dir.to_path_buf()),
+            ..Config::default()
+        });
+        let result = router
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "a", "query": "q", "response_format": "json"}),
+            )
+            .await
+            .unwrap();
+        let uri = texts(&result)[0]["snapshot"].as_str().unwrap().to_string();
+        let listed = router
+            .list_resources()
+            .iter()
+            .any(|resource| resource.uri == uri);
+        let snapshot = router.read_resource(&uri).await;
+
+        assert!(listed);
+        let snapshot: Value = serde_json::from_str(&snapshot.unwrap()).unwrap();
+        assert_eq!(snapshot["arguments"]["query"], "q");
+        assert_eq!(snapshot["snippets"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_assistant_context_rejects_unknown_parameters() {
+        let result = router(Config::default())
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "a", "query": "q", "topk": 3}),
+            )
+            .await;
+        match result {
+            Err(ToolError::InvalidParameters(msg)) => assert!(msg.contains("topk"), "{msg}"),
+            other => panic!("Expected invalid parameters, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_arguments_are_validated_against_the_schema() {
+        let router = router(Config::default());
+        let result = router
+            .call_tool(
+                TOOL_ASSISTANT_CONTEXT,
+                json!({"assistant_name": "a", "query": "q", "top_k": "ten", "file_types": "pdf"}),
+            )
+            .await;
+        match result {
+            Err(ToolError::InvalidParameters(msg)) => {
+                assert!(
+                    msg.contains("/top_k: \"ten\" is not of type \"integer\""),
+                    "{msg}"
+                );
+                assert!(msg.contains("/file_types:"), "{msg}");
+            }
+            other => panic!("Expected invalid parameters, got {other:?}"),
+        }
+
+        let batch = router
+            .call_tool(TOOL_BATCH, json!({"calls": [{"arguments": {}}]}))
+            .await;
+        assert!(
+            matches!(batch, Err(ToolError::InvalidParameters(msg)) if msg.contains("/calls/0:"))
+        );
+
+        let neither = router
+            .call_tool(TOOL_ASSISTANT_CONTEXT, json!({"assistant_name": "a"}))
+            .await;
+        assert!(matches!(
+            neither,
+            Err(ToolError::InvalidParameters(msg)) if msg.ends_with("/: one of 'query', 'cursor' is required")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_assistant_context_requires_an_assistant() {
+        let result = router(Config::default())
+            .call_tool(TOOL_ASSISTANT_CONTEXT, json!({"query": "q"}))
+            .await;
+        assert!(matches!(result, Err(ToolError::InvalidParameters(_))));
+    }
 }
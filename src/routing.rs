@@ -0,0 +1,115 @@
+use std::{cmp::Reverse, collections::HashSet};
+
+/// Words too common to carry any signal when matching a query to an assistant.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "about", "for", "from", "how", "in", "is", "it", "of", "on", "or",
+    "the", "to", "what", "when", "where", "which", "who", "why", "with", "do", "does", "i", "my",
+    "we", "our", "you", "your", "can", "this", "that",
+];
+
+/// An assistant the server may pick on the caller's behalf, along with the
+/// description used to match queries against it.
+#[derive(Clone, Debug)]
+pub struct AssistantRoute {
+    pub name: String,
+    pub description: String,
+    keywords: HashSet<String>,
+}
+
+impl AssistantRoute {
+    pub fn new(name: String, description: String) -> Self {
+        let keywords = tokenize(&description).chain(tokenize(&name)).collect();
+        Self {
+            name,
+            description,
+            keywords,
+        }
+    }
+
+    fn score(&self, query_tokens: &HashSet<String>) -> usize {
+        query_tokens.intersection(&self.keywords).count()
+    }
+}
+
+/// Keyword-based selection of the most relevant assistant for a query.
+#[derive(Clone, Debug, Default)]
+pub struct QueryRouter {
+    routes: Vec<AssistantRoute>,
+}
+
+impl QueryRouter {
+    pub fn new(routes: Vec<AssistantRoute>) -> Self {
+        Self { routes }
+    }
+
+    pub fn routes(&self) -> &[AssistantRoute] {
+        &self.routes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Returns the assistant whose description shares the most keywords with
+    /// the query. Ties and queries matching nothing yield `None`, leaving the
+    /// decision to the caller.
+    pub fn select(&self, query: &str) -> Option<&AssistantRoute> {
+        let query_tokens: HashSet<String> = tokenize(query).collect();
+        let mut scored: Vec<(usize, &AssistantRoute)> = self
+            .routes
+            .iter()
+            .map(|route| (route.score(&query_tokens), route))
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        scored.sort_by_key(|(score, _)| Reverse(*score));
+        match scored.as_slice() {
+            [(best, route), rest @ ..] if rest.first().is_none_or(|(next, _)| next < best) => {
+                Some(route)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() > 1)
+        .map(str::to_lowercase)
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> QueryRouter {
+        QueryRouter::new(vec![
+            AssistantRoute::new(
+                "product-docs".to_string(),
+                "Product documentation: installation, configuration, API reference".to_string(),
+            ),
+            AssistantRoute::new(
+                "billing".to_string(),
+                "Invoices, pricing plans and payment methods".to_string(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_select_picks_best_matching_assistant() {
+        let router = router();
+        let route = router
+            .select("How do I change my payment methods?")
+            .unwrap();
+        assert_eq!(route.name, "billing");
+        let route = router.select("API configuration for installation").unwrap();
+        assert_eq!(route.name, "product-docs");
+    }
+
+    #[test]
+    fn test_select_returns_none_without_a_clear_winner() {
+        let router = router();
+        assert!(router.select("What is the meaning of life?").is_none());
+        assert!(router.select("pricing of the API").is_none());
+    }
+}
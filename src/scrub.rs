@@ -0,0 +1,207 @@
+//! Removal of personal data from snippets and answers before they reach the
+//! client, for deployments that must not expose it to external model
+//! providers.
+
+use std::borrow::Cow;
+
+use regex::Regex;
+use serde_json::Value;
+
+/// Patterns that can be enabled by name.
+pub const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    (
+        "email",
+        r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+    ),
+    (
+        "phone",
+        r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{2,4}\)|\b\d{2,4})[\s.-]?\d{3,4}[\s.-]?\d{3,4}\b",
+    ),
+];
+
+/// Replaces matches of named patterns with `[redacted <name>]`.
+#[derive(Clone, Debug, Default)]
+pub struct Scrubber {
+    patterns: Vec<(String, Regex)>,
+}
+
+impl Scrubber {
+    /// Compiles `(name, regex)` patterns.
+    pub fn new(patterns: &[(String, String)]) -> Result<Self, regex::Error> {
+        let patterns = patterns
+            .iter()
+            .map(|(name, pattern)| Ok((name.clone(), Regex::new(pattern)?)))
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// The `(name, regex)` patterns, as given.
+    pub fn patterns(&self) -> Vec<(&str, &str)> {
+        self.patterns
+            .iter()
+            .map(|(name, pattern)| (name.as_str(), pattern.as_str()))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Scrubs the text of snippets, including the text blocks of multimodal
+    /// ones and their highlights.
+    pub fn scrub(&self, snippets: &mut [Value]) {
+        if self.is_empty() {
+            return;
+        }
+        for snippet in snippets {
+            match &mut snippet["content"] {
+                Value::String(text) => self.scrub_in_place(text),
+                Value::Array(blocks) => {
+                    for block in blocks {
+                        if let Some(Value::String(text)) = block.get_mut("text") {
+                            self.scrub_in_place(text);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if let Some(highlights) = snippet.get_mut("highlights") {
+                self.scrub_strings(highlights);
+            }
+        }
+    }
+
+    /// Scrubs a chat answer and the references of its citations. File ids
+    /// and URLs are kept, as they locate the cited file.
+    pub fn scrub_answer(&self, answer: &mut String, citations: &mut [Value]) {
+        if self.is_empty() {
+            return;
+        }
+        self.scrub_in_place(answer);
+        let references = citations
+            .iter_mut()
+            .filter_map(|citation| citation.get_mut("references"))
+            .filter_map(Value::as_array_mut)
+            .flatten();
+        for reference in references {
+            let Value::Object(fields) = reference else {
+                continue;
+            };
+            for (key, value) in fields {
+                if key != "file" {
+                    self.scrub_strings(value);
+                    continue;
+                }
+                for field in ["name", "metadata"] {
+                    if let Some(value) = value.get_mut(field) {
+                        self.scrub_strings(value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Scrubs every string in a value of unknown shape.
+    fn scrub_strings(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => self.scrub_in_place(text),
+            Value::Array(values) => values.iter_mut().for_each(|v| self.scrub_strings(v)),
+            Value::Object(fields) => fields.values_mut().for_each(|v| self.scrub_strings(v)),
+            _ => {}
+        }
+    }
+
+    fn scrub_in_place(&self, text: &mut String) {
+        if let Cow::Owned(scrubbed) = self.scrub_text(text) {
+            *text = scrubbed;
+        }
+    }
+
+    fn scrub_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for (name, pattern) in &self.patterns {
+            if let Cow::Owned(replaced) = pattern.replace_all(&text, format!("[redacted {name}]")) {
+                text = Cow::Owned(replaced);
+            }
+        }
+        text
+    }
+}
+
+/// The pattern of a built-in name, such as `email`.
+pub fn builtin(name: &str) -> Option<&'static str> {
+    BUILTIN_PATTERNS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, pattern)| *pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scrubber(names: &[&str]) -> Scrubber {
+        let patterns: Vec<(String, String)> = names
+            .iter()
+            .map(|name| (name.to_string(), builtin(name).unwrap().to_string()))
+            .collect();
+        Scrubber::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn test_scrubs_emails_and_phone_numbers() {
+        let scrubber = scrubber(&["email", "phone"]);
+        let mut snippets = vec![
+            json!({"content": "Mail jane.doe@example.co.uk or call +1 (555) 123-4567.", "score": 0.9}),
+            json!({"content": "Office: 030 1234 5678. Released 2024-05-01, version 1.2.3."}),
+            json!({"content": [{"type": "text", "text": "ops@example.com"}, {"type": "image"}]}),
+            json!({"content": "x", "highlights": [{"type": "text", "content": "jane@example.com"}]}),
+        ];
+
+        scrubber.scrub(&mut snippets);
+
+        assert_eq!(
+            snippets[0]["content"],
+            "Mail [redacted email] or call [redacted phone]."
+        );
+        assert_eq!(
+            snippets[1]["content"],
+            "Office: [redacted phone]. Released 2024-05-01, version 1.2.3."
+        );
+        assert_eq!(snippets[2]["content"][0]["text"], "[redacted email]");
+        assert_eq!(snippets[3]["highlights"][0]["content"], "[redacted email]");
+        assert_eq!(snippets[0]["score"], 0.9);
+    }
+
+    #[test]
+    fn test_scrubs_answers_and_their_citations() {
+        let scrubber = scrubber(&["email"]);
+        let mut answer = "Write to jane@example.com.".to_string();
+        let mut citations = vec![json!({"position": 3, "references": [{
+            "file": {"id": "f1", "name": "From jane@example.com.pdf", "signed_url": "https://x/a@b.io"},
+            "highlight": {"type": "text", "content": "Contact jane@example.com"}
+        }]})];
+
+        scrubber.scrub_answer(&mut answer, &mut citations);
+
+        assert_eq!(answer, "Write to [redacted email].");
+        let reference = &citations[0]["references"][0];
+        assert_eq!(reference["file"]["name"], "From [redacted email]");
+        assert_eq!(reference["file"]["signed_url"], "https://x/a@b.io");
+        assert_eq!(
+            reference["highlight"]["content"],
+            "Contact [redacted email]"
+        );
+    }
+
+    #[test]
+    fn test_custom_patterns() {
+        let scrubber =
+            Scrubber::new(&[("employee_id".to_string(), r"EMP-\d+".to_string())]).unwrap();
+        let mut snippets = vec![json!({"content": "Owner: EMP-0042"})];
+        scrubber.scrub(&mut snippets);
+        assert_eq!(snippets[0]["content"], "Owner: [redacted employee_id]");
+        assert!(Scrubber::new(&[("bad".to_string(), "(".to_string())]).is_err());
+    }
+}
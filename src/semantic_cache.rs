@@ -0,0 +1,247 @@
+//! Reuse of retrieval results across paraphrased queries.
+//!
+//! Queries are embedded through a configurable embedding endpoint; a query
+//! whose embedding is close enough to an earlier one, with the same assistant
+//! and options, is answered with that query's snippets instead of calling
+//! Pinecone again.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use serde_json::{Value, json};
+
+use crate::pinecone::AssistantContext;
+
+/// How long an embedding request may take before the cache is bypassed.
+const EMBED_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings of the semantic cache. The embedding endpoint takes
+/// `{"model", "input": [query]}` and answers with the vector in
+/// `data[0].embedding` (OpenAI style) or `data[0].values` (Pinecone style).
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct SemanticCacheConfig {
+    pub embedding_url: String,
+    pub embedding_model: Option<String>,
+    /// Sent as a bearer token, if set.
+    pub api_key: Option<String>,
+    /// Cosine similarity from which a cached result is reused.
+    pub threshold: f32,
+    /// How long a result stays reusable.
+    pub ttl: Duration,
+    pub max_entries: usize,
+}
+
+impl SemanticCacheConfig {
+    pub fn new(embedding_url: impl Into<String>) -> Self {
+        Self {
+            embedding_url: embedding_url.into(),
+            embedding_model: None,
+            api_key: None,
+            threshold: 0.95,
+            ttl: Duration::from_secs(3600),
+            max_entries: 1000,
+        }
+    }
+}
+
+struct Entry {
+    /// The assistant and options other than the query.
+    scope: String,
+    embedding: Vec<f32>,
+    snippets: Vec<Value>,
+    stored: Instant,
+}
+
+/// Result of a cache lookup: the cached snippets on a hit, and the query's
+/// embedding to store the fresh result under on a miss.
+#[derive(Default)]
+pub struct Lookup {
+    pub hit: Option<Vec<Value>>,
+    pub embedding: Option<Vec<f32>>,
+}
+
+#[derive(Clone)]
+pub struct SemanticCache {
+    config: SemanticCacheConfig,
+    client: Client,
+    entries: Arc<Mutex<Vec<Entry>>>,
+}
+
+impl SemanticCache {
+    pub fn new(config: SemanticCacheConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+            entries: Arc::default(),
+        }
+    }
+
+    /// The scope a request's results may be reused in: the same assistant
+    /// and the same options besides the query.
+    pub fn scope(assistant_name: &str, request: &AssistantContext) -> String {
+        let options = AssistantContext {
+            query: String::new(),
+            ..request.clone()
+        };
+        let options = serde_json::to_string(&options).unwrap_or_default();
+        format!("{assistant_name}\n{options}")
+    }
+
+    /// Looks up the closest earlier query in `scope`. An unavailable
+    /// embedding endpoint bypasses the cache rather than failing the query.
+    pub async fn lookup(&self, scope: &str, query: &str) -> Lookup {
+        let embedding = match self.embed(query).await {
+            Ok(embedding) => embedding,
+            Err(e) => {
+                tracing::warn!("Bypassing the semantic cache: {e}");
+                return Lookup::default();
+            }
+        };
+        Lookup {
+            hit: self.find(scope, &embedding, Instant::now()),
+            embedding: Some(embedding),
+        }
+    }
+
+    fn find(&self, scope: &str, embedding: &[f32], now: Instant) -> Option<Vec<Value>> {
+        let mut entries = self.lock();
+        entries.retain(|entry| now - entry.stored < self.config.ttl);
+        let (similarity, entry) = entries
+            .iter()
+            .filter(|entry| entry.scope == scope)
+            .map(|entry| (cosine(&entry.embedding, embedding), entry))
+            .max_by(|a, b| a.0.total_cmp(&b.0))?;
+        if similarity < self.config.threshold {
+            return None;
+        }
+        tracing::info!("Reusing cached results of a similar query (similarity {similarity:.3})");
+        Some(entry.snippets.clone())
+    }
+
+    /// Stores the result of a query, evicting the oldest entry when full.
+    pub fn insert(&self, scope: String, embedding: Vec<f32>, snippets: Vec<Value>) {
+        let mut entries = self.lock();
+        if entries.len() >= self.config.max_entries.max(1) {
+            entries.remove(0);
+        }
+        entries.push(Entry {
+            scope,
+            embedding,
+            snippets,
+            stored: Instant::now(),
+        });
+    }
+
+    async fn embed(&self, query: &str) -> Result<Vec<f32>, String> {
+        let mut body = json!({"input": [query]});
+        if let Some(model) = &self.config.embedding_model {
+            body["model"] = json!(model);
+        }
+        let mut request = self
+            .client
+            .post(&self.config.embedding_url)
+            .timeout(EMBED_TIMEOUT)
+            .json(&body);
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("embedding request failed: {e}"))?;
+        let response: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("invalid embedding response: {e}"))?;
+        let data = &response["data"][0];
+        data.get("embedding")
+            .or_else(|| data.get("values"))
+            .and_then(Value::as_array)
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(Value::as_f64)
+                    .map(|v| v as f32)
+                    .collect()
+            })
+            .ok_or_else(|| "embedding response holds no vector".to_string())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    #[tokio::test]
+    async fn test_similar_queries_reuse_results() {
+        let mut server = Server::new_async().await;
+        for (query, vector) in [
+            ("how do I reset my password", "[1.0, 0.0, 0.1]"),
+            ("password reset steps", "[0.99, 0.0, 0.12]"),
+            ("pricing plans", "[0.0, 1.0, 0.0]"),
+        ] {
+            server
+                .mock("POST", "/embeddings")
+                .match_header("authorization", "Bearer secret")
+                .match_body(Matcher::PartialJson(json!({"input": [query]})))
+                .with_body(format!(r#"{{"data": [{{"embedding": {vector}}}]}}"#))
+                .create_async()
+                .await;
+        }
+        let cache = SemanticCache::new(SemanticCacheConfig {
+            api_key: Some("secret".to_string()),
+            ..SemanticCacheConfig::new(format!("{}/embeddings", server.url()))
+        });
+
+        let first = cache.lookup("kb", "how do I reset my password").await;
+        assert!(first.hit.is_none());
+        cache.insert("kb".to_string(), first.embedding.unwrap(), vec![json!("a")]);
+
+        let paraphrase = cache.lookup("kb", "password reset steps").await;
+        let other_scope = cache.lookup("docs", "password reset steps").await;
+        let unrelated = cache.lookup("kb", "pricing plans").await;
+
+        assert_eq!(paraphrase.hit, Some(vec![json!("a")]));
+        assert!(other_scope.hit.is_none());
+        assert!(unrelated.hit.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_endpoint_bypasses_the_cache() {
+        let cache = SemanticCache::new(SemanticCacheConfig::new("http://127.0.0.1:1/embed"));
+        let lookup = cache.lookup("kb", "q").await;
+        assert!(lookup.hit.is_none());
+        assert!(lookup.embedding.is_none());
+    }
+
+    #[test]
+    fn test_entries_expire() {
+        let cache = SemanticCache::new(SemanticCacheConfig::new("http://unused"));
+        cache.insert("kb".to_string(), vec![1.0, 0.0], vec![json!("a")]);
+        let now = Instant::now();
+        assert!(cache.find("kb", &[1.0, 0.0], now).is_some());
+        assert!(
+            cache
+                .find("kb", &[1.0, 0.0], now + Duration::from_secs(3600))
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,629 @@
+//! JSON-RPC message loop serving a [`Router`] over a byte stream (stdio).
+//!
+//! Unlike `mcp_server::Server`, requests are handled concurrently and the
+//! server can issue its own requests and notifications to the client, which
+//! sampling, progress reporting and cancellation rely on.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use mcp_server::Router;
+use mcp_spec::content::Content;
+use mcp_spec::handler::{PromptError, ResourceError};
+use serde_json::{Value, json};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+
+use crate::peer::{Peer, PeerError, RequestContext};
+use crate::router::is_error_result;
+
+/// Protocol versions this server speaks, newest first.
+const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-03-26", "2024-11-05"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const RESOURCE_NOT_FOUND: i64 = -32002;
+
+/// How often a tool call that takes long reports progress, well within the
+/// idle timeouts of common clients (60 seconds in some).
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+type InFlight = Arc<Mutex<HashMap<String, AbortHandle>>>;
+
+/// Whether an IO error means the client went away rather than a fault.
+fn is_disconnect(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        error.kind(),
+        ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+/// Serves `router` until the client closes `input`. A client disconnecting,
+/// by closing `input` or breaking either pipe, is a normal shutdown.
+pub async fn serve<R, I, O>(router: R, input: I, output: O) -> Result<(), ServerError>
+where
+    R: Router + Clone,
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin + Send + 'static,
+{
+    let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Value>();
+    let peer = Peer::new(outgoing);
+    let writer = tokio::spawn(async move {
+        let mut output = output;
+        while let Some(message) = outgoing_rx.recv().await {
+            let mut line = message.to_string();
+            line.push('\n');
+            let written = match output.write_all(line.as_bytes()).await {
+                Ok(()) => output.flush().await,
+                Err(e) => Err(e),
+            };
+            match written {
+                Ok(()) => {}
+                Err(e) if is_disconnect(&e) => {
+                    tracing::info!("Client stopped reading: {e}");
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok::<(), std::io::Error>(())
+    });
+
+    let in_flight: InFlight = Arc::default();
+    let mut lines = BufReader::new(input).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                tracing::info!("Client closed the connection");
+                break;
+            }
+            Err(e) if is_disconnect(&e) => {
+                tracing::info!("Connection to the client lost: {e}");
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::warn!("Received invalid JSON: {e}");
+                respond(
+                    &peer,
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, e.to_string())),
+                );
+                continue;
+            }
+        };
+        handle_message(&router, &peer, &in_flight, message);
+    }
+
+    for (_, task) in in_flight.lock().unwrap_or_else(|e| e.into_inner()).drain() {
+        task.abort();
+    }
+    peer.close();
+    drop(peer);
+    match writer.await {
+        Ok(result) => result.map_err(ServerError::from),
+        Err(e) => Err(std::io::Error::other(e).into()),
+    }
+}
+
+fn handle_message<R: Router + Clone>(
+    router: &R,
+    peer: &Peer,
+    in_flight: &InFlight,
+    message: Value,
+) {
+    let id = message.get("id").cloned();
+    let method = message["method"].as_str().map(str::to_string);
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    match (id, method) {
+        (Some(id), Some(method)) => {
+            tracing::debug!("Received request: {method}");
+            let key = id.to_string();
+            let router = router.clone();
+            let task_peer = peer.clone();
+            let task_in_flight = in_flight.clone();
+            let task_key = key.clone();
+            // Spawned under the lock, so the task can't finish and remove
+            // itself before it is added.
+            let mut tasks = in_flight.lock().unwrap_or_else(|e| e.into_inner());
+            let task = tokio::spawn(async move {
+                let result = handle_request(&router, &task_peer, &method, params).await;
+                task_in_flight
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&task_key);
+                respond(&task_peer, id, result);
+            });
+            tasks.insert(key, task.abort_handle());
+        }
+        (None, Some(method)) => handle_notification(in_flight, &method, &params),
+        (Some(id), None) => {
+            let result = match message.get("error") {
+                Some(error) => Err(PeerError::Client {
+                    code: error["code"].as_i64().unwrap_or(INTERNAL_ERROR),
+                    message: error["message"].as_str().unwrap_or_default().to_string(),
+                }),
+                None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+            };
+            peer.resolve(&id, result);
+        }
+        (None, None) => {
+            respond(
+                peer,
+                Value::Null,
+                Err(RpcError::new(
+                    INVALID_REQUEST,
+                    "Message has neither id nor method",
+                )),
+            );
+        }
+    }
+}
+
+fn handle_notification(in_flight: &InFlight, method: &str, params: &Value) {
+    match method {
+        "notifications/cancelled" => {
+            let key = params["requestId"].to_string();
+            let task = in_flight
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&key);
+            if let Some(task) = task {
+                tracing::info!("Cancelling request {key}: {}", params["reason"]);
+                task.abort();
+            }
+        }
+        "notifications/initialized" => tracing::debug!("Client initialized"),
+        _ => tracing::debug!("Ignoring notification: {method}"),
+    }
+}
+
+async fn handle_request<R: Router>(
+    router: &R,
+    peer: &Peer,
+    method: &str,
+    params: Value,
+) -> Result<Value, RpcError> {
+    match method {
+        "initialize" => {
+            peer.set_client_capabilities(params["capabilities"].clone());
+            let requested = params["protocolVersion"].as_str().unwrap_or_default();
+            let protocol_version = SUPPORTED_PROTOCOL_VERSIONS
+                .iter()
+                .find(|v| **v == requested)
+                .unwrap_or(&SUPPORTED_PROTOCOL_VERSIONS[0]);
+            Ok(json!({
+                "protocolVersion": protocol_version,
+                "capabilities": router.capabilities(),
+                "serverInfo": {
+                    "name": router.name(),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "instructions": router.instructions(),
+            }))
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": router.list_tools() })),
+        "tools/call" => {
+            let name = params["name"]
+                .as_str()
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing tool name"))?;
+            let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+            // MCP has no standard request timeout; clients may announce theirs
+            // as `_meta.timeoutMs`.
+            let deadline = params["_meta"]["timeoutMs"]
+                .as_u64()
+                .map(|ms| Instant::now() + Duration::from_millis(ms));
+            let context = RequestContext::new(
+                peer.clone(),
+                params["_meta"].get("progressToken").cloned(),
+                deadline,
+            );
+            let call = context.clone().scope(router.call_tool(name, arguments));
+            let result = keep_alive(&context, call).await;
+            Ok(match result {
+                Ok(content) => {
+                    let is_error = is_error_result(&content);
+                    json!({ "content": content, "isError": is_error })
+                }
+                Err(e) => json!({ "content": [Content::text(e.to_string())], "isError": true }),
+            })
+        }
+        "resources/list" => {
+            // In scope so the router knows which client to notify of changes.
+            let context = RequestContext::new(peer.clone(), None, None);
+            let resources = context.scope(async { router.list_resources() }).await;
+            Ok(json!({ "resources": resources }))
+        }
+        "resources/read" => {
+            let uri = params["uri"]
+                .as_str()
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing resource uri"))?;
+            match router.read_resource(uri).await {
+                Ok(text) => {
+                    // The type the resource was listed with; resources read
+                    // without being listed are taken as plain text.
+                    let mime_type = router
+                        .list_resources()
+                        .into_iter()
+                        .find(|resource| resource.uri == uri)
+                        .map_or_else(|| "text/plain".to_string(), |resource| resource.mime_type);
+                    Ok(json!({
+                        "contents": [{ "uri": uri, "mimeType": mime_type, "text": text }]
+                    }))
+                }
+                Err(ResourceError::NotFound(message)) => {
+                    Err(RpcError::new(RESOURCE_NOT_FOUND, message))
+                }
+                Err(e) => Err(RpcError::new(INTERNAL_ERROR, e.to_string())),
+            }
+        }
+        "prompts/list" => Ok(json!({ "prompts": router.list_prompts() })),
+        "prompts/get" => {
+            let name = params["name"]
+                .as_str()
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing prompt name"))?;
+            match router.get_prompt(name).await {
+                Ok(text) => Ok(json!({
+                    "messages": [{ "role": "user", "content": { "type": "text", "text": text } }]
+                })),
+                Err(e @ (PromptError::NotFound(_) | PromptError::InvalidParameters(_))) => {
+                    Err(RpcError::new(INVALID_PARAMS, e.to_string()))
+                }
+                Err(e) => Err(RpcError::new(INTERNAL_ERROR, e.to_string())),
+            }
+        }
+        "logging/setLevel" => Ok(json!({})),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Method not found: {method}"),
+        )),
+    }
+}
+
+/// Runs `call`, sending keep-alive progress while it takes long, if the
+/// client asked for progress.
+async fn keep_alive<F: Future>(context: &RequestContext, call: F) -> F::Output {
+    if context.progress_token.is_none() {
+        return call.await;
+    }
+    let start = Instant::now();
+    let mut ticks = tokio::time::interval_at(
+        tokio::time::Instant::now() + KEEP_ALIVE_INTERVAL,
+        KEEP_ALIVE_INTERVAL,
+    );
+    tokio::pin!(call);
+    loop {
+        tokio::select! {
+            output = &mut call => return output,
+            _ = ticks.tick() => context.keep_alive(start.elapsed()),
+        }
+    }
+}
+
+fn respond(peer: &Peer, id: Value, result: Result<Value, RpcError>) {
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message }
+        }),
+    };
+    if peer.send(response).is_err() {
+        tracing::debug!("Dropping response: client disconnected");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+
+    use mcp_server::router::CapabilitiesBuilder;
+    use mcp_spec::handler::ToolError;
+    use mcp_spec::prompt::Prompt;
+    use mcp_spec::{protocol::ServerCapabilities, resource::Resource, tool::Tool};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+    use super::*;
+
+    /// Echoes its arguments; the `ask` tool forwards its `text` to the
+    /// client via sampling.
+    #[derive(Clone)]
+    struct TestRouter;
+
+    impl Router for TestRouter {
+        fn name(&self) -> String {
+            "test".to_string()
+        }
+
+        fn instructions(&self) -> String {
+            "test instructions".to_string()
+        }
+
+        fn capabilities(&self) -> ServerCapabilities {
+            CapabilitiesBuilder::new().with_tools(false).build()
+        }
+
+        fn list_tools(&self) -> Vec<Tool> {
+            vec![Tool::new("echo", "echo", json!({}))]
+        }
+
+        fn call_tool(
+            &self,
+            tool_name: &str,
+            arguments: Value,
+        ) -> Pin<Box<dyn Future<Output = Result<Vec<Content>, ToolError>> + Send + 'static>>
+        {
+            let tool_name = tool_name.to_string();
+            Box::pin(async move {
+                match tool_name.as_str() {
+                    "echo" => Ok(vec![Content::text(arguments.to_string())]),
+                    "slow" => {
+                        tokio::time::sleep(Duration::from_secs(40)).await;
+                        Ok(vec![Content::text("done")])
+                    }
+                    "ask" => {
+                        let peer = RequestContext::current().unwrap().peer;
+                        let reply = peer
+                            .create_message("system", arguments["text"].as_str().unwrap(), 10)
+                            .await
+                            .map_err(|e| ToolError::ExecutionError(e.to_string()))?;
+                        Ok(vec![Content::text(reply)])
+                    }
+                    _ => Err(ToolError::NotFound(tool_name)),
+                }
+            })
+        }
+
+        fn list_resources(&self) -> Vec<Resource> {
+            let data = Resource::new("test://data", None, None).unwrap();
+            vec![data.with_mime_type("application/json")]
+        }
+
+        fn read_resource(
+            &self,
+            uri: &str,
+        ) -> Pin<Box<dyn Future<Output = Result<String, ResourceError>> + Send + 'static>> {
+            let uri = uri.to_string();
+            Box::pin(async move {
+                match uri.as_str() {
+                    "test://data" | "test://unlisted" => Ok("{}".to_string()),
+                    _ => Err(ResourceError::NotFound(uri)),
+                }
+            })
+        }
+
+        fn list_prompts(&self) -> Vec<Prompt> {
+            vec![]
+        }
+
+        fn get_prompt(
+            &self,
+            prompt_name: &str,
+        ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'static>> {
+            let prompt_name = prompt_name.to_string();
+            Box::pin(async move { Err(PromptError::NotFound(prompt_name)) })
+        }
+    }
+
+    struct Client {
+        input: DuplexStream,
+        output: tokio::io::Lines<BufReader<DuplexStream>>,
+    }
+
+    impl Client {
+        fn start() -> (Self, tokio::task::JoinHandle<Result<(), ServerError>>) {
+            let (input, server_input) = tokio::io::duplex(4096);
+            let (server_output, output) = tokio::io::duplex(4096);
+            let server = tokio::spawn(serve(TestRouter, server_input, server_output));
+            let client = Self {
+                input,
+                output: BufReader::new(output).lines(),
+            };
+            (client, server)
+        }
+
+        async fn send(&mut self, message: Value) {
+            let line = format!("{message}\n");
+            self.input.write_all(line.as_bytes()).await.unwrap();
+        }
+
+        async fn receive(&mut self) -> Value {
+            let line = self.output.next_line().await.unwrap().unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initialize_and_call_tool() {
+        let (mut client, server) = Client::start();
+
+        client
+            .send(json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+                "protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": {"name": "t", "version": "1"}
+            }}))
+            .await;
+        let response = client.receive().await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(response["result"]["instructions"], "test instructions");
+
+        client
+            .send(
+                json!({"jsonrpc": "2.0", "id": "a", "method": "tools/call", "params": {
+                    "name": "echo", "arguments": {"x": 1}
+                }}),
+            )
+            .await;
+        let response = client.receive().await;
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["result"]["content"][0]["text"], r#"{"x":1}"#);
+        assert_eq!(response["result"]["isError"], false);
+
+        client
+            .send(json!({"jsonrpc": "2.0", "id": 2, "method": "unknown"}))
+            .await;
+        assert_eq!(client.receive().await["error"]["code"], METHOD_NOT_FOUND);
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resources_are_read_with_their_mime_type() {
+        let (mut client, server) = Client::start();
+        for (id, uri) in [(1, "test://data"), (2, "test://unlisted")] {
+            client
+                .send(json!({"jsonrpc": "2.0", "id": id, "method": "resources/read", "params": {"uri": uri}}))
+                .await;
+        }
+
+        let mut responses = [client.receive().await, client.receive().await];
+        responses.sort_by_key(|response| response["id"].as_u64());
+        let [listed, unlisted] = responses;
+        assert_eq!(
+            listed["result"]["contents"][0]["mimeType"],
+            "application/json"
+        );
+        assert_eq!(unlisted["result"]["contents"][0]["mimeType"], "text/plain");
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sampling_round_trip() {
+        let (mut client, server) = Client::start();
+        client
+            .send(
+                json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+                    "protocolVersion": "2025-03-26", "capabilities": {"sampling": {}}
+                }}),
+            )
+            .await;
+        client.receive().await;
+
+        client
+            .send(
+                json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": {
+                    "name": "ask", "arguments": {"text": "hello"}
+                }}),
+            )
+            .await;
+        let request = client.receive().await;
+        assert_eq!(request["method"], "sampling/createMessage");
+        assert_eq!(request["params"]["messages"][0]["content"]["text"], "hello");
+
+        client
+            .send(json!({"jsonrpc": "2.0", "id": request["id"], "result": {
+                "role": "assistant", "content": {"type": "text", "text": "world"}, "model": "m"
+            }}))
+            .await;
+        let response = client.receive().await;
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["result"]["content"][0]["text"], "world");
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_calls_send_keep_alive_progress() {
+        let (mut client, server) = Client::start();
+        client
+            .send(
+                json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {
+                    "name": "slow", "arguments": {}, "_meta": {"progressToken": "p"}
+                }}),
+            )
+            .await;
+
+        let first = client.receive().await;
+        let second = client.receive().await;
+        let response = client.receive().await;
+        assert_eq!(first["method"], "notifications/progress");
+        assert_eq!(first["params"]["progressToken"], "p");
+        assert_eq!(first["params"]["progress"], 0.0625);
+        assert_eq!(second["params"]["progress"], 0.125);
+        assert_eq!(response["result"]["content"][0]["text"], "done");
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_finished_requests_leave_no_tasks_behind() {
+        let (outgoing, mut responses) = mpsc::unbounded_channel();
+        let peer = Peer::new(outgoing);
+        let in_flight: InFlight = Arc::default();
+        for id in 0..500 {
+            let ping = json!({"jsonrpc": "2.0", "id": id, "method": "ping"});
+            handle_message(&TestRouter, &peer, &in_flight, ping);
+        }
+
+        for _ in 0..500 {
+            responses.recv().await.unwrap();
+        }
+        assert!(in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_broken_output_pipe_is_a_clean_shutdown() {
+        let (client, server) = Client::start();
+        let Client { mut input, output } = client;
+        drop(output);
+
+        for id in 1..=2 {
+            let ping = json!({"jsonrpc": "2.0", "id": id, "method": "ping"});
+            input
+                .write_all(format!("{ping}\n").as_bytes())
+                .await
+                .unwrap();
+        }
+        drop(input);
+
+        server.await.unwrap().unwrap();
+    }
+}
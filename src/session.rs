@@ -0,0 +1,479 @@
+//! State kept for the lifetime of an MCP session.
+//!
+//! Over stdio a server process serves exactly one session, so the state lives
+//! alongside the router and is shared by its clones.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::pinecone::Message;
+
+/// Parameter defaults applied when a tool call omits them.
+#[derive(Clone, Debug, Default)]
+pub struct SessionDefaults {
+    pub assistant_name: Option<String>,
+    pub top_k: Option<u32>,
+}
+
+/// A conversation with an assistant whose history is kept by the server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub id: String,
+    pub assistant_name: String,
+    pub messages: Vec<Message>,
+
+    #[serde(skip, default = "Instant::now")]
+    last_used: Instant,
+}
+
+#[derive(Debug)]
+pub struct SessionState {
+    pub defaults: SessionDefaults,
+    pub chats: HashMap<String, ChatSession>,
+    /// Files cited in retrieval results, by resource URI, exposed as
+    /// resources for the rest of the session. Added through
+    /// [`Session::cite_files`], which bounds them.
+    pub cited_files: BTreeMap<String, Value>,
+    /// URIs of `cited_files`, least recently cited first.
+    cited_order: VecDeque<String>,
+    /// Snippets of recent retrievals, by connection and request.
+    retrievals: HashMap<String, CachedRetrieval>,
+    /// Snippets of the latest successful retrievals, by connection and
+    /// request, to fall back on while Pinecone is down.
+    fallbacks: HashMap<String, CachedRetrieval>,
+    /// The latest queries retrieved for, oldest first, by connection.
+    recent_queries: HashMap<String, VecDeque<String>>,
+    last_activity: Instant,
+}
+
+#[derive(Debug)]
+struct CachedRetrieval {
+    snippets: Vec<Value>,
+    stored: Instant,
+}
+
+/// Retrievals kept per session; the oldest is dropped beyond this.
+const MAX_CACHED_RETRIEVALS: usize = 256;
+
+/// Cited files kept per session; the least recently cited is dropped beyond
+/// this.
+const MAX_CITED_FILES: usize = 512;
+
+/// Stores a retrieval, first dropping the oldest if `retrievals` is full.
+fn insert_bounded(
+    retrievals: &mut HashMap<String, CachedRetrieval>,
+    key: String,
+    snippets: Vec<Value>,
+) {
+    if retrievals.len() >= MAX_CACHED_RETRIEVALS && !retrievals.contains_key(&key) {
+        let oldest = retrievals
+            .iter()
+            .min_by_key(|(_, cached)| cached.stored)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            retrievals.remove(&oldest);
+        }
+    }
+    retrievals.insert(
+        key,
+        CachedRetrieval {
+            snippets,
+            stored: Instant::now(),
+        },
+    );
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            defaults: SessionDefaults::default(),
+            chats: HashMap::new(),
+            cited_files: BTreeMap::new(),
+            cited_order: VecDeque::new(),
+            retrievals: HashMap::new(),
+            fallbacks: HashMap::new(),
+            recent_queries: HashMap::new(),
+            last_activity: Instant::now(),
+        }
+    }
+}
+
+impl SessionState {
+    /// Drops state that has been idle for longer than `ttl`: the defaults
+    /// once the whole session was idle, chats individually.
+    fn evict_idle(&mut self, ttl: Duration) {
+        let now = Instant::now();
+        if now.duration_since(self.last_activity) > ttl {
+            tracing::info!("Session idle for more than {ttl:?}, resetting defaults");
+            self.defaults = SessionDefaults::default();
+            self.cited_files.clear();
+            self.cited_order.clear();
+            self.retrievals.clear();
+            self.fallbacks.clear();
+            self.recent_queries.clear();
+        }
+        self.last_activity = now;
+
+        let before = self.chats.len();
+        self.chats
+            .retain(|_, chat| now.duration_since(chat.last_used) <= ttl);
+        if self.chats.len() < before {
+            tracing::info!("Evicted {} idle chat sessions", before - self.chats.len());
+        }
+    }
+}
+
+/// What [`Session::reset`] discarded.
+#[derive(Debug)]
+pub struct ResetSummary {
+    pub had_defaults: bool,
+    pub chats: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+    chat_dir: Option<PathBuf>,
+    idle_ttl: Option<Duration>,
+    /// Held while chats change on disk, so a turn can't be lost to another
+    /// or bring back a chat that has just ended.
+    chat_writes: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Session {
+    /// Creates the session state; chat histories are additionally persisted
+    /// to `chat_dir` when given, so they survive server restarts. State idle
+    /// for longer than `idle_ttl` is dropped from memory; persisted chats
+    /// are reloaded on their next use.
+    pub fn new(chat_dir: Option<PathBuf>, idle_ttl: Option<Duration>) -> Self {
+        Self {
+            state: Arc::default(),
+            chat_dir,
+            idle_ttl,
+            chat_writes: Arc::default(),
+        }
+    }
+
+    /// Locks the state, first evicting whatever has been idle too long.
+    pub fn lock(&self) -> MutexGuard<'_, SessionState> {
+        // State is plain data, so a panic while holding the lock can't leave
+        // it logically inconsistent; keep serving rather than propagating.
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(ttl) = self.idle_ttl {
+            state.evict_idle(ttl);
+        }
+        state
+    }
+
+    pub fn defaults(&self) -> SessionDefaults {
+        self.lock().defaults.clone()
+    }
+
+    /// Exposes the files cited in a retrieval as resources.
+    pub fn cite_files(&self, files: Vec<(String, Value)>) {
+        let mut state = self.lock();
+        for (uri, file) in files {
+            state.cited_order.retain(|cited| *cited != uri);
+            state.cited_order.push_back(uri.clone());
+            state.cited_files.insert(uri, file);
+        }
+        while state.cited_order.len() > MAX_CITED_FILES {
+            if let Some(oldest) = state.cited_order.pop_front() {
+                state.cited_files.remove(&oldest);
+            }
+        }
+    }
+
+    /// The snippets of an earlier retrieval stored under `key` less than
+    /// `ttl` ago.
+    pub fn cached_retrieval(&self, key: &str, ttl: Duration) -> Option<Vec<Value>> {
+        let mut state = self.lock();
+        match state.retrievals.get(key) {
+            Some(cached) if cached.stored.elapsed() < ttl => Some(cached.snippets.clone()),
+            Some(_) => {
+                state.retrievals.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Stores the snippets of a retrieval under `key`, which should name the
+    /// connection as well as the request so that clients sharing the server
+    /// never see each other's results.
+    pub fn cache_retrieval(&self, key: String, snippets: Vec<Value>) {
+        insert_bounded(&mut self.lock().retrievals, key, snippets);
+    }
+
+    /// Keeps the snippets of a successful retrieval to serve, marked as
+    /// stale, if the same request later fails because Pinecone is down.
+    /// Keyed like [`Session::cache_retrieval`].
+    pub fn keep_fallback(&self, key: String, snippets: Vec<Value>) {
+        insert_bounded(&mut self.lock().fallbacks, key, snippets);
+    }
+
+    /// The snippets kept under `key` less than `max_age` ago, and their age.
+    pub fn fallback(&self, key: &str, max_age: Duration) -> Option<(Vec<Value>, Duration)> {
+        let state = self.lock();
+        let kept = state.fallbacks.get(key)?;
+        let age = kept.stored.elapsed();
+        (age < max_age).then(|| (kept.snippets.clone(), age))
+    }
+
+    /// The latest queries of `connection`, oldest first.
+    pub fn recent_queries(&self, connection: &str) -> Vec<String> {
+        self.lock()
+            .recent_queries
+            .get(connection)
+            .map(|queries| queries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Remembers a query of `connection`, keeping its `max` latest queries.
+    pub fn remember_query(&self, connection: String, query: String, max: usize) {
+        let mut state = self.lock();
+        let queries = state.recent_queries.entry(connection).or_default();
+        queries.retain(|existing| *existing != query);
+        queries.push_back(query);
+        while queries.len() > max {
+            queries.pop_front();
+        }
+    }
+
+    pub async fn start_chat(&self, assistant_name: String) -> ChatSession {
+        let chat = ChatSession {
+            id: new_chat_id(),
+            assistant_name,
+            messages: Vec::new(),
+            last_used: Instant::now(),
+        };
+        let _writing = self.chat_writes.lock().await;
+        self.lock().chats.insert(chat.id.clone(), chat.clone());
+        self.persist_chat(&chat).await;
+        chat
+    }
+
+    /// Looks up a chat in memory, falling back to the persisted copy.
+    pub async fn chat(&self, id: &str) -> Option<ChatSession> {
+        if let Some(chat) = self.lock().chats.get_mut(id) {
+            chat.last_used = Instant::now();
+            return Some(chat.clone());
+        }
+        let path = self.chat_path(id)?;
+        let data = tokio::fs::read(&path).await.ok()?;
+        match serde_json::from_slice::<ChatSession>(&data) {
+            Ok(chat) => {
+                tracing::debug!("Loaded chat session {id} from {}", path.display());
+                self.lock().chats.insert(chat.id.clone(), chat.clone());
+                Some(chat)
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Ignoring unreadable chat session file {}: {e}",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Appends messages to a chat's history. Returns `false`, changing
+    /// nothing, if the chat has ended meanwhile.
+    pub async fn append_to_chat(&self, id: &str, messages: Vec<Message>) -> bool {
+        let _writing = self.chat_writes.lock().await;
+        // Reloads a chat persisted but evicted from memory.
+        if self.chat(id).await.is_none() {
+            return false;
+        }
+        let chat = {
+            let mut state = self.lock();
+            let Some(chat) = state.chats.get_mut(id) else {
+                return false;
+            };
+            chat.messages.extend(messages);
+            chat.last_used = Instant::now();
+            chat.clone()
+        };
+        self.persist_chat(&chat).await;
+        true
+    }
+
+    async fn persist_chat(&self, chat: &ChatSession) {
+        let path = self.chat_path(&chat.id);
+        let data = path.as_ref().map(|_| serde_json::to_vec(chat));
+        if let (Some(path), Some(Ok(data))) = (path, data) {
+            let dir_created = match path.parent() {
+                Some(dir) => tokio::fs::create_dir_all(dir).await,
+                None => Ok(()),
+            };
+            if let Err(e) = dir_created {
+                tracing::warn!("Failed to create chat session directory: {e}");
+            }
+            if let Err(e) = tokio::fs::write(&path, data).await {
+                tracing::warn!("Failed to persist chat session to {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// Forgets a chat; returns whether it existed.
+    pub async fn end_chat(&self, id: &str) -> bool {
+        let _writing = self.chat_writes.lock().await;
+        let in_memory = self.lock().chats.remove(id).is_some();
+        let on_disk = match self.chat_path(id) {
+            Some(path) => tokio::fs::remove_file(path).await.is_ok(),
+            None => false,
+        };
+        in_memory || on_disk
+    }
+
+    /// Discards the defaults, cited files, cached retrievals and all chats of
+    /// the session, including their persisted histories.
+    pub async fn reset(&self) -> ResetSummary {
+        let _writing = self.chat_writes.lock().await;
+        let (defaults, chats) = {
+            let mut state = self.lock();
+            state.cited_files.clear();
+            state.cited_order.clear();
+            state.retrievals.clear();
+            state.fallbacks.clear();
+            state.recent_queries.clear();
+            (
+                std::mem::take(&mut state.defaults),
+                std::mem::take(&mut state.chats),
+            )
+        };
+        for id in chats.keys() {
+            if let Some(path) = self.chat_path(id) {
+                // The file is absent if persisting failed; nothing to undo then.
+                let _ = tokio::fs::remove_file(path).await;
+            }
+        }
+        ResetSummary {
+            had_defaults: defaults.assistant_name.is_some() || defaults.top_k.is_some(),
+            chats: chats.len(),
+        }
+    }
+
+    /// Path of a chat's persisted history. Ids are generated by the server,
+    /// so anything outside their alphabet is rejected to keep lookups inside
+    /// the chat directory.
+    fn chat_path(&self, id: &str) -> Option<PathBuf> {
+        let dir = self.chat_dir.as_ref()?;
+        let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        valid.then(|| dir.join(format!("{id}.json")))
+    }
+}
+
+/// A chat id no client can guess, as knowing one is enough to continue the
+/// chat.
+fn new_chat_id() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("No source of randomness");
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("chat-{hex}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_idle_state_is_evicted() {
+        let session = Session::new(None, Some(Duration::from_millis(50)));
+        session.lock().defaults.top_k = Some(3);
+        session.cite_files(vec![("file://a".to_string(), Value::Null)]);
+        let chat = session.start_chat("kb".to_string()).await;
+        assert!(session.chat(&chat.id).await.is_some());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(session.chat(&chat.id).await.is_none());
+        assert_eq!(session.defaults().top_k, None);
+        assert!(session.lock().cited_files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reset_discards_everything() {
+        let session = Session::new(None, None);
+        session.lock().defaults.assistant_name = Some("kb".to_string());
+        session.cite_files(vec![("file://a".to_string(), Value::Null)]);
+        session.start_chat("kb".to_string()).await;
+        session.start_chat("kb".to_string()).await;
+
+        let summary = session.reset().await;
+        assert!(summary.had_defaults);
+        assert_eq!(summary.chats, 2);
+        assert!(session.lock().chats.is_empty());
+        assert!(session.lock().cited_files.is_empty());
+        assert!(session.defaults().assistant_name.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_turns_are_kept_and_ended_chats_stay_ended() {
+        let session = Session::new(None, None);
+        let chat = session.start_chat("kb".to_string()).await;
+        let (first, second) = tokio::join!(
+            session.append_to_chat(&chat.id, vec![Message::user("a")]),
+            session.append_to_chat(&chat.id, vec![Message::user("b")]),
+        );
+        assert!(first && second);
+        assert_eq!(session.chat(&chat.id).await.unwrap().messages.len(), 2);
+
+        assert!(session.end_chat(&chat.id).await);
+        assert!(
+            !session
+                .append_to_chat(&chat.id, vec![Message::user("c")])
+                .await
+        );
+        assert!(session.chat(&chat.id).await.is_none());
+        assert_ne!(session.start_chat("kb".to_string()).await.id, chat.id);
+    }
+
+    #[test]
+    fn test_cited_files_are_bounded() {
+        let session = Session::new(None, None);
+        let uri = |i: usize| format!("file://{i}");
+        session.cite_files(vec![(uri(0), Value::Null)]);
+        for i in 1..=MAX_CITED_FILES {
+            session.cite_files(vec![(uri(i), Value::Null), (uri(0), Value::Null)]);
+        }
+
+        let state = session.lock();
+        assert_eq!(state.cited_files.len(), MAX_CITED_FILES);
+        assert!(state.cited_files.contains_key(&uri(0)));
+        assert!(!state.cited_files.contains_key(&uri(1)));
+    }
+
+    #[test]
+    fn test_recent_queries_roll_over() {
+        let session = Session::new(None, None);
+        for query in ["a", "b", "a", "c"] {
+            session.remember_query("1".to_string(), query.to_string(), 2);
+        }
+        assert_eq!(session.recent_queries("1"), ["a", "c"]);
+        assert!(session.recent_queries("2").is_empty());
+    }
+
+    #[test]
+    fn test_cached_retrievals_expire() {
+        let session = Session::new(None, None);
+        session.cache_retrieval("1\nkb\nq".to_string(), vec![Value::from("a")]);
+
+        let ttl = Duration::from_secs(60);
+        assert_eq!(
+            session.cached_retrieval("1\nkb\nq", ttl),
+            Some(vec![Value::from("a")])
+        );
+        assert!(session.cached_retrieval("2\nkb\nq", ttl).is_none());
+        assert!(
+            session
+                .cached_retrieval("1\nkb\nq", Duration::ZERO)
+                .is_none()
+        );
+        assert!(session.cached_retrieval("1\nkb\nq", ttl).is_none());
+    }
+}
@@ -0,0 +1,175 @@
+//! Snapshots of retrieval calls: the query, its parameters and the snippets
+//! returned, kept so the evidence behind an answer can be looked up later.
+//! The oldest are deleted as new ones are saved, beyond a count or an age.
+
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// URI prefix of snapshot resources.
+pub const SNAPSHOT_RESOURCE_PREFIX: &str = "pinecone://snapshots/";
+
+/// Snapshots listed as resources, newest first; older ones stay readable.
+const MAX_LISTED_SNAPSHOTS: usize = 100;
+
+#[derive(Clone, Debug)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    max_count: Option<usize>,
+    max_age: Option<Duration>,
+}
+
+impl SnapshotStore {
+    /// A store keeping every snapshot.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            max_count: None,
+            max_age: None,
+        }
+    }
+
+    /// Keeps at most `max_count` snapshots, none older than `max_age`.
+    pub fn with_retention(mut self, max_count: Option<usize>, max_age: Option<Duration>) -> Self {
+        self.max_count = max_count;
+        self.max_age = max_age;
+        self
+    }
+
+    /// Persists a snapshot and returns its id. Ids start with the creation
+    /// time, so they sort chronologically, followed by a content hash.
+    pub async fn save(&self, mut snapshot: Value) -> std::io::Result<String> {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        let hash = format!("{:x}", Sha256::digest(snapshot.to_string().as_bytes()));
+        let id = format!("{created_at}-{}", &hash[..16]);
+        snapshot["id"] = id.clone().into();
+        snapshot["created_at_ms"] = (created_at as u64).into();
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let data = serde_json::to_vec_pretty(&snapshot).map_err(std::io::Error::other)?;
+        tokio::fs::write(self.path(&id), data).await?;
+        self.prune(created_at).await;
+        Ok(id)
+    }
+
+    /// Deletes the snapshots beyond the retention limits, given the time in
+    /// milliseconds.
+    async fn prune(&self, now_ms: u128) {
+        if self.max_count.is_none() && self.max_age.is_none() {
+            return;
+        }
+        let mut ids = self.ids();
+        ids.sort_unstable_by(|a, b| b.cmp(a));
+        let oldest_kept = self
+            .max_age
+            .map_or(0, |age| now_ms.saturating_sub(age.as_millis()));
+        let expired = ids.iter().enumerate().filter(|(index, id)| {
+            self.max_count.is_some_and(|count| *index >= count)
+                || created_at_ms(id).is_some_and(|created| created < oldest_kept)
+        });
+        for (_, id) in expired {
+            if let Err(e) = tokio::fs::remove_file(self.path(id)).await {
+                tracing::warn!("Failed to delete expired snapshot {id}: {e}");
+            }
+        }
+    }
+
+    /// Reads the snapshot with the given id, as saved.
+    pub async fn load(&self, id: &str) -> std::io::Result<String> {
+        if !is_valid_id(id) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no snapshot {id}"),
+            ));
+        }
+        tokio::fs::read_to_string(self.path(id)).await
+    }
+
+    /// Ids of the most recent snapshots, newest first.
+    pub fn recent(&self) -> Vec<String> {
+        let mut ids = self.ids();
+        // Same-width millisecond timestamps sort lexically.
+        ids.sort_unstable_by(|a, b| b.cmp(a));
+        ids.truncate(MAX_LISTED_SNAPSHOTS);
+        ids
+    }
+
+    /// Ids of all saved snapshots, in no particular order.
+    fn ids(&self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let id = name.strip_suffix(".json")?;
+                is_valid_id(id).then(|| id.to_string())
+            })
+            .collect()
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+}
+
+/// When the snapshot with `id` was created, in milliseconds.
+fn created_at_ms(id: &str) -> Option<u128> {
+    id.split_once('-')?.0.parse().ok()
+}
+
+/// Whether `id` has the shape of a snapshot id, so it can't name a path
+/// outside the snapshot directory.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_saved_snapshots_can_be_listed_and_loaded() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let store = SnapshotStore::new(dir.to_path_buf());
+
+        let first = store.save(json!({"query": "a"})).await.unwrap();
+        let second = store.save(json!({"query": "b"})).await.unwrap();
+        let loaded: Value = serde_json::from_str(&store.load(&first).await.unwrap()).unwrap();
+        let recent = store.recent();
+        let escape = store.load("../secret").await;
+
+        assert_ne!(first, second);
+        assert_eq!(loaded["query"], "a");
+        assert_eq!(loaded["id"], first.as_str());
+        assert_eq!(recent.len(), 2);
+        assert!(escape.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_old_and_excess_snapshots_are_deleted() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        std::fs::write(dir.join("1000-0123456789abcdef.json"), "{}").unwrap();
+        let store = SnapshotStore::new(dir.to_path_buf())
+            .with_retention(Some(2), Some(Duration::from_secs(24 * 60 * 60)));
+
+        store.save(json!({"query": "a"})).await.unwrap();
+        let recent_after_one = store.recent();
+        store.save(json!({"query": "b"})).await.unwrap();
+        store.save(json!({"query": "c"})).await.unwrap();
+        let recent = store.recent();
+
+        // The one from 1970 went by age, then the oldest beyond two by count.
+        assert_eq!(recent_after_one.len(), 1);
+        assert_eq!(recent.len(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+//! Spool of uploads that failed because the network was unavailable, so
+//! ingestion started on a laptop that goes offline finishes once it is back.
+//!
+//! Each spooled upload is a directory holding a copy of the file under its
+//! own name, as the assistant names files after them, and an `entry.json`
+//! describing the upload. The copy makes the spool independent of the
+//! original file, which may be a staged one gone once the call returns.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::{Mutex, MutexGuard};
+
+const ENTRY_FILE: &str = "entry.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpoolStatus {
+    /// Waiting for the network to come back.
+    Queued,
+    /// Reached Pinecone but was rejected; not retried in the background.
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpooledUpload {
+    pub id: String,
+    pub assistant_name: String,
+    pub file_name: String,
+    /// The file the upload was made from, if it was one on disk.
+    #[serde(default)]
+    pub source: Option<PathBuf>,
+    #[serde(default)]
+    pub metadata: Option<Map<String, Value>>,
+    pub status: SpoolStatus,
+    /// Uploads tried so far, including the one that spooled it.
+    pub attempts: u32,
+    pub queued_at: u64,
+    pub updated_at: u64,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// The spool directory. Clones share the state of retries.
+#[derive(Clone, Debug)]
+pub struct UploadSpool {
+    dir: PathBuf,
+    /// Held while spooled uploads are retried, so none is sent twice at once.
+    passes: Arc<Mutex<()>>,
+    /// Whether a background task is retrying the spooled uploads.
+    retrying: Arc<AtomicBool>,
+}
+
+impl UploadSpool {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            passes: Arc::default(),
+            retrying: Arc::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Copies `path` into the spool as an upload to retry.
+    pub async fn enqueue(
+        &self,
+        assistant_name: &str,
+        path: &Path,
+        source: Option<&Path>,
+        metadata: Option<&Map<String, Value>>,
+        error: &str,
+    ) -> std::io::Result<SpooledUpload> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} has no file name", path.display()),
+                )
+            })?
+            .to_string_lossy()
+            .into_owned();
+        let queued_at = now();
+        let id = format!(
+            "{queued_at}-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let entry_dir = self.dir.join(&id);
+        tokio::fs::create_dir_all(&entry_dir).await?;
+        let upload = SpooledUpload {
+            id,
+            assistant_name: assistant_name.to_string(),
+            file_name,
+            source: source.map(Path::to_path_buf),
+            metadata: metadata.cloned(),
+            status: SpoolStatus::Queued,
+            attempts: 1,
+            queued_at,
+            updated_at: queued_at,
+            error: Some(error.to_string()),
+        };
+        let written = async {
+            tokio::fs::copy(path, entry_dir.join(&upload.file_name)).await?;
+            self.save(&upload).await
+        };
+        if let Err(e) = written.await {
+            let _ = tokio::fs::remove_dir_all(&entry_dir).await;
+            return Err(e);
+        }
+        Ok(upload)
+    }
+
+    /// The spooled uploads, oldest first, optionally to one assistant only.
+    pub async fn entries(&self, assistant_name: Option<&str>) -> Vec<SpooledUpload> {
+        let mut entries = Vec::new();
+        let mut dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return entries,
+            Err(e) => {
+                tracing::warn!("Failed to read upload spool {}: {e}", self.dir.display());
+                return entries;
+            }
+        };
+        while let Ok(Some(child)) = dir.next_entry().await {
+            let path = child.path().join(ENTRY_FILE);
+            let upload: SpooledUpload = match tokio::fs::read(&path).await {
+                Ok(data) => match serde_json::from_slice(&data) {
+                    Ok(upload) => upload,
+                    Err(e) => {
+                        tracing::warn!(
+                            "Ignoring unreadable spooled upload {}: {e}",
+                            path.display()
+                        );
+                        continue;
+                    }
+                },
+                // Not an entry, or one still being written.
+                Err(_) => continue,
+            };
+            if assistant_name.is_none_or(|name| upload.assistant_name == name) {
+                entries.push(upload);
+            }
+        }
+        entries.sort_by(|a, b| (a.queued_at, &a.id).cmp(&(b.queued_at, &b.id)));
+        entries
+    }
+
+    /// The spooled copy of the upload's file.
+    pub fn file_path(&self, upload: &SpooledUpload) -> PathBuf {
+        self.dir.join(&upload.id).join(&upload.file_name)
+    }
+
+    /// Records the outcome of a retry that didn't upload the file.
+    pub async fn update(&self, upload: &mut SpooledUpload, status: SpoolStatus, error: &str) {
+        upload.status = status;
+        upload.attempts += 1;
+        upload.updated_at = now();
+        upload.error = Some(error.to_string());
+        if let Err(e) = self.save(upload).await {
+            tracing::warn!("Failed to update spooled upload {}: {e}", upload.id);
+        }
+    }
+
+    /// Removes an upload from the spool. Returns whether it was there.
+    pub async fn remove(&self, id: &str) -> bool {
+        // Ids are plain directory names; anything else isn't an entry.
+        if Path::new(id).file_name() != Some(id.as_ref()) {
+            return false;
+        }
+        match tokio::fs::remove_dir_all(self.dir.join(id)).await {
+            Ok(()) => true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => {
+                tracing::warn!("Failed to remove spooled upload {id}: {e}");
+                false
+            }
+        }
+    }
+
+    /// Waits until no other retry of the spooled uploads is running.
+    pub async fn pass(&self) -> MutexGuard<'_, ()> {
+        self.passes.lock().await
+    }
+
+    /// Marks background retries as started. Returns `false` if they already
+    /// were.
+    pub fn start_retrying(&self) -> bool {
+        !self.retrying.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn stop_retrying(&self) {
+        self.retrying.store(false, Ordering::SeqCst);
+    }
+
+    async fn save(&self, upload: &SpooledUpload) -> std::io::Result<()> {
+        let data = serde_json::to_vec_pretty(upload)?;
+        let entry_dir = self.dir.join(&upload.id);
+        // Written aside and renamed, so a crash never leaves half an entry.
+        let temp = entry_dir.join(format!("{ENTRY_FILE}.tmp"));
+        tokio::fs::write(&temp, data).await?;
+        tokio::fs::rename(&temp, entry_dir.join(ENTRY_FILE)).await
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[tokio::test]
+    async fn test_spooled_uploads_survive_a_restart() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let source = dir.join("notes.md");
+        tokio::fs::write(&source, "# Notes").await.unwrap();
+        let spool = UploadSpool::new(dir.join("spool"));
+
+        let mut upload = spool
+            .enqueue("docs", &source, Some(&source), None, "Cannot connect")
+            .await
+            .unwrap();
+        tokio::fs::remove_file(&source).await.unwrap();
+        spool
+            .update(&mut upload, SpoolStatus::Queued, "Cannot connect")
+            .await;
+
+        let reopened = UploadSpool::new(dir.join("spool"));
+        let entries = reopened.entries(Some("docs")).await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].attempts, 2);
+        assert_eq!(entries[0].source.as_deref(), Some(source.as_path()));
+        let copy = tokio::fs::read_to_string(reopened.file_path(&entries[0]))
+            .await
+            .unwrap();
+        assert_eq!(copy, "# Notes");
+        assert!(reopened.entries(Some("other")).await.is_empty());
+
+        assert!(reopened.remove(&upload.id).await);
+        assert!(!reopened.remove("..").await);
+        assert!(reopened.entries(None).await.is_empty());
+    }
+}
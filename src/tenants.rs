@@ -0,0 +1,105 @@
+//! State of calls passing their own API key, kept apart per key.
+//!
+//! The server's session, paged results and upload journal belong to its own
+//! key. Calls with another key get stores of their own, found by a hash of
+//! the key, so no key can page through, continue or resume what was started
+//! with another one.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+use crate::pagination::ResultPages;
+use crate::session::Session;
+use crate::uploads::UploadJournal;
+
+/// Keys whose state is kept at a time; the least recently used is dropped.
+const MAX_TENANTS: usize = 256;
+
+/// The stores of one API key.
+#[derive(Clone)]
+pub struct TenantState {
+    pub session: Session,
+    pub pages: ResultPages,
+    pub uploads: UploadJournal,
+}
+
+struct Tenant {
+    state: TenantState,
+    last_used: Instant,
+}
+
+#[derive(Clone, Default)]
+pub struct Tenants {
+    tenants: Arc<Mutex<HashMap<String, Tenant>>>,
+    chat_dir: Option<PathBuf>,
+    idle_ttl: Option<Duration>,
+    upload_state_dir: Option<PathBuf>,
+}
+
+impl Tenants {
+    /// Persists each key's chats and uploads below `chat_dir` and
+    /// `upload_state_dir`, in a directory named after the key's hash.
+    pub fn new(
+        chat_dir: Option<PathBuf>,
+        idle_ttl: Option<Duration>,
+        upload_state_dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            tenants: Arc::default(),
+            chat_dir,
+            idle_ttl,
+            upload_state_dir,
+        }
+    }
+
+    /// The stores of `api_key`, created on its first use.
+    pub fn state(&self, api_key: &str) -> TenantState {
+        let id = format!("{:x}", Sha256::digest(api_key.as_bytes()));
+        let mut tenants = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(tenant) = tenants.get_mut(&id) {
+            tenant.last_used = Instant::now();
+            return tenant.state.clone();
+        }
+        if tenants.len() >= MAX_TENANTS {
+            let oldest = tenants
+                .iter()
+                .min_by_key(|(_, tenant)| tenant.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                tenants.remove(&oldest);
+            }
+        }
+        let dir = |root: &Option<PathBuf>| root.as_ref().map(|root| root.join("tenants").join(&id));
+        let state = TenantState {
+            session: Session::new(dir(&self.chat_dir), self.idle_ttl),
+            pages: ResultPages::default(),
+            uploads: UploadJournal::new(dir(&self.upload_state_dir)),
+        };
+        tenants.insert(
+            id,
+            Tenant {
+                state: state.clone(),
+                last_used: Instant::now(),
+            },
+        );
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_key_gets_its_own_stores() {
+        let tenants = Tenants::new(None, None, None);
+        tenants.state("key-a").session.lock().defaults.top_k = Some(3);
+
+        assert_eq!(tenants.state("key-a").session.defaults().top_k, Some(3));
+        assert_eq!(tenants.state("key-b").session.defaults().top_k, None);
+    }
+}
@@ -0,0 +1,9 @@
+//! Fixtures shared by the tests of several modules.
+
+use tempfile::TempDir;
+
+/// A fresh directory of its own for a test, deleted with everything in it
+/// when the guard is dropped, even if the test fails first.
+pub(crate) fn temp_dir() -> TempDir {
+    tempfile::tempdir().expect("Cannot create a temporary directory")
+}
@@ -0,0 +1,593 @@
+//! Journal of file uploads, so interrupted ones can be found and resumed.
+//!
+//! The files API takes a file in a single request, so an interrupted transfer
+//! can't continue mid-file; what survives a network failure or a restart is
+//! the record of which uploads never completed, and resuming sends those
+//! files again.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::pinecone::mime_type;
+use tokio::io::AsyncReadExt;
+
+const JOURNAL_FILE: &str = "uploads.json";
+
+/// File metadata field holding the SHA-256 of the uploaded content.
+pub const METADATA_SHA256: &str = "content_sha256";
+
+/// File metadata field holding when the file was uploaded, in Unix seconds,
+/// unless the uploader set it, e.g. to the document's own date, as Unix
+/// seconds, `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SSZ`; dates are stored as Unix
+/// seconds too, for date filters to compare.
+pub const METADATA_CREATED_AT: &str = "created_at";
+
+/// File metadata field holding the lowercase extension of the uploaded file,
+/// such as `pdf`.
+pub const METADATA_FILE_TYPE: &str = "file_type";
+
+/// File metadata field holding a list of tags, as set by the uploader.
+pub const METADATA_TAGS: &str = "tags";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadStatus {
+    /// Being uploaded by this process.
+    InProgress,
+    /// Started but never finished, e.g. because the server was restarted.
+    Interrupted,
+    Failed,
+    /// Finished; only found in journals of older versions, which kept
+    /// completed uploads.
+    Completed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UploadRecord {
+    pub assistant_name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time of the file when the upload started, in seconds
+    /// since the epoch; a changed file is not resumed.
+    pub modified: u64,
+    pub status: UploadStatus,
+    #[serde(default)]
+    pub file_id: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub updated_at: u64,
+    /// Metadata given with the upload, re-sent when it is resumed.
+    #[serde(default)]
+    pub metadata: Option<Map<String, Value>>,
+}
+
+impl UploadRecord {
+    /// Whether the file on disk is still the one the upload started with.
+    pub async fn file_unchanged(&self) -> bool {
+        match file_stamp(&self.path).await {
+            Ok((size, modified)) => size == self.size && modified == self.modified,
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct JournalState {
+    records: BTreeMap<String, UploadRecord>,
+    /// Keys of uploads running in this process.
+    active: HashSet<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UploadJournal {
+    state: Arc<Mutex<JournalState>>,
+    path: Option<PathBuf>,
+    /// Held while the journal is written, so writes of concurrent uploads
+    /// neither share the temporary file nor land out of order.
+    writes: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl UploadJournal {
+    /// Opens the journal persisted in `dir`, if given; otherwise uploads are
+    /// only tracked for the lifetime of the process.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        let path = dir.map(|dir| dir.join(JOURNAL_FILE));
+        let mut records: BTreeMap<String, UploadRecord> = BTreeMap::new();
+        if let Some(path) = &path {
+            match std::fs::read(path) {
+                Ok(data) => match serde_json::from_slice(&data) {
+                    Ok(loaded) => records = loaded,
+                    Err(e) => {
+                        tracing::warn!("Ignoring unreadable upload journal {}: {e}", path.display())
+                    }
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("Failed to read upload journal {}: {e}", path.display()),
+            }
+        }
+        // Journals written before completed uploads were pruned kept them.
+        records.retain(|_, record| record.status != UploadStatus::Completed);
+        // Nothing is running yet, so whatever was in progress was cut off.
+        for record in records.values_mut() {
+            if record.status == UploadStatus::InProgress {
+                record.status = UploadStatus::Interrupted;
+            }
+        }
+        let interrupted = records
+            .values()
+            .filter(|record| record.status == UploadStatus::Interrupted)
+            .count();
+        if interrupted > 0 {
+            tracing::info!("Found {interrupted} interrupted uploads; use resume_uploads to retry");
+        }
+        Self {
+            state: Arc::new(Mutex::new(JournalState {
+                records,
+                active: HashSet::new(),
+            })),
+            path,
+            writes: Arc::default(),
+        }
+    }
+
+    /// Records the start of an upload. Returns a handle to record its outcome
+    /// with, or `None` if the same upload is already running.
+    pub async fn start(
+        &self,
+        assistant_name: &str,
+        path: &Path,
+        metadata: Option<&Map<String, Value>>,
+    ) -> std::io::Result<Option<ActiveUpload>> {
+        let (size, modified) = file_stamp(path).await?;
+        let key = format!("{assistant_name}:{}", path.display());
+        {
+            let mut state = self.lock();
+            if !state.active.insert(key.clone()) {
+                return Ok(None);
+            }
+            let attempts = state.records.get(&key).map_or(0, |record| record.attempts);
+            state.records.insert(
+                key.clone(),
+                UploadRecord {
+                    assistant_name: assistant_name.to_string(),
+                    path: path.to_path_buf(),
+                    size,
+                    modified,
+                    status: UploadStatus::InProgress,
+                    file_id: None,
+                    error: None,
+                    attempts: attempts + 1,
+                    updated_at: now(),
+                    metadata: metadata.cloned(),
+                },
+            );
+        }
+        self.persist().await;
+        Ok(Some(ActiveUpload {
+            journal: self.clone(),
+            key,
+        }))
+    }
+
+    /// Uploads that did not complete, optionally for one assistant only.
+    pub fn unfinished(&self, assistant_name: Option<&str>) -> Vec<UploadRecord> {
+        self.lock()
+            .records
+            .values()
+            .filter(|record| assistant_name.is_none_or(|name| record.assistant_name == name))
+            .cloned()
+            .collect()
+    }
+
+    fn update(&self, key: &str, update: impl FnOnce(&mut UploadRecord)) {
+        if let Some(record) = self.lock().records.get_mut(key) {
+            record.updated_at = now();
+            update(record);
+        }
+    }
+
+    async fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        // Serialized under the write lock, so the last write has the latest
+        // state.
+        let _writing = self.writes.lock().await;
+        let data = match serde_json::to_vec_pretty(&self.lock().records) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Failed to serialize upload journal: {e}");
+                return;
+            }
+        };
+        if let Some(dir) = path.parent() {
+            if let Err(e) = tokio::fs::create_dir_all(dir).await {
+                tracing::warn!("Failed to create upload journal directory: {e}");
+            }
+        }
+        // Write-then-rename so a crash mid-write can't corrupt the journal.
+        let tmp = path.with_extension("json.tmp");
+        let written = match tokio::fs::write(&tmp, data).await {
+            Ok(()) => tokio::fs::rename(&tmp, path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            tracing::warn!(
+                "Failed to persist upload journal to {}: {e}",
+                path.display()
+            );
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JournalState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// An upload in progress. Dropping it without recording an outcome (e.g.
+/// because the request was cancelled) marks the upload interrupted.
+pub struct ActiveUpload {
+    journal: UploadJournal,
+    key: String,
+}
+
+impl ActiveUpload {
+    /// Drops the record of the upload, as there is nothing left to resume.
+    pub async fn complete(self, file_id: &str) {
+        tracing::debug!("Upload {} completed as file {file_id}", self.key);
+        self.journal.lock().records.remove(&self.key);
+        self.journal.persist().await;
+    }
+
+    pub async fn fail(self, error: &str) {
+        self.journal.update(&self.key, |record| {
+            record.status = UploadStatus::Failed;
+            record.error = Some(error.to_string());
+        });
+        self.journal.persist().await;
+    }
+}
+
+impl Drop for ActiveUpload {
+    fn drop(&mut self) {
+        self.journal.lock().active.remove(&self.key);
+        self.journal.update(&self.key, |record| {
+            if record.status == UploadStatus::InProgress {
+                record.status = UploadStatus::Interrupted;
+            }
+        });
+    }
+}
+
+/// Hex SHA-256 of a file's content, read in chunks.
+pub async fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Size and type restrictions on uploads, checked before anything is sent.
+#[derive(Clone, Debug)]
+pub struct UploadLimits {
+    pub max_bytes: u64,
+    /// Lowercase extensions without the dot, and MIME types.
+    pub allowed_types: Vec<String>,
+}
+
+impl UploadLimits {
+    /// Describes why `path` may not be uploaded, if it may not.
+    pub async fn check(&self, path: &Path) -> Result<(), String> {
+        self.check_type(path)?;
+        let size = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| format!("Cannot read {}: {e}", path.display()))?
+            .len();
+        self.check_size(path, size)
+    }
+
+    /// Describes why a file named like `path` may not be uploaded, if its
+    /// type is not allowed.
+    pub fn check_type(&self, path: &Path) -> Result<(), String> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        let mime_type = mime_type(path);
+        let allowed = self
+            .allowed_types
+            .iter()
+            .any(|allowed| *allowed == extension || allowed == mime_type);
+        if !allowed {
+            return Err(format!(
+                "{} has a file type that may not be uploaded (allowed: {})",
+                path.display(),
+                self.allowed_types.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Describes why `size` bytes named `path` may not be uploaded, if they
+    /// are too many.
+    pub fn check_size(&self, path: &Path, size: u64) -> Result<(), String> {
+        if size > self.max_bytes {
+            return Err(format!(
+                "{} is {size} bytes, more than the {} bytes allowed",
+                path.display(),
+                self.max_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Files below `dir` whose path relative to it matches `include` but not
+/// `exclude`, in a stable order. Symbolic links are not followed. Fails if
+/// more than `limit` files match.
+pub async fn collect_files(
+    dir: &Path,
+    include: &GlobSet,
+    exclude: &GlobSet,
+    limit: usize,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&current).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            if include.is_match(relative) && !exclude.is_match(relative) {
+                if files.len() == limit {
+                    return Err(std::io::Error::other(format!(
+                        "more than {limit} files match"
+                    )));
+                }
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// A file written to its own temporary directory, removed on drop.
+pub struct TempFile {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Writes `content` to a fresh temporary directory under `file_name`,
+    /// which must be a plain file name.
+    pub async fn write(file_name: &str, content: &[u8]) -> std::io::Result<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        if Path::new(file_name).file_name() != Some(file_name.as_ref()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{file_name:?} is not a plain file name"),
+            ));
+        }
+        let dir = std::env::temp_dir().join(format!(
+            "assistant-mcp-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::create_dir_all(&dir).await?;
+        let file = Self {
+            path: dir.join(file_name),
+            dir,
+        };
+        tokio::fs::write(&file.path, content).await?;
+        Ok(file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            tracing::warn!("Failed to remove {}: {e}", self.dir.display());
+        }
+    }
+}
+
+async fn file_stamp(path: &Path) -> std::io::Result<(u64, u64)> {
+    let metadata = tokio::fs::metadata(path).await?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Ok((metadata.len(), modified))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[tokio::test]
+    async fn test_file_sha256() {
+        let temp = temp_dir();
+        let path = temp.path().join("sha256.txt");
+        std::fs::write(&path, "abc").unwrap();
+        let hash = file_sha256(&path).await.unwrap();
+        assert_eq!(
+            hash,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_files_applies_patterns() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("docs/drafts")).unwrap();
+        for file in ["a.md", "docs/b.pdf", "docs/c.exe", "docs/drafts/d.md"] {
+            std::fs::write(dir.join(file), "x").unwrap();
+        }
+        let globs = |patterns: &[&str]| {
+            let mut builder = globset::GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(globset::Glob::new(pattern).unwrap());
+            }
+            builder.build().unwrap()
+        };
+
+        let files = collect_files(
+            dir,
+            &globs(&["**/*.md", "**/*.pdf"]),
+            &globs(&["**/drafts/**"]),
+            10,
+        )
+        .await;
+        let too_many = collect_files(dir, &globs(&["**"]), &globs(&[]), 2).await;
+
+        assert_eq!(
+            files.unwrap(),
+            vec![dir.join("a.md"), dir.join("docs/b.pdf")]
+        );
+        assert!(too_many.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upload_limits() {
+        let limits = UploadLimits {
+            max_bytes: 3,
+            allowed_types: vec!["md".to_string(), "application/pdf".to_string()],
+        };
+        let small = TempFile::write("small.MD", b"abc").await.unwrap();
+        let large = TempFile::write("large.md", b"abcd").await.unwrap();
+        let pdf = TempFile::write("doc.pdf", b"%").await.unwrap();
+        let binary = TempFile::write("tool.exe", b"").await.unwrap();
+
+        assert!(limits.check(small.path()).await.is_ok());
+        assert!(limits.check(pdf.path()).await.is_ok());
+        assert!(
+            limits
+                .check(large.path())
+                .await
+                .unwrap_err()
+                .contains("4 bytes")
+        );
+        assert!(
+            limits
+                .check(binary.path())
+                .await
+                .unwrap_err()
+                .contains("file type")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_temp_file_is_removed_on_drop() {
+        let file = TempFile::write("notes.md", b"hi").await.unwrap();
+        let path = file.path().to_path_buf();
+        assert_eq!(path.file_name().unwrap(), "notes.md");
+        assert_eq!(std::fs::read(&path).unwrap(), b"hi");
+        drop(file);
+        assert!(!path.exists());
+
+        assert!(TempFile::write("../escape.md", b"").await.is_err());
+        assert!(TempFile::write("", b"").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_uploads_survive_restart() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let file = dir.join("doc.md");
+        std::fs::write(&file, "content").unwrap();
+
+        let journal = UploadJournal::new(Some(dir.to_path_buf()));
+        let metadata = Map::from_iter([("tag".to_string(), Value::from("a"))]);
+        let interrupted = journal
+            .start("kb", &file, Some(&metadata))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(journal.start("kb", &file, None).await.unwrap().is_none());
+        let done = journal.start("other", &file, None).await.unwrap().unwrap();
+        done.complete("f1").await;
+        // Simulates a crash: the journal on disk still says in progress.
+        std::mem::forget(interrupted);
+
+        let reopened = UploadJournal::new(Some(dir.to_path_buf()));
+        let unfinished = reopened.unfinished(None);
+
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(unfinished[0].assistant_name, "kb");
+        assert_eq!(unfinished[0].status, UploadStatus::Interrupted);
+        assert_eq!(unfinished[0].metadata, Some(metadata));
+        assert!(reopened.unfinished(Some("other")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_uploads_are_all_journaled() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let journal = UploadJournal::new(Some(dir.to_path_buf()));
+        let files: Vec<_> = (0..16)
+            .map(|i| {
+                let file = dir.join(format!("doc-{i}.md"));
+                std::fs::write(&file, "content").unwrap();
+                file
+            })
+            .collect();
+
+        let uploads = futures::future::join_all(files.iter().enumerate().map(|(i, file)| {
+            let journal = journal.clone();
+            async move {
+                let upload = journal.start("kb", file, None).await.unwrap().unwrap();
+                if i % 2 == 0 {
+                    upload.complete("f").await;
+                } else {
+                    upload.fail("rejected").await;
+                }
+            }
+        }));
+        uploads.await;
+
+        let reopened = UploadJournal::new(Some(dir.to_path_buf()));
+        let unfinished = reopened.unfinished(None);
+        let on_disk = std::fs::read_to_string(dir.join(JOURNAL_FILE)).unwrap();
+
+        assert_eq!(unfinished.len(), 8);
+        assert!(unfinished.iter().all(|r| r.status == UploadStatus::Failed));
+        assert!(!on_disk.contains("completed"));
+    }
+}
@@ -0,0 +1,395 @@
+//! Usage of the Pinecone API per assistant (queries, chats, tokens, errors),
+//! totalled for the lifetime of the server and per day, and the cost the
+//! reported tokens add up to.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::logging;
+
+/// How often counters changed since the last write are persisted.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Prices `estimate_cost` applies, in US dollars. The defaults are
+/// Pinecone's list prices for the Standard plan.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Pricing {
+    /// Per million tokens of retrieved context.
+    pub context_tokens: f64,
+    /// Per million prompt tokens of chat requests.
+    pub chat_input_tokens: f64,
+    /// Per million completion tokens of chat responses.
+    pub chat_output_tokens: f64,
+    /// Per API call, on top of its tokens.
+    pub request: f64,
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self {
+            context_tokens: 5.0,
+            chat_input_tokens: 8.0,
+            chat_output_tokens: 15.0,
+            request: 0.0,
+        }
+    }
+}
+
+/// Which API produced the usage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Context,
+    Chat,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Totals {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    errors: u64,
+}
+
+impl Totals {
+    fn add(&mut self, usage: &Value) {
+        let tokens = |key: &str| usage.get(key).and_then(Value::as_u64);
+        let prompt = tokens("prompt_tokens");
+        let completion = tokens("completion_tokens");
+        self.requests += 1;
+        // Responses that only report a total count it as prompt tokens.
+        self.prompt_tokens += prompt
+            .or(tokens("total_tokens").filter(|_| completion.is_none()))
+            .unwrap_or(0);
+        self.completion_tokens += completion.unwrap_or(0);
+    }
+}
+
+/// Counters of one assistant; `context.requests` counts its queries.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct AssistantUsage {
+    context: Totals,
+    chat: Totals,
+}
+
+impl AssistantUsage {
+    fn totals(&mut self, kind: Kind) -> &mut Totals {
+        match kind {
+            Kind::Context => &mut self.context,
+            Kind::Chat => &mut self.chat,
+        }
+    }
+}
+
+/// The counters of one day (UTC), as persisted.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DailyRollup {
+    date: String,
+    assistants: BTreeMap<String, AssistantUsage>,
+}
+
+#[derive(Debug, Default)]
+struct LedgerState {
+    /// Since the server started.
+    totals: BTreeMap<String, AssistantUsage>,
+    /// Day of `today`, counted from the epoch.
+    day: u64,
+    today: BTreeMap<String, AssistantUsage>,
+    /// Whether `today` changed since it was last persisted.
+    dirty: bool,
+    /// A past day's rollup not yet persisted when the day changed.
+    unwritten: Option<(u64, BTreeMap<String, AssistantUsage>)>,
+}
+
+/// Usage counters per assistant, optionally persisted as daily rollups
+/// (`usage-YYYY-MM-DD.json`) for offline reporting.
+#[derive(Clone, Debug, Default)]
+pub struct UsageLedger {
+    state: Arc<Mutex<LedgerState>>,
+    dir: Option<PathBuf>,
+    /// Serializes writes so an older rollup never replaces a newer one.
+    persisting: Arc<tokio::sync::Mutex<()>>,
+    /// Whether a background task persists changed counters.
+    flushing: Arc<AtomicBool>,
+}
+
+impl UsageLedger {
+    /// Creates a ledger persisting rollups to `dir`, if given, continuing
+    /// today's rollup if one was written before a restart.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        let day = logging::today();
+        let mut today = BTreeMap::new();
+        if let Some(dir) = &dir {
+            let path = rollup_path(dir, day);
+            match std::fs::read(&path) {
+                Ok(data) => match serde_json::from_slice::<DailyRollup>(&data) {
+                    Ok(rollup) => today = rollup.assistants,
+                    Err(e) => {
+                        tracing::warn!("Ignoring unreadable usage rollup {}: {e}", path.display())
+                    }
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("Failed to read usage rollup {}: {e}", path.display()),
+            }
+        }
+        Self {
+            state: Arc::new(Mutex::new(LedgerState {
+                totals: BTreeMap::new(),
+                day,
+                today,
+                dirty: false,
+                unwritten: None,
+            })),
+            dir,
+            persisting: Arc::default(),
+            flushing: Arc::default(),
+        }
+    }
+
+    /// Adds the `usage` object of an API response.
+    pub fn record(&self, assistant_name: &str, kind: Kind, usage: &Value) {
+        self.update(assistant_name, logging::today(), |usage_of| {
+            usage_of.totals(kind).add(usage)
+        });
+    }
+
+    /// Counts a failed API call.
+    pub fn record_error(&self, assistant_name: &str, kind: Kind) {
+        self.update(assistant_name, logging::today(), |usage_of| {
+            usage_of.totals(kind).errors += 1
+        });
+    }
+
+    /// Changes the counters, which are persisted within [`FLUSH_INTERVAL`].
+    fn update(&self, assistant_name: &str, day: u64, update: impl Fn(&mut AssistantUsage)) {
+        {
+            let mut state = self.lock();
+            if state.day != day {
+                let past = (state.day, std::mem::take(&mut state.today));
+                if state.dirty {
+                    state.unwritten = Some(past);
+                }
+                state.day = day;
+            }
+            update(state.totals.entry(assistant_name.to_string()).or_default());
+            update(state.today.entry(assistant_name.to_string()).or_default());
+            state.dirty = true;
+        }
+        if self.dir.is_some() && !self.flushing.swap(true, Ordering::SeqCst) {
+            let ledger = self.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(FLUSH_INTERVAL).await;
+                    ledger.flush().await;
+                }
+            });
+        }
+    }
+
+    /// Persists the counters changed since the last write, e.g. before the
+    /// server exits.
+    pub async fn flush(&self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let _persisting = self.persisting.lock().await;
+        let rollups = {
+            let mut state = self.lock();
+            let mut rollups = Vec::new();
+            if let Some((day, assistants)) = state.unwritten.take() {
+                rollups.push((day, assistants));
+            }
+            if std::mem::take(&mut state.dirty) {
+                rollups.push((state.day, state.today.clone()));
+            }
+            rollups
+        };
+        for (day, assistants) in rollups {
+            let rollup = DailyRollup {
+                date: logging::civil_date(day),
+                assistants,
+            };
+            Self::write(dir, &rollup_path(dir, day), &rollup).await;
+        }
+    }
+
+    async fn write(dir: &Path, path: &Path, rollup: &DailyRollup) {
+        let data = match serde_json::to_vec_pretty(rollup) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Failed to serialize usage rollup: {e}");
+                return;
+            }
+        };
+        if let Err(e) = tokio::fs::create_dir_all(dir).await {
+            tracing::warn!("Failed to create usage directory: {e}");
+        }
+        // Write-then-rename so a crash mid-write can't corrupt the rollup.
+        let tmp = path.with_extension("json.tmp");
+        let written = match tokio::fs::write(&tmp, data).await {
+            Ok(()) => tokio::fs::rename(&tmp, path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            tracing::warn!("Failed to persist usage rollup to {}: {e}", path.display());
+        }
+    }
+
+    /// Counters since the server started and for today, for all assistants
+    /// or just one.
+    pub fn counters(&self, assistant_name: Option<&str>) -> Value {
+        let state = self.lock();
+        let select =
+            |assistants: &BTreeMap<String, AssistantUsage>| -> BTreeMap<String, AssistantUsage> {
+                assistants
+                    .iter()
+                    .filter(|(name, _)| assistant_name.is_none_or(|only| only == name.as_str()))
+                    .map(|(name, usage)| (name.clone(), *usage))
+                    .collect()
+            };
+        json!({
+            "since_start": select(&state.totals),
+            "today": {
+                "date": logging::civil_date(state.day),
+                "assistants": select(&state.today),
+            },
+        })
+    }
+
+    /// Totals and their cost per assistant, for all assistants or just one.
+    pub fn estimate(&self, pricing: &Pricing, assistant_name: Option<&str>) -> Value {
+        let per_token = |tokens: u64, per_million: f64| tokens as f64 * per_million / 1e6;
+        let mut total = 0.0;
+        let mut assistants = serde_json::Map::new();
+        for (name, usage) in self.lock().totals.iter() {
+            if assistant_name.is_some_and(|only| only != name) {
+                continue;
+            }
+            let context_tokens = usage.context.prompt_tokens + usage.context.completion_tokens;
+            let context_cost = per_token(context_tokens, pricing.context_tokens)
+                + usage.context.requests as f64 * pricing.request;
+            let chat_cost = per_token(usage.chat.prompt_tokens, pricing.chat_input_tokens)
+                + per_token(usage.chat.completion_tokens, pricing.chat_output_tokens)
+                + usage.chat.requests as f64 * pricing.request;
+            total += context_cost + chat_cost;
+            assistants.insert(
+                name.clone(),
+                json!({
+                    "context": {
+                        "requests": usage.context.requests,
+                        "tokens": context_tokens,
+                        "cost_usd": round(context_cost),
+                    },
+                    "chat": {
+                        "requests": usage.chat.requests,
+                        "input_tokens": usage.chat.prompt_tokens,
+                        "output_tokens": usage.chat.completion_tokens,
+                        "cost_usd": round(chat_cost),
+                    },
+                    "cost_usd": round(context_cost + chat_cost),
+                }),
+            );
+        }
+        json!({
+            "total_cost_usd": round(total),
+            "assistants": assistants,
+            "pricing_usd": {
+                "context_tokens_per_million": pricing.context_tokens,
+                "chat_input_tokens_per_million": pricing.chat_input_tokens,
+                "chat_output_tokens_per_million": pricing.chat_output_tokens,
+                "per_request": pricing.request,
+            },
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LedgerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn rollup_path(dir: &Path, day: u64) -> PathBuf {
+    dir.join(format!("usage-{}.json", logging::civil_date(day)))
+}
+
+/// Rounds to millionths of a dollar, hiding float noise.
+fn round(usd: f64) -> f64 {
+    (usd * 1e6).round() / 1e6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_estimate_applies_pricing_per_kind() {
+        let ledger = UsageLedger::default();
+        ledger.record("kb", Kind::Context, &json!({"total_tokens": 1000}));
+        ledger.record(
+            "kb",
+            Kind::Chat,
+            &json!({"prompt_tokens": 2000, "completion_tokens": 100, "total_tokens": 2100}),
+        );
+        ledger.record("docs", Kind::Context, &json!({"prompt_tokens": 500}));
+        let pricing = Pricing {
+            request: 0.001,
+            ..Pricing::default()
+        };
+
+        let all = ledger.estimate(&pricing, None);
+        let kb = ledger.estimate(&pricing, Some("kb"));
+
+        assert_eq!(kb["assistants"]["kb"]["context"]["cost_usd"], 0.006);
+        assert_eq!(kb["assistants"]["kb"]["chat"]["cost_usd"], 0.0185);
+        assert_eq!(kb["total_cost_usd"], 0.0245);
+        assert!(kb["assistants"].get("docs").is_none());
+        assert_eq!(all["total_cost_usd"], 0.028);
+    }
+
+    #[tokio::test]
+    async fn test_daily_rollups_are_persisted_and_reset() {
+        let temp = temp_dir();
+        let dir = temp.path();
+        let ledger = UsageLedger::new(Some(dir.to_path_buf()));
+        ledger.record("kb", Kind::Context, &json!({"prompt_tokens": 10}));
+        ledger.record_error("kb", Kind::Chat);
+        // Counters are written in the background or when flushed.
+        let today = rollup_path(dir, logging::today());
+        assert!(!today.exists());
+        ledger.flush().await;
+
+        // A restart continues today's rollup.
+        let restarted = UsageLedger::new(Some(dir.to_path_buf()));
+        let counters = restarted.counters(Some("kb"));
+        assert_eq!(
+            counters["today"]["assistants"]["kb"]["context"]["requests"],
+            1
+        );
+        assert_eq!(counters["today"]["assistants"]["kb"]["chat"]["errors"], 1);
+        assert_eq!(counters["since_start"], json!({}));
+
+        // A day's last counters are written even once the next day began.
+        ledger.record_error("kb", Kind::Chat);
+        let tomorrow = logging::today() + 1;
+        ledger.update("kb", tomorrow, |usage| usage.context.requests += 1);
+        ledger.flush().await;
+        let rollup: Value = serde_json::from_slice(&std::fs::read(&today).unwrap()).unwrap();
+        assert_eq!(rollup["assistants"]["kb"]["chat"]["errors"], 2);
+        assert!(rollup_path(dir, tomorrow).exists());
+        let counters = ledger.counters(None);
+        assert_eq!(
+            counters["today"]["assistants"]["kb"]["context"]["requests"],
+            1
+        );
+        assert_eq!(counters["since_start"]["kb"]["context"]["requests"], 2);
+        assert_eq!(counters["today"]["date"], logging::civil_date(tomorrow));
+    }
+}